@@ -0,0 +1,23 @@
+/* C interface to knoll, available when built with the `ffi` feature.
+ *
+ * Each function returns the status code the knoll command would exit with,
+ * and stores either a JSON result or, on failure, an error message in `out`.
+ * Strings stored in `out` must be released with `knoll_free_string`.
+ */
+#ifndef KNOLL_H
+#define KNOLL_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+int knoll_capture(char **out);
+int knoll_apply(const char *config, char **out);
+int knoll_list_modes(int all_modes, char **out);
+void knoll_free_string(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* KNOLL_H */
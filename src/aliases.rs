@@ -0,0 +1,239 @@
+//! This module provides a persistent store of display aliases, so that
+//! displays can be referred to by memorable names rather than by their
+//! UUIDs.  Aliases are kept as a JSON object mapping each name to a UUID,
+//! and may be used anywhere a UUID is accepted, in configurations and on
+//! the command-line.
+use coverage_helper::test;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Formatter;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ConfigGroups, Origin};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The possible failures when reading or updating the alias store.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The name cannot be used as an alias.
+    InvalidName(String),
+    /// There is no alias with the given name.
+    UnknownAlias(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(ie) => write!(f, "Alias store I/O error: {}", ie),
+            Error::Json(je) => write!(f, "Alias store is malformed: {}", je),
+            Error::InvalidName(name) => write!(
+                f,
+                "'{}' cannot be used as an alias, as it is empty, contains \
+                 whitespace, or could be mistaken for a UUID.",
+                name
+            ),
+            Error::UnknownAlias(name) => write!(f, "There is no alias named '{}'.", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::InvalidName(_) | Error::UnknownAlias(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The alias store location used when none is specified.  This is
+/// `~/.config/knoll/aliases`.
+pub fn default_aliases_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".config/knoll/aliases"),
+        None => std::env::temp_dir().join("knoll-aliases"),
+    }
+}
+
+/// Helper to determine whether a name could be mistaken for a UUID, as
+/// knoll reports them.
+fn looks_like_uuid(name: &str) -> bool {
+    name.len() == 32 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// A collection of aliases for display UUIDs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Load the aliases stored at the given path.  If there is no file at
+    /// the path, there are no aliases.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(string) => Ok(serde_json::from_str(string.as_str())?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Store the aliases at the given path, creating any missing parent
+    /// directories.  The file is replaced atomically, so a failure part way
+    /// through will not lose existing aliases.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        std::fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    /// Add or replace the alias with the given name.
+    pub fn set(&mut self, name: &str, uuid: &str) -> Result<(), Error> {
+        if name.is_empty() || name.chars().any(char::is_whitespace) || looks_like_uuid(name) {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+        self.aliases.insert(name.to_owned(), uuid.to_owned());
+        Ok(())
+    }
+
+    /// Remove the alias with the given name, returning the UUID it referred
+    /// to.
+    pub fn remove(&mut self, name: &str) -> Result<String, Error> {
+        self.aliases
+            .remove(name)
+            .ok_or_else(|| Error::UnknownAlias(name.to_owned()))
+    }
+
+    /// Obtain the UUID for the given alias.  Anything that is not an alias
+    /// is assumed to already be a UUID, and is returned unchanged.
+    pub fn resolve(&self, name_or_uuid: &str) -> String {
+        self.aliases
+            .get(name_or_uuid)
+            .cloned()
+            .unwrap_or_else(|| name_or_uuid.to_owned())
+    }
+
+    /// The names of all the aliases.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.aliases.keys()
+    }
+
+    /// Replace any aliases used in place of UUIDs in the configuration
+    /// groups, and in the displays they ignore.
+    pub fn resolve_config_groups(&self, config_groups: &mut ConfigGroups) {
+        for uuid in &mut config_groups.ignored {
+            *uuid = self.resolve(uuid.as_str());
+        }
+        for config in config_groups
+            .groups
+            .iter_mut()
+            .flat_map(|group| group.configs.iter_mut())
+        {
+            config.uuid = self.resolve(config.uuid.as_str());
+            for reference in config.origin.iter_mut().flat_map(Origin::references_mut) {
+                reference.display = self.resolve(reference.display.as_str());
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test adding, removing, and resolving aliases.
+#[test]
+fn test_aliases() {
+    use crate::config::{Config, ConfigGroup};
+
+    let uuid = "37d8832a2d6602cab9f78f30a301b230";
+    let mut aliases = Aliases::default();
+    aliases.set("left", uuid).expect("Setting should not fail");
+    assert_eq!(aliases.resolve("left"), uuid);
+    assert_eq!(aliases.resolve("right"), "right");
+    assert_eq!(aliases.resolve(uuid), uuid);
+
+    let mut config_groups = ConfigGroups {
+        groups: vec![ConfigGroup {
+            name: None,
+            description: None,
+            configs: vec![
+                Config {
+                    uuid: "left".to_owned(),
+                    ..Default::default()
+                },
+                Config {
+                    uuid: "right".to_owned(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }],
+        ignored: vec!["left".to_owned()],
+    };
+    aliases.resolve_config_groups(&mut config_groups);
+    assert_eq!(config_groups.groups[0].configs[0].uuid, uuid);
+    assert_eq!(config_groups.groups[0].configs[1].uuid, "right");
+    assert_eq!(config_groups.ignored, vec![uuid.to_owned()]);
+
+    for name in ["", "two words", "ABCDEF0123456789abcdef0123456789"] {
+        assert!(matches!(
+            aliases.set(name, uuid),
+            Err(Error::InvalidName(_))
+        ));
+    }
+
+    assert_eq!(aliases.remove("left").unwrap(), uuid);
+    assert!(matches!(
+        aliases.remove("left"),
+        Err(Error::UnknownAlias(_))
+    ));
+    assert_eq!(aliases, Aliases::default());
+}
+
+/// Test loading and saving the alias store.
+#[test]
+fn test_aliases_persistence() {
+    let dir = std::env::temp_dir().join(format!("knoll-aliases-test-{}", std::process::id()));
+    let path = dir.join("nested/aliases");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // A missing store has no aliases.
+    assert_eq!(Aliases::load(&path).unwrap(), Aliases::default());
+
+    let mut aliases = Aliases::default();
+    aliases
+        .set("left", "37d8832a2d6602cab9f78f30a301b230")
+        .unwrap();
+    aliases.save(&path).expect("Saving should not fail");
+    assert_eq!(Aliases::load(&path).unwrap(), aliases);
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(matches!(Aliases::load(&path), Err(Error::Json(_))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,146 @@
+//! This module provides a high-level API for using knoll from other Rust
+//! programs, without needing to run the knoll command.  Like the command,
+//! it is generic over the `DisplayState`, so it can be used with either the
+//! real displays or the fake displays used for testing.
+use coverage_helper::test;
+
+use crate::aliases::Aliases;
+use crate::config::ConfigGroups;
+use crate::displays::{ConfigurationScope, DisplayState, DuplicateModes};
+use crate::knoll::{
+    apply_config_groups, collect_mode_groups, label_state, state_to_config, AbandonedCommit,
+    CommitOptions, PipelineOptions,
+};
+use crate::valid_config::validate_config_groups;
+
+pub use crate::display_events::{run_loop, stop_run_loop, DisplayEvent, DisplayEvents};
+pub use crate::knoll::{DisplayModeGroup, Error, ModeSelection};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Options controlling how configuration groups are applied.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// How display modes should be selected.
+    pub selection: ModeSelection,
+    /// Shell command to run after the displays have been changed, if any.
+    pub on_change: Option<String>,
+    /// How long the changes to the displays should last.
+    pub scope: ConfigurationScope,
+    /// How long to fade the displays over while changing them, if at all.
+    pub fade: Option<std::time::Duration>,
+    /// Where to write the state of the displays before changing them, if
+    /// anywhere.
+    pub backup: Option<std::path::PathBuf>,
+    /// How long to wait for committing the changes to complete, if limited.
+    pub commit_timeout: Option<std::time::Duration>,
+}
+
+/// Capture the state of the attached displays as configuration groups.
+/// Applying the result will restore the displays to their current state.
+pub fn capture<DS: DisplayState>() -> Result<ConfigGroups, Error> {
+    Ok(state_to_config(&DS::current()?))
+}
+
+/// Apply the most precise of the given configuration groups to the attached
+/// displays, exactly as the knoll command does, returning the resulting
+/// state of the displays.  If there are no configuration groups, the
+/// displays are left unchanged.
+pub fn apply<DS: DisplayState>(
+    config_groups: ConfigGroups,
+    options: &ApplyOptions,
+) -> Result<ConfigGroups, Error> {
+    let ignored = config_groups.ignored.clone();
+    let valid_groups = validate_config_groups(config_groups)?;
+    let options = PipelineOptions {
+        quiet: true,
+        selection: options.selection.clone(),
+        on_change: options.on_change.clone(),
+        detailed: false,
+        exit_code: false,
+        commit: CommitOptions {
+            scope: options.scope,
+            fade: options.fade,
+            backup: options.backup.clone(),
+            timeout: options.commit_timeout,
+            confirm: false,
+            abandoned: AbandonedCommit::default(),
+        },
+    };
+    let (display_state, chosen_config, _) = apply_config_groups::<DS>(
+        &valid_groups,
+        &ignored,
+        crate::serde::Format::Json,
+        &options,
+    )?;
+    let mut state = state_to_config(&display_state);
+    label_state(&mut state, chosen_config.as_ref());
+    Ok(state)
+}
+
+/// List the display modes of the attached displays, or only of the displays
+/// with the given UUIDs if any are given.  Modes that are not usable for the
+/// desktop are only included if `all_modes` is set.
+pub fn list_modes<DS: DisplayState>(
+    uuids: &[String],
+    all_modes: bool,
+) -> Result<Vec<DisplayModeGroup<DS::DisplayModeType>>, Error> {
+    collect_mode_groups(
+        &DS::current()?,
+        uuids,
+        &Aliases::default(),
+        all_modes,
+        DuplicateModes::default(),
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test the API using the fake displays.
+#[test]
+fn test_api() {
+    use crate::fake_displays::FakeDisplayState;
+
+    // The captured state is a single configuration group with a
+    // configuration for each of the fake displays.
+    let state = capture::<FakeDisplayState>().expect("Capturing should not fail");
+    assert_eq!(state.groups.len(), 1);
+    assert_eq!(state.groups[0].configs.len(), 3);
+
+    // With no configuration groups, the displays are left unchanged.
+    let applied = apply::<FakeDisplayState>(
+        ConfigGroups {
+            groups: vec![],
+            ignored: vec![],
+        },
+        &Default::default(),
+    )
+    .expect("Applying should not fail");
+    assert_eq!(applied, state);
+
+    // Applying the captured state leaves the displays unchanged.
+    let applied = apply::<FakeDisplayState>(state.clone(), &Default::default())
+        .expect("Applying should not fail");
+    assert_eq!(applied, state);
+
+    // Invalid configuration groups are rejected.
+    let invalid = ConfigGroups {
+        groups: vec![Default::default()],
+        ignored: vec![],
+    };
+    assert!(apply::<FakeDisplayState>(invalid, &ApplyOptions::default()).is_err());
+
+    // Modes not usable for the desktop are only listed if requested.
+    let all_modes = list_modes::<FakeDisplayState>(&[], true).expect("Listing should not fail");
+    let usable_modes = list_modes::<FakeDisplayState>(&[], false).expect("Listing should not fail");
+    assert_eq!(all_modes.len(), 3);
+    assert!(all_modes.iter().any(|group| !group.unsafe_modes.is_empty()));
+    assert!(usable_modes
+        .iter()
+        .all(|group| group.unsafe_modes.is_empty()));
+
+    match list_modes::<FakeDisplayState>(&["abcdef1234".to_owned()], false) {
+        Err(Error::UnknownDisplays(uuids, _)) => assert_eq!(uuids, vec!["abcdef1234".to_owned()]),
+        result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+    }
+}
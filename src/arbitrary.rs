@@ -0,0 +1,478 @@
+//! This module provides support for property-based testing of the
+//! configuration types, so that serialization and validation invariants can
+//! be exercised on many randomly generated configurations rather than only
+//! on hand-written examples.  It is only built with the `testing` feature.
+//!
+//! It implements `proptest`'s `Arbitrary` trait for the configuration types,
+//! so that they can be generated with `any::<T>()`, and failing cases are
+//! shrunk to a minimal counterexample.  `proptest_config` gives a
+//! configuration that makes runs reproducible.
+use coverage_helper::test;
+use proptest::prelude::*;
+use proptest::test_runner::{Config as ProptestConfig, RngSeed};
+
+use crate::config::{
+    Config, ConfigGroup, ConfigGroups, Coordinate, Frequency, FrequencyName, Hooks, Origin,
+    Resolution, ResolutionName, Units,
+};
+use crate::displays::{
+    AspectRatio, DisplayKind, ExtentsRange, FrequencyRange, Mirroring, Point, Rotation, Scale,
+    Underscan,
+};
+use crate::expression::{Axis, Expression, Operand, Property, Reference, Term};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The seed used by `proptest_config` when none is given in the environment.
+pub const DEFAULT_SEED: u64 = 0x6b6e6f6c6c;
+
+/// A `proptest` configuration for testing the configuration types.  Unlike
+/// the default, values are generated from a fixed seed unless
+/// `PROPTEST_RNG_SEED` is set, so that runs are reproducible, and failures
+/// are not persisted to regression files.
+pub fn proptest_config() -> ProptestConfig {
+    // The default configuration reads the seed from the environment.
+    let config = ProptestConfig::default();
+    let rng_seed = match config.rng_seed {
+        RngSeed::Random => RngSeed::Fixed(DEFAULT_SEED),
+        seed => seed,
+    };
+    ProptestConfig {
+        rng_seed,
+        failure_persistence: None,
+        ..config
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Short strings of printable characters, as used in hooks and names.
+fn string() -> BoxedStrategy<String> {
+    "[ -~]{0,15}".boxed()
+}
+
+/// UUIDs are drawn from a small pool of prefixes of a few UUIDs, so that
+/// configurations for the same display, ambiguous prefixes, and malformed
+/// UUIDs all occur regularly.
+fn uuid() -> BoxedStrategy<String> {
+    const UUIDS: [&str; 3] = [
+        "37d8832a2d6602cab9f78f30a301b230",
+        "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00",
+        "not-a-uuid",
+    ];
+    prop::sample::select(UUIDS.to_vec())
+        .prop_flat_map(|uuid| (1..=uuid.len()).prop_map(move |len| uuid[..len].to_owned()))
+        .boxed()
+}
+
+/// Helper to generate plausible display extents.
+fn extents() -> BoxedStrategy<Point> {
+    (1..=8192i64, 1..=8192i64)
+        .prop_map(|(x, y)| Point { x, y })
+        .boxed()
+}
+
+impl Arbitrary for Point {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Points within a plausible desktop, including negative coordinates.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (-8192..=8192i64, -8192..=8192i64)
+            .prop_map(|(x, y)| Point { x, y })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Rotation {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop::sample::select(Rotation::VALUES.to_vec()).boxed()
+    }
+}
+
+impl Arbitrary for Scale {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Scales are compared to the hundredth, so only those are generated.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (25..=400u32)
+            .prop_map(|hundredths| Scale(hundredths as f64 / 100.0))
+            .boxed()
+    }
+}
+
+impl Arbitrary for Underscan {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Both valid and invalid amounts, to the hundredth.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (-50..=150i32)
+            .prop_map(|hundredths| Underscan(hundredths as f64 / 100.0))
+            .boxed()
+    }
+}
+
+impl Arbitrary for DisplayKind {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use DisplayKind::*;
+        prop::sample::select(vec![Physical, Sidecar, AirPlay, Virtual]).boxed()
+    }
+}
+
+impl Arbitrary for Units {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![Just(Units::Points), Just(Units::Pixels)].boxed()
+    }
+}
+
+impl Arbitrary for Mirroring {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(Mirroring::Primary),
+            uuid().prop_map(Mirroring::MirrorOf)
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for ResolutionName {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use ResolutionName::*;
+        prop::sample::select(vec![Native, Max, FiveK, FourK, P1440, P1080, P720]).boxed()
+    }
+}
+
+impl Arbitrary for ExtentsRange {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Only ranges with at least one bound, as RON cannot distinguish an
+    /// empty range from a unit value.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            extents().prop_map(|min| ExtentsRange {
+                min: Some(min),
+                max: None,
+            }),
+            extents().prop_map(|max| ExtentsRange {
+                min: None,
+                max: Some(max),
+            }),
+            (extents(), extents()).prop_map(|(min, max)| ExtentsRange {
+                min: Some(min),
+                max: Some(max),
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Resolution {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            extents().prop_map(Resolution::Extents),
+            any::<ResolutionName>().prop_map(Resolution::Named),
+            any::<ExtentsRange>().prop_map(Resolution::Range),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for FrequencyRange {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Only ranges that can be written down, with at least one bound and
+    /// the minimum no greater than the maximum.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            (0..=240usize).prop_map(|min| FrequencyRange {
+                min: Some(min),
+                max: None,
+            }),
+            (0..=240usize).prop_map(|max| FrequencyRange {
+                min: None,
+                max: Some(max),
+            }),
+            (0..=240usize)
+                .prop_flat_map(|min| (Just(min), min..=240usize))
+                .prop_map(|(min, max)| FrequencyRange {
+                    min: Some(min),
+                    max: Some(max),
+                }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for AspectRatio {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (1..=32u32, 1..=32u32)
+            .prop_map(|(width, height)| AspectRatio { width, height })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Frequency {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            (0..=240usize).prop_map(Frequency::Exact),
+            Just(Frequency::Named(FrequencyName::Max)),
+            any::<FrequencyRange>().prop_map(Frequency::Range),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Hooks {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (prop::option::of(string()), prop::option::of(string()))
+            .prop_map(|(pre, post)| Hooks { pre, post })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Expression {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Sums of up to three terms.  Constants are never negative, as they
+    /// are written with a leading operator instead.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let reference = (
+            uuid(),
+            prop::sample::select(vec![Property::Extents, Property::Origin]),
+            prop::sample::select(vec![Axis::X, Axis::Y]),
+        )
+            .prop_map(|(display, property, axis)| Reference {
+                display,
+                property,
+                axis,
+            });
+        let operand = prop_oneof![
+            (0..=8192i64).prop_map(Operand::Constant),
+            reference.prop_map(Operand::Reference),
+        ];
+        let term =
+            (any::<bool>(), operand).prop_map(|(negated, operand)| Term { negated, operand });
+        prop::collection::vec(term, 1..=3)
+            .prop_map(|terms| Expression { terms })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Coordinate {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Usually exact, as expressions are far less common.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            3 => (-8192..=8192i64).prop_map(Coordinate::Exact),
+            1 => any::<Expression>().prop_map(Coordinate::Expression),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Origin {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<Coordinate>(), any::<Coordinate>())
+            .prop_map(|(x, y)| Origin { x, y })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Config {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        // Tuples of strategies are limited in size, so the fields are
+        // generated in two halves.
+        let placement = (
+            uuid(),
+            any::<Option<bool>>(),
+            any::<Option<bool>>(),
+            any::<Option<Origin>>(),
+            any::<Option<Resolution>>(),
+            any::<Option<Units>>(),
+            any::<Option<AspectRatio>>(),
+            any::<Option<bool>>(),
+            any::<Option<Scale>>(),
+        );
+        let mode = (
+            any::<Option<Frequency>>(),
+            prop::option::of(prop::sample::select(vec![8, 10, 16, 24])),
+            prop::option::of(0..1024i32),
+            any::<Option<Rotation>>(),
+            any::<Option<bool>>(),
+            any::<Option<Underscan>>(),
+            any::<Option<Mirroring>>(),
+            any::<Option<DisplayKind>>(),
+            any::<Option<u32>>(),
+        );
+        (placement, mode)
+            .prop_map(
+                |(
+                    (uuid, enabled, skip, origin, extents, units, aspect_ratio, scaled, scale),
+                    (
+                        frequency,
+                        color_depth,
+                        mode_id,
+                        rotation,
+                        closest,
+                        underscan,
+                        mirroring,
+                        kind,
+                        display_id,
+                    ),
+                )| Config {
+                    uuid,
+                    enabled,
+                    skip,
+                    origin,
+                    extents,
+                    units,
+                    aspect_ratio,
+                    scaled,
+                    scale,
+                    frequency,
+                    color_depth,
+                    mode_id,
+                    rotation,
+                    closest,
+                    underscan,
+                    mirroring,
+                    kind,
+                    display_id,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for ConfigGroup {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Groups of up to three configurations.  Conditions are not generated.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            prop::option::of(string()),
+            prop::option::of(string()),
+            prop::collection::vec(any::<Config>(), 0..=3),
+            any::<Option<Hooks>>(),
+        )
+            .prop_map(|(name, description, configs, hooks)| ConfigGroup {
+                name,
+                description,
+                configs,
+                hooks,
+                when: None,
+                wait: None,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ConfigGroups {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop::collection::vec(any::<ConfigGroup>(), 0..=3)
+            .prop_map(|groups| ConfigGroups {
+                groups,
+                ignored: vec![],
+            })
+            .boxed()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+proptest! {
+    #![proptest_config(proptest_config())]
+
+    /// Test that configuration groups are unchanged by serializing and then
+    /// deserializing them, in either format.
+    #[test]
+    fn test_serialization_round_trip(config_groups in any::<ConfigGroups>()) {
+        use crate::serde::{deserialize, serialize_to_string, Format};
+
+        for format in [Format::Json, Format::Ron] {
+            let string = serialize_to_string(format, &config_groups).unwrap();
+            let round_tripped = deserialize::<ConfigGroups>(format, string.as_str()).ok();
+            prop_assert_eq!(round_tripped, Some(config_groups.clone()));
+        }
+    }
+
+    /// Test that validation does not depend upon how the configuration was
+    /// serialized, and that valid groups never configure a display twice.
+    #[test]
+    fn test_validation_invariants(config_groups in any::<ConfigGroups>()) {
+        use crate::serde::{deserialize, serialize_to_string, Format};
+        use crate::valid_config::validate_config_groups;
+
+        let string = serialize_to_string(Format::Ron, &config_groups).unwrap();
+        let round_tripped: ConfigGroups = deserialize(Format::Ron, string.as_str()).unwrap();
+        let valid = validate_config_groups(config_groups.clone());
+        prop_assert_eq!(valid.is_ok(), validate_config_groups(round_tripped).is_ok());
+        if valid.is_ok() {
+            for group in &config_groups.groups {
+                let mut uuids: Vec<&str> = group.configs.iter().map(|c| c.uuid.as_str()).collect();
+                uuids.sort();
+                uuids.dedup();
+                prop_assert_eq!(uuids.len(), group.configs.len());
+            }
+        }
+    }
+}
+
+/// Test that the configuration generates the same values on each run.
+#[test]
+fn test_proptest_config_reproducible() {
+    use proptest::strategy::ValueTree;
+    use proptest::test_runner::TestRunner;
+
+    let generate = || {
+        let mut runner = TestRunner::new(proptest_config());
+        (0..8)
+            .map(|_| any::<Point>().new_tree(&mut runner).unwrap().current())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(generate(), generate());
+}
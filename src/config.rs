@@ -1,10 +1,22 @@
+use crate::displays::AspectRatio;
+use crate::displays::DisplayKind;
+use crate::displays::ExtentsRange;
+use crate::displays::FrequencyRange;
+use crate::displays::Mirroring;
 use crate::displays::Point;
 use crate::displays::Rotation;
+use crate::displays::Scale;
+use crate::displays::Underscan;
+use crate::expression::{Expression, Reference};
 ///! Data structures used for representing the current state of the attached
 /// displays as well as requesting changes to that configuration.
 use coverage_helper::test;
 
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 use std::cmp::{Eq, PartialEq};
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -12,7 +24,7 @@ use std::cmp::{Eq, PartialEq};
 /// Helper to serialize Option values as just the value itself.  It does not
 /// need to handle the case of None, as it is intended to be used with the
 /// serde option `skip_serializing_if = "Option::is_none"`.
-fn serialize_opt<S, T>(opt: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_opt<S, T>(opt: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
     T: Serialize,
@@ -76,6 +88,219 @@ fn test_deserialize_opt() {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Symbolic names for common display resolutions.  These are serialized
+/// as strings rather than as enum variants, as RON would otherwise write
+/// them as identifiers that cannot be read back in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+pub enum ResolutionName {
+    /// The native resolution of the display.  That is, the largest
+    /// resolution in backing pixels that the display supports.
+    #[serde(rename = "native")]
+    Native,
+    /// The largest resolution of the display modes that otherwise match
+    /// the configuration.
+    #[serde(rename = "max")]
+    Max,
+    #[serde(rename = "5k")]
+    FiveK,
+    #[serde(rename = "4k")]
+    FourK,
+    #[serde(rename = "1440p")]
+    P1440,
+    #[serde(rename = "1080p")]
+    P1080,
+    #[serde(rename = "720p")]
+    P720,
+}
+
+impl ResolutionName {
+    /// The name used in configurations.
+    pub fn name(&self) -> &'static str {
+        use ResolutionName::*;
+        match self {
+            Native => "native",
+            Max => "max",
+            FiveK => "5k",
+            FourK => "4k",
+            P1440 => "1440p",
+            P1080 => "1080p",
+            P720 => "720p",
+        }
+    }
+
+    /// The resolution in backing pixels corresponding to this name, if it
+    /// does not depend upon the display.
+    pub fn pixel_extents(&self) -> Option<Point> {
+        use ResolutionName::*;
+        let (x, y) = match self {
+            Native | Max => return None,
+            FiveK => (5120, 2880),
+            FourK => (3840, 2160),
+            P1440 => (2560, 1440),
+            P1080 => (1920, 1080),
+            P720 => (1280, 720),
+        };
+        Some(Point { x, y })
+    }
+}
+
+impl Serialize for ResolutionName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// A requested display resolution.  Either the exact extents of a display
+/// mode, a symbolic name that will be resolved against the backing
+/// pixel resolution of the display modes available on a display, or a range
+/// of acceptable extents.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Resolution {
+    Extents(Point),
+    Named(ResolutionName),
+    Range(ExtentsRange),
+}
+
+/// Symbolic names for refresh frequencies.  Like `ResolutionName`, these are
+/// serialized as strings.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+pub enum FrequencyName {
+    /// The highest frequency of the display modes that otherwise match the
+    /// configuration.
+    #[serde(rename = "max")]
+    Max,
+}
+
+impl FrequencyName {
+    /// The name used in configurations.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrequencyName::Max => "max",
+        }
+    }
+}
+
+impl Serialize for FrequencyName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// A requested refresh frequency.  Either an exact frequency, a symbolic
+/// name that will be resolved against the display modes available on a
+/// display, or a range of acceptable frequencies.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Frequency {
+    Exact(usize),
+    Named(FrequencyName),
+    Range(FrequencyRange),
+}
+
+impl Frequency {
+    /// The range of frequencies that satisfy the request, if it does not
+    /// depend upon the display.
+    pub fn range(&self) -> Option<FrequencyRange> {
+        match self {
+            Frequency::Exact(frequency) => Some(FrequencyRange::exactly(*frequency)),
+            Frequency::Named(_) => None,
+            Frequency::Range(range) => Some(*range),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// One coordinate of a requested origin.  Either an exact position, or an
+/// expression referring to other displays in the configuration group.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Coordinate {
+    Exact(i64),
+    Expression(Expression),
+}
+
+/// A requested origin for a display.  Like a `Point`, it is written as a
+/// pair of coordinates.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize_tuple, Serialize_tuple)]
+pub struct Origin {
+    pub x: Coordinate,
+    pub y: Coordinate,
+}
+
+impl Origin {
+    /// The origin as a point, if neither coordinate is an expression.
+    pub fn exact(&self) -> Option<Point> {
+        match (&self.x, &self.y) {
+            (Coordinate::Exact(x), Coordinate::Exact(y)) => Some(Point { x: *x, y: *y }),
+            _ => None,
+        }
+    }
+
+    /// The references made by either coordinate.
+    pub fn references(&self) -> impl Iterator<Item = &Reference> {
+        [&self.x, &self.y]
+            .into_iter()
+            .filter_map(|coordinate| match coordinate {
+                Coordinate::Expression(expression) => Some(expression),
+                Coordinate::Exact(_) => None,
+            })
+            .flat_map(|expression| expression.references())
+    }
+
+    /// The references made by either coordinate, so that the displays they
+    /// name can be replaced.
+    pub fn references_mut(&mut self) -> impl Iterator<Item = &mut Reference> {
+        [&mut self.x, &mut self.y]
+            .into_iter()
+            .filter_map(|coordinate| match coordinate {
+                Coordinate::Expression(expression) => Some(expression),
+                Coordinate::Exact(_) => None,
+            })
+            .flat_map(|expression| expression.references_mut())
+    }
+}
+
+impl From<Point> for Origin {
+    fn from(point: Point) -> Self {
+        Origin {
+            x: Coordinate::Exact(point.x),
+            y: Coordinate::Exact(point.y),
+        }
+    }
+}
+
+impl std::fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Coordinate::Exact(coordinate) => write!(f, "{}", coordinate),
+            Coordinate::Expression(expression) => write!(f, "{}", expression),
+        }
+    }
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The units in which the geometry of a display is given.  macOS positions
+/// and sizes displays in points, which for scaled display modes cover
+/// several backing pixels each.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    #[default]
+    Points,
+    Pixels,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 /// A Config describes how to configure an individual display.
 #[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
@@ -93,20 +318,50 @@ pub struct Config {
         default
     )]
     pub enabled: Option<bool>,
+    /// If true, the display must be attached for the configuration group to
+    /// match, but knoll leaves all of its settings untouched, such as for a
+    /// display managed by another tool.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub skip: Option<bool>,
+    /// The position of the display on the desktop.  Either coordinate may
+    /// be an expression referring to other displays in the group.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub origin: Option<Origin>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
         deserialize_with = "deserialize_opt",
         default
     )]
-    pub origin: Option<Point>,
+    pub extents: Option<Resolution>,
+    /// The units in which the `origin` and `extents` are given.  Points are
+    /// used unless backing pixels are requested.
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
         deserialize_with = "deserialize_opt",
         default
     )]
-    pub extents: Option<Point>,
+    pub units: Option<Units>,
+    /// The shape of the display mode, such as `16:9`, which can be used
+    /// instead of exact extents to match displays of different sizes.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub aspect_ratio: Option<AspectRatio>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
@@ -114,13 +369,22 @@ pub struct Config {
         default
     )]
     pub scaled: Option<bool>,
+    /// The scale factor of the display mode.  Unlike `scaled`, this can
+    /// distinguish between different degrees of scaling.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub scale: Option<Scale>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
         deserialize_with = "deserialize_opt",
         default
     )]
-    pub frequency: Option<usize>,
+    pub frequency: Option<Frequency>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
@@ -128,6 +392,18 @@ pub struct Config {
         default
     )]
     pub color_depth: Option<usize>,
+    /// The platform specific number identifying the display mode to use.
+    /// When given, the display mode is selected by this number alone,
+    /// rather than by matching the other display mode settings.  This is
+    /// an escape hatch for displays reporting modes that cannot otherwise
+    /// be told apart, and is reported when detailed output is requested.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub mode_id: Option<i32>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "serialize_opt",
@@ -135,25 +411,606 @@ pub struct Config {
         default
     )]
     pub rotation: Option<Rotation>,
+    /// If true, and no display mode matches this configuration exactly,
+    /// the closest available display mode will be used instead.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub closest: Option<bool>,
+    /// The fraction, between `0.0` and `1.0`, by which to underscan the
+    /// display.  Useful for televisions that crop the edges of the desktop.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub underscan: Option<Underscan>,
+    /// How the display is participating in a mirror set, if at all.  This
+    /// is only reported in the output, and is ignored when applying a
+    /// configuration.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub mirroring: Option<Mirroring>,
+    /// The kind of device the display must be for the configuration group
+    /// to match.  This is also reported when detailed output is requested.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub kind: Option<DisplayKind>,
+    /// The platform specific number identifying the display.  This is only
+    /// reported when detailed output is requested, to help correlate displays
+    /// with other tools, and is ignored when applying a configuration.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub display_id: Option<u32>,
+}
+
+/// Hooks are shell commands to run when a configuration group is applied.
+/// Each receives the serialized old and new display state on stdin.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run before the configuration group is applied.  If it fails, the
+    /// configuration group will not be applied.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub pre: Option<String>,
+    /// Run after the configuration group has been applied.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub post: Option<String>,
+}
+
+/// Whether the lid of a laptop is open or closed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LidState {
+    Open,
+    Closed,
+}
+
+/// The source of power for the computer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSource {
+    Battery,
+    Ac,
+}
+
+/// A time of day, with a resolution of minutes.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct TimeOfDay {
+    /// The number of minutes since midnight.
+    minutes: u16,
+}
+
+impl TimeOfDay {
+    /// Construct a time of day from hours and minutes, returning `None` if
+    /// either is out of range.
+    pub fn new(hours: u16, minutes: u16) -> Option<Self> {
+        (hours < 24 && minutes < 60).then_some(TimeOfDay {
+            minutes: hours * 60 + minutes,
+        })
+    }
+}
+
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+impl std::str::FromStr for TimeOfDay {
+    type Err = String;
+
+    /// Parse a time of day of the form `HH:MM`.
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        str.split_once(':')
+            .and_then(|(hours, minutes)| {
+                TimeOfDay::new(hours.trim().parse().ok()?, minutes.trim().parse().ok()?)
+            })
+            .ok_or_else(|| format!("Invalid time of day {}, expected HH:MM", str))
+    }
+}
+
+/// A range of times of day, from the start up to but not including the end.
+/// If the end is not after the start, the range wraps around midnight.
+/// Serialized as a string of the form `HH:MM-HH:MM`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl TimeRange {
+    /// Whether the given time of day falls within the range.
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl std::fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(str: String) -> Result<Self, Self::Error> {
+        let (start, end) = str
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid time range {}, expected HH:MM-HH:MM", str))?;
+        Ok(TimeRange {
+            start: start.parse()?,
+            end: end.parse()?,
+        })
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        range.to_string()
+    }
+}
+
+/// A period of time to wait, serialized as a human readable duration such
+/// as `5s` or `500ms`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct WaitPeriod(pub std::time::Duration);
+
+impl std::fmt::Display for WaitPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
+impl TryFrom<String> for WaitPeriod {
+    type Error = String;
+
+    fn try_from(str: String) -> Result<Self, Self::Error> {
+        humantime::parse_duration(&str)
+            .map(WaitPeriod)
+            .map_err(|e| format!("Invalid wait period {}: {}", str, e))
+    }
+}
+
+impl From<WaitPeriod> for String {
+    fn from(period: WaitPeriod) -> Self {
+        period.to_string()
+    }
+}
+
+/// Conditions on the environment that must hold for a configuration group
+/// to be applied, in addition to its displays being attached.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Hash, Serialize, Deserialize)]
+pub struct Conditions {
+    /// The required state of the lid of the built-in display.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub lid: Option<LidState>,
+    /// The required source of power.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub power: Option<PowerSource>,
+    /// The required SSID of the Wi-Fi network.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub ssid: Option<String>,
+    /// The range of times of day during which the group applies.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt",
+        deserialize_with = "deserialize_opt",
+        default
+    )]
+    pub between: Option<TimeRange>,
+}
+
+impl Conditions {
+    /// The number of conditions that are specified.  Configuration groups
+    /// with more conditions are considered more precise.
+    pub fn len(&self) -> usize {
+        usize::from(self.lid.is_some())
+            + usize::from(self.power.is_some())
+            + usize::from(self.ssid.is_some())
+            + usize::from(self.between.is_some())
+    }
+
+    /// Whether no conditions are specified.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// A ConfigGroup describes how to configure a group attached of displays.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(transparent)]
+/// A group without a name, description, hooks, conditions, or a wait period
+/// is serialized as just the list of configurations, otherwise it is
+/// serialized as a structure with `name`, `description`, `configs`, `hooks`,
+/// `when`, and `wait` fields.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct ConfigGroup {
+    /// A name identifying the group in errors and in the resulting state.
+    pub name: Option<String>,
+    /// A free-form description of the group.
+    pub description: Option<String>,
     /// Order is irrelevant, but it would require some additional effort
     /// to implement Hash for the HashSet in Config.
     pub configs: Vec<Config>,
+    pub hooks: Option<Hooks>,
+    pub when: Option<Conditions>,
+    /// How long the displays must be free of changes before the daemon
+    /// applies this group, overriding the daemon's wait period.
+    pub wait: Option<WaitPeriod>,
+}
+
+/// Helper for deserializing the structure form of a `ConfigGroup`.
+#[derive(Deserialize)]
+struct ConfigGroupStruct {
+    #[serde(deserialize_with = "deserialize_opt", default)]
+    name: Option<String>,
+    #[serde(deserialize_with = "deserialize_opt", default)]
+    description: Option<String>,
+    configs: Vec<Config>,
+    #[serde(deserialize_with = "deserialize_opt", default)]
+    hooks: Option<Hooks>,
+    #[serde(deserialize_with = "deserialize_opt", default)]
+    when: Option<Conditions>,
+    #[serde(deserialize_with = "deserialize_opt", default)]
+    wait: Option<WaitPeriod>,
+}
+
+impl Serialize for ConfigGroup {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.name.is_none()
+            && self.description.is_none()
+            && self.hooks.is_none()
+            && self.when.is_none()
+            && self.wait.is_none()
+        {
+            return self.configs.serialize(serializer);
+        }
+        let mut state = serializer.serialize_struct("ConfigGroup", 6)?;
+        match &self.name {
+            Some(name) => state.serialize_field("name", name)?,
+            None => state.skip_field("name")?,
+        }
+        match &self.description {
+            Some(description) => state.serialize_field("description", description)?,
+            None => state.skip_field("description")?,
+        }
+        state.serialize_field("configs", &self.configs)?;
+        match &self.hooks {
+            Some(hooks) => state.serialize_field("hooks", hooks)?,
+            None => state.skip_field("hooks")?,
+        }
+        match &self.when {
+            Some(when) => state.serialize_field("when", when)?,
+            None => state.skip_field("when")?,
+        }
+        match &self.wait {
+            Some(wait) => state.serialize_field("wait", wait)?,
+            None => state.skip_field("wait")?,
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigGroup {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigGroupVisitor;
+
+        impl<'de> Visitor<'de> for ConfigGroupVisitor {
+            type Value = ConfigGroup;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    f,
+                    "a list of configurations, or configurations with a name, description, \
+                    hooks, conditions, or a wait period"
+                )
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Ok(ConfigGroup {
+                    name: None,
+                    description: None,
+                    configs: Vec::deserialize(SeqAccessDeserializer::new(seq))?,
+                    hooks: None,
+                    when: None,
+                    wait: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let group = ConfigGroupStruct::deserialize(MapAccessDeserializer::new(map))?;
+                // Preserve the structure form when it has no name,
+                // description, hooks, conditions, or wait period, so that it
+                // survives a round trip.
+                let hooks = match (
+                    &group.name,
+                    &group.description,
+                    group.hooks,
+                    &group.when,
+                    &group.wait,
+                ) {
+                    (None, None, None, None, None) => Some(Hooks::default()),
+                    (_, _, hooks, _, _) => hooks,
+                };
+                Ok(ConfigGroup {
+                    name: group.name,
+                    description: group.description,
+                    configs: group.configs,
+                    hooks,
+                    when: group.when,
+                    wait: group.wait,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ConfigGroupVisitor)
+    }
 }
 
 /// ConfigGroups is simply a collection of ConfigGroups for different
-/// possible system configurations
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(transparent)]
+/// possible system configurations.  It is serialized as a list of the
+/// configuration groups, but may also be written as a structure giving the
+/// version of the schema the configuration was written for, along with any
+/// displays to ignore.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct ConfigGroups {
     /// Order is irrelevant, but it would require some additional effort
     /// to implement Hash for the HashSet in Config.
     pub groups: Vec<ConfigGroup>,
+    /// The UUIDs, or prefixes of them, of displays that never participate
+    /// in matching configuration groups or in being configured.
+    pub ignored: Vec<String>,
+}
+
+/// Helper for deserializing the structure form of `ConfigGroups`.  The
+/// version has already been checked, and the configuration migrated to
+/// the current version if need be, by `crate::serde::deserialize_config`.
+#[derive(Deserialize)]
+struct ConfigDocument {
+    #[allow(dead_code)]
+    version: u64,
+    groups: Vec<ConfigGroup>,
+    #[serde(default)]
+    ignored: Vec<String>,
+}
+
+impl Serialize for ConfigGroups {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Only the structure form can hold the ignored displays.
+        if self.ignored.is_empty() {
+            return self.groups.serialize(serializer);
+        }
+        let mut state = serializer.serialize_struct("ConfigGroups", 3)?;
+        state.serialize_field("version", &crate::serde::CONFIG_VERSION)?;
+        state.serialize_field("groups", &self.groups)?;
+        state.serialize_field("ignored", &self.ignored)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigGroups {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigGroupsVisitor;
+
+        impl<'de> Visitor<'de> for ConfigGroupsVisitor {
+            type Value = ConfigGroups;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    f,
+                    "a list of configuration groups, or a version and configuration groups"
+                )
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Ok(ConfigGroups {
+                    groups: Vec::deserialize(SeqAccessDeserializer::new(seq))?,
+                    ignored: vec![],
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let document = ConfigDocument::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(ConfigGroups {
+                    groups: document.groups,
+                    ignored: document.ignored,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ConfigGroupsVisitor)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A field in a configuration that does not correspond to any setting, and
+/// would otherwise be silently ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField {
+    /// The name of the field.
+    pub field: String,
+    /// Description of where the field occurs.
+    pub context: String,
+    /// The fields that are permitted there.
+    pub expected: &'static [&'static str],
+}
+
+impl std::fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown field `{}` in {}, expected one of: {}",
+            self.field,
+            self.context,
+            self.expected.join(", ")
+        )
+    }
+}
+
+/// A `Deserializer` that only records the names of the fields of the struct
+/// it is asked to deserialize.  This allows `struct_fields` to obtain the
+/// same field names used by the derived `Deserialize` implementations.
+struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("expected a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(serde::de::Error::custom("only collecting fields"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Helper to obtain the names of the fields of a struct with a derived
+/// `Deserialize` implementation.
+fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer(&mut fields));
+    fields
+}
+
+/// Helper to find the keys of an object that are not among the given
+/// fields.
+fn check_fields(
+    value: &serde_json::Value,
+    expected: &'static [&'static str],
+    context: &str,
+    unknown: &mut Vec<UnknownField>,
+) {
+    if let serde_json::Value::Object(map) = value {
+        for key in map.keys() {
+            if !expected.contains(&key.as_str()) {
+                unknown.push(UnknownField {
+                    field: key.clone(),
+                    context: context.to_owned(),
+                    expected,
+                });
+            }
+        }
+    }
+}
+
+/// Find the fields of configuration groups that do not correspond to any
+/// setting.  Deserialization ignores such fields, so this is used to detect
+/// typos.  As it is format independent, it operates upon configuration
+/// groups that have been deserialized into a generic `Value`, and assumes
+/// they have otherwise already been successfully deserialized.
+pub fn unknown_fields(value: &serde_json::Value) -> Vec<UnknownField> {
+    let mut unknown = Vec::new();
+    // The configuration groups may be wrapped in a structure giving the
+    // version of the schema.
+    let groups = match value {
+        serde_json::Value::Object(map) => {
+            check_fields(
+                value,
+                struct_fields::<ConfigDocument>(),
+                "the configuration",
+                &mut unknown,
+            );
+            map.get("groups")
+        }
+        groups => Some(groups),
+    };
+    let groups = match groups {
+        Some(serde_json::Value::Array(groups)) => groups,
+        _ => return unknown,
+    };
+    for (index, group) in groups.iter().enumerate() {
+        let context = format!("configuration group {}", index + 1);
+        let configs = match group {
+            serde_json::Value::Object(map) => {
+                check_fields(
+                    group,
+                    struct_fields::<ConfigGroupStruct>(),
+                    &context,
+                    &mut unknown,
+                );
+                if let Some(hooks) = map.get("hooks") {
+                    let context = format!("the hooks of {}", context);
+                    check_fields(hooks, struct_fields::<Hooks>(), &context, &mut unknown);
+                }
+                if let Some(when) = map.get("when") {
+                    let context = format!("the conditions of {}", context);
+                    check_fields(when, struct_fields::<Conditions>(), &context, &mut unknown);
+                }
+                map.get("configs")
+            }
+            _ => Some(group),
+        };
+        if let Some(serde_json::Value::Array(configs)) = configs {
+            for config in configs {
+                let uuid = config
+                    .get("uuid")
+                    .and_then(|uuid| uuid.as_str())
+                    .unwrap_or_default();
+                let context = format!("the configuration for display {}", uuid);
+                check_fields(config, struct_fields::<Config>(), &context, &mut unknown);
+            }
+        }
+    }
+    unknown
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -165,12 +1022,22 @@ fn test_serialization() {
     let c2 = Config {
         uuid: "ab3456def".to_owned(),
         enabled: Some(true),
-        origin: Some(Point { x: 1, y: 2 }),
-        extents: Some(Point { x: 3, y: 6 }),
+        skip: None,
+        origin: Some(Point { x: 1, y: 2 }.into()),
+        extents: Some(Resolution::Extents(Point { x: 3, y: 6 })),
+        units: None,
+        aspect_ratio: None,
         scaled: Some(true),
-        frequency: Some(60),
+        scale: None,
+        frequency: Some(Frequency::Exact(60)),
         color_depth: Some(8),
+        mode_id: None,
         rotation: Some(Rotation::Ninety),
+        closest: None,
+        underscan: None,
+        mirroring: None,
+        kind: None,
+        display_id: None,
     };
 
     let c1_json_str =
@@ -232,10 +1099,22 @@ fn test_serialization() {
     );
 
     let cg1 = ConfigGroup {
+        name: None,
+        description: None,
         configs: vec![c1.clone(), c2.clone()],
+        hooks: None,
+        when: None,
+        wait: None,
     };
 
-    let cg2 = ConfigGroup { configs: vec![c1] };
+    let cg2 = ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![c1],
+        hooks: None,
+        when: None,
+        wait: None,
+    };
 
     let cg1_json_str =
         serde_json::ser::to_string_pretty(&cg1).expect("Serialization should not fail");
@@ -308,8 +1187,12 @@ fn test_serialization() {
 
     let cgs1 = ConfigGroups {
         groups: vec![cg1.clone(), cg2.clone()],
+        ignored: vec![],
+    };
+    let cgs2 = ConfigGroups {
+        groups: vec![cg2],
+        ignored: vec![],
     };
-    let cgs2 = ConfigGroups { groups: vec![cg2] };
 
     let cgs1_json_str =
         serde_json::ser::to_string_pretty(&cgs1).expect("Serialization should not fail");
@@ -418,12 +1301,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: None,
+            skip: None,
             origin: None,
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -435,12 +1328,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: Some(false),
-            origin: Some(Point { x: 1, y: 2 }),
+            skip: None,
+            origin: Some(Point { x: 1, y: 2 }.into()),
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -451,12 +1354,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef123".to_owned(),
             enabled: Some(true),
+            skip: None,
             origin: None,
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -467,12 +1380,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: None,
+            skip: None,
             origin: None,
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -483,12 +1406,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: Some(false),
+            skip: None,
             origin: None,
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -499,12 +1432,22 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: None,
-            origin: Some(Point { x: 1, y: 2 }),
+            skip: None,
+            origin: Some(Point { x: 1, y: 2 }.into()),
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: None,
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
@@ -516,15 +1459,59 @@ fn test_deserialization() {
         Config {
             uuid: "abcdef1234".to_owned(),
             enabled: Some(false),
-            origin: Some(Point { x: 0, y: 1 }),
+            skip: None,
+            origin: Some(Point { x: 0, y: 1 }.into()),
             extents: None,
+            units: None,
+            aspect_ratio: None,
             scaled: None,
+            scale: None,
             frequency: None,
             color_depth: None,
+            mode_id: None,
             rotation: Some(Rotation::OneEighty),
+            closest: None,
+            underscan: None,
+            mirroring: None,
+            kind: None,
+            display_id: None,
         }
     );
 
+    let c: Config = ron::de::from_str("(uuid: \"abcdef1234\", extents: \"1440p\")")
+        .expect("Deserialization should not fail");
+    assert_eq!(c.extents, Some(Resolution::Named(ResolutionName::P1440)));
+    let ron = ron::ser::to_string(&c).expect("Serialization should not fail");
+    assert_eq!(ron, "(uuid:\"abcdef1234\",extents:\"1440p\")");
+    assert_eq!(ron::de::from_str::<Config>(ron.as_str()).unwrap(), c);
+
+    let c: Config = ron::de::from_str("(uuid: \"abcdef1234\", scale: 1.5)")
+        .expect("Deserialization should not fail");
+    assert_eq!(c.scale, Some(Scale(1.5)));
+    assert_eq!(
+        serde_json::ser::to_string(&c).expect("Serialization should not fail"),
+        "{\"uuid\":\"abcdef1234\",\"scale\":1.5}"
+    );
+
+    let c: Config = ron::de::from_str("(uuid: \"abcdef1234\", underscan: 0.25)")
+        .expect("Deserialization should not fail");
+    assert_eq!(c.underscan, Some(Underscan(0.25)));
+
+    let c = Config {
+        uuid: "abcdef1234".to_owned(),
+        kind: None,
+        display_id: Some(2),
+        ..Config::default()
+    };
+    assert_eq!(
+        serde_json::ser::to_string(&c).expect("Serialization should not fail"),
+        "{\"uuid\":\"abcdef1234\",\"display_id\":2}"
+    );
+
+    let c: Config = serde_json::de::from_str("{\"uuid\":\"abcdef1234\",\"extents\": \"native\"}")
+        .expect("Deserialization should not fail");
+    assert_eq!(c.extents, Some(Resolution::Named(ResolutionName::Native)));
+
     match ron::de::from_str::<'static, Config>("(uuid: \"abcdef1234\", rotation:45)") {
         Err(e) => {
             // Validate expected error message.
@@ -540,16 +1527,31 @@ fn test_deserialization() {
     assert_eq!(
         cg,
         ConfigGroup {
+            name: None,
+            description: None,
             configs: vec![Config {
                 uuid: "abcdef1234".to_owned(),
                 enabled: None,
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
-            }]
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
+            }],
+            hooks: None,
+            when: None,
+            wait: None,
         }
     );
 
@@ -558,16 +1560,31 @@ fn test_deserialization() {
     assert_eq!(
         cg,
         ConfigGroup {
+            name: None,
+            description: None,
             configs: vec![Config {
                 uuid: "abcdef1234".to_owned(),
                 enabled: None,
-                origin: Some(Point { x: 1, y: 2 }),
+                skip: None,
+                origin: Some(Point { x: 1, y: 2 }.into()),
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
-            }]
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
+            }],
+            hooks: None,
+            when: None,
+            wait: None,
         }
     );
 
@@ -577,17 +1594,364 @@ fn test_deserialization() {
         cgs,
         ConfigGroups {
             groups: vec![ConfigGroup {
+                name: None,
+                description: None,
                 configs: vec![Config {
                     uuid: "abcdef1234".to_owned(),
                     enabled: None,
-                    origin: Some(Point { x: 1, y: 2 }),
+                    skip: None,
+                    origin: Some(Point { x: 1, y: 2 }.into()),
                     extents: None,
+                    units: None,
+                    aspect_ratio: None,
                     scaled: None,
+                    scale: None,
                     frequency: None,
                     color_depth: None,
+                    mode_id: None,
                     rotation: None,
-                }]
-            }]
+                    closest: None,
+                    underscan: None,
+                    mirroring: None,
+                    kind: None,
+                    display_id: None,
+                }],
+                hooks: None,
+                when: None,
+                wait: None,
+            }],
+            ignored: vec![],
         }
     );
 }
+
+/// Check that ranges of frequencies and extents, and aspect ratios, can be
+/// given in place of exact values.
+#[test]
+fn test_range_serialization() {
+    let c: Config = serde_json::de::from_str(
+        r#"{"uuid": "abcdef1234", "frequency": ">=120", "extents": {"min": [2560, 1440]}}"#,
+    )
+    .expect("Deserialization should not fail");
+    assert_eq!(
+        c.frequency,
+        Some(Frequency::Range(FrequencyRange {
+            min: Some(120),
+            max: None
+        }))
+    );
+    assert_eq!(
+        c.extents,
+        Some(Resolution::Range(ExtentsRange {
+            min: Some(Point { x: 2560, y: 1440 }),
+            max: None
+        }))
+    );
+    let json = serde_json::ser::to_string(&c).expect("Serialization should not fail");
+    assert_eq!(
+        json,
+        r#"{"uuid":"abcdef1234","extents":{"min":[2560,1440]},"frequency":">=120"}"#
+    );
+
+    let c: Config = ron::de::from_str("(uuid: \"abcdef1234\", frequency: \"60-120\")")
+        .expect("Deserialization should not fail");
+    assert_eq!(
+        c.frequency.and_then(|frequency| frequency.range()),
+        Some(FrequencyRange {
+            min: Some(60),
+            max: Some(120)
+        })
+    );
+    let c: Config = ron::de::from_str("(uuid: \"abcdef1234\", frequency: 60)")
+        .expect("Deserialization should not fail");
+    assert_eq!(
+        c.frequency.and_then(|frequency| frequency.range()),
+        Some(FrequencyRange::exactly(60))
+    );
+    let c: Config =
+        ron::de::from_str("(uuid: \"abcdef1234\", frequency: \"max\", extents: \"max\")")
+            .expect("Deserialization should not fail");
+    assert_eq!(c.frequency, Some(Frequency::Named(FrequencyName::Max)));
+    assert_eq!(c.extents, Some(Resolution::Named(ResolutionName::Max)));
+    let ron = ron::ser::to_string(&c).expect("Serialization should not fail");
+    assert_eq!(
+        ron,
+        "(uuid:\"abcdef1234\",extents:\"max\",frequency:\"max\")"
+    );
+
+    let c: Config = serde_json::de::from_str(r#"{"uuid": "abcdef1234", "aspect_ratio": "16:10"}"#)
+        .expect("Deserialization should not fail");
+    assert_eq!(
+        c.aspect_ratio,
+        Some(AspectRatio {
+            width: 16,
+            height: 10
+        })
+    );
+    let ron = ron::ser::to_string(&c).expect("Serialization should not fail");
+    assert_eq!(ron, "(uuid:\"abcdef1234\",aspect_ratio:\"16:10\")");
+    for invalid in ["16", "16:0", "wide:9", "16x9"] {
+        let json = format!(r#"{{"uuid": "abcdef1234", "aspect_ratio": "{}"}}"#, invalid);
+        assert!(
+            serde_json::de::from_str::<Config>(&json).is_err(),
+            "{} should be rejected",
+            invalid
+        );
+    }
+
+    for invalid in ["120", ">120", "<=fast", "120-60", "-60"] {
+        let json = format!(r#"{{"uuid": "abcdef1234", "frequency": "{}"}}"#, invalid);
+        assert!(
+            serde_json::de::from_str::<Config>(&json).is_err(),
+            "{} should be rejected",
+            invalid
+        );
+    }
+}
+
+/// Check that configuration groups with hooks survive a round trip, and that
+/// the structure form without hooks is accepted.
+#[test]
+fn test_hooks_serialization() {
+    let cg = ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            ..Config::default()
+        }],
+        hooks: Some(Hooks {
+            pre: None,
+            post: Some("echo done".to_owned()),
+        }),
+        when: None,
+        wait: None,
+    };
+
+    let json_str = serde_json::ser::to_string(&cg).expect("Serialization should not fail");
+    assert_eq!(
+        json_str,
+        r#"{"configs":[{"uuid":"abcdef1234"}],"hooks":{"post":"echo done"}}"#
+    );
+    let json_cg: ConfigGroup =
+        serde_json::de::from_str(&json_str).expect("Deserialization should not fail");
+    assert_eq!(json_cg, cg);
+
+    let ron_str = ron::ser::to_string(&cg).expect("Serialization should not fail");
+    let ron_cg: ConfigGroup = ron::de::from_str(&ron_str).expect("Deserialization should not fail");
+    assert_eq!(ron_cg, cg);
+
+    let cg: ConfigGroup = ron::de::from_str("(configs: [(uuid: \"abcdef1234\")])")
+        .expect("Deserialization should not fail");
+    assert_eq!(cg.hooks, Some(Hooks::default()));
+}
+
+/// Check that configuration groups with conditions survive a round trip, and
+/// do not require hooks.
+#[test]
+fn test_conditions_serialization() {
+    let cg = ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            ..Config::default()
+        }],
+        hooks: None,
+        when: Some(Conditions {
+            lid: Some(LidState::Closed),
+            power: Some(PowerSource::Ac),
+            ssid: Some("OfficeNet".to_owned()),
+            between: Some(TimeRange {
+                start: TimeOfDay::new(20, 0).unwrap(),
+                end: TimeOfDay::new(7, 30).unwrap(),
+            }),
+        }),
+        wait: None,
+    };
+
+    let json_str = serde_json::ser::to_string(&cg).expect("Serialization should not fail");
+    assert_eq!(
+        json_str,
+        r#"{"configs":[{"uuid":"abcdef1234"}],"when":{"lid":"closed","power":"ac","ssid":"OfficeNet","between":"20:00-07:30"}}"#
+    );
+    let json_cg: ConfigGroup =
+        serde_json::de::from_str(&json_str).expect("Deserialization should not fail");
+    assert_eq!(json_cg, cg);
+
+    let ron_cg: ConfigGroup = ron::de::from_str(
+        "(configs: [(uuid: \"abcdef1234\")], when: (lid: closed, power: ac, ssid: \"OfficeNet\", between: \"20:00-07:30\"))",
+    )
+    .expect("Deserialization should not fail");
+    assert_eq!(ron_cg, cg);
+}
+
+/// Check that configuration groups with a wait period survive a round trip,
+/// and do not require hooks or conditions.
+#[test]
+fn test_wait_serialization() {
+    let cg = ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            ..Config::default()
+        }],
+        hooks: None,
+        when: None,
+        wait: Some(WaitPeriod(std::time::Duration::from_millis(5500))),
+    };
+
+    let json_str = serde_json::ser::to_string(&cg).expect("Serialization should not fail");
+    assert_eq!(
+        json_str,
+        r#"{"configs":[{"uuid":"abcdef1234"}],"wait":"5s 500ms"}"#
+    );
+    let json_cg: ConfigGroup =
+        serde_json::de::from_str(&json_str).expect("Deserialization should not fail");
+    assert_eq!(json_cg, cg);
+
+    let ron_cg: ConfigGroup =
+        ron::de::from_str("(configs: [(uuid: \"abcdef1234\")], wait: \"5500ms\")")
+            .expect("Deserialization should not fail");
+    assert_eq!(ron_cg, cg);
+
+    assert!(
+        serde_json::de::from_str::<ConfigGroup>(
+            r#"{"configs":[{"uuid":"abcdef1234"}],"wait":"soon"}"#
+        )
+        .is_err(),
+        "Invalid wait periods should be rejected"
+    );
+}
+
+/// Check that configuration groups with a name and description survive a
+/// round trip, and do not require hooks or conditions.
+#[test]
+fn test_name_serialization() {
+    let cg = ConfigGroup {
+        name: Some("office".to_owned()),
+        description: Some("Docked at the office".to_owned()),
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            ..Config::default()
+        }],
+        hooks: None,
+        when: None,
+        wait: None,
+    };
+
+    let json_str = serde_json::ser::to_string(&cg).expect("Serialization should not fail");
+    assert_eq!(
+        json_str,
+        r#"{"name":"office","description":"Docked at the office","configs":[{"uuid":"abcdef1234"}]}"#
+    );
+    let json_cg: ConfigGroup =
+        serde_json::de::from_str(&json_str).expect("Deserialization should not fail");
+    assert_eq!(json_cg, cg);
+
+    let ron_str = ron::ser::to_string(&cg).expect("Serialization should not fail");
+    let ron_cg: ConfigGroup = ron::de::from_str(&ron_str).expect("Deserialization should not fail");
+    assert_eq!(ron_cg, cg);
+}
+
+/// Check that ignored displays survive a round trip, which requires the
+/// structure form of the configuration.
+#[test]
+fn test_ignored_serialization() {
+    let cgs = ConfigGroups {
+        groups: vec![],
+        ignored: vec!["abcdef1234".to_owned()],
+    };
+    let json_str = serde_json::ser::to_string(&cgs).expect("Serialization should not fail");
+    assert_eq!(
+        json_str,
+        format!(
+            r#"{{"version":{},"groups":[],"ignored":["abcdef1234"]}}"#,
+            crate::serde::CONFIG_VERSION
+        )
+    );
+    let json_cgs: ConfigGroups =
+        serde_json::de::from_str(&json_str).expect("Deserialization should not fail");
+    assert_eq!(json_cgs, cgs);
+
+    let cgs = ConfigGroups {
+        ignored: vec![],
+        ..cgs
+    };
+    let json_str = serde_json::ser::to_string(&cgs).expect("Serialization should not fail");
+    assert_eq!(json_str, "[]");
+}
+
+/// Check parsing of time ranges, and that ranges may wrap around midnight.
+#[test]
+fn test_time_range() {
+    let time = |str: &str| str.parse::<TimeOfDay>().expect("Parsing should not fail");
+    let day = TimeRange::try_from("07:00-20:00".to_owned()).expect("Parsing should not fail");
+    assert!(day.contains(time("07:00")));
+    assert!(day.contains(time("12:00")));
+    assert!(!day.contains(time("20:00")));
+    assert!(!day.contains(time("23:00")));
+
+    let night = TimeRange::try_from("20:00-07:00".to_owned()).expect("Parsing should not fail");
+    assert!(night.contains(time("20:00")));
+    assert!(night.contains(time("0:15")));
+    assert!(!night.contains(time("07:00")));
+    assert!(!night.contains(time("12:00")));
+
+    for str in ["20:00", "20:00-7", "24:00-07:00", "20:60-07:00", "a:b-c:d"] {
+        assert!(
+            TimeRange::try_from(str.to_owned()).is_err(),
+            "Parsing {} should fail",
+            str
+        );
+    }
+}
+
+/// Check that unknown fields are found in each part of a configuration.
+#[test]
+fn test_unknown_fields() {
+    assert_eq!(struct_fields::<Hooks>(), &["pre", "post"]);
+
+    let value: ron::Value = ron::de::from_str(
+        "[[(uuid: \"abcdef1234\", rotaton: 90)], \
+        (configs: [(uuid: \"foobarbaz\")], hook: (pre: \"true\"), \
+        when: (lid: closed, powr: ac))]",
+    )
+    .expect("Deserialization should not fail");
+    let value = serde_json::to_value(value).expect("Conversion should not fail");
+    let unknown: Vec<(String, String)> = unknown_fields(&value)
+        .into_iter()
+        .map(|uf| (uf.field, uf.context))
+        .collect();
+    assert_eq!(
+        unknown,
+        vec![
+            (
+                "rotaton".to_owned(),
+                "the configuration for display abcdef1234".to_owned()
+            ),
+            ("hook".to_owned(), "configuration group 2".to_owned()),
+            (
+                "powr".to_owned(),
+                "the conditions of configuration group 2".to_owned()
+            ),
+        ]
+    );
+
+    let value: serde_json::Value =
+        serde_json::from_str(r#"[[{"uuid": "abcdef1234", "rotation": 90}]]"#)
+            .expect("Deserialization should not fail");
+    assert!(unknown_fields(&value).is_empty());
+
+    // The structure form of the configuration is also checked.
+    let value: serde_json::Value = serde_json::from_str(
+        r#"{"version": 1, "verison": 1, "groups": [[{"uuid": "abcdef1234", "rotaton": 90}]]}"#,
+    )
+    .expect("Deserialization should not fail");
+    let unknown: Vec<String> = unknown_fields(&value)
+        .into_iter()
+        .map(|uf| uf.field)
+        .collect();
+    assert_eq!(unknown, vec!["verison".to_owned(), "rotaton".to_owned()]);
+}
@@ -0,0 +1,331 @@
+//! This module provides the protocol used to control a running knoll daemon
+//! over a Unix domain socket.  Each connection carries exactly one request
+//! and one response.  The client writes its request and then shuts down the
+//! writing half of the connection, the daemon reads the request until the
+//! end of the stream, writes its response, and closes the connection.
+//! Requests and responses are serialized using the same formats as
+//! configurations.
+use coverage_helper::test;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::ConfigGroups;
+use crate::serde::{deserialize, serialize, Format};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The possible failures when communicating over the control socket.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Utf8(std::string::FromUtf8Error),
+    Serde(crate::serde::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(ie) => write!(f, "Control socket I/O error: {}", ie),
+            Error::Utf8(ue) => write!(f, "Invalid UTF-8 on control socket: {}", ue),
+            Error::Serde(se) => write!(f, "Invalid control message: {}", se),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+            Error::Serde(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Error::Utf8(e)
+    }
+}
+
+impl From<crate::serde::Error> for Error {
+    fn from(e: crate::serde::Error) -> Self {
+        Error::Serde(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Requests that may be sent to a running daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    /// Obtain the current display state.
+    GetState,
+    /// Reload the configuration and reconfigure the displays.  This also
+    /// forgets any configuration group selected with `Apply`.
+    Reload,
+    /// Apply the configuration group for exactly the displays with these
+    /// UUIDs, in preference to the most precise configuration group, for
+    /// as long as those displays remain attached.
+    Apply(Vec<String>),
+    /// Obtain the status of the daemon.
+    Status,
+    /// Obtain the metrics collected by the daemon.
+    Metrics,
+    /// Reconfigure the displays immediately, without waiting for the
+    /// display configuration to quiesce.
+    Trigger,
+    /// Stop the daemon.
+    Shutdown,
+}
+
+/// The number of recent errors retained in the `DaemonStatus`.
+const RECENT_ERRORS: usize = 10;
+
+/// A summary of the activity of a running daemon.  Times are reported in
+/// RFC 3339 format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    /// The process id of the daemon.
+    pub pid: u32,
+    /// When the daemon was started.
+    pub started: String,
+    /// When the daemon last successfully reconfigured the displays.
+    pub last_reconfiguration: Option<String>,
+    /// The UUIDs of the configuration group that was last applied.
+    pub last_group: Option<Vec<String>>,
+    /// The most recent errors encountered, prefixed with when they occurred,
+    /// oldest first.
+    pub recent_errors: Vec<String>,
+}
+
+impl DaemonStatus {
+    /// Create the status for a daemon starting now.
+    pub fn new() -> Self {
+        DaemonStatus {
+            pid: std::process::id(),
+            started: now(),
+            last_reconfiguration: None,
+            last_group: None,
+            recent_errors: Vec::new(),
+        }
+    }
+
+    /// Record that the configuration group for the given displays was
+    /// successfully applied.
+    pub fn record_reconfiguration(&mut self, uuids: Vec<String>) {
+        self.last_reconfiguration = Some(now());
+        self.last_group = Some(uuids);
+    }
+
+    /// Record an error, forgetting the oldest error if there are too many.
+    pub fn record_error(&mut self, error: String) {
+        if self.recent_errors.len() >= RECENT_ERRORS {
+            self.recent_errors.remove(0);
+        }
+        self.recent_errors.push(format!("{}: {}", now(), error));
+    }
+}
+
+impl Default for DaemonStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters and timings collected by a running daemon, for monitoring its
+/// health.  Durations are reported in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    /// The number of display configuration events received.
+    pub events: u64,
+    /// The number of successful reconfigurations.
+    pub reconfigurations: u64,
+    /// The number of failed reconfigurations.
+    pub failures: u64,
+    /// The number of display configuration transactions committed.
+    pub commits: u64,
+    /// How long the most recent commit took.
+    pub last_commit_ms: Option<u64>,
+    /// How long the slowest commit took.
+    pub max_commit_ms: Option<u64>,
+    /// How long all commits took in total.
+    pub total_commit_ms: u64,
+}
+
+impl Metrics {
+    /// Record how long committing a display configuration transaction took.
+    pub fn record_commit(&mut self, duration: std::time::Duration) {
+        let ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        self.commits += 1;
+        self.last_commit_ms = Some(ms);
+        self.max_commit_ms = Some(self.max_commit_ms.map_or(ms, |max| max.max(ms)));
+        self.total_commit_ms = self.total_commit_ms.saturating_add(ms);
+    }
+}
+
+/// Helper to format the current time.
+fn now() -> String {
+    humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string()
+}
+
+/// Responses sent by a running daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    /// The request was accepted.
+    Ok,
+    /// The current display state.
+    State(ConfigGroups),
+    /// The status of the daemon.
+    Status(DaemonStatus),
+    /// The metrics collected by the daemon.
+    Metrics(Metrics),
+    /// The request failed.  The argument is the error message.
+    Error(String),
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The control socket location used when none is specified.  This is
+/// `~/.local/run/knoll.sock`, or `knoll.sock` in the temporary directory if
+/// the home directory is not known.
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".local/run/knoll.sock"),
+        None => std::env::temp_dir().join("knoll.sock"),
+    }
+}
+
+/// Helper to read a complete message from the stream.
+fn read_message<T: DeserializeOwned>(stream: &mut UnixStream, format: Format) -> Result<T, Error> {
+    let mut buffer = Vec::new();
+    stream.read_to_end(&mut buffer)?;
+    let string = String::from_utf8(buffer)?;
+    Ok(deserialize(format, string.as_str())?)
+}
+
+/// Helper to write a complete message to the stream, and then signal that
+/// nothing more will be written.
+fn write_message<T: Serialize>(
+    stream: &mut UnixStream,
+    format: Format,
+    message: &T,
+) -> Result<(), Error> {
+    serialize(format, message, &mut *stream)?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+    Ok(())
+}
+
+/// Send a request to the daemon listening on the given socket and wait for
+/// its response.
+pub fn send_request(path: &Path, format: Format, request: &Request) -> Result<Response, Error> {
+    let mut stream = UnixStream::connect(path)?;
+    write_message(&mut stream, format, request)?;
+    read_message(&mut stream, format)
+}
+
+/// How long the daemon waits when reading a request from, or writing a
+/// response to, a client.  Requests are handled one at a time, so a client
+/// that never finishes its request must not hold up the others.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Receive a request from a newly accepted connection.
+pub fn receive_request(stream: &mut UnixStream, format: Format) -> Result<Request, Error> {
+    receive_request_within(stream, format, TIMEOUT)
+}
+
+/// Variant of `receive_request` that gives up on reading the request, or
+/// later writing the response, after the given timeout.
+fn receive_request_within(
+    stream: &mut UnixStream,
+    format: Format,
+    timeout: Duration,
+) -> Result<Request, Error> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    read_message(stream, format)
+}
+
+/// Send the response to a request received with `receive_request`.
+pub fn send_response(
+    stream: &mut UnixStream,
+    format: Format,
+    response: &Response,
+) -> Result<(), Error> {
+    write_message(stream, format, response)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that only the most recent errors are retained.
+#[test]
+fn test_status_recent_errors() {
+    let mut status = DaemonStatus::new();
+    for i in 0..RECENT_ERRORS + 2 {
+        status.record_error(format!("error {}", i));
+    }
+    assert_eq!(status.recent_errors.len(), RECENT_ERRORS);
+    assert!(status.recent_errors[0].ends_with("error 2"));
+}
+
+/// Check that commit timings are accumulated.
+#[test]
+fn test_metrics_record_commit() {
+    let mut metrics = Metrics::default();
+    metrics.record_commit(std::time::Duration::from_millis(30));
+    metrics.record_commit(std::time::Duration::from_millis(10));
+    assert_eq!(metrics.commits, 2);
+    assert_eq!(metrics.last_commit_ms, Some(10));
+    assert_eq!(metrics.max_commit_ms, Some(30));
+    assert_eq!(metrics.total_commit_ms, 40);
+}
+
+/// Check that requests and responses survive a round trip over a socket.
+#[test]
+fn test_request_response_round_trip() {
+    for format in [Format::Json, Format::Ron] {
+        let (mut client, mut server) = UnixStream::pair().expect("Creating a socket pair failed.");
+        let request = Request::Apply(vec!["abcdef1234".to_owned()]);
+        write_message(&mut client, format, &request).expect("Writing the request failed.");
+        assert_eq!(
+            receive_request(&mut server, format).expect("Receiving the request failed."),
+            request
+        );
+
+        let response = Response::Error("failure".to_owned());
+        send_response(&mut server, format, &response).expect("Sending the response failed.");
+        assert_eq!(
+            read_message::<Response>(&mut client, format).expect("Reading the response failed."),
+            response
+        );
+    }
+}
+
+/// Check that a client that never finishes its request is given up on.
+#[test]
+fn test_receive_request_timeout() {
+    let (mut client, mut server) = UnixStream::pair().expect("Creating a socket pair failed.");
+    client
+        .write_all(b"\"Status\"")
+        .expect("Writing the partial request failed.");
+    match receive_request_within(&mut server, Format::Json, Duration::from_millis(50)) {
+        Err(Error::Io(ie)) => assert!(matches!(
+            ie.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        )),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
@@ -25,6 +25,14 @@ pub struct DisplayID {
     id: CGDirectDisplayID,
 }
 
+impl DisplayID {
+    /// The underlying `CGDirectDisplayID`.  Only intended for diagnostic
+    /// purposes.
+    pub fn raw(&self) -> u32 {
+        self.id
+    }
+}
+
 /// https://github.com/NUIKit/CGSInternal/blob/master/CGSDisplays.h
 #[derive(Debug, Default, Clone)]
 #[repr(C)]
@@ -42,7 +50,7 @@ pub struct CGSDisplayModeDescription {
     dc4: u16,
     pub freq: u16,
     dc5: [u8; 16], // dc5[4] seems to also contain the same value as mode.
-    // If 2.0, the mode is scaled.
+    // If greater than 1.0, the mode is scaled.  Typically 2.0.
     pub scale: f32,
 }
 
@@ -57,6 +65,14 @@ pub struct CGPoint {
     pub y: f64,
 }
 
+/// https://developer.apple.com/documentation/corefoundation/cgsize
+#[derive(Debug)]
+#[repr(C)]
+pub struct CGSize {
+    pub width: f64,
+    pub height: f64,
+}
+
 /// https://developer.apple.com/documentation/corefoundation/cgrect
 #[derive(Debug)]
 #[repr(C)]
@@ -88,11 +104,7 @@ pub enum CGError {
 #[repr(i32)]
 #[allow(non_camel_case_types)]
 pub enum CGConfigureOption {
-    // Currently not used but included for possible future use.
-    #[allow(unused)]
     kCGConfigureForAppOnly = 0,
-    // Currently not used but included for possible future use.
-    #[allow(unused)]
     kCGConfigureForSession = 1,
     kCGConfigurePermanently = 2,
 }
@@ -108,6 +120,19 @@ pub const kCFAllocatorDefault: *const c_void = null();
 pub type CGDisplayConfigRef = *mut c_void;
 pub type CGDisplayModeRef = *const c_void;
 pub type CGDisplayFadeInterval = f32;
+pub type CGDisplayFadeReservationToken = u32;
+pub type CGDisplayBlendFraction = f32;
+pub type CGDisplayReservationInterval = f32;
+
+/// Blend fraction at which the display is shown normally.
+#[allow(non_upper_case_globals)]
+pub const kCGDisplayBlendNormal: CGDisplayBlendFraction = 0.0;
+/// Blend fraction at which the display is entirely the fade color.
+#[allow(non_upper_case_globals)]
+pub const kCGDisplayBlendSolidColor: CGDisplayBlendFraction = 1.0;
+/// The longest a fade reservation may be held for.
+#[allow(non_upper_case_globals)]
+pub const kCGMaxDisplayReservationInterval: CGDisplayReservationInterval = 15.0;
 
 /// Wrapper over CFArray.
 pub type CGDisplayModeArray = *const c_void;
@@ -120,6 +145,102 @@ pub type CFIndex = c_int;
 pub type CFString = *const c_void;
 pub type CFDictionary = *const c_void;
 pub type CFStringEncoding = u32;
+pub type CFRunLoop = *const c_void;
+pub type CFRunLoopSource = *const c_void;
+pub type IOObject = u32;
+pub type IONotificationPort = *mut c_void;
+
+////////////////////////////////////////////////////////////////////////////////
+// Owned CoreFoundation references
+
+/// Helper to define a wrapper that owns a reference to a CoreFoundation
+/// object, and releases it when dropped, so that it cannot be leaked by an
+/// early return or a panic.  Following the Create Rule, only references
+/// obtained from functions with `Create` or `Copy` in their name should be
+/// wrapped.
+macro_rules! cf_owned {
+    ($(#[$meta:meta])* $name:ident, $raw:ty) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name($raw);
+
+        impl $name {
+            /// Take ownership of the reference, or `None` if it is null.
+            fn new(raw: $raw) -> Option<Self> {
+                if raw.is_null() {
+                    None
+                } else {
+                    Some($name(raw))
+                }
+            }
+
+            /// The underlying reference, which remains owned by the wrapper.
+            pub fn as_raw(&self) -> $raw {
+                self.0
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe { CFRelease(self.0) }
+            }
+        }
+    };
+}
+
+cf_owned!(
+    /// An owned `CFString`.
+    CfString,
+    CFString
+);
+cf_owned!(
+    /// An owned `CFUUID`.
+    CfUuid,
+    CFUUID
+);
+cf_owned!(
+    /// An owned `CFArray`, such as an array of display modes.
+    CfArray,
+    CFArray
+);
+cf_owned!(
+    /// An owned `CFDictionary`.
+    CfDictionary,
+    CFDictionary
+);
+cf_owned!(
+    /// An owned `CGDisplayMode`, which is also a CoreFoundation type.  Modes
+    /// obtained from a `CfArray` are owned by the array, so are not wrapped.
+    CgDisplayMode,
+    CGDisplayModeRef
+);
+
+impl CfString {
+    /// Create a string from a C string, or `None` if it could not be created.
+    pub fn from_cstr(string: &std::ffi::CStr) -> Option<Self> {
+        CfString::new(unsafe {
+            CFStringCreateWithCString(
+                kCFAllocatorDefault,
+                string.as_ptr() as *const u8,
+                CFStringBuiltInEncodings::UTF8.into(),
+            )
+        })
+    }
+
+    /// Convert to a Rust string, or `None` if the string is not ASCII or is
+    /// longer than `max_len`.
+    pub fn to_ascii(&self, max_len: usize) -> Option<String> {
+        let mut buffer = vec![0u8; max_len + 1];
+        if !cf_string_get_cstring(self.0, &mut buffer, CFStringBuiltInEncodings::ASCII.into()) {
+            return None;
+        }
+        std::ffi::CStr::from_bytes_until_nul(&buffer)
+            .ok()
+            .map(|cstr| cstr.to_string_lossy().into_owned())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
 
 // https://developer.apple.com/documentation/corefoundation/cfstringbuiltinencodings
 #[derive(Debug)]
@@ -127,7 +248,6 @@ pub type CFStringEncoding = u32;
 #[allow(non_camel_case_types)]
 pub enum CFStringBuiltInEncodings {
     ASCII = 1536,
-    // Currently not used but included for possible future use.
     UTF8 = 134217984,
 }
 
@@ -143,18 +263,22 @@ impl From<CFStringBuiltInEncodings> for CFStringEncoding {
 // kDisplayProductIDGeneric
 // kDisplayVendorIDUnknown
 
-#[link(name = "AppKit", kind = "framework")]
-#[link(name = "IOKit", kind = "framework")]
-#[link(name = "ApplicationServices", kind = "framework")]
-#[link(name = "DisplayServices", kind = "framework")]
-#[link(name = "CoreDisplay", kind = "framework")]
-#[link(name = "OSD", kind = "framework")]
-#[link(name = "MonitorPanel", kind = "framework")]
-#[link(name = "SkyLight", kind = "framework")]
+//HACK#[link(name = "AppKit", kind = "framework")]
+//HACK#[link(name = "IOKit", kind = "framework")]
+//HACK#[link(name = "ApplicationServices", kind = "framework")]
+//HACK#[link(name = "DisplayServices", kind = "framework")]
+//HACK#[link(name = "CoreDisplay", kind = "framework")]
+//HACK#[link(name = "OSD", kind = "framework")]
+//HACK#[link(name = "MonitorPanel", kind = "framework")]
+//HACK#[link(name = "SkyLight", kind = "framework")]
+//HACK#[link(name = "CoreWLAN", kind = "framework")]
 extern "C" {
     /// https://developer.apple.com/documentation/corefoundation/1521153-cfrelease
     fn CFRelease(cf: CFTypeRef);
 
+    /// https://developer.apple.com/documentation/dispatch/1452927-dispatch_get_global_queue
+    fn dispatch_get_global_queue(identifier: isize, flags: usize) -> *mut c_void;
+
     /// https://developer.apple.com/documentation/corefoundation/1542721-cfstringgetcstring/
     fn CFStringGetCString(
         string: CFString,
@@ -166,6 +290,75 @@ extern "C" {
     /// https://developer.apple.com/documentation/corefoundation/1543625-cfuuidcreatestring/
     fn CFUUIDCreateString(allocator: CFAllocator, uuid: CFUUID) -> CFString;
 
+    /// https://developer.apple.com/documentation/corefoundation/1516782-cfdictionarycreate
+    fn CFDictionaryCreate(
+        allocator: CFAllocator,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        num_values: CFIndex,
+        key_call_backs: *const c_void,
+        value_call_backs: *const c_void,
+    ) -> CFDictionary;
+
+    /// https://developer.apple.com/documentation/corefoundation/kcfbooleantrue
+    static kCFBooleanTrue: CFTypeRef;
+
+    /// https://developer.apple.com/documentation/corefoundation/kcfbooleanfalse
+    static kCFBooleanFalse: CFTypeRef;
+
+    /// https://developer.apple.com/documentation/corefoundation/1542942-cfstringcreatewithcstring
+    fn CFStringCreateWithCString(
+        allocator: CFAllocator,
+        c_str: *const u8,
+        encoding: CFStringEncoding,
+    ) -> CFString;
+
+    /// https://developer.apple.com/documentation/iokit/1514687-ioservicematching
+    fn IOServiceMatching(name: *const u8) -> CFDictionary;
+
+    /// https://developer.apple.com/documentation/iokit/1514535-ioservicegetmatchingservice
+    /// Consumes a reference to the matching dictionary.
+    fn IOServiceGetMatchingService(main_port: u32, matching: CFDictionary) -> IOObject;
+
+    /// https://developer.apple.com/documentation/corefoundation/1516716-cfdictionarygetvalue
+    /// The result is owned by the dictionary.
+    fn CFDictionaryGetValue(dict: CFDictionary, key: *const c_void) -> *const c_void;
+
+    /// https://developer.apple.com/documentation/iokit/1514293-ioregistryentrycreatecfproperty
+    fn IORegistryEntryCreateCFProperty(
+        entry: IOObject,
+        key: CFString,
+        allocator: CFAllocator,
+        options: u32,
+    ) -> CFTypeRef;
+
+    /// https://developer.apple.com/documentation/iokit/1514627-ioobjectrelease
+    fn IOObjectRelease(object: IOObject) -> c_int;
+
+    /// https://developer.apple.com/documentation/iokit/1514475-ioregistryentrysetcfproperty
+    fn IORegistryEntrySetCFProperty(entry: IOObject, key: CFString, value: CFTypeRef) -> c_int;
+
+    /// https://developer.apple.com/documentation/iokit/1557092-iopmassertiondeclareuseractivity
+    fn IOPMAssertionDeclareUserActivity(
+        name: CFString,
+        user_type: u32,
+        assertion_id: *mut u32,
+    ) -> c_int;
+
+    /// https://developer.apple.com/documentation/iokit/1557090-iopmassertionrelease
+    fn IOPMAssertionRelease(assertion_id: u32) -> c_int;
+
+    /// https://developer.apple.com/documentation/corefoundation/kcftypedictionarykeycallbacks
+    /// Only ever used by address, so the actual structure is not declared.
+    static kCFTypeDictionaryKeyCallBacks: c_void;
+
+    /// https://developer.apple.com/documentation/corefoundation/kcftypedictionaryvaluecallbacks
+    /// Only ever used by address, so the actual structure is not declared.
+    static kCFTypeDictionaryValueCallBacks: c_void;
+
+    /// https://developer.apple.com/documentation/coregraphics/kcgdisplayshowduplicatelowresolutionmodes
+    static kCGDisplayShowDuplicateLowResolutionModes: CFString;
+
     /// https://developer.apple.com/documentation/corefoundation/1388772-cfarraygetcount/
     fn CFArrayGetCount(array: CFArray) -> usize;
 
@@ -210,6 +403,18 @@ extern "C" {
     /// https://developer.apple.com/documentation/coregraphics/1455558-cgdisplayisinmirrorset
     fn CGDisplayIsInMirrorSet(display_id: CGDirectDisplayID) -> bool;
 
+    /// https://developer.apple.com/documentation/coregraphics/1454524-cgsessioncopycurrentdictionary
+    fn CGSessionCopyCurrentDictionary() -> CFDictionary;
+
+    /// https://developer.apple.com/library/archive/documentation/System/Conceptual/ManPages_iPhoneOS/man3/sysctlbyname.3.html
+    fn sysctlbyname(
+        name: *const std::ffi::c_char,
+        old: *mut c_void,
+        old_length: *mut usize,
+        new: *mut c_void,
+        new_length: usize,
+    ) -> c_int;
+
     /// https://developer.apple.com/documentation/colorsync/1458801-cgdisplaycreateuuidfromdisplayid/
     fn CGDisplayCreateUUIDFromDisplayID(display_id: CGDirectDisplayID) -> CFUUID;
 
@@ -249,6 +454,12 @@ extern "C" {
     /// https://developer.apple.com/documentation/coregraphics/1456406-cgdisplaymodegetpixelheight/
     fn CGDisplayModeGetPixelHeight(mode: CGDisplayModeRef) -> usize;
 
+    /// https://developer.apple.com/documentation/coregraphics/1454760-cgdisplaymodecopypixelencoding
+    /// Deprecated, but there is no replacement for determining the number
+    /// of bits per color channel of a display mode.  Caller is responsible
+    /// for releasing.
+    fn CGDisplayModeCopyPixelEncoding(mode: CGDisplayModeRef) -> CFString;
+
     /// https://developer.apple.com/documentation/coregraphics/cgdisplaymode/1454928-isusablefordesktopgui
     fn CGDisplayModeIsUsableForDesktopGUI(mode: CGDisplayModeRef) -> bool;
 
@@ -265,6 +476,27 @@ extern "C" {
     /// https://developer.apple.com/documentation/coregraphics/1455522-cgcanceldisplayconfiguration
     fn CGCancelDisplayConfiguration(config: CGDisplayConfigRef) -> CGError;
 
+    /// https://developer.apple.com/documentation/coregraphics/1455431-cgacquiredisplayfadereservation
+    fn CGAcquireDisplayFadeReservation(
+        seconds: CGDisplayReservationInterval,
+        token: *mut CGDisplayFadeReservationToken,
+    ) -> CGError;
+
+    /// https://developer.apple.com/documentation/coregraphics/1455414-cgreleasedisplayfadereservation
+    fn CGReleaseDisplayFadeReservation(token: CGDisplayFadeReservationToken) -> CGError;
+
+    /// https://developer.apple.com/documentation/coregraphics/1455224-cgdisplayfade
+    fn CGDisplayFade(
+        token: CGDisplayFadeReservationToken,
+        duration: CGDisplayFadeInterval,
+        start_blend: CGDisplayBlendFraction,
+        end_blend: CGDisplayBlendFraction,
+        red: f32,
+        green: f32,
+        blue: f32,
+        synchronous: bool,
+    ) -> CGError;
+
     /// https://developer.apple.com/documentation/coregraphics/1454103-cgconfiguredisplayfadeeffect
     fn CGConfigureDisplayFadeEffect(
         config: CGDisplayConfigRef,
@@ -308,23 +540,60 @@ extern "C" {
         user_info: *mut c_void,
     ) -> CGError;
 
-    // TODO Callbacks do not appear to leak if process exits, but might good
-    //   to clean up?  Need to determine appropriate location to call.
-    // CGDisplayRemoveReconfigurationCallback
+    /// https://developer.apple.com/documentation/coregraphics/1455285-cgdisplayremovereconfigurationca
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: extern "C" fn(),
+        user_info: *mut c_void,
+    ) -> CGError;
 
-    // TODO Finish adding support for configuring display mirrors.
-    // fn CGDisplayMirrorsDisplay
+    /// https://developer.apple.com/documentation/coregraphics/1454255-cgdisplaymirrorsdisplay
+    fn CGDisplayMirrorsDisplay(display_id: CGDirectDisplayID) -> CGDirectDisplayID;
 
     /// https://developer.apple.com/documentation/appkit/1428475-nsapplicationload
     pub fn NSApplicationLoad() -> bool;
     /// https://developer.apple.com/documentation/corefoundation/1542011-cfrunlooprun/
     pub fn CFRunLoopRun();
+    /// https://developer.apple.com/documentation/corefoundation/1542890-cfrunloopgetmain
+    fn CFRunLoopGetMain() -> CFRunLoop;
+    /// https://developer.apple.com/documentation/corefoundation/1541796-cfrunloopstop
+    fn CFRunLoopStop(run_loop: CFRunLoop);
+    /// https://developer.apple.com/documentation/corefoundation/1543356-cfrunloopaddsource
+    fn CFRunLoopAddSource(run_loop: CFRunLoop, source: CFRunLoopSource, mode: CFString);
+    /// https://developer.apple.com/documentation/corefoundation/kcfrunloopdefaultmode
+    static kCFRunLoopDefaultMode: CFString;
+
+    /// https://developer.apple.com/documentation/iokit/1523839-iopscopypowersourcesinfo
+    fn IOPSCopyPowerSourcesInfo() -> CFTypeRef;
+    /// https://developer.apple.com/documentation/iokit/1523846-iopsgetprovidingpowersourcetype
+    /// The result is not owned by the caller.
+    fn IOPSGetProvidingPowerSourceType(snapshot: CFTypeRef) -> CFString;
+    /// https://developer.apple.com/documentation/iokit/1523868-iopsnotificationcreaterunloopsource
+    fn IOPSNotificationCreateRunLoopSource(
+        callback: extern "C" fn(*mut c_void),
+        context: *mut c_void,
+    ) -> CFRunLoopSource;
+
+    /// https://developer.apple.com/documentation/iokit/1557114-ioregisterforsystempower
+    fn IORegisterForSystemPower(
+        refcon: *mut c_void,
+        port: *mut IONotificationPort,
+        callback: extern "C" fn(*mut c_void, IOObject, u32, *mut c_void),
+        notifier: *mut IOObject,
+    ) -> IOObject;
+    /// https://developer.apple.com/documentation/iokit/1557035-ioallowpowerchange
+    fn IOAllowPowerChange(kernel_port: IOObject, notification_id: isize) -> c_int;
+    /// https://developer.apple.com/documentation/iokit/1541039-ionotificationportgetrunloopsour
+    /// The result is owned by the notification port.
+    fn IONotificationPortGetRunLoopSource(port: IONotificationPort) -> CFRunLoopSource;
 
     // Private Core Graphics APIs ////////////////////////////////////////////
     // https://github.com/NUIKit/CGSInternal/blob/master/CGSDisplays.h
 
     fn CGSGetCurrentDisplayMode(display: CGDirectDisplayID, mode_num: *mut c_int) -> CGError;
 
+    // https://github.com/MonitorControl/MonitorControl/blob/main/MonitorControl/Support/Bridging-Header.h
+    fn CoreDisplay_DisplayCreateInfoDictionary(display: CGDirectDisplayID) -> CFDictionary;
+
     fn CGSGetNumberOfDisplayModes(display: CGDirectDisplayID, num_modes: *mut c_int) -> CGError;
 
     fn CGSGetDisplayModeDescriptionOfLength(
@@ -364,21 +633,21 @@ pub fn cf_string_get_cstring(
     unsafe { CFStringGetCString(string, buffer.as_mut_ptr(), buffer.len() as c_int, encoding) }
 }
 
-pub fn cf_uuid_create_string(allocator: CFAllocator, uuid: CFUUID) -> CFString {
-    unsafe { CFUUIDCreateString(allocator, uuid) }
+pub fn cf_uuid_create_string(allocator: CFAllocator, uuid: &CfUuid) -> Option<CfString> {
+    CfString::new(unsafe { CFUUIDCreateString(allocator, uuid.as_raw()) })
 }
 
+/// Passing an empty slice only counts the online displays.
 pub fn cg_get_online_display_list(
     online_displays: &mut [DisplayID],
     display_count: &mut u32,
 ) -> CGError {
-    unsafe {
-        CGGetOnlineDisplayList(
-            online_displays.len() as u32,
-            online_displays.as_mut_ptr() as *mut CGDirectDisplayID,
-            display_count,
-        )
-    }
+    let buffer = if online_displays.is_empty() {
+        std::ptr::null_mut()
+    } else {
+        online_displays.as_mut_ptr() as *mut CGDirectDisplayID
+    };
+    unsafe { CGGetOnlineDisplayList(online_displays.len() as u32, buffer, display_count) }
 }
 
 pub fn cg_get_active_display_list(
@@ -422,36 +691,57 @@ pub fn cg_display_is_in_mirror_set(display_id: DisplayID) -> bool {
     unsafe { CGDisplayIsInMirrorSet(display_id.id) }
 }
 
-pub fn cg_display_create_uuid_from_display_id(display_id: DisplayID) -> CFUUID {
-    unsafe { CGDisplayCreateUUIDFromDisplayID(display_id.id) }
+/// Returns the display that the given display is mirroring, if any.  The
+/// primary display of a mirror set does not mirror any display.
+pub fn cg_display_mirrors_display(display_id: DisplayID) -> Option<DisplayID> {
+    // kCGNullDirectDisplay is returned if the display is not a mirror.
+    let id = unsafe { CGDisplayMirrorsDisplay(display_id.id) };
+    (id != 0).then_some(DisplayID { id })
+}
+
+pub fn cg_display_create_uuid_from_display_id(display_id: DisplayID) -> Option<CfUuid> {
+    CfUuid::new(unsafe { CGDisplayCreateUUIDFromDisplayID(display_id.id) })
 }
 
-pub fn cg_display_mode_release(mode: CGDisplayModeRef) {
-    unsafe { CGDisplayModeRelease(mode) }
+pub fn cg_display_copy_all_display_modes(display_id: DisplayID) -> Option<CfArray> {
+    CfArray::new(unsafe { CGDisplayCopyAllDisplayModes(display_id.id, null()) })
 }
 
-pub fn cg_display_copy_all_display_modes(display_id: DisplayID) -> CGDisplayModeArray {
-    unsafe { CGDisplayCopyAllDisplayModes(display_id.id, null()) }
+/// Variant of `cg_display_copy_all_display_modes` that also includes the
+/// low resolution duplicates of scaled modes.  Without these, the scaled
+/// modes reported by `cgs_get_display_mode_description` will be missing.
+pub fn cg_display_copy_all_display_modes_with_duplicates(display_id: DisplayID) -> Option<CfArray> {
+    unsafe {
+        let keys = [kCGDisplayShowDuplicateLowResolutionModes];
+        let values = [kCFBooleanTrue];
+        let options = CfDictionary::new(CFDictionaryCreate(
+            kCFAllocatorDefault,
+            keys.as_ptr(),
+            values.as_ptr(),
+            1,
+            &kCFTypeDictionaryKeyCallBacks,
+            &kCFTypeDictionaryValueCallBacks,
+        ))?;
+        CfArray::new(CGDisplayCopyAllDisplayModes(
+            display_id.id,
+            options.as_raw(),
+        ))
+    }
 }
 
 // Derived helpers
-pub fn cg_display_modes_get_count(modes: CGDisplayModeArray) -> usize {
-    unsafe { CFArrayGetCount(modes) }
+pub fn cg_display_modes_get_count(modes: &CfArray) -> usize {
+    unsafe { CFArrayGetCount(modes.as_raw()) }
 }
 
-pub fn cg_display_modes_at_index(modes: CGDisplayModeArray, idx: CFIndex) -> CGDisplayModeRef {
-    unsafe { CFArrayGetValueAtIndex(modes, idx) }
+/// The mode is owned by the array, so must not be used after the array is
+/// dropped.
+pub fn cg_display_modes_at_index(modes: &CfArray, idx: CFIndex) -> CGDisplayModeRef {
+    unsafe { CFArrayGetValueAtIndex(modes.as_raw(), idx) }
 }
 
-pub fn cg_display_copy_display_mode(display_id: DisplayID) -> Option<CGDisplayModeRef> {
-    unsafe {
-        let mode = CGDisplayCopyDisplayMode(display_id.id);
-        if !mode.is_null() {
-            Some(mode)
-        } else {
-            None
-        }
-    }
+pub fn cg_display_copy_display_mode(display_id: DisplayID) -> Option<CgDisplayMode> {
+    CgDisplayMode::new(unsafe { CGDisplayCopyDisplayMode(display_id.id) })
 }
 
 pub fn cg_display_mode_get_width(mode: &CGDisplayModeRef) -> usize {
@@ -482,6 +772,12 @@ pub fn cg_display_mode_get_io_display_mode_id(mode: &CGDisplayModeRef) -> i32 {
     unsafe { CGDisplayModeGetIODisplayModeID(*mode) }
 }
 
+/// Obtain the pixel encoding of the display mode, for example
+/// `--RRRRRRRRRRGGGGGGGGGGBBBBBBBBBB` for a mode with ten bits per channel.
+pub fn cg_display_mode_copy_pixel_encoding(mode: &CGDisplayModeRef) -> Option<String> {
+    CfString::new(unsafe { CGDisplayModeCopyPixelEncoding(*mode) })?.to_ascii(63)
+}
+
 pub fn cg_display_mode_is_usable_for_desktop_gui(mode: &CGDisplayModeRef) -> bool {
     unsafe { CGDisplayModeIsUsableForDesktopGUI(*mode) }
 }
@@ -545,6 +841,32 @@ pub fn cg_configure_display_fade_effect(
     }
 }
 
+pub fn cg_acquire_display_fade_reservation(
+    seconds: CGDisplayReservationInterval,
+) -> Result<CGDisplayFadeReservationToken, CGError> {
+    let mut token: CGDisplayFadeReservationToken = 0;
+    let error = unsafe { CGAcquireDisplayFadeReservation(seconds, &mut token) };
+    match error {
+        CGError::success => Ok(token),
+        _ => Err(error),
+    }
+}
+
+pub fn cg_release_display_fade_reservation(token: CGDisplayFadeReservationToken) -> CGError {
+    unsafe { CGReleaseDisplayFadeReservation(token) }
+}
+
+/// Synchronously fade all the displays between the given blend fractions
+/// of black.
+pub fn cg_display_fade(
+    token: CGDisplayFadeReservationToken,
+    duration: CGDisplayFadeInterval,
+    start_blend: CGDisplayBlendFraction,
+    end_blend: CGDisplayBlendFraction,
+) -> CGError {
+    unsafe { CGDisplayFade(token, duration, start_blend, end_blend, 0.0, 0.0, 0.0, true) }
+}
+
 pub fn cg_configure_display_mirror_of_display(
     config_ref: &CGDisplayConfigRef,
     display_id: DisplayID,
@@ -557,6 +879,10 @@ pub fn cg_display_register_reconfiguration_callback(cb: extern "C" fn()) -> CGEr
     unsafe { CGDisplayRegisterReconfigurationCallback(cb, null_mut()) }
 }
 
+pub fn cg_display_remove_reconfiguration_callback(cb: extern "C" fn()) -> CGError {
+    unsafe { CGDisplayRemoveReconfigurationCallback(cb, null_mut()) }
+}
+
 pub fn ns_application_load() -> bool {
     unsafe { NSApplicationLoad() }
 }
@@ -565,6 +891,299 @@ pub fn cf_run_loop_run() {
     unsafe { CFRunLoopRun() }
 }
 
+/// Helper to obtain whether the lid of the built-in display is closed, from
+/// the `AppleClamshellState` property of the power management root domain.
+/// Returns `None` if the state is not available, for example on machines
+/// without a built-in display.
+pub fn io_clamshell_closed() -> Option<bool> {
+    unsafe {
+        // The default main port is represented by zero.
+        let service = IOServiceGetMatchingService(
+            0,
+            IOServiceMatching(c"IOPMrootDomain".as_ptr() as *const u8),
+        );
+        if service == 0 {
+            return None;
+        }
+        let key = CfString::new(CFStringCreateWithCString(
+            kCFAllocatorDefault,
+            c"AppleClamshellState".as_ptr() as *const u8,
+            CFStringBuiltInEncodings::ASCII.into(),
+        ));
+        let property = match &key {
+            Some(key) => {
+                IORegistryEntryCreateCFProperty(service, key.as_raw(), kCFAllocatorDefault, 0)
+            }
+            None => null(),
+        };
+        IOObjectRelease(service);
+        if property.is_null() {
+            return None;
+        }
+        let closed = if property == kCFBooleanTrue {
+            Some(true)
+        } else if property == kCFBooleanFalse {
+            Some(false)
+        } else {
+            None
+        };
+        CFRelease(property);
+        closed
+    }
+}
+
+/// Helper to put all displays to sleep, by asking the `IODisplayWrangler`
+/// to treat the user as idle.  The system itself stays awake.  Returns false
+/// if the request could not be made.
+pub fn io_display_wrangler_request_idle() -> bool {
+    unsafe {
+        let service = IOServiceGetMatchingService(
+            0,
+            IOServiceMatching(c"IODisplayWrangler".as_ptr() as *const u8),
+        );
+        if service == 0 {
+            return false;
+        }
+        let result = match CfString::from_cstr(c"IORequestIdle") {
+            Some(key) => IORegistryEntrySetCFProperty(service, key.as_raw(), kCFBooleanTrue),
+            None => -1,
+        };
+        IOObjectRelease(service);
+        result == 0
+    }
+}
+
+/// Helper to wake all displays, by declaring local user activity on behalf
+/// of the named process.  Returns false if the activity could not be
+/// declared.
+pub fn io_pm_declare_user_activity(name: &std::ffi::CStr) -> bool {
+    // kIOPMUserActiveLocal
+    const USER_ACTIVE_LOCAL: u32 = 0;
+    let Some(name) = CfString::from_cstr(name) else {
+        return false;
+    };
+    unsafe {
+        let mut assertion_id: u32 = 0;
+        if IOPMAssertionDeclareUserActivity(name.as_raw(), USER_ACTIVE_LOCAL, &mut assertion_id)
+            != 0
+        {
+            return false;
+        }
+        // The activity has already been declared, so there is no need to
+        // keep the displays awake any longer than the user's idle settings.
+        IOPMAssertionRelease(assertion_id);
+        true
+    }
+}
+
+/// Properties of a display reported by its CoreDisplay info dictionary.
+#[derive(Debug, Default)]
+pub struct DisplayInfo {
+    /// Whether the display is streamed to via AirPlay.
+    pub airplay: bool,
+    /// Whether the display has no physical device of its own.
+    pub virtual_device: bool,
+    /// The English name of the display product, if reported.
+    pub product_name: Option<String>,
+}
+
+/// Helper to obtain the properties of a display from the private
+/// `CoreDisplay_DisplayCreateInfoDictionary` function.  Properties that are
+/// absent take their default values.
+pub fn cd_display_info(display_id: DisplayID) -> DisplayInfo {
+    unsafe {
+        let Some(info) = CfDictionary::new(CoreDisplay_DisplayCreateInfoDictionary(display_id.id))
+        else {
+            return DisplayInfo::default();
+        };
+        // The values are owned by the dictionary, so must not be released.
+        let value = |dict: CFDictionary, key: &std::ffi::CStr| {
+            CfString::from_cstr(key)
+                .map(|key| CFDictionaryGetValue(dict, key.as_raw()))
+                .filter(|value| !value.is_null())
+        };
+        let flag = |key: &std::ffi::CStr| value(info.as_raw(), key) == Some(kCFBooleanTrue);
+        let product_name = value(info.as_raw(), c"DisplayProductName")
+            .and_then(|names| value(names, c"en_US"))
+            .and_then(|name| {
+                let mut buffer = [0u8; 256];
+                cf_string_get_cstring(name, &mut buffer, CFStringBuiltInEncodings::UTF8.into())
+                    .then(|| std::ffi::CStr::from_bytes_until_nul(&buffer).ok())
+                    .flatten()
+                    .map(|cstr| cstr.to_string_lossy().into_owned())
+            });
+        DisplayInfo {
+            airplay: flag(c"kCGDisplayIsAirPlay"),
+            virtual_device: flag(c"kCGDisplayIsVirtualDevice"),
+            product_name,
+        }
+    }
+}
+
+/// Properties of the window server session knoll is running in.
+#[derive(Debug, Default)]
+pub struct SessionInfo {
+    /// Whether the session is the one currently using the console.
+    pub on_console: bool,
+    /// Whether the screen is locked.
+    pub screen_locked: bool,
+}
+
+/// Helper to obtain the properties of the current window server session.
+/// Returns `None` if there is no session, for example when running from an
+/// SSH login that has no window server connection.
+pub fn cg_session_info() -> Option<SessionInfo> {
+    unsafe {
+        let info = CfDictionary::new(CGSessionCopyCurrentDictionary())?;
+        let flag = |key: &std::ffi::CStr| {
+            CfString::from_cstr(key).map(|key| CFDictionaryGetValue(info.as_raw(), key.as_raw()))
+                == Some(kCFBooleanTrue)
+        };
+        Some(SessionInfo {
+            on_console: flag(c"kCGSSessionOnConsoleKey"),
+            screen_locked: flag(c"CGSSessionScreenIsLocked"),
+        })
+    }
+}
+
+/// Helper to determine whether the given C symbol can be resolved in the
+/// running process.
+pub fn dl_symbol_present(name: &std::ffi::CStr) -> bool {
+    unsafe { !libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()).is_null() }
+}
+
+/// Helper to determine whether the given Objective-C class is available in
+/// the running process.
+pub fn objc_class_present(name: &str) -> bool {
+    objc::runtime::Class::get(name).is_some()
+}
+
+/// Helper to obtain the version of macOS, such as `14.5`, from the
+/// `kern.osproductversion` sysctl.  Returns `None` if it is unavailable.
+pub fn os_product_version() -> Option<String> {
+    let mut buffer = [0u8; 32];
+    let mut length = buffer.len();
+    let result = unsafe {
+        sysctlbyname(
+            c"kern.osproductversion".as_ptr(),
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut length,
+            null_mut(),
+            0,
+        )
+    };
+    if result != 0 {
+        return None;
+    }
+    std::ffi::CStr::from_bytes_until_nul(&buffer[..length.min(buffer.len())])
+        .ok()
+        .map(|cstr| cstr.to_string_lossy().into_owned())
+}
+
+/// Helper to obtain whether the computer is currently running on battery
+/// power.  Returns `None` if the power source could not be determined.
+pub fn io_ps_on_battery() -> Option<bool> {
+    unsafe {
+        let snapshot = IOPSCopyPowerSourcesInfo();
+        if snapshot.is_null() {
+            return None;
+        }
+        let source_type = IOPSGetProvidingPowerSourceType(snapshot);
+        let mut buffer = [0u8; 64];
+        let on_battery = if !source_type.is_null()
+            && cf_string_get_cstring(
+                source_type,
+                &mut buffer,
+                CFStringBuiltInEncodings::ASCII.into(),
+            ) {
+            let source_type = std::ffi::CStr::from_bytes_until_nul(&buffer)
+                .ok()
+                .and_then(|s| s.to_str().ok());
+            match source_type {
+                Some("AC Power") => Some(false),
+                Some("Battery Power") => Some(true),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        CFRelease(snapshot);
+        on_battery
+    }
+}
+
+/// Register a callback to be invoked on the main thread's run loop whenever
+/// the state of the power sources changes.  Returns false if the callback
+/// could not be registered.
+pub fn io_ps_register_notification(cb: extern "C" fn(*mut c_void)) -> bool {
+    unsafe {
+        let source = IOPSNotificationCreateRunLoopSource(cb, null_mut());
+        if source.is_null() {
+            return false;
+        }
+        // The run loop retains the source, so our reference can be released.
+        CFRunLoopAddSource(CFRunLoopGetMain(), source, kCFRunLoopDefaultMode);
+        CFRelease(source);
+        true
+    }
+}
+
+// https://developer.apple.com/documentation/iokit/iopmlib_h/iomessages
+const K_IO_MESSAGE_CAN_SYSTEM_SLEEP: u32 = 0xE0000270;
+const K_IO_MESSAGE_SYSTEM_WILL_SLEEP: u32 = 0xE0000280;
+const K_IO_MESSAGE_SYSTEM_HAS_POWERED_ON: u32 = 0xE0000300;
+
+/// The connection to the power management root domain, along with the
+/// callback to invoke when the system wakes.
+static SYSTEM_POWER: std::sync::OnceLock<(IOObject, extern "C" fn())> = std::sync::OnceLock::new();
+
+/// Callback for system power events.  Sleep must be acknowledged, otherwise
+/// the system will wait for a timeout before sleeping.
+extern "C" fn system_power_changed(
+    _refcon: *mut c_void,
+    _service: IOObject,
+    message_type: u32,
+    message_argument: *mut c_void,
+) {
+    let Some((root_port, on_wake)) = SYSTEM_POWER.get() else {
+        return;
+    };
+    match message_type {
+        K_IO_MESSAGE_CAN_SYSTEM_SLEEP | K_IO_MESSAGE_SYSTEM_WILL_SLEEP => unsafe {
+            IOAllowPowerChange(*root_port, message_argument as isize);
+        },
+        K_IO_MESSAGE_SYSTEM_HAS_POWERED_ON => on_wake(),
+        _ => { /* No-op */ }
+    }
+}
+
+/// Register a callback to be invoked on the main thread's run loop whenever
+/// the system wakes from sleep.  Only a single callback may be registered.
+/// Returns false if the callback could not be registered.
+pub fn io_register_for_system_wake(cb: extern "C" fn()) -> bool {
+    unsafe {
+        let mut port: IONotificationPort = null_mut();
+        let mut notifier: IOObject = 0;
+        let root_port =
+            IORegisterForSystemPower(null_mut(), &mut port, system_power_changed, &mut notifier);
+        if root_port == 0 || SYSTEM_POWER.set((root_port, cb)).is_err() {
+            return false;
+        }
+        CFRunLoopAddSource(
+            CFRunLoopGetMain(),
+            IONotificationPortGetRunLoopSource(port),
+            kCFRunLoopDefaultMode,
+        );
+        true
+    }
+}
+
+/// Stop the main thread's run loop, causing `cf_run_loop_run` to return.
+/// Safe to call from any thread.
+pub fn cf_run_loop_stop_main() {
+    unsafe { CFRunLoopStop(CFRunLoopGetMain()) }
+}
+
 pub fn cgs_get_current_display_mode(display: DisplayID, mode: &mut i32) -> CGError {
     unsafe { CGSGetCurrentDisplayMode(display.id, mode) }
 }
@@ -604,27 +1223,223 @@ pub fn cgs_configure_display_enabled(
     unsafe { CGSConfigureDisplayEnabled(*config_ref, display_id.id, enabled) }
 }
 
+/// The presumed prototype of the private `CGSSetDisplayRotation` function.
+/// Its existence is referenced here:
+/// https://github.com/NUIKit/CGSInternal/issues/3
+/// but there is no documentation of its prototype, so this is inferred from
+/// `CGDisplayRotation`, which reports the rotation in degrees as a double.
+type CGSSetDisplayRotationFn =
+    unsafe extern "C" fn(display: CGDirectDisplayID, degrees: f64) -> CGError;
+
+/// Helper to set the rotation of a display via the private
+/// `CGSSetDisplayRotation` function.  As the function is undocumented, it
+/// is looked up at runtime rather than linked against, and `None` is
+/// returned if it is not present.  Given that its prototype is inferred,
+/// callers should verify that the rotation took effect.
+pub fn cgs_set_display_rotation(display_id: DisplayID, degrees: f64) -> Option<CGError> {
+    static FUNCTION: once_cell::sync::Lazy<Option<CGSSetDisplayRotationFn>> =
+        once_cell::sync::Lazy::new(|| unsafe {
+            let symbol = libc::dlsym(libc::RTLD_DEFAULT, c"CGSSetDisplayRotation".as_ptr());
+            (!symbol.is_null())
+                .then(|| std::mem::transmute::<*mut c_void, CGSSetDisplayRotationFn>(symbol))
+        });
+    FUNCTION.map(|function| unsafe { function(display_id.id, degrees) })
+}
+
+/// The presumed prototype of the private `CGSGetDisplayList` function, as
+/// declared by https://github.com/NUIKit/CGSInternal.  Unlike
+/// `CGGetOnlineDisplayList`, it also lists the displays that have been
+/// disabled with `CGSConfigureDisplayEnabled`.
+type CGSGetDisplayListFn = unsafe extern "C" fn(
+    max_displays: u32,
+    displays: *mut CGDirectDisplayID,
+    display_count: *mut u32,
+) -> CGError;
+
+/// Helper to list all of the attached displays, including those that have
+/// been disabled, via the private `CGSGetDisplayList` function.  Passing an
+/// empty slice only counts the displays.  As with `cgs_set_display_rotation`
+/// the function is looked up at runtime, and `None` is returned if it is not
+/// present.
+pub fn cgs_get_display_list(
+    displays: &mut [DisplayID],
+    display_count: &mut u32,
+) -> Option<CGError> {
+    static FUNCTION: once_cell::sync::Lazy<Option<CGSGetDisplayListFn>> =
+        once_cell::sync::Lazy::new(|| unsafe {
+            let symbol = libc::dlsym(libc::RTLD_DEFAULT, c"CGSGetDisplayList".as_ptr());
+            (!symbol.is_null())
+                .then(|| std::mem::transmute::<*mut c_void, CGSGetDisplayListFn>(symbol))
+        });
+    let buffer = if displays.is_empty() {
+        std::ptr::null_mut()
+    } else {
+        displays.as_mut_ptr() as *mut CGDirectDisplayID
+    };
+    FUNCTION.map(|function| unsafe { function(displays.len() as u32, buffer, display_count) })
+}
+
 /// Helper to set the rotation of a display via the MPDisplay Objective-C class.
-// TODO As this is the only use of Objective-C in this code-base,
-//   in the future it may be worth investigate the private
-//   `CGSSetDisplayRotation` function instead.  Its existence is
-//   referenced here: https://github.com/NUIKit/CGSInternal/issues/3
-//   but there is no documentation of what its expected function
-//   prototype would be.  So some further detective work will be
-//   necessary.
+/// This provides no indication of whether it succeeded, so callers should
+/// verify that the rotation took effect.
 pub fn mpd_set_rotation(display_id: DisplayID, rotation: i32) {
-    // https://github.com/phatblat/macOSPrivateFrameworks/tree/9047371eb80f925642c8a7c4f1e00095aec66044/PrivateFrameworks/MonitorPanel
     unsafe {
-        let cls = class!(MPDisplay);
-        let obj: *mut Object = objc::msg_send![cls, alloc];
-        assert_ne!(
-            obj,
-            null_mut(),
-            "Received a null pointer as a result of Objective-C message send."
-        );
-        let _: () = objc::msg_send![obj, initWithCGSDisplayID:display_id.id];
+        let obj = mpd_display(display_id);
         let _: () = objc::msg_send![obj, setOrientation:rotation];
     }
-    // TODO Need to wait to confirm operation succeeded?
-    //   So far I have never seen a rotation fail in practice.
+}
+
+/// Helper to obtain the underscan of a display via the MPDisplay Objective-C
+/// class.  Returns `None` if the display does not support underscan.
+pub fn mpd_underscan(display_id: DisplayID) -> Option<f32> {
+    unsafe {
+        let obj = mpd_display(display_id);
+        let supported: bool = objc::msg_send![obj, hasUnderscan];
+        if supported {
+            let underscan: f32 = objc::msg_send![obj, underscan];
+            Some(underscan)
+        } else {
+            None
+        }
+    }
+}
+
+/// Helper to set the underscan of a display via the MPDisplay Objective-C
+/// class.  The underscan is a value between 0.0 and 1.0.
+pub fn mpd_set_underscan(display_id: DisplayID, underscan: f32) {
+    unsafe {
+        let obj = mpd_display(display_id);
+        let _: () = objc::msg_send![obj, setUnderscan:underscan];
+    }
+}
+
+/// Helper to obtain the SSID of the Wi-Fi network the computer is currently
+/// connected to, via the CWWiFiClient Objective-C class.  Returns `None` if
+/// not connected, or if the process is not permitted to know the SSID.
+pub fn cw_current_ssid() -> Option<String> {
+    unsafe {
+        let client: *mut Object = objc::msg_send![class!(CWWiFiClient), sharedWiFiClient];
+        if client.is_null() {
+            return None;
+        }
+        let interface: *mut Object = objc::msg_send![client, interface];
+        if interface.is_null() {
+            return None;
+        }
+        let ssid: *mut Object = objc::msg_send![interface, ssid];
+        if ssid.is_null() {
+            return None;
+        }
+        let utf8: *const std::os::raw::c_char = objc::msg_send![ssid, UTF8String];
+        if utf8.is_null() {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(utf8)
+            .to_str()
+            .ok()
+            .map(String::from)
+    }
+}
+
+/// Helper to construct an MPDisplay Objective-C object for the given display.
+unsafe fn mpd_display(display_id: DisplayID) -> *mut Object {
+    // https://github.com/phatblat/macOSPrivateFrameworks/tree/9047371eb80f925642c8a7c4f1e00095aec66044/PrivateFrameworks/MonitorPanel
+    let cls = class!(MPDisplay);
+    let obj: *mut Object = objc::msg_send![cls, alloc];
+    assert_ne!(
+        obj,
+        null_mut(),
+        "Received a null pointer as a result of Objective-C message send."
+    );
+    let _: () = objc::msg_send![obj, initWithCGSDisplayID:display_id.id];
+    obj
+}
+
+/// A virtual display created with the private CGVirtualDisplay Objective-C
+/// classes.  The display remains attached until this is dropped.
+// The classes are described by the headers extracted here:
+// https://github.com/w0lfschild/macOS_headers/tree/master/macOS/Frameworks/CoreGraphics
+#[derive(Debug)]
+pub struct CgVirtualDisplay {
+    display: *mut Object,
+    display_id: DisplayID,
+}
+
+impl CgVirtualDisplay {
+    /// Create a virtual display with a single display mode of the given
+    /// extents and refresh frequency.  The size of the display is derived
+    /// from the pixel density.  The serial number distinguishes the display
+    /// from other virtual displays, as it determines its UUID.  Returns
+    /// `None` if virtual displays are not supported, or the display could
+    /// not be created.
+    pub fn new(
+        name: &str,
+        width: u32,
+        height: u32,
+        ppi: u32,
+        frequency: f64,
+        serial_number: u32,
+    ) -> Option<Self> {
+        let descriptor_class = objc::runtime::Class::get("CGVirtualDisplayDescriptor")?;
+        let name = std::ffi::CString::new(name).ok()?;
+        let millimeters = |pixels: u32| pixels as f64 / ppi as f64 * 25.4;
+        unsafe {
+            let descriptor: *mut Object = objc::msg_send![descriptor_class, new];
+            let ns_name: *mut Object =
+                objc::msg_send![class!(NSString), stringWithUTF8String:name.as_ptr()];
+            let _: () = objc::msg_send![descriptor, setName: ns_name];
+            let _: () = objc::msg_send![descriptor, setMaxPixelsWide: width];
+            let _: () = objc::msg_send![descriptor, setMaxPixelsHigh: height];
+            let size = CGSize {
+                width: millimeters(width),
+                height: millimeters(height),
+            };
+            let _: () = objc::msg_send![descriptor, setSizeInMillimeters: size];
+            let _: () = objc::msg_send![descriptor, setSerialNum: serial_number];
+            let _: () = objc::msg_send![descriptor, setQueue: dispatch_get_global_queue(0, 0)];
+
+            let display: *mut Object = objc::msg_send![class!(CGVirtualDisplay), alloc];
+            let display: *mut Object = objc::msg_send![display, initWithDescriptor: descriptor];
+            objc_release(descriptor);
+            if display.is_null() {
+                return None;
+            }
+
+            let mode: *mut Object = objc::msg_send![class!(CGVirtualDisplayMode), alloc];
+            let mode: *mut Object =
+                objc::msg_send![mode, initWithWidth:width height:height refreshRate:frequency];
+            let modes: *mut Object = objc::msg_send![class!(NSArray), arrayWithObject: mode];
+            let settings: *mut Object = objc::msg_send![class!(CGVirtualDisplaySettings), new];
+            let _: () = objc::msg_send![settings, setModes: modes];
+            let applied: objc::runtime::BOOL = objc::msg_send![display, applySettings: settings];
+            objc_release(settings);
+            objc_release(mode);
+            if applied == objc::runtime::NO {
+                objc_release(display);
+                return None;
+            }
+
+            let id: CGDirectDisplayID = objc::msg_send![display, displayID];
+            Some(CgVirtualDisplay {
+                display,
+                display_id: DisplayID { id },
+            })
+        }
+    }
+
+    /// The `DisplayID` of the virtual display.
+    pub fn display_id(&self) -> DisplayID {
+        self.display_id
+    }
+}
+
+impl Drop for CgVirtualDisplay {
+    fn drop(&mut self) {
+        unsafe { objc_release(self.display) }
+    }
+}
+
+/// Helper to release an Objective-C object obtained from `alloc` or `new`.
+unsafe fn objc_release(obj: *mut Object) {
+    let _: () = objc::msg_send![obj, release];
 }
@@ -0,0 +1,122 @@
+//! Non-fatal diagnostics, such as warnings about likely mistakes in a
+//! configuration, are reported to standard error independently of the
+//! logging verbosity.  When strict checking is requested they are instead
+//! promoted to errors.
+use coverage_helper::test;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::valid_config::Warning;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A `Write` that may be shared, so that both the logger and diagnostics
+/// can write to standard error.
+#[derive(Clone)]
+pub struct SharedWriter {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl SharedWriter {
+    /// Wrap the given writer so that it may be shared.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        SharedWriter {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /// Hold the lock while writing the entire buffer, so that it is not
+    /// interleaved with writes from elsewhere.
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.writer.lock().unwrap().write_all(buf)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Destination for diagnostics.
+#[derive(Clone)]
+pub struct Diagnostics {
+    writer: SharedWriter,
+    /// Whether warnings should be treated as errors.
+    strict: bool,
+    /// The most recently reported warnings.  As daemon mode rereads the
+    /// configuration repeatedly, the same warnings are not reported again.
+    reported: Arc<Mutex<Vec<Warning>>>,
+}
+
+impl Diagnostics {
+    /// Create a new `Diagnostics` reporting to the given writer.
+    pub fn new(writer: SharedWriter, strict: bool) -> Self {
+        Diagnostics {
+            writer,
+            strict,
+            reported: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Report the given warnings, unless they are the same as those last
+    /// reported.  In strict mode, if there are any warnings they are
+    /// returned as an error instead.
+    pub fn report(&self, warnings: Vec<Warning>) -> Result<(), Vec<Warning>> {
+        if self.strict && !warnings.is_empty() {
+            return Err(warnings);
+        }
+        let mut reported = self.reported.lock().unwrap();
+        if *reported == warnings {
+            return Ok(());
+        }
+        *reported = warnings.clone();
+        let mut writer = self.writer.clone();
+        for warning in warnings {
+            // There is nowhere else to report a failure to write a warning.
+            let _ = writeln!(writer, "Warning: {}", warning);
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test that warnings are written, or promoted to errors in strict mode.
+#[test]
+fn test_diagnostics_report() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let writer = SharedWriter::new(Buffer(buffer.clone()));
+    let warning = Warning::ZeroFrequency("abcdef1234".to_owned());
+
+    let diagnostics = Diagnostics::new(writer.clone(), false);
+    assert!(diagnostics.report(vec![warning.clone()]).is_ok());
+    let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    assert_eq!(output, format!("Warning: {}\n", warning));
+    // The same warnings are not reported twice in a row.
+    assert!(diagnostics.report(vec![warning.clone()]).is_ok());
+    let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    assert_eq!(output, format!("Warning: {}\n", warning));
+
+    let diagnostics = Diagnostics::new(writer, true);
+    assert_eq!(diagnostics.report(vec![]), Ok(()));
+    assert_eq!(
+        diagnostics.report(vec![warning.clone()]),
+        Err(vec![warning])
+    );
+}
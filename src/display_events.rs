@@ -0,0 +1,157 @@
+//! This module provides a subscription to changes in the display
+//! configuration, for library users that would like to react to displays
+//! being attached, detached or reconfigured without reimplementing the
+//! callback plumbing used by the daemon.  macOS only delivers the underlying
+//! callbacks while the main thread's run loop is running, so `run_loop`
+//! must be called from the main thread, with the events consumed from
+//! another.
+use coverage_helper::test;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::core_graphics;
+use crate::displays::{DisplayState, Error, ReconfigurationCallback};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Changes to the display configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayEvent {
+    /// The display configuration changed.  macOS typically reports several
+    /// of these for a single change, such as once per affected display.
+    Reconfigured,
+    /// A display was attached.
+    Added { uuid: String },
+    /// A display was detached.
+    Removed { uuid: String },
+}
+
+// The reconfiguration callback does not carry any state, so the channels of
+// all subscribers are kept globally.  Subscribers that have been dropped
+// are removed the next time the callback is invoked.
+static SUBSCRIBERS: Mutex<Vec<Sender<()>>> = Mutex::new(Vec::new());
+
+/// Callback for display configuration events, which notifies all
+/// subscribers.  It must not panic, as it is invoked from C.
+extern "C" fn notify_subscribers() {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.retain(|sender| sender.send(()).is_ok());
+    }
+}
+
+/// A subscription to display configuration events.  Iterating blocks until
+/// the next event, and ends only if the subscription is disconnected.
+/// After each reconfiguration, the attached displays are compared with
+/// those before it to report any that were attached or detached.
+pub struct DisplayEvents<DS: DisplayState> {
+    receiver: Receiver<()>,
+    /// The UUIDs of the displays attached when last checked.
+    displays: BTreeSet<String>,
+    /// Events that have been determined, but not yet returned.
+    pending: VecDeque<DisplayEvent>,
+    /// The callback remains registered while there are subscriptions.
+    _callback: ReconfigurationCallback<DS>,
+}
+
+impl<DS: DisplayState> DisplayEvents<DS> {
+    /// Subscribe to display configuration events.
+    pub fn subscribe() -> Result<Self, Error> {
+        let (sender, receiver) = channel();
+        SUBSCRIBERS
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?
+            .push(sender);
+        Ok(DisplayEvents {
+            receiver,
+            displays: Self::current_displays()?,
+            pending: VecDeque::new(),
+            _callback: ReconfigurationCallback::register(notify_subscribers),
+        })
+    }
+
+    /// Helper to obtain the UUIDs of the currently attached displays.
+    fn current_displays() -> Result<BTreeSet<String>, Error> {
+        Ok(DS::current()?.get_displays().keys().cloned().collect())
+    }
+
+    /// Helper to determine the events resulting from a reconfiguration.
+    fn reconfigured(&mut self) -> Result<DisplayEvent, Error> {
+        let displays = Self::current_displays()?;
+        for uuid in displays.difference(&self.displays) {
+            self.pending
+                .push_back(DisplayEvent::Added { uuid: uuid.clone() });
+        }
+        for uuid in self.displays.difference(&displays) {
+            self.pending
+                .push_back(DisplayEvent::Removed { uuid: uuid.clone() });
+        }
+        self.displays = displays;
+        Ok(DisplayEvent::Reconfigured)
+    }
+
+    /// Wait for the next event, for at most the given duration.  Returns
+    /// `None` if no event occurred in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<DisplayEvent, Error>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(Ok(event));
+        }
+        self.receiver
+            .recv_timeout(timeout)
+            .ok()
+            .map(|()| self.reconfigured())
+    }
+}
+
+impl<DS: DisplayState> Iterator for DisplayEvents<DS> {
+    type Item = Result<DisplayEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(Ok(event));
+        }
+        self.receiver.recv().ok().map(|()| self.reconfigured())
+    }
+}
+
+/// Run the main thread's run loop, so that display configuration events are
+/// delivered.  This must be called from the main thread, and only returns
+/// once `stop_run_loop` is called.
+pub fn run_loop() {
+    core_graphics::ns_application_load();
+    core_graphics::cf_run_loop_run();
+}
+
+/// Stop the run loop started by `run_loop`.  Safe to call from any thread.
+pub fn stop_run_loop() {
+    core_graphics::cf_run_loop_stop_main();
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test that subscribers are notified of reconfigurations.
+#[test]
+fn test_display_events() {
+    use crate::fake_displays::FakeDisplayState;
+
+    let mut events =
+        DisplayEvents::<FakeDisplayState>::subscribe().expect("Subscribing should not fail");
+    assert!(events.next_timeout(Duration::ZERO).is_none());
+
+    notify_subscribers();
+    // The fake displays never change, so only the reconfiguration itself is
+    // reported.
+    assert_eq!(
+        events
+            .next()
+            .map(|result| result.expect("Events should not fail")),
+        Some(DisplayEvent::Reconfigured)
+    );
+    assert!(events.next_timeout(Duration::ZERO).is_none());
+
+    // Dropped subscribers are no longer notified.
+    drop(events);
+    notify_subscribers();
+    assert!(SUBSCRIBERS.lock().unwrap().is_empty());
+}
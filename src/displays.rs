@@ -1,9 +1,14 @@
 ///! Traits providing an abstract interface for inspecting and modifying the
 /// system's display state.
-use serde::Serialize;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Formatter;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::environment::Environment;
 
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
@@ -85,6 +90,337 @@ impl std::fmt::Display for Point {
     }
 }
 
+/// Helper to compute how far a value is outside of an inclusive range whose
+/// bounds may be absent.
+fn range_distance(min: Option<i64>, max: Option<i64>, value: i64) -> u64 {
+    match (min, max) {
+        (Some(min), _) if value < min => min.abs_diff(value),
+        (_, Some(max)) if value > max => max.abs_diff(value),
+        _ => 0,
+    }
+}
+
+/// An inclusive range of extents, either bound of which may be absent.  An
+/// extent lies in the range when both its width and its height lie between
+/// those of the bounds.  It is written as a map, such as
+/// `{"min": [2560, 1440]}`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtentsRange {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub min: Option<Point>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max: Option<Point>,
+}
+
+impl ExtentsRange {
+    /// The range containing only the given extents.
+    pub fn exactly(extents: &Point) -> Self {
+        ExtentsRange {
+            min: Some(extents.clone()),
+            max: Some(extents.clone()),
+        }
+    }
+
+    /// The squared Euclidean distance from the given extents to the nearest
+    /// extents in the range, which is zero if they lie in the range.
+    pub fn distance(&self, extents: &Point) -> u64 {
+        let dx = range_distance(
+            self.min.as_ref().map(|p| p.x),
+            self.max.as_ref().map(|p| p.x),
+            extents.x,
+        );
+        let dy = range_distance(
+            self.min.as_ref().map(|p| p.y),
+            self.max.as_ref().map(|p| p.y),
+            extents.y,
+        );
+        dx * dx + dy * dy
+    }
+
+    /// Do the given extents lie in the range?
+    pub fn contains(&self, extents: &Point) -> bool {
+        self.distance(extents) == 0
+    }
+}
+
+impl std::fmt::Display for ExtentsRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{}", min),
+            (Some(min), Some(max)) => write!(f, "{} to {}", min, max),
+            (Some(min), None) => write!(f, "at least {}", min),
+            (None, Some(max)) => write!(f, "at most {}", max),
+            (None, None) => write!(f, "any"),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The shape of a display mode, as the ratio of its width to its height.  It
+/// is written as a string, such as `"16:9"`.  As display modes are not
+/// always exactly in proportion, such as 1366x768 for 16:9, extents are
+/// considered to have the aspect ratio if they are within one percent of it.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// The tolerance, as a fraction, within which extents are considered to
+    /// have an aspect ratio.
+    const TOLERANCE: f64 = 0.01;
+
+    /// How far the shape of the given extents is from the aspect ratio, as
+    /// a fraction of the aspect ratio.
+    fn deviation(&self, extents: &Point) -> f64 {
+        if extents.y == 0 {
+            return f64::INFINITY;
+        }
+        let ratio = self.width as f64 / self.height as f64;
+        let actual = extents.x as f64 / extents.y as f64;
+        (actual - ratio).abs() / ratio
+    }
+
+    /// Do the given extents have this aspect ratio?
+    pub fn matches(&self, extents: &Point) -> bool {
+        self.deviation(extents) <= Self::TOLERANCE
+    }
+}
+
+impl std::fmt::Display for AspectRatio {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)
+    }
+}
+
+impl std::str::FromStr for AspectRatio {
+    type Err = String;
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid aspect ratio `{}`, expected the form `W:H`, such as `16:9`.",
+                str
+            )
+        };
+        let (width, height) = str.split_once(':').ok_or_else(invalid)?;
+        let number = |str: &str| {
+            str.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(invalid)
+        };
+        Ok(AspectRatio {
+            width: number(width)?,
+            height: number(height)?,
+        })
+    }
+}
+
+impl Serialize for AspectRatio {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AspectRatio {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let str = String::deserialize(deserializer)?;
+        str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// An inclusive range of refresh frequencies, either bound of which may be
+/// absent.  It is written as a string, such as `">=120"`, `"<=60"`, or
+/// `"60-120"`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub struct FrequencyRange {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl FrequencyRange {
+    /// The range containing only the given frequency.
+    pub fn exactly(frequency: usize) -> Self {
+        FrequencyRange {
+            min: Some(frequency),
+            max: Some(frequency),
+        }
+    }
+
+    /// The distance from the given frequency to the nearest frequency in the
+    /// range, which is zero if it lies in the range.
+    pub fn distance(&self, frequency: usize) -> u64 {
+        range_distance(
+            self.min.map(|min| min as i64),
+            self.max.map(|max| max as i64),
+            frequency as i64,
+        )
+    }
+
+    /// Does the given frequency lie in the range?
+    pub fn contains(&self, frequency: usize) -> bool {
+        self.distance(frequency) == 0
+    }
+}
+
+impl std::fmt::Display for FrequencyRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, "{}-{}", min, max),
+            (Some(min), None) => write!(f, ">={}", min),
+            (None, Some(max)) => write!(f, "<={}", max),
+            (None, None) => write!(f, ">=0"),
+        }
+    }
+}
+
+impl std::str::FromStr for FrequencyRange {
+    type Err = String;
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid frequency range `{}`, expected one of `>=N`, `<=N`, or `N-M`.",
+                str
+            )
+        };
+        let number = |str: &str| str.trim().parse::<usize>().map_err(|_| invalid());
+        let str = str.trim();
+        let range = if let Some(min) = str.strip_prefix(">=") {
+            FrequencyRange {
+                min: Some(number(min)?),
+                max: None,
+            }
+        } else if let Some(max) = str.strip_prefix("<=") {
+            FrequencyRange {
+                min: None,
+                max: Some(number(max)?),
+            }
+        } else if let Some((min, max)) = str.split_once('-') {
+            FrequencyRange {
+                min: Some(number(min)?),
+                max: Some(number(max)?),
+            }
+        } else {
+            return Err(invalid());
+        };
+        match range {
+            FrequencyRange {
+                min: Some(min),
+                max: Some(max),
+            } if min > max => Err(format!(
+                "Invalid frequency range `{}`, the minimum is greater than the maximum.",
+                str
+            )),
+            _ => Ok(range),
+        }
+    }
+}
+
+impl Serialize for FrequencyRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FrequencyRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let str = String::deserialize(deserializer)?;
+        str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The scale factor of a display mode.  This is the ratio of backing pixels
+/// to points along each axis, so `1.0` is unscaled and `2.0` is a typical
+/// "Retina" mode.  As scale factors are only ever small values with few
+/// significant digits, they are compared to the nearest hundredth so that
+/// they may be used for equality and hashing.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Scale(pub f64);
+
+impl Scale {
+    /// Helper to obtain the rounded representation used for comparisons.
+    fn hundredths(&self) -> i64 {
+        (self.0 * 100.0).round() as i64
+    }
+
+    /// Is this a scale factor a display mode could have?
+    pub fn is_valid(&self) -> bool {
+        self.0.is_finite() && self.0 > 0.0
+    }
+}
+
+impl PartialEq for Scale {
+    fn eq(&self, other: &Self) -> bool {
+        self.hundredths() == other.hundredths()
+    }
+}
+
+impl Eq for Scale {}
+
+impl Hash for Scale {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hundredths().hash(state)
+    }
+}
+
+impl std::fmt::Display for Scale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The amount of underscan applied to a display, as a fraction between
+/// `0.0` (no underscan) and `1.0` (maximum underscan).  This is primarily
+/// useful for televisions connected over HDMI that would otherwise crop the
+/// edges of the desktop.  Like `Scale`, values are compared to the nearest
+/// hundredth.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Underscan(pub f64);
+
+impl Underscan {
+    /// Helper to obtain the rounded representation used for comparisons.
+    fn hundredths(&self) -> i64 {
+        (self.0 * 100.0).round() as i64
+    }
+
+    /// Is this underscan amount within the allowed range?
+    pub fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.0)
+    }
+}
+
+impl PartialEq for Underscan {
+    fn eq(&self, other: &Self) -> bool {
+        self.hundredths() == other.hundredths()
+    }
+}
+
+impl Eq for Underscan {}
+
+impl Hash for Underscan {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hundredths().hash(state)
+    }
+}
+
+impl std::fmt::Display for Underscan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Debug, Clone)]
@@ -106,6 +442,13 @@ pub enum Error {
     /// system.
     /// The argument is the error message.
     Internal(String),
+    /// Reported when committing a configuration transaction did not complete
+    /// in time.  The commit is abandoned rather than cancelled, so its
+    /// changes may still be applied.  The argument is how long was waited.
+    Timeout(std::time::Duration),
+    /// Reported when a configuration transaction is committed while an
+    /// earlier commit that timed out is still in progress.
+    CommitPending,
 }
 
 impl std::fmt::Display for Error {
@@ -137,6 +480,21 @@ impl std::fmt::Display for Error {
                 write!(f, "Lock poison error: {}", msg)
             }
             Error::Internal(msg) => write!(f, "{}", msg),
+            Error::Timeout(timeout) => {
+                write!(
+                    f,
+                    "Committing the display configuration did not complete within {}; \
+                    it was abandoned, but the changes may still be applied",
+                    humantime::format_duration(*timeout)
+                )
+            }
+            Error::CommitPending => {
+                write!(
+                    f,
+                    "An abandoned commit of the display configuration is still in \
+                    progress, and its changes may still be applied"
+                )
+            }
         }
     }
 }
@@ -156,9 +514,14 @@ impl std::error::Error for Error {
 /// it might be possible to static prevent confusing `DisplayMode`s in
 /// this fashion using something like path dependent types.  In the
 /// meantime, this is dynamically checked.
-pub trait DisplayMode: Clone + std::fmt::Debug + Serialize {
-    /// Return whether the display mode is scaled (2x rather than 1x).
-    fn scaled(&self) -> bool;
+pub trait DisplayMode: Clone + std::fmt::Debug + Serialize + Send + Sync + 'static {
+    /// Return the scale factor of the display mode.
+    fn scale(&self) -> Scale;
+    /// Return whether the display mode is scaled (for example 2x rather
+    /// than 1x).
+    fn scaled(&self) -> bool {
+        self.scale().0 > 1.0
+    }
     /// Return the color depth of the display mode in bits.
     fn color_depth(&self) -> usize;
     /// Return the refresh frequency of the display mode in Hertz.
@@ -169,30 +532,363 @@ pub trait DisplayMode: Clone + std::fmt::Debug + Serialize {
     /// always correspond to the resolution of the display in landscape
     /// orientation.
     fn extents(&self) -> &Point;
+    /// Return whether the display mode is usable for the desktop GUI.  Modes
+    /// that are not may still be selected, but may not display correctly.
+    fn usable_for_desktop(&self) -> bool;
+    /// Return the platform specific number identifying this display mode,
+    /// if there is one.  Only intended for diagnostic purposes.
+    fn mode_number(&self) -> Option<i32> {
+        None
+    }
+    /// Return the platform specific flags describing this display mode,
+    /// if there are any.  Only intended for diagnostic purposes.
+    fn io_flags(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the display mode resolution in backing pixels.  For scaled
+    /// modes this will be larger than the `extents`.  Like `extents`,
+    /// this is normalized independent of display rotation.  By default,
+    /// this is approximated from the `extents` and the `scale`, but
+    /// implementations should override it if the platform can report the
+    /// actual backing resolution.
+    fn pixel_extents(&self) -> Point {
+        let factor = self.scale().0;
+        Point {
+            x: (self.extents().x as f64 * factor).round() as i64,
+            y: (self.extents().y as f64 * factor).round() as i64,
+        }
+    }
+
+    /// Returns the resolution a scaled display mode looks like, in the way
+    /// System Settings describes it, computed from the backing pixels and
+    /// the scale factor.  Unscaled display modes look like their backing
+    /// resolution, so there is nothing to report for them.
+    fn looks_like(&self) -> Option<Point> {
+        if !self.scaled() {
+            return None;
+        }
+        let factor = self.scale().0;
+        let pixel_extents = self.pixel_extents();
+        Some(Point {
+            x: (pixel_extents.x as f64 / factor).round() as i64,
+            y: (pixel_extents.y as f64 / factor).round() as i64,
+        })
+    }
+
+    /// Check whether this is the same display mode as the other.  If both
+    /// have platform specific mode numbers, those are compared, otherwise
+    /// their observable properties are compared.
+    fn is_same_mode(&self, other: &Self) -> bool {
+        match (self.mode_number(), other.mode_number()) {
+            (Some(n1), Some(n2)) => n1 == n2,
+            _ => self.has_same_properties(other),
+        }
+    }
+
+    /// Check whether this display mode has the same observable properties
+    /// as the other, regardless of any platform specific mode numbers.
+    fn has_same_properties(&self, other: &Self) -> bool {
+        self.scale() == other.scale()
+            && self.color_depth() == other.color_depth()
+            && self.frequency() == other.frequency()
+            && self.extents() == other.extents()
+            && self.pixel_extents() == other.pixel_extents()
+    }
 
     /// Check whether this display mode matches the given pattern.
     fn match_pattern(&self, pattern: &DisplayModePattern) -> bool {
         pattern.scaled.iter().all(|&s| s == self.scaled())
+            && pattern.scale.iter().all(|&s| s == self.scale())
             && pattern.color_depth.iter().all(|&d| d == self.color_depth())
-            && pattern.frequency.iter().all(|&f| f == self.frequency())
-            && pattern.extents.iter().all(|p| p == self.extents())
+            && pattern
+                .frequency
+                .iter()
+                .all(|f| f.contains(self.frequency()))
+            && pattern.extents.iter().all(|e| e.contains(self.extents()))
+            && pattern
+                .aspect_ratio
+                .iter()
+                .all(|r| r.matches(self.extents()))
+            && pattern
+                .pixel_extents
+                .iter()
+                .all(|p| p.contains(&self.pixel_extents()))
     }
+
+    /// Compute how far this display mode is from matching the given pattern.
+    /// A distance of `ModeDistance::default()` means the mode matches.
+    fn pattern_distance(&self, pattern: &DisplayModePattern) -> ModeDistance {
+        ModeDistance {
+            scaled: pattern.scaled.map_or(0, |s| (s != self.scaled()) as u64)
+                + pattern.scale.map_or(0, |s| (s != self.scale()) as u64),
+            aspect_ratio: pattern
+                .aspect_ratio
+                .map_or(0, |r| (!r.matches(self.extents())) as u64),
+            extents: pattern
+                .extents
+                .as_ref()
+                .map_or(0, |e| e.distance(self.extents()))
+                + pattern
+                    .pixel_extents
+                    .as_ref()
+                    .map_or(0, |p| p.distance(&self.pixel_extents())),
+            frequency: pattern
+                .frequency
+                .map_or(0, |f| f.distance(self.frequency())),
+            color_depth: pattern
+                .color_depth
+                .map_or(0, |d| d.abs_diff(self.color_depth()) as u64),
+        }
+    }
+}
+
+/// A measure of how closely a display mode matches a `DisplayModePattern`.
+/// Distances are compared lexicographically in the order of the fields: a
+/// mode with the requested scaling is always closer than one without, then
+/// one with the requested aspect ratio, followed by the resolution that is
+/// nearest, the refresh frequency that is nearest, and finally the color
+/// depth that is nearest.
+#[derive(Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct ModeDistance {
+    /// The number of ways in which the scaling differs from the pattern.
+    pub scaled: u64,
+    /// Whether the shape differs from the aspect ratio of the pattern.
+    pub aspect_ratio: u64,
+    /// The squared Euclidean distance between the extents, and between
+    /// the pixel extents.
+    pub extents: u64,
+    /// The absolute difference in refresh frequency.
+    pub frequency: u64,
+    /// The absolute difference in color depth.
+    pub color_depth: u64,
 }
 
 /// A `DisplayModePattern` specifies a space of possible `DisplayModes`.
-// TODO We could consider extending the constraints allowed by patterns,
-//   but it would eliminate some of the symmetry between the input and output
-//   formats.
 #[derive(Debug, Clone)]
 pub struct DisplayModePattern {
     /// Should this pattern match on whether the display mode is scaled?
     pub scaled: Option<bool>,
+    /// Should this pattern match on the scale factor of the display mode?
+    pub scale: Option<Scale>,
     /// Should the pattern match on the color depth of the display mode?
     pub color_depth: Option<usize>,
     /// Should this pattern match on the frequency of the display mode?
-    pub frequency: Option<usize>,
+    pub frequency: Option<FrequencyRange>,
     /// Should the pattern match on the resolution of the display mode?
-    pub extents: Option<Point>,
+    pub extents: Option<ExtentsRange>,
+    /// Should the pattern match on the shape of the display mode?
+    pub aspect_ratio: Option<AspectRatio>,
+    /// Should the pattern match on the resolution of the display mode in
+    /// backing pixels?
+    pub pixel_extents: Option<ExtentsRange>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A `ModePreference` is a policy for choosing amongst several display modes
+/// that all match the same `DisplayModePattern`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum ModePreference {
+    /// Prefer the modes with the highest refresh frequency.
+    HighestFrequency,
+    /// Prefer the modes with the largest number of pixels.
+    HighestResolution,
+    /// Prefer the modes with the greatest color depth.
+    HighestColorDepth,
+    /// Prefer scaled modes over unscaled modes.
+    ScaledFirst,
+    /// Prefer unscaled modes over scaled modes.
+    UnscaledFirst,
+}
+
+impl ModePreference {
+    /// Constant containing all the possible `ModePreference` values.
+    pub const VALUES: [ModePreference; 5] = {
+        use ModePreference::*;
+        [
+            HighestFrequency,
+            HighestResolution,
+            HighestColorDepth,
+            ScaledFirst,
+            UnscaledFirst,
+        ]
+    };
+
+    /// The name used to refer to this preference on the command-line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModePreference::HighestFrequency => "highest-frequency",
+            ModePreference::HighestResolution => "highest-resolution",
+            ModePreference::HighestColorDepth => "highest-color-depth",
+            ModePreference::ScaledFirst => "scaled-first",
+            ModePreference::UnscaledFirst => "unscaled-first",
+        }
+    }
+
+    /// Helper to compute the value this preference will attempt to maximize
+    /// for the given display mode.
+    fn key<DM: DisplayMode>(&self, mode: &DM) -> i64 {
+        match self {
+            ModePreference::HighestFrequency => mode.frequency() as i64,
+            ModePreference::HighestResolution => mode.extents().x * mode.extents().y,
+            ModePreference::HighestColorDepth => mode.color_depth() as i64,
+            ModePreference::ScaledFirst => mode.scaled() as i64,
+            ModePreference::UnscaledFirst => !mode.scaled() as i64,
+        }
+    }
+
+    /// Narrow the given display modes down to those that are the most
+    /// preferred according to this policy.  Ties are all retained.
+    pub fn narrow<DM: DisplayMode>(&self, modes: Vec<DM>) -> Vec<DM> {
+        match modes.iter().map(|m| self.key(m)).max() {
+            Some(best) => modes.into_iter().filter(|m| self.key(m) == best).collect(),
+            None => modes,
+        }
+    }
+}
+
+impl TryFrom<&str> for ModePreference {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ModePreference::VALUES
+            .into_iter()
+            .find(|preference| preference.name() == value)
+            .ok_or(format!("{} is not a known mode preference.", value))
+    }
+}
+
+impl std::fmt::Display for ModePreference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A `DuplicateModes` is a policy for handling display modes that have
+/// identical observable properties.  Some displays report several such
+/// modes, differing only in their platform specific mode numbers.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DuplicateModes {
+    /// Keep only the first of the duplicates, in the order the platform
+    /// reports them.
+    #[default]
+    KeepFirst,
+    /// Keep only the duplicate with the highest mode number.
+    KeepHighestModeId,
+    /// Keep all the duplicates, which must then be told apart by their
+    /// mode numbers.
+    All,
+}
+
+impl DuplicateModes {
+    /// Constant containing all the possible `DuplicateModes` values.
+    pub const VALUES: [DuplicateModes; 3] = {
+        use DuplicateModes::*;
+        [KeepFirst, KeepHighestModeId, All]
+    };
+
+    /// The name used to refer to this policy on the command-line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicateModes::KeepFirst => "keep-first",
+            DuplicateModes::KeepHighestModeId => "keep-highest-mode-id",
+            DuplicateModes::All => "all",
+        }
+    }
+
+    /// Remove the duplicates from the given display modes according to this
+    /// policy.  The order of the remaining modes is preserved.
+    pub fn dedup<DM: DisplayMode>(&self, modes: Vec<DM>) -> Vec<DM> {
+        if *self == DuplicateModes::All {
+            return modes;
+        }
+        let keep = |index: usize, mode: &DM| {
+            modes.iter().enumerate().all(|(other_index, other)| {
+                other_index == index
+                    || !mode.has_same_properties(other)
+                    || match self {
+                        DuplicateModes::KeepHighestModeId => {
+                            (mode.mode_number(), other_index) > (other.mode_number(), index)
+                        }
+                        _ => index < other_index,
+                    }
+            })
+        };
+        let kept: Vec<bool> = modes
+            .iter()
+            .enumerate()
+            .map(|(index, mode)| keep(index, mode))
+            .collect();
+        modes
+            .into_iter()
+            .zip(kept)
+            .filter_map(|(mode, keep)| keep.then_some(mode))
+            .collect()
+    }
+}
+
+impl TryFrom<&str> for DuplicateModes {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        DuplicateModes::VALUES
+            .into_iter()
+            .find(|policy| policy.name() == value)
+            .ok_or(format!("{} is not a known duplicate mode policy.", value))
+    }
+}
+
+impl std::fmt::Display for DuplicateModes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The kind of device a display is.  Displays other than physical monitors
+/// may need different handling, for example Sidecar and AirPlay displays do
+/// not support adjusting their brightness, and do not keep the same UUID.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayKind {
+    /// A monitor attached to the computer, including a built-in display.
+    #[default]
+    Physical,
+    /// An iPad being used as a display via Sidecar.
+    Sidecar,
+    /// A display being streamed to via AirPlay.
+    AirPlay,
+    /// Any other display without a physical monitor, such as one created
+    /// by `knoll virtual create`.
+    Virtual,
+}
+
+impl std::fmt::Display for DisplayKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DisplayKind::Physical => "physical",
+            DisplayKind::Sidecar => "sidecar",
+            DisplayKind::AirPlay => "airplay",
+            DisplayKind::Virtual => "virtual",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How a display participates in a mirror set, where several displays show
+/// the same content.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mirroring {
+    /// The display is the primary display of the mirror set, whose content
+    /// the other displays in the set show.
+    Primary,
+    /// The display shows the content of the display with the given UUID.
+    MirrorOf(String),
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -202,9 +898,8 @@ pub trait Display: std::fmt::Debug {
     /// Obtain the UUID of this display.
     fn uuid(&self) -> &str;
 
-    /// Is this display enabled?  Currently, due to limitations of the APIs
-    /// currently used, this will always be true as disabled displays will
-    /// not be reported as being attached.
+    /// Is this display enabled?  This will always be true, as disabled
+    /// displays are only reported by `DisplayState::disabled_displays`.
     fn enabled(&self) -> bool;
 
     /// Where is the upper left corner of this display located?
@@ -213,12 +908,36 @@ pub trait Display: std::fmt::Debug {
     /// What is the current rotation state of the display?
     fn rotation(&self) -> Rotation;
 
+    /// What is the current underscan of the display?  Returns `None` if the
+    /// display does not support adjusting its underscan.
+    fn underscan(&self) -> Option<Underscan>;
+
+    /// What kind of device is this display?
+    fn kind(&self) -> DisplayKind;
+
     /// The type of the display mode associated with this Display.
     // TODO Perhaps in the future we could more tightly couple this with
     //   something akin to path dependent types.  For now dynamically check
     //   that DisplayModes are not used with the incorrect display.
     type DisplayModeType: DisplayMode;
 
+    /// Return the platform specific number identifying this display, if
+    /// there is one.  Only intended for diagnostic purposes.
+    fn display_id(&self) -> Option<u32> {
+        None
+    }
+
+    /// What is the name of this display, if it has one?  This is intended
+    /// for people to recognize the display by, and need not be unique.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// How is this display participating in a mirror set, if at all?
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
     /// Obtain the currently configured. display mode.
     fn current_mode(&self) -> &Self::DisplayModeType;
 
@@ -239,10 +958,80 @@ pub trait Display: std::fmt::Debug {
             })
             .collect()
     }
+
+    /// The native resolution of this display in backing pixels.  That is,
+    /// the largest resolution supported by any of its display modes.
+    fn native_pixel_extents(&self) -> Option<Point> {
+        self.possible_modes()
+            .iter()
+            .map(|m| m.pixel_extents())
+            .max_by_key(|p| p.x * p.y)
+    }
+
+    /// Helper to return those display modes for this display that are
+    /// closest to the provided pattern.  If any modes match the pattern
+    /// exactly, this is equivalent to `matching_modes`.
+    fn closest_modes(&self, pattern: &DisplayModePattern) -> Vec<Self::DisplayModeType> {
+        let best = self
+            .possible_modes()
+            .iter()
+            .map(|m| m.pattern_distance(pattern))
+            .min();
+        self.possible_modes()
+            .iter()
+            .filter(|m| Some(m.pattern_distance(pattern)) == best)
+            .cloned()
+            .collect()
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// How long changes committed by a `DisplayConfigTransaction` should last.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ConfigurationScope {
+    /// The changes are reverted when the process exits.
+    App,
+    /// The changes are reverted when the user logs out.
+    Session,
+    /// The changes persist, and become the user's display preferences.
+    #[default]
+    Permanent,
+}
+
+impl ConfigurationScope {
+    /// Constant containing all the possible `ConfigurationScope` values.
+    pub const VALUES: [ConfigurationScope; 3] = {
+        use ConfigurationScope::*;
+        [App, Session, Permanent]
+    };
+
+    /// The name used to refer to this scope on the command-line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfigurationScope::App => "app",
+            ConfigurationScope::Session => "session",
+            ConfigurationScope::Permanent => "permanent",
+        }
+    }
+}
+
+impl TryFrom<&str> for ConfigurationScope {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ConfigurationScope::VALUES
+            .into_iter()
+            .find(|scope| scope.name() == value)
+            .ok_or(format!("{} is not a known configuration scope.", value))
+    }
+}
+
+impl std::fmt::Display for ConfigurationScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 /// An abstraction for representing a configuration "transaction" that will
 /// update the overall state of the attached displays.
 ///
@@ -269,38 +1058,82 @@ pub trait DisplayConfigTransaction {
     /// Will return an error if there is no display with the given UUID.
     fn set_origin(&mut self, uuid: &str, point: &Point) -> Result<(), Error>;
 
-    /// Set the enablement state of the given display.  Given current API
-    /// limitations, once a display is disabled, and the configuration
-    /// completes, it will no longer register as attached.
+    /// Set the underscan of the given display.
+    /// Will return an error if there is no display with the given UUID.
+    fn set_underscan(&mut self, uuid: &str, underscan: Underscan) -> Result<(), Error>;
+
+    /// Set the enablement state of the given display.  Once a display is
+    /// disabled, and the configuration completes, it will no longer register
+    /// as attached, but it can be enabled again by its UUID.  Only whether a
+    /// disabled display is enabled can be configured.
     /// Will return an error if there is no display with the given UUID.
     fn set_enabled(&mut self, uuid: &str, enabled: bool) -> Result<(), Error>;
 
+    /// Fade the displays out to black and back in over the given duration
+    /// when the transaction is committed, rather than abruptly changing
+    /// them.  Implementations may shorten the fade, and fading is best
+    /// effort: failing to fade will not cause the commit to fail.
+    fn set_fade(&mut self, duration: std::time::Duration) -> Result<(), Error>;
+
     /// Attempt to apply the requested configuration changes and close out
-    /// the transaction.
-    fn commit(self) -> Result<(), Error>;
+    /// the transaction.  The scope determines how long the changes last.
+    fn commit(self, scope: ConfigurationScope) -> Result<(), Error>;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A description of a virtual display to create.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualDisplaySpec {
+    /// The name the display reports.
+    pub name: String,
+    /// The extents of the display in pixels.
+    pub extents: Point,
+    /// The pixel density of the display in pixels per inch.
+    pub ppi: u32,
+    /// The refresh frequency of the display in Hz.
+    pub frequency: usize,
+}
+
+/// A virtual display, which remains attached until it is dropped.
+pub trait VirtualDisplay: std::fmt::Debug {
+    /// The UUID of the virtual display, which may be used to configure it
+    /// like any other display.
+    fn uuid(&self) -> &str;
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
 /// An abstract representation of the currently attached displays.
-pub trait DisplayState: Sized {
+pub trait DisplayState: Sized + 'static {
     /// Obtain the current display state.
     fn current() -> Result<Self, Error>;
 
+    /// Obtain the current state of the environment the displays are
+    /// being used in.
+    fn environment() -> Environment;
+
     /// The type of display modes used by displays.
     type DisplayModeType: DisplayMode;
     /// The type of displays.  It must be the case that uses the same
     /// type for display modes as the `DisplayState` does.
     type DisplayType: Display<DisplayModeType = Self::DisplayModeType>;
     /// The type of a configuration transaction.  It must be the case that
-    /// uses the same type for display modes as the `DisplayState` does.
-    type DisplayConfigTransactionType: DisplayConfigTransaction<
-        DisplayModeType = Self::DisplayModeType,
-    >;
+    /// uses the same type for display modes as the `DisplayState` does.  It
+    /// must be possible to commit it from another thread.
+    type DisplayConfigTransactionType: DisplayConfigTransaction<DisplayModeType = Self::DisplayModeType>
+        + Send
+        + 'static;
+    /// The type of virtual displays.
+    type VirtualDisplayType: VirtualDisplay;
 
     /// Obtain a map of UUIDs to `Display`s
     fn get_displays(&self) -> &BTreeMap<String, Self::DisplayType>;
 
+    /// Obtain the UUIDs of the displays that are attached but disabled, and
+    /// so are not included in `get_displays`.
+    fn disabled_displays(&self) -> BTreeSet<String>;
+
     /// Obtain a configuration transaction that can be used to modify the
     /// state of attached displays.  Note that any changes applied when
     /// `complete()` is called on the resulting configuration transaction,
@@ -308,4 +1141,102 @@ pub trait DisplayState: Sized {
     /// changes it will be necessary to obtain the new state with
     /// `current()`.
     fn configure(&self) -> Result<Self::DisplayConfigTransactionType, Error>;
+
+    /// Create and attach a virtual display as described.  The display is
+    /// detached when the result is dropped, and does not outlive the process.
+    fn create_virtual_display(spec: &VirtualDisplaySpec)
+        -> Result<Self::VirtualDisplayType, Error>;
+
+    /// Put all displays to sleep, without changing their configuration.
+    /// The system itself remains awake.
+    fn sleep_displays() -> Result<(), Error>;
+
+    /// Wake all displays that were put to sleep.
+    fn wake_displays() -> Result<(), Error>;
+
+    /// Obtain the facts about the system used to diagnose why the displays
+    /// might not be configurable.
+    fn system_facts() -> crate::doctor::SystemFacts;
+
+    /// Register a function to be called whenever the display configuration
+    /// changes, such as when a display is attached or detached.  Prefer
+    /// `ReconfigurationCallback`, which removes the callback when dropped.
+    fn register_reconfiguration_callback(callback: extern "C" fn());
+
+    /// Remove a function registered with `register_reconfiguration_callback`.
+    fn remove_reconfiguration_callback(callback: extern "C" fn());
+
+    /// Run the event loop that delivers reconfiguration callbacks on the
+    /// current thread, until `stop_event_loop` is called.
+    fn run_event_loop();
+
+    /// Stop the event loop started by `run_event_loop`.  Safe to call from
+    /// any thread.
+    fn stop_event_loop();
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The number of `ReconfigurationCallback` handles for each callback that is
+/// currently registered, keyed by the type of display state and the address
+/// of the callback.
+static RECONFIGURATION_CALLBACKS: Mutex<Vec<(TypeId, usize, usize)>> = Mutex::new(Vec::new());
+
+/// A handle to a function registered to be called whenever the display
+/// configuration changes.  The function is removed when the handle is
+/// dropped.  The same function may be registered by several handles, such as
+/// by several daemons in the same process, in which case it is only
+/// registered once, and only removed once all of the handles are dropped.
+#[must_use = "the callback is removed when the handle is dropped"]
+pub struct ReconfigurationCallback<DS: DisplayState> {
+    callback: extern "C" fn(),
+    _display_state: PhantomData<DS>,
+}
+
+impl<DS: DisplayState> ReconfigurationCallback<DS> {
+    /// Register a function to be called whenever the display configuration
+    /// changes.
+    pub fn register(callback: extern "C" fn()) -> Self {
+        let key = (TypeId::of::<DS>(), callback as usize);
+        // A poisoned lock only means a panic occurred while updating the
+        // counts, which cannot leave them inconsistent.
+        let mut callbacks = RECONFIGURATION_CALLBACKS
+            .lock()
+            .unwrap_or_else(|pe| pe.into_inner());
+        match callbacks.iter_mut().find(|(t, c, _)| (*t, *c) == key) {
+            Some((_, _, count)) => *count += 1,
+            None => {
+                DS::register_reconfiguration_callback(callback);
+                callbacks.push((key.0, key.1, 1));
+            }
+        }
+        ReconfigurationCallback {
+            callback,
+            _display_state: PhantomData,
+        }
+    }
+}
+
+impl<DS: DisplayState> Drop for ReconfigurationCallback<DS> {
+    fn drop(&mut self) {
+        let key = (TypeId::of::<DS>(), self.callback as usize);
+        let mut callbacks = RECONFIGURATION_CALLBACKS
+            .lock()
+            .unwrap_or_else(|pe| pe.into_inner());
+        if let Some(index) = callbacks.iter().position(|(t, c, _)| (*t, *c) == key) {
+            callbacks[index].2 -= 1;
+            if callbacks[index].2 == 0 {
+                callbacks.remove(index);
+                DS::remove_reconfiguration_callback(self.callback);
+            }
+        }
+    }
+}
+
+impl<DS: DisplayState> std::fmt::Debug for ReconfigurationCallback<DS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconfigurationCallback")
+            .field("callback", &(self.callback as usize))
+            .finish()
+    }
 }
@@ -0,0 +1,313 @@
+//! This module diagnoses common reasons that knoll is unable to inspect or
+//! configure the displays, such as private APIs that are missing from the
+//! running version of macOS, or running in a session where the window
+//! server will not accept configuration changes.
+use coverage_helper::test;
+use serde::Serialize;
+
+/// The oldest major version of macOS that knoll is known to work with.
+pub const MINIMUM_MACOS_MAJOR_VERSION: u32 = 11;
+
+/// Facts about the system knoll is running on, which are examined to
+/// diagnose problems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemFacts {
+    /// The version of macOS, such as `14.5`, if it could be determined.
+    pub os_version: Option<String>,
+    /// Private symbols knoll cannot work without that did not resolve.
+    pub missing_required_symbols: Vec<String>,
+    /// Private symbols that only some features depend upon that did not
+    /// resolve.
+    pub missing_optional_symbols: Vec<String>,
+    /// Whether knoll is running from an SSH login.
+    pub ssh: bool,
+    /// Whether there is a window server session at all.
+    pub session: bool,
+    /// Whether the session is the one currently using the console.
+    pub on_console: bool,
+    /// Whether the screen is locked.
+    pub screen_locked: bool,
+}
+
+impl Default for SystemFacts {
+    /// The facts of a system on which nothing should prevent knoll from
+    /// working.
+    fn default() -> Self {
+        SystemFacts {
+            os_version: Some(format!("{}.0", MINIMUM_MACOS_MAJOR_VERSION)),
+            missing_required_symbols: Vec::new(),
+            missing_optional_symbols: Vec::new(),
+            ssh: false,
+            session: true,
+            on_console: true,
+            screen_locked: false,
+        }
+    }
+}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Nothing was found that would prevent knoll from working.
+    Ok,
+    /// Something was found that may prevent knoll from working, or that
+    /// disables some of its features.
+    Warning,
+    /// Something was found that will prevent knoll from working.
+    Error,
+}
+
+/// The result of checking for one possible cause of failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    /// A short name for what was checked.
+    pub name: &'static str,
+    pub status: Status,
+    /// A description of what was found.
+    pub message: String,
+    /// What could be done to resolve the problem, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advice: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Ok,
+            message: message.into(),
+            advice: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: Status,
+        message: impl Into<String>,
+        advice: impl Into<String>,
+    ) -> Self {
+        Check {
+            name,
+            status,
+            message: message.into(),
+            advice: Some(advice.into()),
+        }
+    }
+}
+
+/// The output of `knoll doctor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// The names of the checks that found a problem that will prevent knoll
+    /// from working.
+    pub fn errors(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == Status::Error)
+            .map(|check| check.name.to_owned())
+            .collect()
+    }
+}
+
+/// Check the given facts for common causes of failure.
+pub fn examine(facts: &SystemFacts) -> Report {
+    Report {
+        checks: vec![
+            check_os_version(facts),
+            check_symbols(facts),
+            check_ssh(facts),
+            check_session(facts),
+            check_screen_lock(facts),
+        ],
+    }
+}
+
+fn check_os_version(facts: &SystemFacts) -> Check {
+    const NAME: &str = "macos-version";
+    let Some(version) = &facts.os_version else {
+        return Check::problem(
+            NAME,
+            Status::Warning,
+            "The version of macOS could not be determined.",
+            format!(
+                "knoll requires macOS {} or later; check the version in System Settings.",
+                MINIMUM_MACOS_MAJOR_VERSION
+            ),
+        );
+    };
+    let major = version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok());
+    match major {
+        Some(major) if major >= MINIMUM_MACOS_MAJOR_VERSION => {
+            Check::ok(NAME, format!("macOS {} is supported.", version))
+        }
+        _ => Check::problem(
+            NAME,
+            Status::Warning,
+            format!("macOS {} is older than knoll supports.", version),
+            format!(
+                "Upgrade to macOS {} or later, as knoll may not work correctly.",
+                MINIMUM_MACOS_MAJOR_VERSION
+            ),
+        ),
+    }
+}
+
+fn check_symbols(facts: &SystemFacts) -> Check {
+    const NAME: &str = "private-symbols";
+    if !facts.missing_required_symbols.is_empty() {
+        Check::problem(
+            NAME,
+            Status::Error,
+            format!(
+                "Private symbols knoll requires are missing: {}.",
+                facts.missing_required_symbols.join(", ")
+            ),
+            "This version of macOS has changed its private display APIs; \
+            upgrade knoll, or report an issue including the version of macOS.",
+        )
+    } else if !facts.missing_optional_symbols.is_empty() {
+        Check::problem(
+            NAME,
+            Status::Warning,
+            format!(
+                "Private symbols some features rely upon are missing: {}.",
+                facts.missing_optional_symbols.join(", ")
+            ),
+            "Rotation, underscan, virtual displays, or re-enabling displays may be \
+            unavailable on this version of macOS.",
+        )
+    } else {
+        Check::ok(NAME, "All of the private symbols knoll uses resolved.")
+    }
+}
+
+fn check_ssh(facts: &SystemFacts) -> Check {
+    const NAME: &str = "ssh";
+    if facts.ssh {
+        Check::problem(
+            NAME,
+            Status::Warning,
+            "knoll is running from an SSH login.",
+            "The window server may refuse changes from an SSH login; run knoll from \
+            a terminal on the Mac itself, or as a launchd agent with `knoll service \
+            install`.",
+        )
+    } else {
+        Check::ok(NAME, "knoll is not running from an SSH login.")
+    }
+}
+
+fn check_session(facts: &SystemFacts) -> Check {
+    const NAME: &str = "session";
+    if !facts.session {
+        Check::problem(
+            NAME,
+            Status::Error,
+            "There is no window server session.",
+            "Log in to the Mac, and run knoll as the user logged in at the console.",
+        )
+    } else if !facts.on_console {
+        Check::problem(
+            NAME,
+            Status::Warning,
+            "The session is not the one using the console.",
+            "Switch to this user with fast user switching, as changes are only \
+            applied to the console session.",
+        )
+    } else {
+        Check::ok(NAME, "The session is using the console.")
+    }
+}
+
+fn check_screen_lock(facts: &SystemFacts) -> Check {
+    const NAME: &str = "screen-lock";
+    if facts.screen_locked {
+        Check::problem(
+            NAME,
+            Status::Warning,
+            "The screen is locked.",
+            "Unlock the screen, as macOS may ignore configuration changes while it \
+            is locked.",
+        )
+    } else {
+        Check::ok(NAME, "The screen is not locked.")
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test that a healthy system passes every check.
+#[test]
+fn test_examine_healthy() {
+    let report = examine(&SystemFacts::default());
+    assert!(report
+        .checks
+        .iter()
+        .all(|check| check.status == Status::Ok && check.advice.is_none()));
+    assert!(report.errors().is_empty());
+}
+
+/// Test that each problem is reported with advice, and that only problems
+/// that prevent knoll from working are errors.
+#[test]
+fn test_examine_problems() {
+    let status = |facts: SystemFacts, name: &str| {
+        let report = examine(&facts);
+        let check = report
+            .checks
+            .into_iter()
+            .find(|check| check.name == name)
+            .expect("Missing check");
+        assert!(check.advice.is_some());
+        check.status
+    };
+
+    for version in [None, Some("10.15.7".to_owned()), Some("unknown".to_owned())] {
+        let facts = SystemFacts {
+            os_version: version,
+            ..SystemFacts::default()
+        };
+        assert_eq!(status(facts, "macos-version"), Status::Warning);
+    }
+    let facts = SystemFacts {
+        missing_optional_symbols: vec!["CGSSetDisplayRotation".to_owned()],
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts, "private-symbols"), Status::Warning);
+    let facts = SystemFacts {
+        missing_required_symbols: vec!["CGSConfigureDisplayMode".to_owned()],
+        missing_optional_symbols: vec!["CGSSetDisplayRotation".to_owned()],
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts, "private-symbols"), Status::Error);
+    let facts = SystemFacts {
+        ssh: true,
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts, "ssh"), Status::Warning);
+    let facts = SystemFacts {
+        on_console: false,
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts, "session"), Status::Warning);
+    let facts = SystemFacts {
+        session: false,
+        on_console: false,
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts.clone(), "session"), Status::Error);
+    assert_eq!(examine(&facts).errors(), vec!["session".to_owned()]);
+    let facts = SystemFacts {
+        screen_locked: true,
+        ..SystemFacts::default()
+    };
+    assert_eq!(status(facts, "screen-lock"), Status::Warning);
+}
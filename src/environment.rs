@@ -0,0 +1,108 @@
+//! This module describes the environment in which the displays are being
+//! used, beyond the displays themselves, so that configuration groups can
+//! be made conditional upon it.
+use coverage_helper::test;
+
+use crate::config::{Conditions, LidState, PowerSource, TimeOfDay};
+
+/// The observed state of the environment.  Any aspect of the environment
+/// that could not be determined is `None`, and will never satisfy a
+/// condition upon it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// The state of the lid of the built-in display, if there is one.
+    pub lid: Option<LidState>,
+    /// The source of power for the computer.
+    pub power: Option<PowerSource>,
+    /// The SSID of the Wi-Fi network the computer is connected to.
+    pub ssid: Option<String>,
+    /// The local time of day.
+    pub time: Option<TimeOfDay>,
+}
+
+impl Environment {
+    /// Whether all of the given conditions hold in this environment.
+    pub fn satisfies(&self, conditions: &Conditions) -> bool {
+        (conditions.lid.is_none() || conditions.lid == self.lid)
+            && (conditions.power.is_none() || conditions.power == self.power)
+            && (conditions.ssid.is_none() || conditions.ssid == self.ssid)
+            && conditions
+                .between
+                .is_none_or(|range| self.time.is_some_and(|time| range.contains(time)))
+    }
+}
+
+/// Obtain the current local time of day.  Returns `None` if the local time
+/// could not be determined.
+pub fn local_time() -> Option<TimeOfDay> {
+    // SAFETY: time accepts a null pointer, and tm is a plain data type that
+    // is fully initialized by localtime_r when it succeeds.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        TimeOfDay::new(tm.tm_hour as u16, tm.tm_min as u16)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that conditions are only satisfied by a matching environment.
+#[test]
+fn test_environment_satisfies() {
+    use crate::config::TimeRange;
+
+    let closed = Environment {
+        lid: Some(LidState::Closed),
+        power: Some(PowerSource::Ac),
+        ssid: Some("OfficeNet".to_owned()),
+        time: TimeOfDay::new(22, 0),
+    };
+    let unknown = Environment::default();
+    let no_conditions = Conditions::default();
+    let lid_closed = Conditions {
+        lid: Some(LidState::Closed),
+        ..Conditions::default()
+    };
+    let lid_open = Conditions {
+        lid: Some(LidState::Open),
+        ..Conditions::default()
+    };
+    let closed_on_battery = Conditions {
+        lid: Some(LidState::Closed),
+        power: Some(PowerSource::Battery),
+        ..Conditions::default()
+    };
+    let at_office = Conditions {
+        ssid: Some("OfficeNet".to_owned()),
+        ..Conditions::default()
+    };
+    let at_home = Conditions {
+        ssid: Some("HomeNet".to_owned()),
+        ..Conditions::default()
+    };
+    let at_night = Conditions {
+        between: Some(TimeRange::try_from("20:00-07:00".to_owned()).unwrap()),
+        ..Conditions::default()
+    };
+    let during_day = Conditions {
+        between: Some(TimeRange::try_from("07:00-20:00".to_owned()).unwrap()),
+        ..Conditions::default()
+    };
+
+    assert!(closed.satisfies(&no_conditions));
+    assert!(closed.satisfies(&lid_closed));
+    assert!(!closed.satisfies(&lid_open));
+    assert!(!closed.satisfies(&closed_on_battery));
+    assert!(closed.satisfies(&at_office));
+    assert!(!closed.satisfies(&at_home));
+    assert!(!unknown.satisfies(&at_office));
+    assert!(closed.satisfies(&at_night));
+    assert!(!closed.satisfies(&during_day));
+    assert!(!unknown.satisfies(&at_night));
+    assert!(unknown.satisfies(&no_conditions));
+    assert!(!unknown.satisfies(&lid_closed));
+    assert!(!unknown.satisfies(&lid_open));
+}
@@ -0,0 +1,141 @@
+//! This module provides the structured event stream written by the daemon.
+//! Each event is written as a single line of JSON, so that other tools can
+//! follow changes to the displays by reading the output of the daemon.
+use coverage_helper::test;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::config::ConfigGroups;
+
+/// The kinds of events reported by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum EventKind {
+    /// A display was attached.
+    DisplayAdded { uuid: String },
+    /// A display was detached.
+    DisplayRemoved { uuid: String },
+    /// A configuration group was applied.
+    ConfigApplied {
+        /// The UUIDs of the configuration group that was applied.
+        uuids: Vec<String>,
+        /// The resulting display state.
+        state: ConfigGroups,
+    },
+    /// Reconfiguration failed.
+    Error { message: String },
+}
+
+/// An event, along with when it occurred.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    /// When the event occurred, in RFC 3339 format.
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl Event {
+    /// Construct an event that has just occurred.
+    pub fn now(kind: EventKind) -> Self {
+        Event {
+            timestamp: humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string(),
+            kind,
+        }
+    }
+}
+
+/// Writes events as newline delimited JSON.
+pub struct EventWriter {
+    output: Box<dyn Write + Send>,
+    /// The UUIDs of the displays that were attached when last checked, if
+    /// they have been checked.
+    displays: Option<BTreeSet<String>>,
+}
+
+impl EventWriter {
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        EventWriter {
+            output,
+            displays: None,
+        }
+    }
+
+    /// Write a single event, flushing it immediately so that readers see it
+    /// without delay.  The event is written all at once, so that it is not
+    /// interleaved with other output sharing the same destination.
+    pub fn write(&mut self, kind: EventKind) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(&Event::now(kind))?;
+        line.push(b'\n');
+        self.output.write_all(&line)?;
+        self.output.flush()
+    }
+
+    /// Write events for any displays that have been attached or detached
+    /// since the last call.  The first call only records the displays.
+    pub fn update_displays(&mut self, displays: BTreeSet<String>) -> std::io::Result<()> {
+        if let Some(previous) = self.displays.take() {
+            for uuid in displays.difference(&previous) {
+                self.write(EventKind::DisplayAdded { uuid: uuid.clone() })?;
+            }
+            for uuid in previous.difference(&displays) {
+                self.write(EventKind::DisplayRemoved { uuid: uuid.clone() })?;
+            }
+        }
+        self.displays = Some(displays);
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check the serialization of events, and that display changes are
+/// reported.
+#[test]
+fn test_event_writer() {
+    use std::sync::{Arc, Mutex};
+
+    /// Helper to capture the output of the writer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = Buffer::default();
+    let mut writer = EventWriter::new(Box::new(buffer.clone()));
+    let displays = |uuids: &[&str]| uuids.iter().map(|u| u.to_string()).collect();
+    writer
+        .update_displays(displays(&["a", "b"]))
+        .expect("Writing should not fail");
+    writer
+        .update_displays(displays(&["b", "c"]))
+        .expect("Writing should not fail");
+    writer
+        .write(EventKind::Error {
+            message: "failure".to_owned(),
+        })
+        .expect("Writing should not fail");
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let events: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Each line should be JSON"))
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["event"], "display-added");
+    assert_eq!(events[0]["uuid"], "c");
+    assert_eq!(events[1]["event"], "display-removed");
+    assert_eq!(events[1]["uuid"], "a");
+    assert_eq!(events[2]["event"], "error");
+    assert_eq!(events[2]["message"], "failure");
+    assert!(events[2]["timestamp"].is_string());
+}
@@ -0,0 +1,288 @@
+//! This module provides the expressions that may be used in place of a
+//! coordinate of a display's origin.  An expression is a sum of integers
+//! and references to the origin or extents of other displays in the same
+//! configuration group, such as `"${left.origin.x} + ${left.extents.x}"`.
+//! References are only evaluated once display modes have been selected, so
+//! that layouts adjust when the resolution of a display changes.
+use coverage_helper::test;
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The properties of a display that may be referenced.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum Property {
+    /// The extents of the display mode selected for the display, as
+    /// arranged on the desktop.  That is, accounting for any rotation.
+    Extents,
+    /// The origin of the display.
+    Origin,
+}
+
+impl Property {
+    /// The name used in expressions.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Property::Extents => "extents",
+            Property::Origin => "origin",
+        }
+    }
+}
+
+/// The axes along which a property may be referenced.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+    /// The name used in expressions.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+        }
+    }
+}
+
+/// A reference to one coordinate of a property of a display, written as
+/// `${display.property.axis}`.  The display is named by the same UUID,
+/// UUID prefix, or alias used for it in the configuration group.
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+pub struct Reference {
+    pub display: String,
+    pub property: Property,
+    pub axis: Axis,
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "${{{}.{}.{}}}",
+            self.display,
+            self.property.name(),
+            self.axis.name()
+        )
+    }
+}
+
+/// A single value summed by an expression.
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+pub enum Operand {
+    Constant(i64),
+    Reference(Reference),
+}
+
+/// An operand of an expression, along with whether it is subtracted.
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+pub struct Term {
+    pub negated: bool,
+    pub operand: Operand,
+}
+
+/// A sum of constants and references to other displays.  It is written as a
+/// string, such as `"${left.extents.x}"` or `"-${right.extents.x} - 10"`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+pub struct Expression {
+    pub terms: Vec<Term>,
+}
+
+impl Expression {
+    /// The references in the expression, in order.
+    pub fn references(&self) -> impl Iterator<Item = &Reference> {
+        self.terms.iter().filter_map(|term| match &term.operand {
+            Operand::Reference(reference) => Some(reference),
+            Operand::Constant(_) => None,
+        })
+    }
+
+    /// The references in the expression, in order, so that the displays
+    /// they name can be replaced.
+    pub fn references_mut(&mut self) -> impl Iterator<Item = &mut Reference> {
+        self.terms
+            .iter_mut()
+            .filter_map(|term| match &mut term.operand {
+                Operand::Reference(reference) => Some(reference),
+                Operand::Constant(_) => None,
+            })
+    }
+
+    /// Evaluate the expression, using `lookup` to obtain the value of each
+    /// reference.  If any reference has no value, neither does the
+    /// expression.
+    pub fn evaluate(&self, mut lookup: impl FnMut(&Reference) -> Option<i64>) -> Option<i64> {
+        self.terms.iter().try_fold(0i64, |sum, term| {
+            let value = match &term.operand {
+                Operand::Constant(constant) => *constant,
+                Operand::Reference(reference) => lookup(reference)?,
+            };
+            Some(if term.negated {
+                sum - value
+            } else {
+                sum + value
+            })
+        })
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, term) in self.terms.iter().enumerate() {
+            match (index, term.negated) {
+                (0, false) => {}
+                (0, true) => write!(f, "-")?,
+                (_, false) => write!(f, " + ")?,
+                (_, true) => write!(f, " - ")?,
+            }
+            match &term.operand {
+                Operand::Constant(constant) => write!(f, "{}", constant)?,
+                Operand::Reference(reference) => write!(f, "{}", reference)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Helper to parse the contents of a reference, between the braces.
+fn parse_reference(str: &str) -> Option<Reference> {
+    // Aliases may themselves contain periods, so split from the end.
+    let mut parts = str.trim().rsplitn(3, '.');
+    let axis = match parts.next()? {
+        "x" => Axis::X,
+        "y" => Axis::Y,
+        _ => return None,
+    };
+    let property = match parts.next()? {
+        "extents" => Property::Extents,
+        "origin" => Property::Origin,
+        _ => return None,
+    };
+    let display = parts.next()?;
+    if display.is_empty() || display.chars().any(|c| c.is_whitespace() || c == '}') {
+        return None;
+    }
+    Some(Reference {
+        display: display.to_owned(),
+        property,
+        axis,
+    })
+}
+
+impl std::str::FromStr for Expression {
+    type Err = String;
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid expression `{}`, expected a sum of integers and references \
+                such as `${{display.extents.x}}`.",
+                str
+            )
+        };
+        let mut terms = Vec::new();
+        let mut rest = str.trim_start();
+        loop {
+            // Only the first term may omit an operator.
+            let negated = if let Some(after) = rest.strip_prefix('-') {
+                rest = after.trim_start();
+                true
+            } else if let Some(after) = rest.strip_prefix('+') {
+                rest = after.trim_start();
+                false
+            } else if terms.is_empty() {
+                false
+            } else {
+                return Err(invalid());
+            };
+            let operand = if let Some(after) = rest.strip_prefix("${") {
+                let (reference, after) = after.split_once('}').ok_or_else(invalid)?;
+                rest = after;
+                Operand::Reference(parse_reference(reference).ok_or_else(invalid)?)
+            } else {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let (digits, after) = rest.split_at(end);
+                rest = after;
+                Operand::Constant(digits.parse().map_err(|_| invalid())?)
+            };
+            terms.push(Term { negated, operand });
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return Ok(Expression { terms });
+            }
+        }
+    }
+}
+
+impl Serialize for Expression {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expression {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let str = String::deserialize(deserializer)?;
+        str.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test parsing, printing, and evaluating expressions.
+#[test]
+fn test_expression() {
+    let expression: Expression = "${left.origin.x} + ${left.extents.x}-10"
+        .parse()
+        .expect("Expression should parse");
+    assert_eq!(
+        expression.to_string(),
+        "${left.origin.x} + ${left.extents.x} - 10"
+    );
+    assert_eq!(
+        expression.references().collect::<Vec<_>>(),
+        vec![
+            &Reference {
+                display: "left".to_owned(),
+                property: Property::Origin,
+                axis: Axis::X,
+            },
+            &Reference {
+                display: "left".to_owned(),
+                property: Property::Extents,
+                axis: Axis::X,
+            },
+        ]
+    );
+    let value = |reference: &Reference| match reference.property {
+        Property::Origin => Some(100),
+        Property::Extents => Some(1920),
+    };
+    assert_eq!(expression.evaluate(value), Some(2010));
+    assert_eq!(expression.evaluate(|_| None), None);
+
+    // Aliases may contain periods, and the first term may be negated.
+    let expression: Expression = " -${my.tv.extents.y} ".parse().unwrap();
+    assert_eq!(expression.to_string(), "-${my.tv.extents.y}");
+    assert_eq!(expression.evaluate(|_| Some(1080)), Some(-1080));
+
+    for invalid in [
+        "",
+        "${left.extents}",
+        "${left.size.x}",
+        "${.extents.x}",
+        "${left.extents.x",
+        "${left.extents.x} 10",
+        "10 +",
+        "ten",
+    ] {
+        assert!(
+            invalid.parse::<Expression>().is_err(),
+            "`{}` should not parse",
+            invalid
+        );
+    }
+}
@@ -1,25 +1,35 @@
 /// allow testing various aspects of knoll independent of the displays
 /// actually attached to the computer.
-use once_cell::sync::Lazy;
-use serde::Serialize;
-use std::collections::BTreeMap;
-use std::sync::Mutex;
+use coverage_helper::test;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
 
 use crate::displays::*;
+use crate::doctor::SystemFacts;
+use crate::environment::Environment;
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize)]
 pub struct FakeDisplayMode {
     #[serde(skip_serializing)]
     pub uuid: String,
-    pub scaled: bool,
+    pub scale: Scale,
     pub color_depth: usize,
     pub frequency: usize,
     pub extents: Point,
+    pub pixel_extents: Point,
+    #[serde(skip_serializing)]
+    pub usable_for_desktop: bool,
+    /// Stands in for the platform specific mode number.  This is the index
+    /// of the mode amongst the modes of its display.
+    #[serde(skip_serializing)]
+    pub mode_id: i32,
 }
 
 impl DisplayMode for FakeDisplayMode {
-    fn scaled(&self) -> bool {
-        self.scaled
+    fn scale(&self) -> Scale {
+        self.scale
     }
 
     fn color_depth(&self) -> usize {
@@ -33,6 +43,82 @@ impl DisplayMode for FakeDisplayMode {
     fn extents(&self) -> &Point {
         &self.extents
     }
+
+    fn pixel_extents(&self) -> Point {
+        self.pixel_extents.clone()
+    }
+
+    fn usable_for_desktop(&self) -> bool {
+        self.usable_for_desktop
+    }
+
+    fn mode_number(&self) -> Option<i32> {
+        Some(self.mode_id)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The operations on the fake displays that can be made to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FakeOperation {
+    Current,
+    Configure,
+    SetMode,
+    SetRotation,
+    SetOrigin,
+    SetUnderscan,
+    SetEnabled,
+    Commit,
+    CreateVirtualDisplay,
+    SleepDisplays,
+    WakeDisplays,
+}
+
+/// A failure to inject into an operation on the fake displays, so that
+/// knoll's handling of errors can be exercised deterministically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InjectedFailure {
+    pub operation: FakeOperation,
+    /// Only fail the operation for this display, or for any display if
+    /// `None`.  Operations that do not involve a specific display only fail
+    /// if this is `None`.  A commit involves every display with an edit.
+    pub uuid: Option<String>,
+    /// How many times the operation should fail before succeeding again, or
+    /// `None` to fail until the failure is cleared.
+    pub times: Option<usize>,
+}
+
+/// The scope of the most recent commit, if any.
+static LAST_COMMIT_SCOPE: Mutex<Option<ConfigurationScope>> = Mutex::new(None);
+
+/// The failures that are currently being injected.
+static INJECTED_FAILURES: Mutex<Vec<InjectedFailure>> = Mutex::new(Vec::new());
+
+/// Helper to determine whether an operation should fail, counting down the
+/// remaining failures if so.
+fn check_injected_failure(operation: FakeOperation, uuid: Option<&str>) -> Result<(), Error> {
+    let mut failures = INJECTED_FAILURES
+        .lock()
+        .map_err(|pe| Error::Poisoned(format!("{}", pe)))?;
+    let Some(index) = failures.iter().position(|failure| {
+        failure.operation == operation
+            && failure.times != Some(0)
+            && (failure.uuid.is_none() || failure.uuid.as_deref() == uuid)
+    }) else {
+        return Ok(());
+    };
+    match &mut failures[index].times {
+        Some(1) => {
+            failures.remove(index);
+        }
+        Some(times) => *times -= 1,
+        None => { /* No-op */ }
+    }
+    Err(Error::Internal(match uuid {
+        Some(uuid) => format!("Injected failure of {:?} for display {}", operation, uuid),
+        None => format!("Injected failure of {:?}", operation),
+    }))
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -42,22 +128,34 @@ enum FakeDisplayEdit {
     SetMode(FakeDisplayMode),
     SetRotation(Rotation),
     SetOrigin(Point),
+    SetUnderscan(Underscan),
     SetEnabled(bool),
 }
 
+impl FakeDisplayEdit {
+    /// The operation that requested the edit.
+    fn operation(&self) -> FakeOperation {
+        match self {
+            FakeDisplayEdit::SetMode(_) => FakeOperation::SetMode,
+            FakeDisplayEdit::SetRotation(_) => FakeOperation::SetRotation,
+            FakeDisplayEdit::SetOrigin(_) => FakeOperation::SetOrigin,
+            FakeDisplayEdit::SetUnderscan(_) => FakeOperation::SetUnderscan,
+            FakeDisplayEdit::SetEnabled(_) => FakeOperation::SetEnabled,
+        }
+    }
+}
+
 pub struct FakeDisplayConfigTransaction {
     dropped: bool,
     edit_map: BTreeMap<String, Vec<FakeDisplayEdit>>,
 }
 
 impl FakeDisplayConfigTransaction {
-    fn new(display_map: &BTreeMap<String, FakeDisplay>) -> Result<Self, Error> {
+    fn new<'l>(uuids: impl Iterator<Item = &'l String>) -> Result<Self, Error> {
+        check_injected_failure(FakeOperation::Configure, None)?;
         Ok(Self {
             dropped: false,
-            edit_map: display_map
-                .iter()
-                .map(|(uuid, _)| (uuid.clone(), Vec::new()))
-                .collect(),
+            edit_map: uuids.map(|uuid| (uuid.clone(), Vec::new())).collect(),
         })
     }
 
@@ -67,6 +165,7 @@ impl FakeDisplayConfigTransaction {
         if self.dropped {
             return Err(Error::InvalidTransactionState);
         }
+        check_injected_failure(edit.operation(), Some(uuid))?;
 
         match self.edit_map.get_mut(uuid) {
             Some(edits) => {
@@ -100,36 +199,76 @@ impl DisplayConfigTransaction for FakeDisplayConfigTransaction {
         self.record_edit(uuid, FakeDisplayEdit::SetOrigin(point.clone()))
     }
 
+    fn set_underscan(&mut self, uuid: &str, underscan: Underscan) -> Result<(), Error> {
+        self.record_edit(uuid, FakeDisplayEdit::SetUnderscan(underscan))
+    }
+
     fn set_enabled(&mut self, uuid: &str, enabled: bool) -> Result<(), Error> {
         self.record_edit(uuid, FakeDisplayEdit::SetEnabled(enabled))
     }
 
-    fn commit(mut self) -> Result<(), Error> {
+    /// The fake displays change instantaneously, so there is nothing to fade.
+    fn set_fade(&mut self, _duration: std::time::Duration) -> Result<(), Error> {
+        if self.dropped {
+            return Err(Error::InvalidTransactionState);
+        }
+        Ok(())
+    }
+
+    fn commit(mut self, scope: ConfigurationScope) -> Result<(), Error> {
         if self.dropped {
             return Err(Error::InvalidTransactionState);
         }
         self.dropped = true;
+        let delay = COMMIT_DELAY.lock().ok().and_then(|delay| *delay);
+        if let Some(delay) = delay {
+            std::thread::sleep(delay);
+        }
+        if let Ok(mut last_scope) = LAST_COMMIT_SCOPE.lock() {
+            *last_scope = Some(scope);
+        }
 
-        let mut guard = CURRENT_FAKE_DISPLAYS
-            .lock()
-            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?;
-
-        // Iterate through the recorded edits applying them.
-        while let Some((uuid, edits)) = self.edit_map.pop_first() {
-            match guard.get_mut(&uuid) {
-                Some(display) => {
-                    for edit in edits {
-                        display.apply_edit(edit);
+        // Check for failures before applying any edits, so that a failed
+        // commit leaves the displays unchanged.
+        let edited: Vec<&String> = self
+            .edit_map
+            .iter()
+            .filter(|(_, edits)| !edits.is_empty())
+            .map(|(uuid, _)| uuid)
+            .collect();
+        if edited.is_empty() {
+            check_injected_failure(FakeOperation::Commit, None)?;
+        }
+        for uuid in edited {
+            check_injected_failure(FakeOperation::Commit, Some(uuid))?;
+        }
+
+        // Simulate macOS reporting success while silently discarding the
+        // requested changes.
+        if IGNORE_COMMITS.load(std::sync::atomic::Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        with_fake_displays(|displays| {
+            // Iterate through the recorded edits applying them.
+            while let Some((uuid, edits)) = self.edit_map.pop_first() {
+                match displays.get_mut(&uuid) {
+                    Some(display) => {
+                        for edit in edits {
+                            display.apply_edit(edit);
+                        }
+                    }
+                    None => {
+                        self.dropped = true;
+                        return Err(Error::UnknownUUID(uuid));
                     }
-                }
-                None => {
-                    self.dropped = true;
-                    return Err(Error::UnknownUUID(uuid));
                 }
             }
-        }
-
-        Ok(())
+            if NORMALIZE_ORIGINS.load(std::sync::atomic::Ordering::SeqCst) {
+                normalize_origins(displays);
+            }
+            Ok(())
+        })?
     }
 }
 
@@ -149,6 +288,15 @@ pub struct FakeDisplay {
     enabled: bool,
     origin: Point,
     rotation: Rotation,
+    /// `None` if the fake display does not support underscan.
+    underscan: Option<Underscan>,
+    kind: DisplayKind,
+    name: Option<String>,
+    /// The UUID of the display this display mirrors, if any.
+    mirror_of: Option<String>,
+    /// Whether another display mirrors this display.  Only computed when
+    /// obtaining the current state.
+    mirrored: bool,
     mode: FakeDisplayMode,
     modes: Vec<FakeDisplayMode>,
 }
@@ -170,6 +318,11 @@ impl FakeDisplay {
             FakeDisplayEdit::SetOrigin(origin) => {
                 self.origin = origin;
             }
+            FakeDisplayEdit::SetUnderscan(underscan) => {
+                // Check that the display actually supports underscan.
+                assert!(self.underscan.is_some());
+                self.underscan = Some(underscan);
+            }
             FakeDisplayEdit::SetEnabled(enabled) => {
                 self.enabled = enabled;
             }
@@ -194,6 +347,26 @@ impl Display for FakeDisplay {
         self.rotation
     }
 
+    fn underscan(&self) -> Option<Underscan> {
+        self.underscan
+    }
+
+    fn kind(&self) -> DisplayKind {
+        self.kind
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match &self.mirror_of {
+            Some(primary) => Some(Mirroring::MirrorOf(primary.clone())),
+            None if self.mirrored => Some(Mirroring::Primary),
+            None => None,
+        }
+    }
+
     type DisplayModeType = FakeDisplayMode;
 
     fn current_mode(&self) -> &Self::DisplayModeType {
@@ -207,48 +380,793 @@ impl Display for FakeDisplay {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// A description of a fake display mode in a scenario.  Only the extents
+/// are required.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioMode {
+    pub extents: Point,
+    #[serde(default = "ScenarioMode::default_scale")]
+    pub scale: Scale,
+    #[serde(default = "ScenarioMode::default_color_depth")]
+    pub color_depth: usize,
+    #[serde(default = "ScenarioMode::default_frequency")]
+    pub frequency: usize,
+    /// Defaults to the extents multiplied by the scale.
+    #[serde(default)]
+    pub pixel_extents: Option<Point>,
+    #[serde(default = "ScenarioMode::default_usable_for_desktop")]
+    pub usable_for_desktop: bool,
+}
+
+impl ScenarioMode {
+    fn default_scale() -> Scale {
+        Scale(1.0)
+    }
+
+    fn default_color_depth() -> usize {
+        8
+    }
+
+    fn default_frequency() -> usize {
+        60
+    }
+
+    fn default_usable_for_desktop() -> bool {
+        true
+    }
+
+    /// Helper to convert to a `FakeDisplayMode` for the given display, with
+    /// the given mode number.
+    fn to_mode(&self, uuid: &str, mode_id: i32) -> FakeDisplayMode {
+        let pixel_extents = self.pixel_extents.clone().unwrap_or_else(|| Point {
+            x: (self.extents.x as f64 * self.scale.0).round() as i64,
+            y: (self.extents.y as f64 * self.scale.0).round() as i64,
+        });
+        FakeDisplayMode {
+            uuid: uuid.to_owned(),
+            scale: self.scale,
+            color_depth: self.color_depth,
+            frequency: self.frequency,
+            extents: self.extents.clone(),
+            pixel_extents,
+            usable_for_desktop: self.usable_for_desktop,
+            mode_id,
+        }
+    }
+}
+
+/// A description of a fake display in a scenario, along with its initial
+/// settings.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioDisplay {
+    pub uuid: String,
+    #[serde(default = "ScenarioDisplay::default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "ScenarioDisplay::default_origin")]
+    pub origin: Point,
+    #[serde(default = "ScenarioDisplay::default_rotation")]
+    pub rotation: Rotation,
+    /// Omitted if the display does not support underscan.
+    #[serde(default)]
+    pub underscan: Option<Underscan>,
+    /// Defaults to a physical display.
+    #[serde(default)]
+    pub kind: DisplayKind,
+    /// The name of the display product, if any.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The UUID of the display this display mirrors, if any.
+    #[serde(default)]
+    pub mirror_of: Option<String>,
+    /// The display modes supported by the display.  There must be at least
+    /// one.
+    pub modes: Vec<ScenarioMode>,
+    /// The index of the current display mode in `modes`.
+    #[serde(default)]
+    pub mode: usize,
+}
+
+impl ScenarioDisplay {
+    /// Helper to construct the fake display described, checking that it is
+    /// consistent.
+    fn fake_display(&self) -> Result<FakeDisplay, Error> {
+        let modes: Vec<FakeDisplayMode> = self
+            .modes
+            .iter()
+            .enumerate()
+            .map(|(index, mode)| mode.to_mode(&self.uuid, index as i32))
+            .collect();
+        let mode = modes.get(self.mode).cloned().ok_or_else(|| {
+            self.inconsistent("has no display mode with the current mode's index")
+        })?;
+        Ok(FakeDisplay {
+            uuid: self.uuid.clone(),
+            enabled: self.enabled,
+            origin: self.origin.clone(),
+            rotation: self.rotation,
+            underscan: self.underscan,
+            kind: self.kind,
+            name: self.name.clone(),
+            mirror_of: self.mirror_of.clone(),
+            mirrored: false,
+            mode,
+            modes,
+        })
+    }
+
+    /// Helper to report a problem with the description of the display.
+    fn inconsistent(&self, problem: &str) -> Error {
+        Error::Internal(format!("The fake display {} {}.", self.uuid, problem))
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_origin() -> Point {
+        Point { x: 0, y: 0 }
+    }
+
+    fn default_rotation() -> Rotation {
+        Rotation::Zero
+    }
+}
+
+/// A scenario describes the displays that `FakeDisplayState` should report,
+/// so that multi-monitor cases can be exercised without the hardware.
+/// Scenario files contain a list of displays, in either JSON or, if the
+/// file has a `.ron` extension, RON.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Scenario {
+    pub displays: Vec<ScenarioDisplay>,
+}
+
+impl Scenario {
+    /// Load a scenario from a file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let failed = |e: &dyn std::fmt::Display| {
+            Error::Internal(format!(
+                "Failed to load the fake display scenario {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        let string = std::fs::read_to_string(path).map_err(|e| failed(&e))?;
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => crate::serde::Format::Ron,
+            _ => crate::serde::Format::Json,
+        };
+        crate::serde::deserialize(format, string.as_str()).map_err(|e| failed(&e))
+    }
+
+    /// Helper to construct the fake displays described by the scenario,
+    /// checking that it is consistent.
+    fn fake_displays(&self) -> Result<BTreeMap<String, FakeDisplay>, Error> {
+        let mut displays = BTreeMap::new();
+        for display in &self.displays {
+            if displays
+                .insert(display.uuid.clone(), display.fake_display()?)
+                .is_some()
+            {
+                return Err(display.inconsistent("appears more than once"));
+            }
+        }
+        for display in &self.displays {
+            if let Some(primary) = &display.mirror_of {
+                if *primary == display.uuid || !displays.contains_key(primary) {
+                    return Err(display.inconsistent("mirrors a display not in the scenario"));
+                }
+            }
+        }
+        Ok(displays)
+    }
+
+    /// A realistic topology, used when no other scenario is given: a laptop
+    /// with a Retina display, an external 4K monitor, and a virtual display
+    /// reporting a refresh rate of 0 Hz.  Each supports many modes, such as
+    /// scaled Retina modes and several refresh rates, so that ambiguity,
+    /// fuzzy matching, and filtering of modes can be exercised.
+    pub fn realistic() -> Self {
+        /// Helper to produce every combination of extents, scale and
+        /// frequency.
+        fn modes(
+            extents: &[(i64, i64)],
+            scales: &[f64],
+            frequencies: &[usize],
+        ) -> Vec<ScenarioMode> {
+            let mut modes = Vec::new();
+            for &(x, y) in extents {
+                for &scale in scales {
+                    for &frequency in frequencies {
+                        modes.push(ScenarioMode {
+                            extents: Point { x, y },
+                            scale: Scale(scale),
+                            color_depth: ScenarioMode::default_color_depth(),
+                            frequency,
+                            pixel_extents: None,
+                            usable_for_desktop: true,
+                        });
+                    }
+                }
+            }
+            modes
+        }
+
+        // The built-in display only offers scaled modes, at its native
+        // resolution of 3024x1964 and below.
+        let mut laptop_modes = modes(
+            &[(1512, 982), (1800, 1169), (1352, 878), (1147, 745)],
+            &[2.0],
+            &[120, 60, 50],
+        );
+        laptop_modes.extend(modes(&[(3024, 1964)], &[1.0], &[120, 60]));
+
+        // The 4K monitor offers both scaled and unscaled modes, as well as
+        // modes that are not usable for the desktop.
+        let mut monitor_modes = modes(
+            &[(3840, 2160), (2560, 1440), (1920, 1080), (1280, 720)],
+            &[1.0],
+            &[60, 50, 30],
+        );
+        monitor_modes.extend(modes(
+            &[(1920, 1080), (1600, 900), (1280, 720)],
+            &[2.0],
+            &[60, 30],
+        ));
+        monitor_modes.extend(
+            modes(&[(640, 480), (720, 480)], &[1.0], &[60])
+                .into_iter()
+                .map(|mode| ScenarioMode {
+                    usable_for_desktop: false,
+                    ..mode
+                }),
+        );
+
+        Scenario {
+            displays: vec![
+                ScenarioDisplay {
+                    uuid: "4c7f1a2e9d3b4e8f8a6c0b5d2e1f3a47".to_owned(),
+                    enabled: true,
+                    origin: Point { x: 0, y: 0 },
+                    rotation: Rotation::Zero,
+                    underscan: None,
+                    kind: DisplayKind::Physical,
+                    name: Some("Built-in Retina Display".to_owned()),
+                    mirror_of: None,
+                    modes: laptop_modes,
+                    mode: 0,
+                },
+                ScenarioDisplay {
+                    uuid: "9a3e5b7c1d2f4a6b8c0d1e2f3a4b5c6d".to_owned(),
+                    enabled: true,
+                    origin: Point { x: 1512, y: -1178 },
+                    rotation: Rotation::Zero,
+                    underscan: Some(Underscan(0.0)),
+                    kind: DisplayKind::Physical,
+                    name: Some("LG UltraFine".to_owned()),
+                    mirror_of: None,
+                    modes: monitor_modes,
+                    mode: 3,
+                },
+                ScenarioDisplay {
+                    uuid: "e1d2c3b4a5964877a8b9c0d1e2f3a4b5".to_owned(),
+                    enabled: true,
+                    origin: Point { x: -1920, y: 0 },
+                    rotation: Rotation::Zero,
+                    underscan: None,
+                    kind: DisplayKind::Virtual,
+                    name: None,
+                    mirror_of: None,
+                    modes: modes(
+                        &[(1920, 1080), (1680, 1050), (1280, 800)],
+                        &[1.0, 2.0],
+                        &[0],
+                    ),
+                    mode: 0,
+                },
+            ],
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The environment variable naming a scenario file to initialize the fake
+/// displays from.  Without it, the `Scenario::realistic` displays are used.
+pub const SCENARIO_VAR: &str = "KNOLL_FAKE_SCENARIO";
+
+/// The functions registered to be called when the fake displays change.
+static RECONFIGURATION_CALLBACKS: Mutex<Vec<extern "C" fn()>> = Mutex::new(Vec::new());
+
+/// Whether the fake event loop has been asked to stop, along with a
+/// condition variable to wake it.
+static EVENT_LOOP_STOPPED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+/// The current fake displays, or `None` until they are first used.
+static CURRENT_FAKE_DISPLAYS: Mutex<Option<BTreeMap<String, FakeDisplay>>> = Mutex::new(None);
+
+/// Helper to access the current fake displays, initializing them from the
+/// scenario named by `SCENARIO_VAR` on first use.
+fn with_fake_displays<R, F: FnOnce(&mut BTreeMap<String, FakeDisplay>) -> R>(
+    f: F,
+) -> Result<R, Error> {
+    let mut guard = CURRENT_FAKE_DISPLAYS
+        .lock()
+        .map_err(|pe| Error::Poisoned(format!("{}", pe)))?;
+    if guard.is_none() {
+        let scenario = match std::env::var_os(SCENARIO_VAR) {
+            Some(path) => Scenario::load(Path::new(&path))?,
+            None => Scenario::realistic(),
+        };
+        *guard = Some(scenario.fake_displays()?);
+    }
+    // Okay to unwrap, as the displays were just initialized if necessary.
+    Ok(f(guard.as_mut().unwrap()))
+}
+
+/// Whether the fake displays have been put to sleep.
+static DISPLAYS_ASLEEP: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// How long committing a transaction should take, to simulate a hung commit.
+static COMMIT_DELAY: Mutex<Option<std::time::Duration>> = Mutex::new(None);
+
+/// Whether committing a transaction should succeed without applying any of
+/// its edits.
+static IGNORE_COMMITS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Whether committing a transaction should adjust the origins of the
+/// displays, as macOS does to remove gaps and overlaps between them.
+static NORMALIZE_ORIGINS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Helper to stand in for the adjustments macOS makes to origins, by
+/// translating the enabled displays so that the upper left corner of their
+/// bounding box is at the origin.
+fn normalize_origins(displays: &mut BTreeMap<String, FakeDisplay>) {
+    let enabled = || displays.values().filter(|display| display.enabled);
+    let (Some(min_x), Some(min_y)) = (
+        enabled().map(|display| display.origin.x).min(),
+        enabled().map(|display| display.origin.y).min(),
+    ) else {
+        return;
+    };
+    for display in displays.values_mut().filter(|display| display.enabled) {
+        display.origin.x -= min_x;
+        display.origin.y -= min_y;
+    }
+}
+
+/// The system facts reported by the fake displays.
+static SYSTEM_FACTS: Mutex<Option<SystemFacts>> = Mutex::new(None);
+
+/// The number of fake virtual displays created so far, used to give each a
+/// distinct UUID.
+static VIRTUAL_DISPLAYS_CREATED: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+
+/// A fake virtual display, which is detached from the fake displays when
+/// dropped.
+#[derive(Debug)]
+pub struct FakeVirtualDisplay {
+    uuid: String,
+}
+
+impl VirtualDisplay for FakeVirtualDisplay {
+    fn uuid(&self) -> &str {
+        &self.uuid
+    }
+}
+
+impl Drop for FakeVirtualDisplay {
+    fn drop(&mut self) {
+        // The display may have already been removed by a test.
+        let _ = FakeDisplayState::remove_display(&self.uuid);
+    }
+}
+
 #[derive(Debug)]
 pub struct FakeDisplayState {
     displays: BTreeMap<String, FakeDisplay>,
+    disabled: BTreeSet<String>,
 }
 
-static CURRENT_FAKE_DISPLAYS: Lazy<Mutex<BTreeMap<String, FakeDisplay>>> =
-    Lazy::new(|| Mutex::new(BTreeMap::new()));
-
+// Intended for testing, so not used by the knoll command itself.
+#[allow(dead_code)]
 impl FakeDisplayState {
-    // Intended for testing, but currently not used.
-    #[allow(dead_code)]
-    fn set_displays(displays: BTreeMap<String, FakeDisplay>) {
-        *CURRENT_FAKE_DISPLAYS.lock().unwrap() = displays;
+    /// Replace the fake displays with those described by the scenario.
+    /// As the fake displays are shared by the whole process, tests that
+    /// use different scenarios must not run concurrently.
+    pub fn set_scenario(scenario: &Scenario) -> Result<(), Error> {
+        let displays = scenario.fake_displays()?;
+        with_fake_displays(|current| *current = displays)
+    }
+
+    /// Replace the fake displays with those described by a scenario file.
+    pub fn load_scenario(path: &Path) -> Result<(), Error> {
+        Self::set_scenario(&Scenario::load(path)?)
+    }
+
+    /// Attach a display, as if it had been plugged in, and invoke the
+    /// reconfiguration callbacks.
+    pub fn add_display(display: &ScenarioDisplay) -> Result<(), Error> {
+        let fake = display.fake_display()?;
+        let attached = with_fake_displays(|displays| {
+            if displays.contains_key(&fake.uuid) {
+                return false;
+            }
+            displays.insert(fake.uuid.clone(), fake);
+            true
+        })?;
+        if !attached {
+            return Err(display.inconsistent("is already attached"));
+        }
+        Self::reconfigured()
+    }
+
+    /// Detach the display with the given UUID, as if it had been unplugged,
+    /// and invoke the reconfiguration callbacks.
+    pub fn remove_display(uuid: &str) -> Result<(), Error> {
+        with_fake_displays(|displays| displays.remove(uuid))?
+            .ok_or_else(|| Error::UnknownUUID(uuid.to_owned()))?;
+        Self::reconfigured()
+    }
+
+    /// Helper to invoke the reconfiguration callbacks.  The callbacks are
+    /// invoked without holding any locks, as they may inspect the displays.
+    fn reconfigured() -> Result<(), Error> {
+        let callbacks = RECONFIGURATION_CALLBACKS
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?
+            .clone();
+        for callback in callbacks {
+            callback();
+        }
+        Ok(())
+    }
+
+    /// Make an operation on the fake displays fail.  Like the fake displays,
+    /// injected failures are shared by the whole process.
+    pub fn inject_failure(failure: InjectedFailure) -> Result<(), Error> {
+        INJECTED_FAILURES
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?
+            .push(failure);
+        Ok(())
+    }
+
+    /// The scope of the most recently attempted commit to the fake displays,
+    /// if there has been one.  The fake displays do not revert changes
+    /// when the scope ends.
+    pub fn last_commit_scope() -> Option<ConfigurationScope> {
+        LAST_COMMIT_SCOPE.lock().ok().and_then(|scope| *scope)
+    }
+
+    /// Whether the fake displays are currently asleep.
+    pub fn displays_asleep() -> bool {
+        DISPLAYS_ASLEEP.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Delay committing transactions by the given duration, or stop delaying
+    /// them if `None`.
+    pub fn set_commit_delay(delay: Option<std::time::Duration>) -> Result<(), Error> {
+        *COMMIT_DELAY
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))? = delay;
+        Ok(())
+    }
+
+    /// Make committing transactions succeed without changing the fake
+    /// displays, as macOS sometimes does for configurations it does not
+    /// like.
+    pub fn set_ignore_commits(ignore: bool) {
+        IGNORE_COMMITS.store(ignore, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Make committing transactions adjust the origins of the displays, as
+    /// macOS does, rather than placing them exactly as requested.
+    pub fn set_normalize_origins(normalize: bool) {
+        NORMALIZE_ORIGINS.store(normalize, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Set the system facts reported by `system_facts`, or restore the
+    /// default facts of a healthy system if `None`.
+    pub fn set_system_facts(facts: Option<SystemFacts>) -> Result<(), Error> {
+        *SYSTEM_FACTS
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))? = facts;
+        Ok(())
+    }
+
+    /// Stop injecting any failures.
+    pub fn clear_injected_failures() -> Result<(), Error> {
+        INJECTED_FAILURES
+            .lock()
+            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?
+            .clear();
+        Ok(())
     }
 }
 
 impl DisplayState for FakeDisplayState {
     fn current() -> Result<Self, Error> {
+        check_injected_failure(FakeOperation::Current, None)?;
         // The current semantics is that once a display becomes disabled, it
         // will no longer appear in the list of available displays.  So we
-        // filter them out before returning the current state.
-        let enabled_displays: BTreeMap<String, FakeDisplay> = CURRENT_FAKE_DISPLAYS
-            .lock()
-            .map_err(|pe| Error::Poisoned(format!("{}", pe)))?
-            .clone()
-            .into_iter()
-            .filter(|(_, display)| display.enabled)
-            .collect();
+        // separate them out before returning the current state.
+        let (enabled_displays, disabled_displays): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            with_fake_displays(|displays| {
+                displays
+                    .iter()
+                    .map(|(uuid, display)| {
+                        let mut display = display.clone();
+                        display.mirrored = displays
+                            .values()
+                            .any(|other| other.mirror_of.as_ref() == Some(uuid));
+                        (uuid.clone(), display)
+                    })
+                    .partition(|(_, display)| display.enabled)
+            })?;
         Ok(Self {
             displays: enabled_displays,
+            disabled: disabled_displays.into_keys().collect(),
         })
     }
 
+    fn environment() -> Environment {
+        Environment::default()
+    }
+
     type DisplayModeType = FakeDisplayMode;
     type DisplayType = FakeDisplay;
     type DisplayConfigTransactionType = FakeDisplayConfigTransaction;
+    type VirtualDisplayType = FakeVirtualDisplay;
 
     fn get_displays(&self) -> &BTreeMap<String, Self::DisplayType> {
         &self.displays
     }
 
+    fn disabled_displays(&self) -> BTreeSet<String> {
+        self.disabled.clone()
+    }
+
     fn configure(&self) -> Result<Self::DisplayConfigTransactionType, Error> {
-        FakeDisplayConfigTransaction::new(&self.displays)
+        FakeDisplayConfigTransaction::new(self.displays.keys().chain(&self.disabled))
+    }
+
+    /// The fake virtual display has a single display mode, and is placed at
+    /// the origin, overlapping any other displays.
+    fn create_virtual_display(
+        spec: &VirtualDisplaySpec,
+    ) -> Result<Self::VirtualDisplayType, Error> {
+        check_injected_failure(FakeOperation::CreateVirtualDisplay, None)?;
+        let count = VIRTUAL_DISPLAYS_CREATED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let uuid = format!("f1c7{:028x}", count);
+        Self::add_display(&ScenarioDisplay {
+            uuid: uuid.clone(),
+            enabled: true,
+            origin: Point { x: 0, y: 0 },
+            rotation: Rotation::Zero,
+            underscan: None,
+            kind: DisplayKind::Virtual,
+            name: None,
+            mirror_of: None,
+            modes: vec![ScenarioMode {
+                extents: spec.extents.clone(),
+                scale: Scale(1.0),
+                color_depth: 8,
+                frequency: spec.frequency,
+                pixel_extents: None,
+                usable_for_desktop: true,
+            }],
+            mode: 0,
+        })?;
+        Ok(FakeVirtualDisplay { uuid })
+    }
+
+    fn sleep_displays() -> Result<(), Error> {
+        check_injected_failure(FakeOperation::SleepDisplays, None)?;
+        DISPLAYS_ASLEEP.store(true, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn wake_displays() -> Result<(), Error> {
+        check_injected_failure(FakeOperation::WakeDisplays, None)?;
+        DISPLAYS_ASLEEP.store(false, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn system_facts() -> SystemFacts {
+        SYSTEM_FACTS
+            .lock()
+            .ok()
+            .and_then(|facts| facts.clone())
+            .unwrap_or_default()
+    }
+
+    fn register_reconfiguration_callback(callback: extern "C" fn()) {
+        if let Ok(mut callbacks) = RECONFIGURATION_CALLBACKS.lock() {
+            callbacks.push(callback);
+        }
+    }
+
+    fn remove_reconfiguration_callback(callback: extern "C" fn()) {
+        if let Ok(mut callbacks) = RECONFIGURATION_CALLBACKS.lock() {
+            if let Some(index) = callbacks
+                .iter()
+                .position(|c| *c as usize == callback as usize)
+            {
+                callbacks.remove(index);
+            }
+        }
+    }
+
+    fn run_event_loop() {
+        let (lock, condvar) = &EVENT_LOOP_STOPPED;
+        let Ok(mut stopped) = lock.lock() else {
+            return;
+        };
+        while !*stopped {
+            match condvar.wait(stopped) {
+                Ok(guard) => stopped = guard,
+                Err(_) => return,
+            }
+        }
+        // Reset, so that the event loop can be run again.
+        *stopped = false;
+    }
+
+    fn stop_event_loop() {
+        let (lock, condvar) = &EVENT_LOOP_STOPPED;
+        if let Ok(mut stopped) = lock.lock() {
+            *stopped = true;
+            condvar.notify_all();
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test that scenarios are parsed with the expected defaults, and that
+/// inconsistent scenarios are rejected.
+#[test]
+fn test_scenario() {
+    let scenario: Scenario = serde_json::from_str(
+        r#"[
+            {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+             "modes": [{"extents": [1920, 1080]},
+                       {"extents": [1280, 720], "scale": 2.0, "frequency": 120}],
+             "mode": 1},
+            {"uuid": "94226c6fcef04e9b8503ffa88fedba08", "enabled": false,
+             "origin": [1280, 0], "rotation": 90, "underscan": 0.0,
+             "modes": [{"extents": [2560, 1440], "usable_for_desktop": false}]}
+        ]"#,
+    )
+    .expect("Deserialization should not fail");
+    let displays = scenario
+        .fake_displays()
+        .expect("The scenario should be consistent");
+    assert_eq!(displays.len(), 2);
+
+    let first = &displays["37d8832a2d6602cab9f78f30a301b230"];
+    assert!(first.enabled());
+    assert_eq!(first.origin(), &Point { x: 0, y: 0 });
+    assert_eq!(first.rotation(), Rotation::Zero);
+    assert!(first.underscan().is_none());
+    assert_eq!(first.possible_modes().len(), 2);
+    assert_eq!(first.current_mode().frequency(), 120);
+    assert_eq!(
+        first.current_mode().pixel_extents(),
+        Point { x: 2560, y: 1440 }
+    );
+    assert_eq!(first.possible_modes()[0].color_depth(), 8);
+    assert_eq!(first.possible_modes()[0].frequency(), 60);
+
+    let second = &displays["94226c6fcef04e9b8503ffa88fedba08"];
+    assert!(!second.enabled());
+    assert_eq!(second.origin(), &Point { x: 1280, y: 0 });
+    assert_eq!(second.rotation(), Rotation::Ninety);
+    assert!(second.underscan().is_some());
+    assert!(!second.current_mode().usable_for_desktop());
+
+    let mut duplicated = scenario.clone();
+    duplicated.displays[1].uuid = duplicated.displays[0].uuid.clone();
+    assert!(matches!(
+        duplicated.fake_displays(),
+        Err(Error::Internal(_))
+    ));
+
+    let mut out_of_range = scenario;
+    out_of_range.displays[0].mode = 2;
+    assert!(matches!(
+        out_of_range.fake_displays(),
+        Err(Error::Internal(_))
+    ));
+}
+
+/// Test that the realistic topology is consistent and offers a variety of
+/// display modes.
+#[test]
+fn test_realistic_scenario() {
+    let displays = Scenario::realistic()
+        .fake_displays()
+        .expect("The scenario should be consistent");
+    assert_eq!(displays.len(), 3);
+    let modes: Vec<&FakeDisplayMode> = displays
+        .values()
+        .flat_map(|display| display.possible_modes())
+        .collect();
+    assert!(modes.iter().any(|mode| mode.scale() == Scale(2.0)));
+    assert!(modes.iter().any(|mode| mode.frequency() == 0));
+    assert!(modes.iter().any(|mode| !mode.usable_for_desktop()));
+    // The physical displays offer modes with the same extents at several
+    // refresh rates.
+    for display in displays.values() {
+        let mode = display.current_mode();
+        if mode.frequency() == 0 {
+            continue;
+        }
+        assert!(
+            display
+                .possible_modes()
+                .iter()
+                .filter(|other| other.extents() == mode.extents() && other.scale() == mode.scale())
+                .count()
+                > 1
+        );
+    }
+}
+
+/// Test that reconfiguration callbacks are registered once, however many
+/// handles there are, and removed once all of the handles are dropped.
+#[test]
+fn test_reconfiguration_callback() {
+    // Use a callback that no other test uses, as callbacks are shared.
+    extern "C" fn callback() {}
+    let address = callback as extern "C" fn() as usize;
+    let registered = || {
+        RECONFIGURATION_CALLBACKS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| **c as usize == address)
+            .count()
+    };
+
+    let first = ReconfigurationCallback::<FakeDisplayState>::register(callback);
+    let second = ReconfigurationCallback::<FakeDisplayState>::register(callback);
+    assert_eq!(registered(), 1);
+    drop(first);
+    assert_eq!(registered(), 1);
+    drop(second);
+    assert_eq!(registered(), 0);
+}
+
+/// Test that injected failures occur only for the requested operation and
+/// display, and only as many times as requested.
+#[test]
+fn test_injected_failures() {
+    // Use a display that no other test uses, as injected failures are shared.
+    let uuid = "test_injected_failures";
+    FakeDisplayState::inject_failure(InjectedFailure {
+        operation: FakeOperation::SetMode,
+        uuid: Some(uuid.to_owned()),
+        times: Some(2),
+    })
+    .unwrap();
+
+    assert!(check_injected_failure(FakeOperation::SetOrigin, Some(uuid)).is_ok());
+    assert!(check_injected_failure(FakeOperation::SetMode, Some("other")).is_ok());
+    assert!(check_injected_failure(FakeOperation::SetMode, None).is_ok());
+    for _ in 0..2 {
+        match check_injected_failure(FakeOperation::SetMode, Some(uuid)) {
+            Err(Error::Internal(message)) => assert_eq!(
+                message,
+                "Injected failure of SetMode for display test_injected_failures"
+            ),
+            result => panic!("Unexpected result: {:?}", result),
+        }
     }
+    assert!(check_injected_failure(FakeOperation::SetMode, Some(uuid)).is_ok());
 }
@@ -0,0 +1,170 @@
+//! This module provides a C interface to knoll, so that applications written
+//! in other languages, such as Swift or Objective-C, can reuse knoll rather
+//! than reimplementing display configuration.  It is only built with the
+//! `ffi` feature.  Configurations and results are exchanged as JSON strings,
+//! and each function returns the same status code the knoll command would
+//! exit with: zero on success, or otherwise with the error message as the
+//! result.  Results must be released with `knoll_free_string`.  Panics are
+//! caught and reported as errors, as unwinding into the caller is undefined
+//! behavior.
+use coverage_helper::test;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+use crate::api;
+use crate::config::ConfigGroups;
+use crate::displays;
+use crate::displays::DisplayState;
+use crate::knoll::Error;
+use crate::real_displays::RealDisplayState;
+use crate::serde::Format;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Helper to capture the display state as JSON.
+fn capture_json<DS: DisplayState>() -> Result<String, Error> {
+    Ok(crate::serde::serialize_to_string(
+        Format::Json,
+        &api::capture::<DS>()?,
+    )?)
+}
+
+/// Helper to apply a configuration given as JSON, returning the resulting
+/// display state as JSON.
+fn apply_json<DS: DisplayState>(config: &str) -> Result<String, Error> {
+    let config_groups: ConfigGroups = crate::serde::deserialize_config(Format::Json, config)?;
+    let state = api::apply::<DS>(config_groups, &api::ApplyOptions::default())?;
+    Ok(crate::serde::serialize_to_string(Format::Json, &state)?)
+}
+
+/// Helper to list the display modes of the attached displays as JSON.
+fn list_modes_json<DS: DisplayState>(all_modes: bool) -> Result<String, Error> {
+    Ok(crate::serde::serialize_to_string(
+        Format::Json,
+        &api::list_modes::<DS>(&[], all_modes)?,
+    )?)
+}
+
+/// Helper to run the function, reporting a panic as an error rather than
+/// unwinding into the caller.
+fn catch_panic(f: impl FnOnce() -> Result<String, Error>) -> Result<String, Error> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(Error::Displays(displays::Error::Internal(format!(
+            "knoll panicked: {}",
+            message
+        ))))
+    })
+}
+
+/// Helper to store a result for the caller and convert it to a status code.
+///
+/// # Safety
+///
+/// `out` must be a valid pointer to write a string pointer to.
+unsafe fn finish(result: Result<String, Error>, out: *mut *mut c_char) -> c_int {
+    let (status, string) = match result {
+        Ok(string) => (0, string),
+        Err(e) => (e.exit_code(), e.to_string()),
+    };
+    // Neither JSON nor error messages should contain a nul byte, but replace
+    // any rather than fail.
+    let string = CString::new(string.replace('\0', "")).unwrap_or_default();
+    *out = string.into_raw();
+    status
+}
+
+/// Capture the state of the attached displays, storing it in `out` as a
+/// JSON configuration.
+///
+/// # Safety
+///
+/// `out` must be a valid pointer to write a string pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn knoll_capture(out: *mut *mut c_char) -> c_int {
+    finish(catch_panic(capture_json::<RealDisplayState>), out)
+}
+
+/// Apply the most precise of the configuration groups given as JSON,
+/// storing the resulting display state in `out` as a JSON configuration.
+///
+/// # Safety
+///
+/// `config` must be a valid nul-terminated string, and `out` must be a valid
+/// pointer to write a string pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn knoll_apply(config: *const c_char, out: *mut *mut c_char) -> c_int {
+    let config = CStr::from_ptr(config);
+    let result = catch_panic(|| {
+        config
+            .to_str()
+            .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+            .and_then(apply_json::<RealDisplayState>)
+    });
+    finish(result, out)
+}
+
+/// List the display modes of the attached displays, storing them in `out`
+/// as JSON.  Modes not usable for the desktop are only included if
+/// `all_modes` is non-zero.
+///
+/// # Safety
+///
+/// `out` must be a valid pointer to write a string pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn knoll_list_modes(all_modes: c_int, out: *mut *mut c_char) -> c_int {
+    finish(
+        catch_panic(|| list_modes_json::<RealDisplayState>(all_modes != 0)),
+        out,
+    )
+}
+
+/// Release a string returned by one of the other functions.
+///
+/// # Safety
+///
+/// `string` must have been returned by one of the other functions, and not
+/// already released, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn knoll_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test the JSON helpers and result handling using the fake displays.
+#[test]
+fn test_ffi() {
+    use crate::fake_displays::FakeDisplayState;
+
+    let state = capture_json::<FakeDisplayState>().unwrap();
+    assert!(state.starts_with("[\n  [\n    {\n      \"uuid\""));
+    assert_eq!(apply_json::<FakeDisplayState>("[]").unwrap(), state);
+    assert!(list_modes_json::<FakeDisplayState>(true)
+        .unwrap()
+        .contains("\"unsafe_modes\""));
+
+    let mut out: *mut c_char = std::ptr::null_mut();
+    let status = unsafe { finish(apply_json::<FakeDisplayState>("[[]"), &mut out) };
+    assert_eq!(status, 3);
+    let message = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
+    assert!(message.starts_with("JSON deserialization error"));
+    unsafe { knoll_free_string(out) };
+    unsafe { knoll_free_string(std::ptr::null_mut()) };
+
+    // Panics are reported as errors.
+    let status = unsafe { finish(catch_panic(|| panic!("unexpected")), &mut out) };
+    assert_eq!(status, 6);
+    let message = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
+    assert!(
+        message.ends_with("knoll panicked: unexpected"),
+        "{}",
+        message
+    );
+    unsafe { knoll_free_string(out) };
+}
@@ -1,56 +1,246 @@
-use log::{set_boxed_logger, set_max_level, Log, SetLoggerError};
+use coverage_helper::test;
+use log::{set_boxed_logger, set_max_level, LevelFilter, Log, SetLoggerError};
 use simplelog::SharedLogger;
-use std::ops::Deref;
-use std::sync::{Arc, RwLock};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 /// An indirect logger is an implementation of log::Log that delegates
-/// all calls to a simplelog::SharedLogger.  This allows the logger to be
-/// updated at runtime.  
+/// all calls to a simplelog::SharedLogger.  As the `log` crate only allows
+/// the logger to be set once per process, this allows each invocation of
+/// knoll within the same process to log to its own destination.
 ///
-/// This is not really necessary for ordinary operation, but is useful in
-/// testing where we may invoke the knoll command multiple times in the
-/// same process lifetime.
-#[derive(Clone)]
+/// Each invocation starts a `LogSession` with its logger, and records are
+/// delegated to the logger of the session of the thread that logs them.
+/// Threads started with `spawn` belong to the same session as the thread
+/// that started them.  Records logged by threads without a live session,
+/// such as those started by the operating system, are delegated to the
+/// logger of the most recent live session, or once there are none, that of
+/// the most recent session.
+#[derive(Clone, Default)]
 pub struct IndirectLogger {
-    logger: Arc<RwLock<Box<dyn SharedLogger>>>,
+    loggers: Arc<RwLock<Loggers>>,
 }
 
-impl IndirectLogger {
-    /// Update this IndirectLogger to make use of the new logger in
-    /// subsequent calls to log::Log functions.
-    pub fn update(&self, logger: Box<dyn SharedLogger>) {
-        set_max_level(logger.level());
-        *self.logger.write().unwrap() = logger;
+/// The loggers an `IndirectLogger` delegates to.
+#[derive(Default)]
+struct Loggers {
+    /// The identifier to give the next session.
+    next_session: u64,
+    /// The logger of each live session.
+    sessions: BTreeMap<u64, Arc<dyn SharedLogger>>,
+    /// The logger of the most recent session, which is kept once the
+    /// session ends.
+    latest: Option<Arc<dyn SharedLogger>>,
+}
+
+impl Loggers {
+    /// The most verbose level of any of the loggers.
+    fn level(&self) -> LevelFilter {
+        self.sessions
+            .values()
+            .chain(&self.latest)
+            .map(|logger| logger.level())
+            .max()
+            .unwrap_or(LevelFilter::Off)
     }
+}
 
-    /// Construct a new IndirectLogger that delegates to the given logger.
-    pub fn new(logger: Box<dyn SharedLogger>) -> Self {
-        IndirectLogger {
-            logger: Arc::new(RwLock::new(logger)),
-        }
+thread_local! {
+    /// The logging session the current thread belongs to, if any.
+    static SESSION: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// A session of logging to a particular logger, which lasts until it is
+/// dropped.
+pub struct LogSession {
+    indirect_logger: IndirectLogger,
+    id: u64,
+    /// The session the thread belonged to before this one started.
+    previous: Option<u64>,
+}
+
+impl Drop for LogSession {
+    fn drop(&mut self) {
+        let mut loggers = self.indirect_logger.write();
+        loggers.sessions.remove(&self.id);
+        set_max_level(loggers.level());
+        SESSION.with(|session| session.set(self.previous));
     }
+}
 
-    /// Initialize the global logger with the given logger wrapped by
-    /// an IndirectLogger.  This function returns a clone of IndirectLogger
-    /// that can be used to update the logger at a later time.
-    pub fn init(logger: Box<dyn SharedLogger>) -> Result<Self, SetLoggerError> {
-        set_max_level(logger.deref().level());
-        let indirect_logger = IndirectLogger::new(logger);
+impl IndirectLogger {
+    /// Construct a new IndirectLogger without any sessions, which discards
+    /// everything logged until one starts.
+    pub fn new() -> Self {
+        IndirectLogger::default()
+    }
+
+    /// Initialize the global logger with a new IndirectLogger.  This
+    /// function returns a clone of the IndirectLogger that can be used to
+    /// start sessions.
+    pub fn init() -> Result<Self, SetLoggerError> {
+        let indirect_logger = IndirectLogger::new();
         set_boxed_logger(Box::new(indirect_logger.clone()))?;
         Ok(indirect_logger)
     }
+
+    /// Start a session that logs to the given logger, which the current
+    /// thread belongs to until the session is dropped.
+    pub fn start_session(&self, logger: Box<dyn SharedLogger>) -> LogSession {
+        let logger: Arc<dyn SharedLogger> = Arc::from(logger);
+        let mut loggers = self.write();
+        let id = loggers.next_session;
+        loggers.next_session += 1;
+        loggers.sessions.insert(id, logger.clone());
+        loggers.latest = Some(logger);
+        set_max_level(loggers.level());
+        LogSession {
+            indirect_logger: self.clone(),
+            id,
+            previous: SESSION.with(|session| session.replace(Some(id))),
+        }
+    }
+
+    /// Helper to read the loggers, even if another thread panicked while
+    /// logging, so that logging never panics.
+    fn read(&self) -> RwLockReadGuard<'_, Loggers> {
+        self.loggers.read().unwrap_or_else(|pe| pe.into_inner())
+    }
+
+    /// Helper to update the loggers, even if another thread panicked while
+    /// logging.
+    fn write(&self) -> RwLockWriteGuard<'_, Loggers> {
+        self.loggers.write().unwrap_or_else(|pe| pe.into_inner())
+    }
+
+    /// Helper to apply the function to the logger for the current thread.
+    fn with_logger<T: Default>(&self, f: impl FnOnce(&dyn SharedLogger) -> T) -> T {
+        let loggers = self.read();
+        let session = SESSION.with(Cell::get);
+        match session
+            .and_then(|id| loggers.sessions.get(&id))
+            .or_else(|| loggers.sessions.values().next_back())
+            .or(loggers.latest.as_ref())
+        {
+            Some(logger) => f(logger.as_ref()),
+            None => T::default(),
+        }
+    }
+}
+
+/// Spawn a thread that belongs to the same logging session as the current
+/// thread.
+pub fn spawn<F, T>(f: F) -> std::thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let id = SESSION.with(Cell::get);
+    std::thread::spawn(move || {
+        SESSION.with(|session| session.set(id));
+        f()
+    })
 }
 
 impl Log for IndirectLogger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        self.logger.read().unwrap().deref().enabled(metadata)
+        self.with_logger(|logger| logger.enabled(metadata))
     }
 
     fn log(&self, record: &log::Record) {
-        self.logger.read().unwrap().deref().log(record)
+        self.with_logger(|logger| logger.log(record))
     }
 
     fn flush(&self) {
-        self.logger.read().unwrap().deref().flush()
+        self.with_logger(|logger| logger.flush())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+/// Check that records are delegated to the logger of the session of the
+/// thread that logs them.
+fn test_sessions() {
+    use crate::diagnostics::SharedWriter;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    /// A writer whose output can be inspected once written.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
     }
+
+    let logger_to = |buffer: &Buffer| -> Box<dyn SharedLogger> {
+        simplelog::WriteLogger::new(
+            LevelFilter::Info,
+            simplelog::Config::default(),
+            SharedWriter::new(buffer.clone()),
+        )
+    };
+    let log = |indirect_logger: &IndirectLogger, message: &str| {
+        indirect_logger.log(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .args(format_args!("{}", message))
+                .build(),
+        )
+    };
+
+    let indirect_logger = IndirectLogger::new();
+    // Nothing is logged before there are any sessions.
+    log(&indirect_logger, "discarded");
+
+    let (first, second) = (Buffer::default(), Buffer::default());
+    let first_session = indirect_logger.start_session(logger_to(&first));
+    let other = {
+        let indirect_logger = indirect_logger.clone();
+        let second = second.clone();
+        std::thread::spawn(move || {
+            let _session = indirect_logger.start_session(logger_to(&second));
+            // Threads spawned by a session belong to it.
+            let spawned = indirect_logger.clone();
+            spawn(move || log(&spawned, "second spawned"))
+                .join()
+                .unwrap();
+            log(&indirect_logger, "second");
+        })
+    };
+    other.join().unwrap();
+    log(&indirect_logger, "first");
+    // Threads without a session use the most recent live session.
+    let orphan = indirect_logger.clone();
+    std::thread::spawn(move || log(&orphan, "first orphan"))
+        .join()
+        .unwrap();
+    drop(first_session);
+    // Without any live session, the most recent logger is used.
+    log(&indirect_logger, "latest");
+
+    assert!(first.contents().contains("first"));
+    assert!(first.contents().contains("first orphan"));
+    assert!(!first.contents().contains("second"));
+    assert!(second.contents().contains("second spawned"));
+    assert!(second.contents().contains("second"));
+    assert!(!second.contents().contains("first"));
+    assert!(!first.contents().contains("discarded"));
+    assert!(!second.contents().contains("discarded"));
+    // The second session started after the first.
+    assert!(second.contents().contains("latest"));
 }
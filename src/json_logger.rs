@@ -0,0 +1,156 @@
+//! This module provides a logger that writes each record as a single line of
+//! JSON, so that logs can be ingested by log aggregation tools.
+use coverage_helper::test;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use simplelog::{Config, SharedLogger};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// The JSON representation of a log record.
+#[derive(Debug, Serialize)]
+struct JsonRecord<'a> {
+    /// When the record was logged, in RFC 3339 format.
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+}
+
+/// A logger that writes records as newline delimited JSON.
+pub struct JsonLogger<W: Write + Send> {
+    level: LevelFilter,
+    /// Records whose target starts with this are ignored, if given.
+    ignored_target: Option<&'static str>,
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLogger<W> {
+    pub fn new(level: LevelFilter, writer: W) -> Box<Self> {
+        Box::new(JsonLogger {
+            level,
+            ignored_target: None,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Ignore records whose target starts with the given target.
+    pub fn ignore_target(mut self: Box<Self>, target: &'static str) -> Box<Self> {
+        self.ignored_target = Some(target);
+        self
+    }
+}
+
+impl<W: Write + Send> Log for JsonLogger<W> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+            && self
+                .ignored_target
+                .is_none_or(|target| !metadata.target().starts_with(target))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let json_record = JsonRecord {
+            timestamp: humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string(),
+            level: record.level().as_str(),
+            target: record.target(),
+            message: record.args().to_string(),
+            module_path: record.module_path(),
+            file: record.file(),
+            line: record.line(),
+        };
+        // There is nowhere to report failures to log, so they are ignored.
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = serde_json::to_writer(&mut *writer, &json_record);
+            let _ = writer.write_all(b"\n");
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> SharedLogger for JsonLogger<W> {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that records are written as JSON, and that records above the
+/// level are ignored.
+#[test]
+fn test_json_logger() {
+    let logger = JsonLogger::new(LevelFilter::Info, Vec::new());
+    logger.log(
+        &Record::builder()
+            .args(format_args!("Reconfiguring \"displays\"."))
+            .level(log::Level::Info)
+            .target("knoll::knoll")
+            .line(Some(42))
+            .build(),
+    );
+    logger.log(
+        &Record::builder()
+            .args(format_args!("Ignored."))
+            .level(log::Level::Debug)
+            .build(),
+    );
+
+    let output = String::from_utf8(logger.writer.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let json: serde_json::Value = serde_json::from_str(lines[0]).expect("Should be JSON");
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["target"], "knoll::knoll");
+    assert_eq!(json["message"], "Reconfiguring \"displays\".");
+    assert_eq!(json["line"], 42);
+    assert!(json["timestamp"].is_string());
+    assert!(json.get("file").is_none());
+}
+
+#[test]
+fn test_json_logger_ignore_target() {
+    let logger = JsonLogger::new(LevelFilter::Info, Vec::new()).ignore_target("knoll::detail");
+    logger.log(
+        &Record::builder()
+            .args(format_args!("Selected a mode."))
+            .level(log::Level::Info)
+            .target("knoll::detail")
+            .build(),
+    );
+    logger.log(
+        &Record::builder()
+            .args(format_args!("Applied the configuration."))
+            .level(log::Level::Info)
+            .target("knoll::knoll")
+            .build(),
+    );
+
+    let output = String::from_utf8(logger.writer.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let json: serde_json::Value = serde_json::from_str(lines[0]).expect("Should be JSON");
+    assert_eq!(json["message"], "Applied the configuration.");
+}
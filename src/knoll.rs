@@ -1,23 +1,42 @@
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use humantime;
 use log::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use simplelog::{SharedLogger, TermLogger, WriteLogger};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Formatter;
 use std::io::IsTerminal;
-use std::io::{BufReader, Read, Write};
-use std::path::PathBuf;
-use std::sync::{Condvar, LazyLock, Mutex, RwLock};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, Weak};
+use std::time::Instant;
 
+use crate::aliases;
+use crate::aliases::Aliases;
 use crate::config::*;
+use crate::control;
 use crate::core_graphics;
+use crate::diagnostics::{Diagnostics, SharedWriter};
 use crate::displays;
 use crate::displays::*;
-use crate::indirect_logger::IndirectLogger;
-use crate::serde::serialize_to_string;
+use crate::environment;
+use crate::events;
+use crate::expression::{Axis, Property};
+use crate::indirect_logger;
+use crate::indirect_logger::{IndirectLogger, LogSession};
+use crate::json_logger::JsonLogger;
+use crate::log_file;
+use crate::notification;
+use crate::pid_file;
+use crate::serde::{serialize_to_string, Formatted};
+use crate::serve;
+use crate::service;
+use crate::signals;
 use crate::valid_config;
 use crate::valid_config::*;
+use crate::webhook;
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -25,55 +44,169 @@ use crate::valid_config::*;
 #[derive(Debug)]
 pub enum Error {
     // Wrapper errors.
+    Aliases(aliases::Error),
     Argument(clap::Error),
     Config(valid_config::Error),
+    Control(control::Error),
     Displays(displays::Error),
     Io(std::io::Error),
     Utf8(std::string::FromUtf8Error),
     Serde(crate::serde::Error),
     Duration(humantime::DurationError),
     LogInit(SetLoggerError),
+    PidFile(pid_file::Error),
+    Service(service::Error),
+    Webhook(webhook::Error),
 
     // knoll module errors.
     NoConfigGroups,
-    // TODO For these errors fall back to storing the configuration as a
-    //   string because we cannot thread the configured serialization
-    //   format through `std::fmt::Display`.
-    NoMatchingConfigGroup(Vec<String>),
-    NoMatchingDisplayMode(String),
-    AmbiguousDisplayMode(Vec<String>),
-    AmbiguousConfigGroup(Vec<String>),
+    NoMatchingConfigGroup(Vec<String>, Vec<String>, BTreeMap<String, String>),
+    NoMatchingDisplayMode(Formatted),
+    UnsupportedUnderscan(String),
+    UnknownDisplays(Vec<String>, BTreeMap<String, String>),
+    UnknownConfigGroup(Vec<String>),
+    DaemonRunning(PathBuf),
+    NoVirtualDisplay(PathBuf),
+    Unhealthy(Vec<String>),
+    ConfigurationIgnored(Vec<String>),
+    DaemonError(String),
+    UnexpectedResponse(String),
+    HookFailed(String, String),
+    ScriptFailed(String, String),
+    AmbiguousDisplayMode(Vec<Formatted>),
+    AmbiguousConfigGroup(Vec<Formatted>),
+    Strict(Vec<Warning>),
+    UnknownFields(Vec<UnknownField>),
+    ConfirmationUnavailable,
+    DetachUnavailable,
+    Unconfirmed,
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         use crate::knoll::Error::*;
         match self {
+            Aliases(e) => Some(e),
             Argument(e) => Some(e),
             Config(e) => Some(e),
+            Control(e) => Some(e),
             Displays(e) => Some(e),
             Io(e) => Some(e),
             Utf8(e) => Some(e),
             Serde(e) => Some(e),
             Duration(e) => Some(e),
             LogInit(e) => Some(e),
+            PidFile(e) => Some(e),
+            Service(e) => Some(e),
+            Webhook(e) => Some(e),
             _ => None,
         }
     }
 }
 
+impl Error {
+    /// The exit code the command-line tool uses for this error, so that
+    /// scripts can distinguish between categories of failure:
+    ///
+    /// * `2`: Invalid command-line arguments.
+    /// * `3`: The configuration could not be parsed or is invalid, or has
+    ///   warnings and `--strict` was given.
+    /// * `4`: No configuration group or display mode matches the displays.
+    /// * `5`: More than one configuration group, display mode, or display
+    ///   matches.
+    /// * `6`: The operating system failed to inspect or configure displays,
+    ///   did not apply a committed configuration, `knoll doctor` found a
+    ///   problem, or another I/O failure occurred.
+    /// * `7`: Communication with the daemon failed, a daemon is already
+    ///   running, or there is no virtual display to destroy.
+    /// * `8`: A hook or configuration script failed.
+    /// * `9`: The changes were not confirmed when `--confirm` was given.
+    ///
+    /// Requests for help or the version are reported as errors by argument
+    /// parsing, but exit successfully.
+    pub fn exit_code(&self) -> i32 {
+        use crate::knoll::Error::*;
+        match self {
+            Argument(ce) => ce.exit_code(),
+            Duration(_) | Webhook(_) | Aliases(aliases::Error::InvalidName(_)) => 2,
+            Config(valid_config::Error::AmbiguousUuidPrefix(_, _)) => 5,
+            Config(_)
+            | Aliases(aliases::Error::Json(_))
+            | Serde(_)
+            | Utf8(_)
+            | NoConfigGroups
+            | Strict(_)
+            | UnknownFields(_) => 3,
+            ConfirmationUnavailable | DetachUnavailable => 2,
+            NoMatchingConfigGroup(..)
+            | NoMatchingDisplayMode(_)
+            | UnsupportedUnderscan(_)
+            | UnknownDisplays(..)
+            | UnknownConfigGroup(_)
+            | Aliases(aliases::Error::UnknownAlias(_)) => 4,
+            AmbiguousConfigGroup(_) | AmbiguousDisplayMode(_) => 5,
+            PidFile(pid_file::Error::Locked(_, _)) => 7,
+            Aliases(_)
+            | Displays(_)
+            | Io(_)
+            | LogInit(_)
+            | PidFile(_)
+            | Service(_)
+            | Unhealthy(_)
+            | ConfigurationIgnored(_) => 6,
+            Control(_)
+            | DaemonRunning(_)
+            | NoVirtualDisplay(_)
+            | DaemonError(_)
+            | UnexpectedResponse(_) => 7,
+            HookFailed(_, _) | ScriptFailed(_, _) => 8,
+            Unconfirmed => 9,
+        }
+    }
+}
+
 impl From<clap::Error> for Error {
     fn from(e: clap::Error) -> Self {
         Error::Argument(e)
     }
 }
 
+impl From<aliases::Error> for Error {
+    fn from(e: aliases::Error) -> Self {
+        Error::Aliases(e)
+    }
+}
+
 impl From<valid_config::Error> for Error {
     fn from(e: valid_config::Error) -> Self {
         Error::Config(e)
     }
 }
 
+impl From<control::Error> for Error {
+    fn from(e: control::Error) -> Self {
+        Error::Control(e)
+    }
+}
+
+impl From<pid_file::Error> for Error {
+    fn from(e: pid_file::Error) -> Self {
+        Error::PidFile(e)
+    }
+}
+
+impl From<webhook::Error> for Error {
+    fn from(e: webhook::Error) -> Self {
+        Error::Webhook(e)
+    }
+}
+
+impl From<service::Error> for Error {
+    fn from(e: service::Error) -> Self {
+        Error::Service(e)
+    }
+}
+
 impl From<displays::Error> for Error {
     fn from(e: displays::Error) -> Self {
         Error::Displays(e)
@@ -110,52 +243,183 @@ impl From<SetLoggerError> for Error {
     }
 }
 
+/// Helper to write suggestions for UUIDs that did not match an attached
+/// display, keyed by the UUID given.
+fn write_suggestions(
+    f: &mut Formatter<'_>,
+    suggestions: &BTreeMap<String, String>,
+) -> std::fmt::Result {
+    if suggestions.is_empty() {
+        return Ok(());
+    }
+    let suggestions: Vec<String> = suggestions
+        .iter()
+        .map(|(given, suggested)| format!("{} for {}", suggested, given))
+        .collect();
+    write!(f, "  Did you mean {}?", suggestions.join(", "))
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use crate::knoll::Error::*;
 
         match self {
-            // TODO Detect if the output is a terminal to determine when
-            //  to use ANSI escape codes.
-            Argument(ce) => write!(f, "{}", ce.render().ansi()),
+            // ANSI escape codes are only used when the alternate flag is
+            // given, which indicates the output is a terminal.
+            Argument(ce) if f.alternate() => write!(f, "{}", ce.render().ansi()),
+            Argument(ce) => write!(f, "{}", ce.render()),
             NoConfigGroups => write!(
                 f,
                 "The parsed input contains no configuration groups.  \
             Daemon mode requires at least one configuration group."
             ),
-            NoMatchingConfigGroup(uuids) => {
+            NoMatchingConfigGroup(uuids, names, suggestions) => {
                 write!(
                     f,
                     "No configuration group matches these currently attached displays: {}.",
                     uuids.join(", ")
+                )?;
+                if !names.is_empty() {
+                    write!(
+                        f,
+                        "  The named groups considered were: {}.",
+                        names.join(", ")
+                    )?;
+                }
+                write_suggestions(f, suggestions)
+            }
+            AmbiguousConfigGroup(groups) => {
+                write!(f, "Ambiguous choice of configurations groups:")?;
+                for group in groups {
+                    write!(f, " {}", group)?;
+                }
+                Ok(())
+            }
+            NoMatchingDisplayMode(config) => {
+                write!(
+                    f,
+                    "No display mode matches the given configuration: {}",
+                    config
+                )
+            }
+            UnsupportedUnderscan(uuid) => {
+                write!(f, "Display {} does not support adjusting underscan.", uuid)
+            }
+            UnknownDisplays(uuids, suggestions) => {
+                write!(
+                    f,
+                    "No currently attached displays have these UUIDs: {}.",
+                    uuids.join(", ")
+                )?;
+                write_suggestions(f, suggestions)
+            }
+            UnknownConfigGroup(uuids) => {
+                write!(
+                    f,
+                    "No configuration group contains exactly these displays: {}.",
+                    uuids.join(", ")
                 )
             }
-            AmbiguousConfigGroup(str) => {
+            DaemonRunning(path) => {
                 write!(
                     f,
-                    "Ambiguous choice of configurations groups: {}",
-                    str.join(" ")
+                    "A daemon is already listening on the control socket {}.",
+                    path.display()
                 )
             }
-            NoMatchingDisplayMode(str) => {
+            NoVirtualDisplay(path) => {
                 write!(
                     f,
-                    "No display mode matches the given configuration: {}",
-                    str
+                    "No virtual display holds the PID file {}.",
+                    path.display()
+                )
+            }
+            Unhealthy(checks) => {
+                write!(
+                    f,
+                    "Found problems that will prevent knoll from working: {}.",
+                    checks.join(", ")
                 )
             }
-            AmbiguousDisplayMode(str) => {
-                write!(f, "Ambiguous choice of display mode: {}", str.join(" "))
+            ConfigurationIgnored(settings) => {
+                write!(
+                    f,
+                    "The configuration was committed, but macOS did not apply it: {}.",
+                    settings.join("; ")
+                )
+            }
+            ConfirmationUnavailable => write!(
+                f,
+                "Confirming changes requires standard error to be a terminal."
+            ),
+            DetachUnavailable => write!(
+                f,
+                "Detaching is only supported when running the knoll command directly."
+            ),
+            Unconfirmed => write!(f, "The changes were not confirmed, so none were made."),
+            DaemonError(msg) => write!(f, "The daemon reported an error: {}", msg),
+            UnexpectedResponse(response) => {
+                write!(f, "Unexpected response from the daemon: {}", response)
+            }
+            HookFailed(command, status) => {
+                write!(f, "The hook `{}` failed: {}", command, status)
+            }
+            ScriptFailed(command, status) => {
+                write!(
+                    f,
+                    "The configuration script `{}` failed: {}",
+                    command, status
+                )
+            }
+            AmbiguousDisplayMode(modes) => {
+                write!(f, "Ambiguous choice of display mode:")?;
+                for mode in modes {
+                    write!(f, " {}", mode)?;
+                }
+                Ok(())
+            }
+            Strict(warnings) => {
+                write!(
+                    f,
+                    "The configuration has warnings, which are treated as errors \
+                    by --strict:"
+                )?;
+                for warning in warnings {
+                    write!(f, "\n  * {}", warning)?;
+                }
+                Ok(())
+            }
+            UnknownFields(fields) => {
+                write!(f, "The configuration contains unknown fields:")?;
+                for field in fields {
+                    write!(f, "\n  * {}", field)?;
+                }
+                Ok(())
             }
             Config(ce) => {
                 write!(f, "{}", ce)
             }
+            Control(ce) => {
+                write!(f, "{}", ce)
+            }
             Displays(de) => {
                 write!(f, "{}", de)
             }
+            Aliases(ae) => {
+                write!(f, "{}", ae)
+            }
+            PidFile(pe) => {
+                write!(f, "{}", pe)
+            }
+            Service(se) => {
+                write!(f, "{}", se)
+            }
+            Webhook(we) => {
+                write!(f, "{}", we)
+            }
             Serde(se) => se.fmt(f),
             Utf8(ue) => write!(f, "Invalid UTF-8 in input: {}", ue),
-            Duration(de) => write!(f, "Invalid wait period duration: {}", de),
+            Duration(de) => write!(f, "Invalid duration: {}", de),
             LogInit(le) => write!(f, "Error initializing logger: {}", le),
 
             // TODO Not specific enough to determine input versus output error?
@@ -167,6 +431,10 @@ impl std::fmt::Display for Error {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The log target for the step by step details of applying a configuration
+/// group, which are omitted when the daemon is asked to be quiet.
+const DETAIL_TARGET: &str = "knoll::detail";
+
 /// Helper to convert a verbosity magnitude into a logging `LevelFilter`.
 /// Verbosity of `0` corresponds to only logging `Error`s.
 fn verbosity_to_filter(verbosity: usize) -> LevelFilter {
@@ -179,21 +447,110 @@ fn verbosity_to_filter(verbosity: usize) -> LevelFilter {
     }
 }
 
-/// A handle to the current global IndirectLogger.
-static GLOBAL_LOGGER: LazyLock<RwLock<Option<IndirectLogger>>> =
-    LazyLock::new(|| RwLock::new(None));
+/// Whether this process was detached from its controlling terminal by
+/// `detach`.
+static DETACHED: AtomicBool = AtomicBool::new(false);
+
+/// Detach from the controlling terminal by forking, if the arguments request
+/// a detached daemon.  The child starts a new session, and its standard input
+/// and output are redirected to `/dev/null` and its standard error to the log
+/// file.  Returns true in the parent process, which has nothing left to do,
+/// and false otherwise.  `run` refuses `--detach` unless this was called
+/// first.
+///
+/// # Safety
+///
+/// The process must not have started any other threads, as the child of a
+/// multi-threaded process may only call async-signal-safe functions.  In
+/// practice this means it can only be called at the start of `main`.
+pub unsafe fn detach(args: &Vec<String>) -> Result<bool, Error> {
+    use std::os::fd::AsRawFd;
+
+    // Invalid arguments are left to be reported by `run`.
+    let Ok(matches) = argument_parse(args) else {
+        return Ok(false);
+    };
+    if !detach_requested(&matches) {
+        return Ok(false);
+    }
+    let log_path = log_file_path(&matches, true).expect("A log file is always used when detaching");
+
+    // SAFETY: The caller guarantees that no other threads have been started,
+    // so the child process is in a consistent state after forking.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            let null = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/null")?;
+            let log = log_file::open_append(&log_path)?;
+            // SAFETY: The file descriptors are valid for the duration of the
+            // calls, and dup2 leaves the targets open after they are dropped.
+            unsafe {
+                if libc::setsid() == -1
+                    || libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) == -1
+                    || libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) == -1
+                    || libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) == -1
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+            }
+            DETACHED.store(true, Ordering::SeqCst);
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Helper to determine whether the arguments request a detached daemon.
+fn detach_requested(matches: &ArgMatches) -> bool {
+    matches
+        .subcommand_matches("daemon")
+        .is_some_and(|sub_matches| sub_matches.get_flag("DETACH"))
+}
+
+/// Helper to determine the file to log to, if any.  When detaching, there
+/// will be no terminal to log to, so a log file is always used.
+fn log_file_path(matches: &ArgMatches, detach: bool) -> Option<PathBuf> {
+    matches
+        .get_one::<PathBuf>("LOG_FILE")
+        .cloned()
+        .or_else(|| detach.then(|| service::log_path(service::DEFAULT_LABEL)))
+}
+
+/// A handle to the global IndirectLogger, once it has been initialized.
+/// This must be global, as the `log` crate only allows the logger to be set
+/// once per process, but each invocation of knoll logs to its own session of
+/// it, so that several may run within the same process.
+static GLOBAL_LOGGER: Mutex<Option<IndirectLogger>> = Mutex::new(None);
 
 /// Helper to configure the logger by verbosity and depending on whether it
-/// is writing to a terminal or not.
-fn configure_logger<ERR: Write + IsTerminal + Send + 'static>(
+/// is writing to a terminal or not.  Returns the logging session, which
+/// lasts until it is dropped.
+fn configure_logger<ERR: Write + Send + 'static>(
     verbosity: usize,
     stderr: ERR,
-) -> Result<(), SetLoggerError> {
+    terminal: bool,
+    json: bool,
+    quiet: bool,
+) -> Result<LogSession, SetLoggerError> {
     let mut config_builder = simplelog::ConfigBuilder::new();
     config_builder.set_time_format_rfc3339();
+    if quiet {
+        config_builder.add_filter_ignore_str(DETAIL_TARGET);
+    }
 
     let level_filter = verbosity_to_filter(verbosity);
-    let session_logger: Box<dyn SharedLogger> = if stderr.is_terminal() {
+    let session_logger: Box<dyn SharedLogger> = if json {
+        // JSON logs are intended for tools, so are never colored.
+        let logger = JsonLogger::new(level_filter, stderr);
+        if quiet {
+            logger.ignore_target(DETAIL_TARGET)
+        } else {
+            logger
+        }
+    } else if terminal {
         // If the destination is a terminal, use the `Termlogger`.
         TermLogger::new(
             level_filter,
@@ -206,23 +563,37 @@ fn configure_logger<ERR: Write + IsTerminal + Send + 'static>(
         WriteLogger::new(level_filter, config_builder.build(), stderr)
     };
 
-    // Update or initialize the global logger.
-    let mut opt_logger = GLOBAL_LOGGER.write().unwrap();
-    match opt_logger.as_mut() {
-        Some(logger) => logger.update(session_logger),
-        None => {
-            *opt_logger = Some(IndirectLogger::init(session_logger)?);
-        }
-    }
-
-    Ok(())
+    // Initialize the global logger if necessary, and start a session of it.
+    let mut opt_logger = GLOBAL_LOGGER.lock().unwrap_or_else(|pe| pe.into_inner());
+    let logger = match opt_logger.as_ref() {
+        Some(logger) => logger,
+        None => opt_logger.insert(IndirectLogger::init()?),
+    };
+    Ok(logger.start_session(session_logger))
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The outcome of running knoll, so that callers do not need to parse
+/// the output to find out what happened.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunResult {
+    /// The display state once knoll finished, for modes that apply a single
+    /// configuration.
+    pub state: Option<ConfigGroups>,
+    /// The UUIDs of the configuration group that was applied, if any was.
+    pub applied_group: Option<Vec<String>>,
+    /// Whether any changes were made to the displays.
+    pub changed: bool,
+    /// The exit code the command-line tool uses on success.  This is `1`
+    /// when `--exit-code` was given and changes were made, and otherwise `0`.
+    pub exit_code: i32,
+}
+
 /// Generic entry point to the knoll command-line tool.  
 /// It is parameterized by the DisplayState implementation as well as
-/// the input, output, and error targets.
+/// the input, output, and error targets.  On success, returns the exit code
+/// to use.
 pub fn run<
     'l,
     DS: DisplayState,
@@ -234,7 +605,23 @@ pub fn run<
     stdin: IN,
     stdout: OUT,
     stderr: ERR,
-) -> Result<(), Error> {
+) -> Result<i32, Error> {
+    run_structured::<DS, IN, OUT, ERR>(args, stdin, stdout, stderr).map(|result| result.exit_code)
+}
+
+/// Variant of `run` that also returns the outcome of running knoll.
+pub fn run_structured<
+    'l,
+    DS: DisplayState,
+    IN: Read + IsTerminal,
+    OUT: Write + 'l,
+    ERR: Write + IsTerminal + Send + 'static,
+>(
+    args: &Vec<String>,
+    stdin: IN,
+    stdout: OUT,
+    stderr: ERR,
+) -> Result<RunResult, Error> {
     // Handle parsing the command-line arguments.
     let matches = argument_parse(args)?;
 
@@ -247,47 +634,465 @@ pub fn run<
         _ => panic!("Invalid serialization format"),
     };
 
+    // Standard error is shared between logging and diagnostics.
+    let terminal = stderr.is_terminal();
+    let stderr = SharedWriter::new(stderr);
+
+    // Forking is only safe before any threads have started, which cannot be
+    // known here, so the process must already have been detached by `detach`.
+    let detach = detach_requested(&matches);
+    if detach && !DETACHED.load(Ordering::SeqCst) {
+        return Err(Error::DetachUnavailable);
+    }
+
     // Set up logging.
     let verbosity = matches.get_count("VERBOSITY").into();
-    configure_logger(verbosity, stderr)?;
+    let json_logs = matches
+        .get_one::<String>("LOG_FORMAT")
+        .is_some_and(|log_format| log_format == "json");
+    let quiet_daemon = matches
+        .subcommand_matches("daemon")
+        .is_some_and(|sub_matches| sub_matches.get_flag("QUIET"));
+    let log_file = log_file_path(&matches, detach);
+    let _log_session = match &log_file {
+        Some(path) => {
+            let rotation = log_file::Rotation {
+                max_size: matches.get_one::<u64>("LOG_MAX_SIZE").copied(),
+                max_age: matches
+                    .get_one::<String>("LOG_MAX_AGE")
+                    .map(|age| humantime::parse_duration(age))
+                    .transpose()?,
+                // Calling unwrap here should be okay, as there is a default value.
+                keep: *matches.get_one::<usize>("LOG_KEEP").unwrap(),
+            };
+            let file = log_file::RotatingFile::open(path, rotation)?;
+            configure_logger(verbosity, file, false, json_logs, quiet_daemon)?
+        }
+        None => configure_logger(verbosity, stderr.clone(), terminal, json_logs, quiet_daemon)?,
+    };
+    let strict = matches.get_flag("STRICT");
+    let diagnostics = Diagnostics::new(stderr, strict);
+    // Strict checking of warnings implies strict checking of fields.
+    let strict_config = strict || matches.get_flag("STRICT_CONFIG");
+
+    // Determine how display modes should be selected.
+    let selection = mode_selection(&matches);
+    // Displays to leave out of matching and configuration entirely.
+    let ignore = ignored_displays(&matches);
+
+    // Aliases may be used in place of UUIDs anywhere a display is named.
+    let aliases_path = matches
+        .get_one::<PathBuf>("ALIASES_FILE")
+        .cloned()
+        .unwrap_or_else(aliases::default_aliases_path);
+    let aliases = Aliases::load(&aliases_path)?;
 
     // Check to see which program mode should be used.
     match matches.subcommand() {
         Some(("daemon", sub_matches)) => {
             info!("Daemon mode selected.");
 
-            let config_reader =
-                ConfigReader::new(format, stdin, sub_matches.get_one::<PathBuf>("IN"))?;
+            if detach {
+                info!("Detached daemon with pid {}.", std::process::id());
+            }
+
+            let config_reader = ConfigReader::new(
+                format,
+                stdin,
+                sub_matches.get_one::<PathBuf>("IN"),
+                config_script::<DS>(sub_matches),
+                diagnostics,
+                strict_config,
+                aliases,
+            )?
+            .ignoring(ignore);
 
             // Calling unwrap here should be okay, as there is a default value.
             let wait_string = sub_matches.get_one::<String>("WAIT").unwrap();
             let wait_period = humantime::parse_duration(wait_string)?;
-            daemon_command::<DS>(config_reader, format, wait_period)
+            let max_wait_string = sub_matches.get_one::<String>("MAX_WAIT").unwrap();
+            let max_wait = humantime::parse_duration(max_wait_string)?;
+            let wake_wait_string = sub_matches.get_one::<String>("WAKE_WAIT").unwrap();
+            let wake_wait = humantime::parse_duration(wake_wait_string)?;
+            let cooldown_string = sub_matches.get_one::<String>("COOLDOWN").unwrap();
+            let cooldown = humantime::parse_duration(cooldown_string)?;
+            let enforce = if sub_matches.get_flag("ENFORCE") {
+                let interval_string = sub_matches.get_one::<String>("ENFORCE_INTERVAL").unwrap();
+                Some(humantime::parse_duration(interval_string)?)
+            } else {
+                None
+            };
+            let retry_delay_string = sub_matches.get_one::<String>("RETRY_DELAY").unwrap();
+            let retry_policy = RetryPolicy {
+                // Calling unwrap here should be okay, as there is a default value.
+                retries: *sub_matches.get_one::<u32>("RETRIES").unwrap(),
+                delay: humantime::parse_duration(retry_delay_string)?,
+            };
+            // Only listen for control requests if requested.
+            let control_socket =
+                if sub_matches.get_flag("CONTROL") || sub_matches.contains_id("SOCKET") {
+                    Some(socket_path(sub_matches))
+                } else {
+                    None
+                };
+            let pid_file = sub_matches
+                .get_one::<PathBuf>("PID_FILE")
+                .cloned()
+                .unwrap_or_else(pid_file::default_pid_file_path);
+            let webhook = sub_matches
+                .get_one::<String>("WEBHOOK")
+                .map(|url| webhook::Webhook::parse(url))
+                .transpose()?;
+            let options = DaemonOptions {
+                wait_period,
+                max_wait,
+                wake_wait,
+                cooldown,
+                retry_policy,
+                selection,
+                on_change: on_change_hook(&matches),
+                commit: commit_options(&matches)?,
+                webhook,
+                notify: sub_matches.get_flag("NOTIFY"),
+                metrics_file: sub_matches.get_one::<PathBuf>("METRICS_FILE").cloned(),
+                observe: sub_matches.get_flag("OBSERVE"),
+                events: sub_matches.get_flag("EVENTS"),
+                output: sub_matches.get_one::<PathBuf>("OUT").cloned(),
+                append: sub_matches.get_flag("APPEND"),
+                control_socket,
+                pid_file,
+                replace: sub_matches.get_flag("REPLACE"),
+                max_events: sub_matches.get_one::<u64>("MAX_EVENTS").copied(),
+                enforce,
+            };
+            daemon_command::<DS>(config_reader, format, options).map(|_| RunResult::default())
+        }
+        Some(("trigger", sub_matches)) => {
+            info!("Trigger mode selected.");
+
+            trigger_command(&socket_path(sub_matches), format).map(|_| RunResult::default())
+        }
+        Some(("status", sub_matches)) => {
+            info!("Status mode selected.");
+
+            let mut output = open_output(stdout, sub_matches)?;
+            status_command(&socket_path(sub_matches), &mut output, format)?;
+            output.finish()?;
+            Ok(RunResult::default())
+        }
+        Some(("service", sub_matches)) => {
+            info!("Service mode selected.");
+
+            service_command(sub_matches, stdout, format).map(|_| RunResult::default())
+        }
+        Some(("virtual", sub_matches)) => {
+            info!("Virtual display mode selected.");
+
+            virtual_command::<DS, OUT>(sub_matches, stdout).map(|_| RunResult::default())
+        }
+        Some(("bench", sub_matches)) => {
+            info!("Bench mode selected.");
+
+            let config_reader = ConfigReader::new(
+                format,
+                stdin,
+                sub_matches.get_one::<PathBuf>("IN"),
+                config_script::<DS>(sub_matches),
+                diagnostics,
+                strict_config,
+                aliases,
+            )?
+            .ignoring(ignore);
+            let mut output = open_output(stdout, sub_matches)?;
+            // Calling unwrap here should be okay, as there is a default value.
+            let iterations = *sub_matches.get_one::<u32>("ITERATIONS").unwrap();
+            bench_command::<DS>(
+                config_reader,
+                &mut output,
+                format,
+                &selection,
+                iterations,
+                sub_matches.get_flag("DRY_RUN"),
+            )?;
+            output.finish()?;
+            Ok(RunResult::default())
+        }
+        Some(("doctor", sub_matches)) => {
+            info!("Doctor mode selected.");
+
+            let report = crate::doctor::examine(&DS::system_facts());
+            let mut output = open_output(stdout, sub_matches)?;
+            crate::serde::serialize(format, &report, &mut output)?;
+            output.finish()?;
+            let errors = report.errors();
+            if errors.is_empty() {
+                Ok(RunResult::default())
+            } else {
+                Err(Error::Unhealthy(errors))
+            }
+        }
+        Some(("sleep", _)) => {
+            info!("Sleep mode selected.");
+
+            DS::sleep_displays()?;
+            Ok(RunResult::default())
+        }
+        Some(("wake", _)) => {
+            info!("Wake mode selected.");
+
+            DS::wake_displays()?;
+            Ok(RunResult::default())
+        }
+        Some(("alias", sub_matches)) => {
+            info!("Alias mode selected.");
+
+            alias_command(sub_matches, aliases, &aliases_path, stdout, format)
+                .map(|_| RunResult::default())
+        }
+        Some(("serve", sub_matches)) => {
+            info!("Serve mode selected.");
+
+            // Responses may be written from more than one thread once
+            // subscribed, so the output must be shareable.
+            let output = match sub_matches.get_one::<PathBuf>("OUT") {
+                Some(path) => {
+                    SharedWriter::new(create_output_file(path, sub_matches.get_flag("APPEND"))?)
+                }
+                None => SharedWriter::new(std::io::stdout()),
+            };
+            let options = PipelineOptions {
+                quiet: false,
+                selection,
+                on_change: on_change_hook(&matches),
+                detailed: false,
+                exit_code: false,
+                commit: commit_options(&matches)?,
+            };
+            serve_command::<DS>(
+                BufReader::new(stdin),
+                output,
+                diagnostics,
+                strict_config,
+                &aliases,
+                &options,
+            )
+            .map(|_| RunResult::default())
+        }
+        Some(("state", sub_matches)) => {
+            info!("State mode selected.");
+
+            let mut output = open_output(stdout, sub_matches)?;
+            state_command::<DS>(
+                &mut output,
+                format,
+                sub_matches.get_flag("DETAILED"),
+                sub_matches.get_flag("NORMALIZE"),
+            )?;
+            output.finish()?;
+            Ok(RunResult::default())
         }
         Some(("list", sub_matches)) => {
             info!("List mode selected.");
 
-            let mut output = open_output(stdout, sub_matches.get_one::<PathBuf>("OUT"))?;
-            list_command::<DS>(output.as_mut(), format)
+            let mut output = open_output(stdout, sub_matches)?;
+            let options = ListOptions {
+                all_modes: sub_matches.get_flag("ALL_MODES"),
+                detailed: sub_matches.get_flag("DETAILED"),
+                compact: sub_matches.get_flag("COMPACT"),
+                duplicates: duplicate_modes(sub_matches),
+                sort: sub_matches.get_one::<String>("SORT").map(|s| {
+                    ModeSort::try_from(s.as_str())
+                        .expect("Sort orders should be validated by argument parsing.")
+                }),
+                by_resolution: sub_matches.get_flag("BY_RESOLUTION"),
+                displays: sub_matches.get_flag("DISPLAYS"),
+                uuids: sub_matches
+                    .get_many::<String>("UUIDS")
+                    .map(|values| values.map(|value| aliases.resolve(value)).collect())
+                    .unwrap_or_default(),
+            };
+            list_command::<DS>(&mut output, format, &aliases, &options)?;
+            output.finish()?;
+            Ok(RunResult::default())
         }
         _ => {
             info!("Pipeline mode selected.");
             // Should we print the resulting configuration?
             let quiet = matches.get_flag("QUIET");
-            let config_reader = ConfigReader::new(format, stdin, matches.get_one::<PathBuf>("IN"))?;
-            let mut output = open_output(stdout, matches.get_one::<PathBuf>("OUT"))?;
+            // The changes are shown on the terminal, so there must be one
+            // to confirm them on.
+            let confirm = matches.get_flag("CONFIRM");
+            if confirm && !terminal {
+                return Err(Error::ConfirmationUnavailable);
+            }
+            let options = PipelineOptions {
+                quiet,
+                selection,
+                on_change: on_change_hook(&matches),
+                detailed: matches.get_flag("DETAILED"),
+                exit_code: matches.get_flag("EXIT_CODE"),
+                commit: CommitOptions {
+                    confirm,
+                    ..commit_options(&matches)?
+                },
+            };
+
+            // In streaming mode, each line of the input is a separate
+            // configuration to apply in turn.
+            if matches.get_flag("STREAM") {
+                let input: Box<dyn Read> = match matches.get_one::<PathBuf>("IN") {
+                    Some(path) => Box::new(std::fs::File::open(path)?),
+                    None => Box::new(stdin),
+                };
+                let mut output = open_output(stdout, &matches)?;
+                let reader = |line| {
+                    ConfigReader::from_string(
+                        format,
+                        line,
+                        diagnostics.clone(),
+                        strict_config,
+                        aliases.clone(),
+                    )
+                    .ignoring(ignore.clone())
+                };
+                stream_command::<DS>(input, reader, &mut output, format, &options)?;
+                output.finish()?;
+                return Ok(RunResult::default());
+            }
 
-            pipeline_command::<DS>(quiet, config_reader, output.as_mut(), format)
+            let config_reader = ConfigReader::new(
+                format,
+                stdin,
+                matches.get_one::<PathBuf>("IN"),
+                config_script::<DS>(&matches),
+                diagnostics,
+                strict_config,
+                aliases,
+            )?
+            .ignoring(ignore);
+            let mut output = open_output(stdout, &matches)?;
+            let result = pipeline_command::<DS>(config_reader, &mut output, format, &options)?;
+            output.finish()?;
+            Ok(result)
         }
     }
 }
 
+/// Helper to obtain the path of the control socket from the command-line
+/// arguments, falling back to the default location.
+fn socket_path(matches: &ArgMatches) -> PathBuf {
+    matches
+        .get_one::<PathBuf>("SOCKET")
+        .cloned()
+        .unwrap_or_else(control::default_socket_path)
+}
+
+/// Helper to extract the displays to ignore from the command-line
+/// arguments.
+fn ignored_displays(matches: &ArgMatches) -> Vec<String> {
+    matches
+        .get_many::<String>("IGNORE")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Helper to extract the requested `ModeSelection` from the command-line
+/// arguments.  The preference choices have already been restricted by the
+/// argument parser, so conversion should not fail.
+fn mode_selection(matches: &ArgMatches) -> ModeSelection {
+    let preferences = matches
+        .get_many::<String>("PREFER")
+        .map(|values| {
+            values
+                .map(|s| {
+                    ModePreference::try_from(s.as_str())
+                        .expect("Mode preferences should be validated by argument parsing.")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    ModeSelection {
+        preferences,
+        fuzzy: matches.get_flag("FUZZY"),
+        duplicates: duplicate_modes(matches),
+    }
+}
+
+/// Helper to extract the requested `DuplicateModes` policy from the
+/// command-line arguments.  The choices have already been restricted by the
+/// argument parser, so conversion should not fail.
+fn duplicate_modes(matches: &ArgMatches) -> DuplicateModes {
+    matches
+        .get_one::<String>("DUPLICATE_MODES")
+        .map(|s| {
+            DuplicateModes::try_from(s.as_str())
+                .expect("Duplicate mode policies should be validated by argument parsing.")
+        })
+        .unwrap_or_default()
+}
+
+/// Helper to extract the command to run after applying a configuration group
+/// from the command-line arguments, if any.
+fn on_change_hook(matches: &ArgMatches) -> Option<String> {
+    matches.get_one::<String>("ON_CHANGE").cloned()
+}
+
+/// Helper to extract how configuration changes should be committed from
+/// the command-line arguments.
+fn commit_options(matches: &ArgMatches) -> Result<CommitOptions, Error> {
+    let scope = matches
+        .get_one::<String>("SCOPE")
+        .map(|s| {
+            ConfigurationScope::try_from(s.as_str())
+                .expect("Configuration scopes should be validated by argument parsing.")
+        })
+        .unwrap_or_default();
+    let fade = matches
+        .get_one::<String>("FADE")
+        .map(|fade| humantime::parse_duration(fade))
+        .transpose()?;
+    let backup = matches
+        .get_one::<String>("BACKUP")
+        .map(|backup| match backup.as_str() {
+            "auto" => default_backup_path(),
+            path => PathBuf::from(path),
+        });
+    // A timeout of zero disables it.
+    let timeout = matches
+        .get_one::<String>("COMMIT_TIMEOUT")
+        .map(|timeout| humantime::parse_duration(timeout))
+        .transpose()?
+        .filter(|timeout| !timeout.is_zero());
+    Ok(CommitOptions {
+        scope,
+        fade,
+        backup,
+        timeout,
+        confirm: false,
+        abandoned: AbandonedCommit::default(),
+    })
+}
+
+/// Parse display extents given as a width and height separated by a comma,
+/// for example `1920,1080`.  Suitable for use as a clap value parser.
+fn parse_extents(str: &str) -> Result<Point, String> {
+    str.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .filter(|&(x, y): &(i64, i64)| x > 0 && y > 0)
+        .map(|(x, y)| Point { x, y })
+        .ok_or_else(|| format!("Invalid extents {}, expected e.g. 1920,1080", str))
+}
+
 /// Helper for parsing the command-line arguments.
 fn argument_parse(args: &Vec<String>) -> Result<ArgMatches, clap::Error> {
     // Clap argument parsing setup.
 
     let in_arg = Arg::new("IN")
-        .help("File to read from instead of standard input")
+        .help(
+            "File to read from instead of standard input, or a Rhai script to evaluate \
+            if it has a .rhai extension",
+        )
         .long("input")
         .short('i')
         .value_parser(clap::value_parser!(std::path::PathBuf));
@@ -297,7 +1102,36 @@ fn argument_parse(args: &Vec<String>) -> Result<ArgMatches, clap::Error> {
         .short('o')
         .value_parser(clap::value_parser!(std::path::PathBuf));
     let file_args = [in_arg.clone(), out_arg.clone()];
+    let script_arg = Arg::new("SCRIPT")
+        .long("script")
+        .value_name("COMMAND")
+        .help(
+            "Shell command that writes the configuration to standard output, \
+            given the current display state on standard input",
+        )
+        .conflicts_with("IN")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
+
+    let exit_code_arg = Arg::new("EXIT_CODE")
+        .long("exit-code")
+        .help("Exit with a status of 1 if the displays were changed, rather than 0")
+        .conflicts_with("STREAM")
+        .action(ArgAction::SetTrue);
 
+    let stream_arg = Arg::new("STREAM")
+        .long("stream")
+        .help(
+            "Read a stream of newline-delimited configurations, applying each in turn \
+            and writing the resulting state after each",
+        )
+        .action(ArgAction::SetTrue);
+    let state_detailed_arg = Arg::new("DETAILED")
+        .long("detailed")
+        .help(
+            "Include the display ID of each display and the mode id of its display mode \
+            in the resulting state",
+        )
+        .action(ArgAction::SetTrue);
     let quiet_arg = Arg::new("QUIET")
         .short('q')
         .long("quiet")
@@ -310,6 +1144,58 @@ fn argument_parse(args: &Vec<String>) -> Result<ArgMatches, clap::Error> {
         .help("Increase verbosity of information emitted to stderr")
         .action(ArgAction::Count)
         .global(true);
+    let log_file_arg = Arg::new("LOG_FILE")
+        .long("log-file")
+        .help("File to append log output to instead of stderr")
+        .value_parser(clap::value_parser!(std::path::PathBuf))
+        .global(true);
+    let log_format_arg = Arg::new("LOG_FORMAT")
+        .long("log-format")
+        .help("Choose log output format")
+        .default_value("text")
+        .value_parser(["text", "json"])
+        .global(true);
+    let log_max_size_arg = Arg::new("LOG_MAX_SIZE")
+        .long("log-max-size")
+        .help("Rotate the log file once it reaches this size, e.g. 10M")
+        .value_parser(log_file::parse_size)
+        .global(true);
+    let log_max_age_arg = Arg::new("LOG_MAX_AGE")
+        .long("log-max-age")
+        .help("Rotate the log file once it is this old, e.g. 1day")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .global(true);
+    let log_keep_arg = Arg::new("LOG_KEEP")
+        .long("log-keep")
+        .help("How many rotated log files to keep")
+        .default_value("5")
+        .value_parser(clap::value_parser!(usize))
+        .global(true);
+    let aliases_file_arg = Arg::new("ALIASES_FILE")
+        .long("aliases-file")
+        .help("Location of the display alias store [default: ~/.config/knoll/aliases]")
+        .value_parser(clap::value_parser!(std::path::PathBuf))
+        .global(true);
+    let append_arg = Arg::new("APPEND")
+        .long("append")
+        .help("Append to the output file rather than replacing it")
+        .action(ArgAction::SetTrue)
+        .global(true);
+    let strict_arg = Arg::new("STRICT")
+        .long("strict")
+        .help("Treat warnings about the configuration as errors")
+        .action(ArgAction::SetTrue)
+        .global(true);
+    let stdio_arg = Arg::new("STDIO")
+        .long("stdio")
+        .help("Read requests from standard input and write responses to standard output")
+        .action(ArgAction::SetTrue)
+        .required(true);
+    let strict_config_arg = Arg::new("STRICT_CONFIG")
+        .long("strict-config")
+        .help("Reject configurations containing unknown fields")
+        .action(ArgAction::SetTrue)
+        .global(true);
     let format_arg = Arg::new("FORMAT")
         .long("format")
         .help("Choose serialization format")
@@ -317,41 +1203,461 @@ fn argument_parse(args: &Vec<String>) -> Result<ArgMatches, clap::Error> {
         .value_parser(["json", "ron"])
         .global(true);
 
+    let prefer_arg = Arg::new("PREFER")
+        .help(
+            "Policy for choosing amongst multiple matching display modes.  \
+            May be repeated, later policies break ties left by earlier ones",
+        )
+        .long("prefer")
+        .short('p')
+        .action(ArgAction::Append)
+        .value_parser(ModePreference::VALUES.map(|p| p.name()))
+        .global(true);
+
+    let duplicate_modes_arg = Arg::new("DUPLICATE_MODES")
+        .long("duplicate-modes")
+        .help(
+            "Policy for display modes with identical properties: keep the first \
+            reported, keep the one with the highest mode id, or keep them all",
+        )
+        .default_value(DuplicateModes::default().name())
+        .value_parser(DuplicateModes::VALUES.map(|p| p.name()))
+        .global(true);
+
+    let ignore_arg = Arg::new("IGNORE")
+        .long("ignore")
+        .value_name("UUID")
+        .help(
+            "Ignore the display with this UUID or alias, so that it is never matched \
+            against configuration groups or configured.  May be repeated",
+        )
+        .action(ArgAction::Append)
+        .global(true);
+
+    let fuzzy_arg = Arg::new("FUZZY")
+        .long("fuzzy")
+        .help("Use the closest display mode when no display mode matches exactly")
+        .action(ArgAction::SetTrue)
+        .global(true);
+
+    let on_change_arg = Arg::new("ON_CHANGE")
+        .long("on-change")
+        .help("Shell command to run after applying a configuration group")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .global(true);
+
+    let scope_arg = Arg::new("SCOPE")
+        .long("scope")
+        .help(
+            "How long configuration changes should last: until knoll exits, \
+            until the user logs out, or permanently",
+        )
+        .default_value(ConfigurationScope::default().name())
+        .value_parser(ConfigurationScope::VALUES.map(|s| s.name()))
+        .global(true);
+
+    let backup_arg = Arg::new("BACKUP")
+        .long("backup")
+        .value_name("PATH|auto")
+        .help(
+            "Write the state of the displays to this file before changing them, \
+            or to ~/.local/state/knoll/backup if auto",
+        )
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .global(true);
+
+    let commit_timeout_arg = Arg::new("COMMIT_TIMEOUT")
+        .long("commit-timeout")
+        .help(
+            "Give up on committing configuration changes that take longer than this, \
+            rather than waiting indefinitely",
+        )
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .global(true);
+
+    let confirm_arg = Arg::new("CONFIRM")
+        .long("confirm")
+        .help("Show the changes and ask on the terminal before applying them")
+        .action(ArgAction::SetTrue);
+
+    let fade_arg = Arg::new("FADE")
+        .long("fade")
+        .help("Fade the displays out and back in over the given duration while changing them")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+        .global(true);
+
+    let webhook_arg = Arg::new("WEBHOOK")
+        .long("webhook")
+        .help("http URL to POST a JSON notification to after each reconfiguration")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
+
+    let notify_arg = Arg::new("NOTIFY")
+        .long("notify")
+        .help("Post a Notification Center message after each reconfiguration")
+        .action(ArgAction::SetTrue);
+
+    let metrics_file_arg = Arg::new("METRICS_FILE")
+        .long("metrics-file")
+        .help("File to write daemon metrics to after each reconfiguration")
+        .value_parser(clap::value_parser!(std::path::PathBuf));
+
+    let observe_arg = Arg::new("OBSERVE")
+        .long("observe")
+        .help("Log the configuration that would be applied without changing the displays")
+        .action(ArgAction::SetTrue);
+
+    let detach_arg = Arg::new("DETACH")
+        .long("detach")
+        .help("Run the daemon in the background, logging to a file")
+        .action(ArgAction::SetTrue);
+
+    let events_arg = Arg::new("EVENTS")
+        .long("events")
+        .help("Write a JSON line to the output for each display and configuration change")
+        .action(ArgAction::SetTrue);
+
     let wait_arg = Arg::new("WAIT")
-        .help("Home long to wait after a reconfiguation event to update")
+        .help("How long reconfiguration events must stop for before updating")
         .long("wait")
         .short('w')
         .default_value("2s")
         .value_parser(clap::builder::NonEmptyStringValueParser::new());
 
-    let cmd = Command::new("knoll")
-        .version(clap::crate_version!())
-        .about("Tool for configuring and arranging displays")
-        .args(vec![quiet_arg, verbose_arg, format_arg])
-        .args(&file_args)
-        .subcommands([
-            Command::new("daemon")
-                .about("Run in daemon mode updating when the hardware configuration changes")
-                .arg(in_arg)
-                .arg(wait_arg),
-            Command::new("list")
-                .about("Print information about available display modes")
-                .arg(out_arg),
-        ]);
+    let max_wait_arg = Arg::new("MAX_WAIT")
+        .help("The longest to wait for reconfiguration events to stop before updating")
+        .long("max-wait")
+        .default_value("10s")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
 
-    cmd.try_get_matches_from(args)
-}
+    let wake_wait_arg = Arg::new("WAKE_WAIT")
+        .help("How long reconfiguration events must stop for before updating after waking")
+        .long("wake-wait")
+        .default_value("5s")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
 
-////////////////////////////////////////////////////////////////////////////////
+    let cooldown_arg = Arg::new("COOLDOWN")
+        .help("How long to ignore reconfiguration events after an update")
+        .long("cooldown")
+        .default_value("0s")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
 
-/// `ConfigReader` is a helper to abstract away from reading the configuration,
-/// rather than just reading it directly.  Daemon mode takes advantage of this
-/// so that it is possible to update the configuration without having to restart
+    let retries_arg = Arg::new("RETRIES")
+        .help("How many times to retry when the displays fail to reconfigure")
+        .long("retries")
+        .default_value("3")
+        .value_parser(clap::value_parser!(u32));
+
+    let retry_delay_arg = Arg::new("RETRY_DELAY")
+        .help("How long to wait before the first retry, doubling for each retry after")
+        .long("retry-delay")
+        .default_value("500ms")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
+
+    let enforce_arg = Arg::new("ENFORCE")
+        .long("enforce")
+        .help("Periodically reapply the configuration if the displays drift from it")
+        .conflicts_with("OBSERVE")
+        .action(ArgAction::SetTrue);
+
+    let enforce_interval_arg = Arg::new("ENFORCE_INTERVAL")
+        .help("How often to check whether the displays have drifted when enforcing")
+        .long("enforce-interval")
+        .default_value("30s")
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
+
+    let all_modes_arg = Arg::new("ALL_MODES")
+        .long("all-modes")
+        .help("Also list display modes that are not usable for the desktop")
+        .action(ArgAction::SetTrue);
+
+    let detailed_arg = Arg::new("DETAILED")
+        .long("detailed")
+        .help("Include low-level details such as mode numbers and IO flags")
+        .action(ArgAction::SetTrue);
+
+    let compact_arg = Arg::new("COMPACT")
+        .long("compact")
+        .help("Print one display mode per line instead of serializing")
+        .conflicts_with("DETAILED")
+        .action(ArgAction::SetTrue);
+
+    let sort_arg = Arg::new("SORT")
+        .long("sort")
+        .help("Sort the display modes, largest first, instead of listing them as reported")
+        .value_parser(ModeSort::VALUES.map(|s| s.name()));
+
+    let by_resolution_arg = Arg::new("BY_RESOLUTION")
+        .long("by-resolution")
+        .help("Nest the display modes of each display by their resolution")
+        .conflicts_with("COMPACT")
+        .action(ArgAction::SetTrue);
+
+    let normalize_arg = Arg::new("NORMALIZE")
+        .long("normalize")
+        .help(
+            "Place the upper left corner of the desktop at the origin, rather than \
+            the upper left corner of the main display",
+        )
+        .action(ArgAction::SetTrue);
+
+    let displays_arg = Arg::new("DISPLAYS")
+        .long("displays")
+        .help("Summarize the current settings of each display instead of listing display modes")
+        .conflicts_with_all(["ALL_MODES", "DETAILED", "COMPACT", "SORT", "BY_RESOLUTION"])
+        .action(ArgAction::SetTrue);
+
+    let uuids_arg = Arg::new("UUIDS")
+        .help("Only list the display modes of the displays with these UUIDs or aliases")
+        .action(ArgAction::Append)
+        .num_args(0..);
+
+    let control_arg = Arg::new("CONTROL")
+        .long("control")
+        .help("Listen for control requests on a unix domain socket")
+        .action(ArgAction::SetTrue);
+
+    let socket_arg = Arg::new("SOCKET")
+        .long("socket")
+        .help(
+            "Location of the control socket, implies --control [default: ~/.local/run/knoll.sock]",
+        )
+        .value_parser(clap::value_parser!(std::path::PathBuf));
+
+    let pid_file_arg = Arg::new("PID_FILE")
+        .long("pid-file")
+        .help("Location of the PID file [default: ~/.local/run/knoll.pid]")
+        .value_parser(clap::value_parser!(std::path::PathBuf));
+
+    let virtual_pid_file_arg = Arg::new("PID_FILE")
+        .long("pid-file")
+        .help("Location of the PID file [default: ~/.local/run/knoll-virtual.pid]")
+        .value_parser(clap::value_parser!(std::path::PathBuf));
+
+    let replace_arg = Arg::new("REPLACE")
+        .long("replace")
+        .help("Stop an already running daemon rather than refusing to start")
+        .action(ArgAction::SetTrue);
+
+    let max_events_arg = Arg::new("MAX_EVENTS")
+        .long("max-events")
+        .help("Exit after this many reconfigurations, including the initial one")
+        .value_parser(clap::value_parser!(u64).range(1..));
+    let daemon_quiet_arg = Arg::new("QUIET")
+        .short('q')
+        .long("quiet")
+        .help("Only log a single line summarizing each reconfiguration")
+        .action(ArgAction::SetTrue);
+
+    let label_arg = Arg::new("LABEL")
+        .long("label")
+        .help("Label of the launchd agent")
+        .default_value(service::DEFAULT_LABEL)
+        .value_parser(clap::builder::NonEmptyStringValueParser::new());
+
+    let cmd = Command::new("knoll")
+        .version(clap::crate_version!())
+        .about("Tool for configuring and arranging displays")
+        .args(vec![
+            quiet_arg,
+            state_detailed_arg.clone(),
+            stream_arg,
+            script_arg.clone().conflicts_with("STREAM"),
+            exit_code_arg,
+            verbose_arg,
+            log_file_arg,
+            log_format_arg,
+            log_max_size_arg,
+            log_max_age_arg,
+            log_keep_arg,
+            aliases_file_arg,
+            format_arg,
+            append_arg,
+            strict_arg,
+            strict_config_arg,
+            prefer_arg,
+            duplicate_modes_arg,
+            ignore_arg,
+            fuzzy_arg,
+            on_change_arg,
+            scope_arg,
+            fade_arg,
+            backup_arg,
+            commit_timeout_arg,
+            confirm_arg,
+        ])
+        .args(&file_args)
+        .subcommands([
+            Command::new("daemon")
+                .about("Run in daemon mode updating when the hardware configuration changes")
+                .arg(in_arg.clone())
+                .arg(script_arg.clone())
+                .arg(wait_arg)
+                .arg(max_wait_arg)
+                .arg(wake_wait_arg)
+                .arg(cooldown_arg)
+                .arg(retries_arg)
+                .arg(retry_delay_arg)
+                .arg(webhook_arg)
+                .arg(notify_arg)
+                .arg(metrics_file_arg)
+                .arg(observe_arg)
+                .arg(enforce_arg)
+                .arg(enforce_interval_arg)
+                .arg(events_arg)
+                .arg(out_arg.clone())
+                .arg(control_arg)
+                .arg(socket_arg.clone())
+                .arg(pid_file_arg)
+                .arg(replace_arg)
+                .arg(detach_arg)
+                .arg(max_events_arg)
+                .arg(daemon_quiet_arg),
+            Command::new("trigger")
+                .about("Ask a running daemon to reconfigure the displays immediately")
+                .arg(socket_arg.clone()),
+            Command::new("status")
+                .about("Report the status of a running daemon")
+                .arg(socket_arg.clone())
+                .arg(out_arg.clone()),
+            Command::new("service")
+                .about("Manage running the daemon as a launchd agent")
+                .subcommand_required(true)
+                .subcommands([
+                    Command::new("install")
+                        .about("Install and start a launchd agent running the daemon")
+                        .arg(in_arg.clone().required(true))
+                        .arg(label_arg.clone()),
+                    Command::new("uninstall")
+                        .about("Stop and remove the launchd agent")
+                        .arg(label_arg.clone()),
+                    Command::new("status")
+                        .about("Report the status of the launchd agent")
+                        .arg(label_arg)
+                        .arg(out_arg.clone()),
+                ]),
+            Command::new("virtual")
+                .about("Manage virtual displays, which have no physical monitor")
+                .subcommand_required(true)
+                .subcommands([
+                    Command::new("create")
+                        .about(
+                            "Create a virtual display, print its UUID, and keep it attached \
+                            until destroyed",
+                        )
+                        .arg(
+                            Arg::new("EXTENTS")
+                                .long("extents")
+                                .help("Width and height of the display in pixels, e.g. 1920,1080")
+                                .required(true)
+                                .value_parser(parse_extents),
+                        )
+                        .arg(
+                            Arg::new("PPI")
+                                .long("ppi")
+                                .help("Pixel density of the display in pixels per inch")
+                                .default_value("110")
+                                .value_parser(clap::value_parser!(u32).range(1..)),
+                        )
+                        .arg(
+                            Arg::new("FREQUENCY")
+                                .long("frequency")
+                                .help("Refresh frequency of the display in Hz")
+                                .default_value("60")
+                                .value_parser(clap::value_parser!(usize)),
+                        )
+                        .arg(
+                            Arg::new("NAME")
+                                .long("name")
+                                .help("Name the display reports")
+                                .default_value("knoll virtual display")
+                                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+                        )
+                        .arg(virtual_pid_file_arg.clone()),
+                    Command::new("destroy")
+                        .about("Destroy a virtual display created by knoll")
+                        .arg(virtual_pid_file_arg),
+                ]),
+            Command::new("bench")
+                .about("Time each stage of configuring the displays, without changing them")
+                .arg(in_arg)
+                .arg(script_arg)
+                .arg(out_arg.clone())
+                .arg(
+                    Arg::new("ITERATIONS")
+                        .long("iterations")
+                        .help("Number of times to repeat each stage")
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    Arg::new("DRY_RUN")
+                        .long("dry-run")
+                        .help(
+                            "Also time staging the changes for the configuration, \
+                            cancelling them rather than committing",
+                        )
+                        .action(ArgAction::SetTrue),
+                ),
+            Command::new("doctor")
+                .about("Check for common reasons that displays cannot be configured")
+                .arg(out_arg.clone()),
+            Command::new("sleep")
+                .about("Put the displays to sleep without changing their configuration"),
+            Command::new("wake").about("Wake displays that were put to sleep"),
+            Command::new("alias")
+                .about("Manage aliases that may be used in place of display UUIDs")
+                .subcommand_required(true)
+                .subcommands([
+                    Command::new("set")
+                        .about("Add or replace an alias for a display")
+                        .arg(Arg::new("NAME").help("Name of the alias").required(true))
+                        .arg(
+                            Arg::new("UUID")
+                                .help("UUID of the display the alias refers to")
+                                .required(true),
+                        ),
+                    Command::new("remove")
+                        .about("Remove an alias")
+                        .arg(Arg::new("NAME").help("Name of the alias").required(true)),
+                    Command::new("list")
+                        .about("Print the aliases and the displays they refer to")
+                        .arg(out_arg.clone()),
+                ]),
+            Command::new("serve")
+                .about("Serve requests from another program, one JSON line at a time")
+                .arg(stdio_arg)
+                .arg(out_arg.clone()),
+            Command::new("state")
+                .about("Print the current state of the displays as a configuration")
+                .arg(out_arg.clone())
+                .arg(state_detailed_arg)
+                .arg(normalize_arg),
+            Command::new("list")
+                .about("Print information about available display modes")
+                .arg(out_arg)
+                .arg(all_modes_arg)
+                .arg(detailed_arg)
+                .arg(compact_arg)
+                .arg(sort_arg)
+                .arg(by_resolution_arg)
+                .arg(displays_arg)
+                .arg(uuids_arg),
+        ]);
+
+    cmd.try_get_matches_from(args)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// `ConfigReader` is a helper to abstract away from reading the configuration,
+/// rather than just reading it directly.  Daemon mode takes advantage of this
+/// so that it is possible to update the configuration without having to restart
 /// knoll.  However, it will only be able to reload if the input is specified as
 /// a file.  If there was no file input and `stdin` is to be use, then it will
 /// be read once and subsequent calls to `groups()` will yield the same
 /// configuration.  If `stdin` happens to be a terminal, rather than a pipe,
 /// etc. the result will be empty.
+#[derive(Clone)]
 struct ConfigReader {
     /// Format to use when deserializing configurations.
     format: crate::serde::Format,
@@ -359,20 +1665,88 @@ struct ConfigReader {
     opt_path: Option<PathBuf>,
     /// Current configurations.
     config_string: String,
+    /// Where to report warnings about the configuration.
+    diagnostics: Diagnostics,
+    /// Whether to reject configurations containing unknown fields.
+    strict: bool,
+    /// Aliases that may be used in place of UUIDs.
+    aliases: Aliases,
+    /// Optional script to generate the configurations with.
+    script: Option<Script>,
+    /// Displays to ignore regardless of the configuration.
+    ignore: Vec<String>,
+    /// Displays ignored by the most recently read configuration.
+    ignored: Vec<String>,
+}
+
+/// A script that generates the configurations each time they are needed.
+#[derive(Clone)]
+struct Script {
+    source: ScriptSource,
+    /// Obtain the current display state to provide to the script.
+    state: fn() -> Result<ConfigGroups, Error>,
+}
+
+/// The kinds of configuration script.
+#[derive(Clone)]
+enum ScriptSource {
+    /// A shell command.  It is provided the current display state on
+    /// `stdin`, and its output is used as though it had been read from a
+    /// file.
+    Command(String),
+    /// A file containing a Rhai script, which is evaluated to obtain the
+    /// configurations.  Only supported with the `scripting` feature.
+    Rhai(PathBuf),
+}
+
+impl std::fmt::Display for ScriptSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptSource::Command(command) => write!(f, "{}", command),
+            ScriptSource::Rhai(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Helper to obtain the configuration script, if one was given, either at
+/// the top-level or to a subcommand.  Input files with a `.rhai` extension
+/// are scripts.
+fn config_script<DS: DisplayState>(sub_matches: &ArgMatches) -> Option<Script> {
+    let source = match sub_matches.get_one::<String>("SCRIPT") {
+        Some(command) => ScriptSource::Command(command.clone()),
+        None => ScriptSource::Rhai(
+            sub_matches
+                .get_one::<PathBuf>("IN")
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))?
+                .clone(),
+        ),
+    };
+    Some(Script {
+        source,
+        state: || Ok(state_to_config(&DS::current()?)),
+    })
 }
 
 impl ConfigReader {
-    /// Create a new `ConfigReader` given the file format, the current `stdin`
-    /// and possibly a path to read a configuration from.
+    /// Create a new `ConfigReader` given the file format, the current `stdin`,
+    /// possibly a path or script to read a configuration from, where to
+    /// report warnings, whether to reject unknown fields, and the aliases to
+    /// resolve.
     fn new<IN: Read + IsTerminal>(
         format: crate::serde::Format,
         stdin: IN,
         opt_path: Option<&PathBuf>,
+        script: Option<Script>,
+        diagnostics: Diagnostics,
+        strict: bool,
+        aliases: Aliases,
     ) -> Result<Self, Error> {
         let config_string = match opt_path {
-            // If we are reading from a file, we can skip reading it here,
-            // as we'll reload it every time the configuration is requested.
+            // If we are reading from a file or running a script, we can skip
+            // reading it here, as we'll reload it every time the
+            // configuration is requested.
             Some(_) => String::new(),
+            None if script.is_some() => String::new(),
             None => {
                 // If stdin is a terminal rather than a redirect, do not try to
                 // read from it.  Otherwise, BufRead may block forever waiting
@@ -393,103 +1767,309 @@ impl ConfigReader {
             opt_path: opt_path.cloned(),
             config_string,
             format,
+            diagnostics,
+            strict,
+            aliases,
+            script,
+            ignore: vec![],
+            ignored: vec![],
         })
     }
 
-    /// Parse and validate configuration groups.  If the `ConfigReader` was
-    /// created with an input file, this will reload the configurations
-    /// groups from that file first.
+    /// Create a new `ConfigReader` for a configuration that has already
+    /// been read.
+    fn from_string(
+        format: crate::serde::Format,
+        config_string: String,
+        diagnostics: Diagnostics,
+        strict: bool,
+        aliases: Aliases,
+    ) -> Self {
+        Self {
+            opt_path: None,
+            config_string,
+            format,
+            diagnostics,
+            strict,
+            aliases,
+            script: None,
+            ignore: vec![],
+            ignored: vec![],
+        }
+    }
+
+    /// Also ignore the given displays, in addition to those the
+    /// configuration ignores.
+    fn ignoring(mut self, ignore: Vec<String>) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Parse and validate configuration groups, reporting any warnings.  If
+    /// the `ConfigReader` was created with an input file or script, this
+    /// will reload the configurations groups from it first.
     fn groups(&mut self) -> Result<Vec<ValidConfigGroup>, Error> {
-        // If the configuration is being read from a file, reload it now.
-        match &self.opt_path {
-            Some(path) => self.config_string = std::fs::read_to_string(path)?,
-            None => { /* No-op */ }
+        // If the configuration is being generated by a script or read from
+        // a file, reload it now.  Rhai scripts evaluate to a configuration
+        // that is then parsed as JSON, regardless of the format.
+        let mut format = self.format;
+        match (&self.script, &self.opt_path) {
+            (
+                Some(
+                    script @ Script {
+                        source: ScriptSource::Rhai(path),
+                        ..
+                    },
+                ),
+                _,
+            ) => {
+                format = crate::serde::Format::Json;
+                self.config_string = run_rhai_script(script, path)?;
+            }
+            (Some(script), _) => self.config_string = run_script(script, self.format)?,
+            (None, Some(path)) => self.config_string = std::fs::read_to_string(path)?,
+            (None, None) => { /* No-op */ }
         }
 
         // If the input is empty return no configuration groups, as
         // deserialization will fail.
         if self.config_string.is_empty() {
+            self.ignored = vec![];
             return Ok(vec![]);
         }
 
-        // Deserialize and validate the configurations.
-        Ok(validate_config_groups(crate::serde::deserialize(
-            self.format,
-            self.config_string.as_str(),
-        )?)?)
+        // Deserialize and validate the configurations.  If the configuration
+        // was read from a file, parse errors will refer to it.
+        // Configurations written for older versions of the schema are
+        // migrated first.
+        let mut config_groups =
+            crate::serde::deserialize_config(format, self.config_string.as_str()).map_err(|e| {
+                match &self.opt_path {
+                    Some(path) => e.with_path(path),
+                    None => e,
+                }
+            })?;
+        // Deserialization ignores unknown fields, so check for them
+        // separately if requested.
+        if self.strict {
+            let value = crate::serde::migrate(crate::serde::deserialize_value(
+                format,
+                &self.config_string,
+            )?)?;
+            let unknown = unknown_fields(&value);
+            if !unknown.is_empty() {
+                return Err(Error::UnknownFields(unknown));
+            }
+        }
+        self.aliases.resolve_config_groups(&mut config_groups);
+        self.ignored = std::mem::take(&mut config_groups.ignored);
+        let groups = validate_config_groups(config_groups)?;
+        self.diagnostics
+            .report(check_config_groups(&groups))
+            .map_err(Error::Strict)?;
+        Ok(groups)
+    }
+
+    /// The displays to ignore, both those given when the `ConfigReader` was
+    /// created and those ignored by the configuration most recently read by
+    /// `groups`.
+    fn ignored(&self) -> Vec<String> {
+        self.ignore
+            .iter()
+            .map(|uuid| self.aliases.resolve(uuid))
+            .chain(self.ignored.iter().cloned())
+            .collect()
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Helper for handling the output argument.  It no output path was provided,
-/// stdout will be used instead.  Will return a boxed `BufWrite` that can be
-/// used to write the program output.
+/// The destination of the program output.
+enum Output<'l> {
+    /// Standard output, or a file being appended to.
+    Stream(Box<dyn Write + 'l>),
+    /// A temporary file alongside the output file, which only replaces it
+    /// once the output is finished, so that a failure part way through
+    /// writing cannot leave a truncated file behind.
+    Atomic {
+        file: tempfile::NamedTempFile,
+        path: PathBuf,
+    },
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stream(writer) => writer.write(buf),
+            Output::Atomic { file, .. } => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stream(writer) => writer.flush(),
+            Output::Atomic { file, .. } => file.flush(),
+        }
+    }
+}
+
+impl Output<'_> {
+    /// Complete writing the output.  For atomic output, this replaces the
+    /// output file.  If this is not called, atomic output is discarded.
+    fn finish(mut self) -> std::io::Result<()> {
+        self.flush()?;
+        match self {
+            Output::Stream(_) => Ok(()),
+            Output::Atomic { file, path } => {
+                file.persist(path).map_err(|pe| pe.error)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Helper to open a file that output is written to as it is produced,
+/// either appending to it or replacing its contents.
+fn create_output_file(path: &Path, append: bool) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+}
+
+/// Helper for handling the output arguments.  If no output path was
+/// provided, stdout will be used instead.  Otherwise, the output is appended
+/// to the file if requested, or written atomically.
 fn open_output<'l, OUT: Write + 'l>(
     stdout: OUT,
-    opt_path: Option<&PathBuf>,
-) -> std::io::Result<Box<dyn Write + 'l>> {
-    let output: Box<dyn Write> = match opt_path {
-        Some(path) => Box::new(std::fs::File::open(path)?),
-        None => Box::new(stdout),
+    matches: &ArgMatches,
+) -> std::io::Result<Output<'l>> {
+    let Some(path) = matches.get_one::<PathBuf>("OUT") else {
+        return Ok(Output::Stream(Box::new(stdout)));
     };
-
-    Ok(output)
+    if matches.get_flag("APPEND") {
+        return Ok(Output::Stream(Box::new(create_output_file(path, true)?)));
+    }
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file = tempfile::Builder::new().prefix(".knoll").tempfile_in(dir)?;
+    Ok(Output::Atomic {
+        file,
+        path: path.clone(),
+    })
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Helper find the configuration group for the current display state.
-// TODO Detect when configuration change would be a no-op.
+/// Helper to determine whether each display in the resolved configuration
+/// group is of the kind its configuration requires, if any.
+fn kinds_match<D: Display>(
+    valid_group: &ValidConfigGroup,
+    displays: &std::collections::BTreeMap<String, D>,
+) -> bool {
+    valid_group
+        .kinds()
+        .into_iter()
+        .all(|(uuid, kind)| displays.get(uuid).map(|display| display.kind()) == Some(kind))
+}
+
+/// Helper find the configuration group for the current display state and
+/// environment.  Among the configuration groups whose displays are all
+/// attached and whose conditions hold, and which are of the required kinds,
+/// the most precise is the one with the most displays, and then the most
+/// conditions and required kinds.  Ignored displays are treated as though
+/// they were not attached.
 fn find_most_precise_config_group<DS: DisplayState>(
     vcgs: &[ValidConfigGroup],
     display_state: &DS,
+    ignored: &[String],
     format: crate::serde::Format,
 ) -> Result<ValidConfigGroup, Error> {
     let displays = display_state.get_displays();
-    let num_displays = displays.len();
+    let disabled = display_state.disabled_displays();
+    // Disabled displays can also be matched, but only by configurations that
+    // say whether they should be enabled.
+    let mut attached: BTreeMap<String, ()> = displays
+        .keys()
+        .chain(&disabled)
+        .map(|uuid| (uuid.clone(), ()))
+        .collect();
+    let ignored = resolve_ignored(ignored, &attached)?;
+    attached.retain(|uuid, _| !ignored.contains(uuid));
+    let num_displays = attached.len();
+    let environment = DS::environment();
 
     let mut matching = Vec::new();
-    let mut best_len = 0;
+    let mut best = (0, 0);
 
     for valid_group in vcgs {
-        let group_len = valid_group.uuids.len();
+        let precision = (valid_group.uuids.len(), valid_group.requirements());
         // Only proceed if the config has at most as many displays
-        // as there are currently, if it has at least as many displays as
-        // the current best and all of the configs correspond to one of the
-        // active displays.
-        if group_len <= num_displays
-            && best_len <= group_len
-            && valid_group.uuids.iter().all(|c| displays.contains_key(c))
+        // as there are currently, if it is at least as precise as
+        // the current best, and its conditions hold.
+        if precision.0 <= num_displays
+            && best <= precision
+            && environment.satisfies(&valid_group.conditions())
         {
-            // If the new group is larger than the current best, then
+            // All of the configs must also correspond to one of the active
+            // displays, possibly by a prefix of its UUID, of the required
+            // kind if any.
+            let Some(valid_group) = valid_group.resolve(&attached)? else {
+                continue;
+            };
+            if valid_group
+                .configs
+                .iter()
+                .any(|(uuid, config)| disabled.contains(uuid) && config.enabled.is_none())
+            {
+                continue;
+            }
+            if !kinds_match(&valid_group, displays) {
+                continue;
+            }
+            // If the new group is more precise than the current best, then
             // we can eliminate all the current matches.
-            if best_len < group_len {
+            if best < precision {
                 matching.clear();
-                best_len = group_len;
+                best = precision;
             }
-            matching.push(valid_group.clone());
+            matching.push(valid_group);
         }
     }
 
     // No matching configurations
-    if best_len == 0 {
+    if best.0 == 0 {
+        // Suggest attached displays for any that the groups refer to which
+        // are not attached, in case of a typo or truncated UUID.
+        let mut suggestions = BTreeMap::new();
+        for uuid in vcgs.iter().flat_map(|vcg| &vcg.uuids) {
+            if let Ok(None) = resolve_uuid(uuid, &attached) {
+                if let Some(suggested) = suggest_uuid(uuid, attached.keys()) {
+                    suggestions.insert(uuid.clone(), suggested);
+                }
+            }
+        }
         Err(Error::NoMatchingConfigGroup(
-            displays.keys().cloned().collect(),
+            displays
+                .keys()
+                .filter(|uuid| !ignored.contains(*uuid))
+                .cloned()
+                .collect(),
+            vcgs.iter().filter_map(|vcg| vcg.name.clone()).collect(),
+            suggestions,
         ))
     }
     // Ambiguous configurations.
     else if matching.len() > 1 {
-        // TODO A little annoying that it is necessary to use a loop rather
-        // than mapping so that ? can be used.
-        let mut cg_strs = Vec::new();
-        for vcg in matching {
-            let cg = ConfigGroup {
-                configs: vcg.configs.values().cloned().collect(),
-            };
-            cg_strs.push(serialize_to_string(format, &cg)?)
-        }
-        Err(Error::AmbiguousConfigGroup(cg_strs))
+        let groups = matching
+            .into_iter()
+            .map(|vcg| Formatted::new(format, vcg.to_config_group()))
+            .collect();
+        Err(Error::AmbiguousConfigGroup(groups))
     } else {
         // Okay to unwrap here as we have verified that there is
         // at least one match.
@@ -499,41 +2079,255 @@ fn find_most_precise_config_group<DS: DisplayState>(
 
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Helper to convert a `Config` to `DisplayModePattern`
-fn mode_pattern_from_config(config: &Config) -> DisplayModePattern {
+/// Helper to convert a `Config` to `DisplayModePattern` for the given display.
+/// Named resolutions are resolved against the display modes of the display,
+/// and extents given in backing pixels are matched against those of the
+/// display modes.
+fn mode_pattern_from_config<D: Display>(display: &D, config: &Config) -> DisplayModePattern {
+    let given = |range: ExtentsRange| match config.units.unwrap_or_default() {
+        Units::Points => (Some(range), None),
+        Units::Pixels => (None, Some(range)),
+    };
+    let (extents, pixel_extents) = match &config.extents {
+        None => (None, None),
+        Some(Resolution::Extents(extents)) => given(ExtentsRange::exactly(extents)),
+        Some(Resolution::Range(range)) => given(range.clone()),
+        // The largest extents are chosen amongst the matching modes.
+        Some(Resolution::Named(ResolutionName::Max)) => (None, None),
+        Some(Resolution::Named(name)) => (
+            None,
+            name.pixel_extents()
+                .or_else(|| display.native_pixel_extents())
+                .map(|extents| ExtentsRange::exactly(&extents)),
+        ),
+    };
+
     DisplayModePattern {
         scaled: config.scaled,
+        scale: config.scale,
         color_depth: config.color_depth,
-        frequency: config.frequency,
-        extents: config.extents.clone(),
+        frequency: config.frequency.and_then(|frequency| frequency.range()),
+        extents,
+        aspect_ratio: config.aspect_ratio,
+        pixel_extents,
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Options controlling how a display mode is selected for a configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ModeSelection {
+    /// Policies used to choose amongst multiple matching display modes,
+    /// in the order they should be applied.
+    pub preferences: Vec<ModePreference>,
+    /// Whether to fall back to the closest display mode when no display mode
+    /// matches exactly.  This may also be requested by individual
+    /// configurations.
+    pub fuzzy: bool,
+    /// How to handle display modes with identical properties.
+    pub duplicates: DuplicateModes,
+}
+
+/// Options controlling how configuration changes are committed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CommitOptions {
+    /// How long the configuration changes should last.
+    pub(crate) scope: ConfigurationScope,
+    /// How long to fade the displays over while changing them, if at all.
+    pub(crate) fade: Option<std::time::Duration>,
+    /// Where to write the state of the displays before committing any
+    /// changes to them, if anywhere.
+    pub(crate) backup: Option<PathBuf>,
+    /// How long to wait for committing the changes to complete, if limited.
+    pub(crate) timeout: Option<std::time::Duration>,
+    /// Whether to ask for confirmation on the terminal before committing.
+    pub(crate) confirm: bool,
+    /// The commit most recently abandoned with these options, which is
+    /// shared by their clones.
+    pub(crate) abandoned: AbandonedCommit,
+}
+
+/// A commit that timed out and was abandoned, if it may still be in
+/// progress.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AbandonedCommit(Arc<Mutex<Option<std::thread::JoinHandle<()>>>>);
+
+impl AbandonedCommit {
+    /// Whether the abandoned commit, if any, is still in progress.
+    fn pending(&self) -> Result<bool, Error> {
+        let mut abandoned = self
+            .0
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?;
+        if abandoned
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            return Ok(true);
+        }
+        if abandoned.take().is_some() {
+            info!("The abandoned commit of the display configuration has completed.");
+        }
+        Ok(false)
+    }
+
+    /// Record a commit that was just abandoned.
+    fn abandon(&self, handle: std::thread::JoinHandle<()>) -> Result<(), Error> {
+        *self
+            .0
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))? = Some(handle);
+        Ok(())
+    }
+}
+
+/// Show the staged changes on the controlling terminal and ask whether they
+/// should be committed.  The terminal is used directly, as standard input
+/// is typically the configuration being applied.
+fn confirm_changes(changes: &[String]) -> Result<bool, Error> {
+    let mut terminal = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    writeln!(terminal, "The following changes will be made:")?;
+    for change in changes {
+        writeln!(terminal, "  {}", change)?;
+    }
+    write!(terminal, "Apply these changes? [y/N] ")?;
+    terminal.flush()?;
+    read_confirmation(BufReader::new(terminal))
+}
+
+/// Helper to read an answer to a confirmation prompt.  Anything other than
+/// yes is treated as declining.
+fn read_confirmation<R: BufRead>(mut input: R) -> Result<bool, Error> {
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Commit the transaction, giving up if it does not complete within the
+/// timeout.  Core Graphics has been observed to occasionally hang while
+/// committing, so when a timeout is given the commit takes place on a
+/// separate thread.  A commit that times out cannot be cancelled while it is
+/// in progress, so it is abandoned, and may yet complete.  No further
+/// commits with the same options are attempted until it does, so that they
+/// cannot race with it.
+fn commit_with_timeout<T: DisplayConfigTransaction + Send + 'static>(
+    cfgtxn: T,
+    commit: &CommitOptions,
+) -> Result<(), Error> {
+    if commit.abandoned.pending()? {
+        return Err(displays::Error::CommitPending.into());
+    }
+    let scope = commit.scope;
+    let Some(timeout) = commit.timeout else {
+        return Ok(cfgtxn.commit(scope)?);
+    };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let handle = indirect_logger::spawn(move || {
+        // The receiver may have given up waiting, so ignore send failures.
+        let _ = sender.send(cfgtxn.commit(scope));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(result?),
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+            error!(
+                "Committing the display configuration is taking longer than {}, \
+                abandoning it.  Its changes may still be applied.",
+                humantime::format_duration(timeout)
+            );
+            commit.abandoned.abandon(handle)?;
+            Err(displays::Error::Timeout(timeout).into())
+        }
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Err(displays::Error::Internal(
+            "Committing the display configuration failed unexpectedly".to_owned(),
+        )
+        .into()),
+    }
+}
+
+/// The location of the backup used when `--backup auto` is given.
+pub fn default_backup_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".local/state/knoll/backup"),
+        None => std::env::temp_dir().join("knoll-backup"),
+    }
+}
+
+/// Write the current state of the displays to the given path, so that it
+/// may be restored by applying it.  The file is only replaced once the
+/// state has been written in full.
+fn write_backup<DS: DisplayState>(
+    display_state: &DS,
+    path: &Path,
+    format: crate::serde::Format,
+) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    let mut file = tempfile::Builder::new().prefix(".knoll").tempfile_in(dir)?;
+    crate::serde::serialize(format, &state_to_config(display_state), &mut file)?;
+    file.persist(path).map_err(|pe| pe.error)?;
+    info!("Backed up the display state to {}.", path.display());
+    Ok(())
+}
+
 /// Helper to select a matching display mode for the given display
-/// using the requested configuration.
+/// using the requested configuration.  If fuzzy matching was requested,
+/// the closest display modes are considered instead of failing when there is
+/// no exact match.  Duplicate modes are then removed according to the
+/// requested policy.  Requests for the largest extents or highest frequency
+/// then narrow down the matching modes, followed by the given preferences
+/// in order.  If the configuration gives a mode id, the display mode with
+/// that mode number is selected directly instead.
 /// Will fail if there is no matching display mode, or if the configuration
-/// does not uniquely determine a display mode.
+/// and preferences do not uniquely determine a display mode.
 fn select_mode<D: Display>(
     display: &D,
     config: &Config,
     format: crate::serde::Format,
+    selection: &ModeSelection,
 ) -> Result<D::DisplayModeType, Error> {
-    let pattern = mode_pattern_from_config(config);
-    let mut modes = display.matching_modes(&pattern);
+    if let Some(mode_id) = config.mode_id {
+        return display
+            .possible_modes()
+            .iter()
+            .find(|m| m.mode_number() == Some(mode_id))
+            .cloned()
+            .ok_or_else(|| Error::NoMatchingDisplayMode(Formatted::new(format, config.clone())));
+    }
+    let pattern = mode_pattern_from_config(display, config);
+    let mut modes = if selection.fuzzy || config.closest == Some(true) {
+        display.closest_modes(&pattern)
+    } else {
+        display.matching_modes(&pattern)
+    };
+    modes = selection.duplicates.dedup(modes);
+    if let Some(Resolution::Named(ResolutionName::Max)) = config.extents {
+        modes = ModePreference::HighestResolution.narrow(modes);
+    }
+    if let Some(Frequency::Named(FrequencyName::Max)) = config.frequency {
+        modes = ModePreference::HighestFrequency.narrow(modes);
+    }
+    for preference in &selection.preferences {
+        modes = preference.narrow(modes);
+    }
     if modes.is_empty() {
-        Err(Error::NoMatchingDisplayMode(serialize_to_string(
-            format, &config,
-        )?))
+        Err(Error::NoMatchingDisplayMode(Formatted::new(
+            format,
+            config.clone(),
+        )))
     } else if modes.len() > 1 {
-        // TODO A little annoying that it is necessary to use a loop rather
-        // than mapping so that ? can be used.
-        let mut mode_strs = Vec::new();
-        for m in modes {
-            mode_strs.push(serialize_to_string(format, &m)?)
-        }
-        Err(Error::AmbiguousDisplayMode(mode_strs))
+        Err(Error::AmbiguousDisplayMode(
+            modes
+                .into_iter()
+                .map(|m| Formatted::new(format, m))
+                .collect(),
+        ))
     } else {
         // The unwrap here is safe we as we've established that set of matching
         // modes is non-empty.
@@ -543,186 +2337,2700 @@ fn select_mode<D: Display>(
 
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Configure displays from configuration group.
-fn configure_displays<DS: DisplayState>(
+/// Helper to select the display mode for each configuration in the
+/// configuration group.
+fn select_modes<DS: DisplayState>(
     display_state: &DS,
-    config_group: ValidConfigGroup,
+    config_group: &ValidConfigGroup,
     format: crate::serde::Format,
-) -> Result<(), Error> {
-    // Determine that we can find appropriate display modes for each
-    // configuration before we start configuring.
+    selection: &ModeSelection,
+) -> Result<HashMap<String, DS::DisplayModeType>, Error> {
     let mut selected_modes = HashMap::new();
+    let disabled = display_state.disabled_displays();
     for (uuid, config) in &config_group.configs {
+        // Modes cannot be selected for disabled displays until they are
+        // enabled, and are not needed for skipped displays.
+        if disabled.contains(uuid) || config.skip == Some(true) {
+            continue;
+        }
         let display = display_state
             .get_displays()
             .get(uuid)
             .expect("Match display somehow missing display configuration");
-        let mode = select_mode(display, config, format)?;
-        info!(
+        if config.underscan.is_some() && display.underscan().is_none() {
+            return Err(Error::UnsupportedUnderscan(uuid.clone()));
+        }
+        let mode = select_mode(display, config, format, selection)?;
+        info!(target: DETAIL_TARGET,
             "For display {}, selected mode {}",
             &uuid,
             serialize_to_string(format, &mode)?
         );
         selected_modes.insert(uuid.clone(), mode);
     }
+    Ok(selected_modes)
+}
 
-    let mut cfgtxn = display_state.configure()?;
+/// Helper to compute the origin requested for a display, evaluating any
+/// expressions against the display modes selected for the other displays in
+/// the configuration group.  Returns `None` if it refers to a display that is
+/// not yet enabled.
+///
+/// Origins given in backing pixels are converted to points using the scale
+/// of the display mode for the display.  Any references to other displays
+/// are measured in the same backing pixels, so that the displays may still
+/// be placed next to one another.
+fn requested_origin<DS: DisplayState>(
+    display_state: &DS,
+    config_group: &ValidConfigGroup,
+    selected_modes: &HashMap<String, DS::DisplayModeType>,
+    uuid: &str,
+    origin: &Origin,
+) -> Option<Point> {
+    let scale = match config_group.configs[uuid].units {
+        Some(Units::Pixels) => selected_modes
+            .get(uuid)
+            .map(|mode| mode.scale())
+            .or_else(|| {
+                let display = display_state.get_displays().get(uuid)?;
+                Some(display.current_mode().scale())
+            })
+            .map_or(1.0, |scale| scale.0),
+        _ => 1.0,
+    };
+    let coordinate = |coordinate: &Coordinate| {
+        let value = match coordinate {
+            Coordinate::Exact(value) => *value,
+            Coordinate::Expression(expression) => expression.evaluate(|reference| {
+                let display = display_state.get_displays().get(&reference.display)?;
+                let config = &config_group.configs[&reference.display];
+                let point = match reference.property {
+                    // Validation ensures that origins do not refer to one
+                    // another in a cycle.
+                    Property::Origin => match &config.origin {
+                        Some(origin) if config.skip != Some(true) => requested_origin(
+                            display_state,
+                            config_group,
+                            selected_modes,
+                            &reference.display,
+                            origin,
+                        )?,
+                        _ => display.origin().clone(),
+                    },
+                    // Display mode extents are always in landscape orientation,
+                    // so account for any rotation.
+                    Property::Extents => {
+                        let (extents, rotation) = match selected_modes.get(&reference.display) {
+                            Some(mode) => (
+                                mode.extents(),
+                                config.rotation.unwrap_or(display.rotation()),
+                            ),
+                            None => (display.current_mode().extents(), display.rotation()),
+                        };
+                        match rotation {
+                            Rotation::Ninety | Rotation::TwoSeventy => Point {
+                                x: extents.y,
+                                y: extents.x,
+                            },
+                            _ => extents.clone(),
+                        }
+                    }
+                };
+                let value = match reference.axis {
+                    Axis::X => point.x,
+                    Axis::Y => point.y,
+                };
+                Some((value as f64 * scale).round() as i64)
+            })?,
+        };
+        Some((value as f64 / scale).round() as i64)
+    };
+    Some(Point {
+        x: coordinate(&origin.x)?,
+        y: coordinate(&origin.y)?,
+    })
+}
+
+/// A transaction with the changes needed to apply a configuration group,
+/// a description of each change, and the display mode selected for each
+/// display.
+type StagedConfiguration<DS> = (
+    <DS as DisplayState>::DisplayConfigTransactionType,
+    Vec<String>,
+    HashMap<String, <DS as DisplayState>::DisplayModeType>,
+);
+
+/// A setting of a display that differs from the one requested by its
+/// configuration.
+enum SettingDifference<'l, DM: DisplayMode> {
+    /// The display should be enabled or disabled.
+    Enabled(bool),
+    /// The current and requested rotation.
+    Rotation(Rotation, Rotation),
+    /// The current and selected display mode.
+    Mode(&'l DM, &'l DM),
+    /// The current and requested origin.
+    Origin(&'l Point, &'l Point),
+    /// The current underscan, if supported, and the requested underscan.
+    Underscan(Option<Underscan>, Underscan),
+}
+
+/// Helper to compute the origin requested for each display in the
+/// configuration group that is enabled and not skipped.
+fn requested_origins<DS: DisplayState>(
+    display_state: &DS,
+    config_group: &ValidConfigGroup,
+    selected_modes: &HashMap<String, DS::DisplayModeType>,
+) -> HashMap<String, Point> {
+    config_group
+        .configs
+        .iter()
+        .filter(|(uuid, config)| {
+            config.skip != Some(true)
+                && config.enabled != Some(false)
+                && display_state.get_displays().contains_key(*uuid)
+        })
+        .filter_map(|(uuid, config)| {
+            let origin = requested_origin(
+                display_state,
+                config_group,
+                selected_modes,
+                uuid,
+                config.origin.as_ref()?,
+            )?;
+            Some((uuid.clone(), origin))
+        })
+        .collect()
+}
+
+/// Compare the displays against the settings requested by the configuration
+/// group, given the display modes selected for it, returning each setting
+/// that differs in the order it should be configured.  This is the only
+/// place settings are compared, so that staging, verifying and enforcing a
+/// configuration agree on what differs.
+///
+/// Origins are compared against the given origins rather than the requested
+/// ones, as macOS adjusts requested origins to remove gaps and overlaps
+/// between displays.  Displays without an origin in `origins` are not
+/// compared.  Settings other than whether a display is enabled are only
+/// compared for displays with a selected mode, as displays must be enabled
+/// before anything else about them can be configured.
+fn setting_differences<'l, DS: DisplayState>(
+    display_state: &'l DS,
+    config_group: &'l ValidConfigGroup,
+    selected_modes: &'l HashMap<String, DS::DisplayModeType>,
+    origins: &'l HashMap<String, Point>,
+) -> Vec<(&'l str, SettingDifference<'l, DS::DisplayModeType>)> {
+    let disabled = display_state.disabled_displays();
+    let mut differences = Vec::new();
     for (uuid, config) in &config_group.configs {
-        if let Some(false) = config.enabled {
-            info!("For display {} has been disabled.", &uuid);
-            // Unwrap is okay as we just checked that there is a value.
-            cfgtxn.set_enabled(uuid, false)?;
-            // TODO Does it make sense to skip the rest?
+        if config.skip == Some(true) {
             continue;
         }
-
-        // TODO roll back rotation if later steps fail?
+        let Some(display) = display_state.get_displays().get(uuid) else {
+            if disabled.contains(uuid) && config.enabled == Some(true) {
+                differences.push((uuid.as_str(), SettingDifference::Enabled(true)));
+            }
+            continue;
+        };
+        if config.enabled == Some(false) {
+            differences.push((uuid.as_str(), SettingDifference::Enabled(false)));
+            continue;
+        }
+        let Some(mode) = selected_modes.get(uuid) else {
+            continue;
+        };
         if let Some(rotation) = config.rotation {
-            info!(
-                "For display {}, using rotation of {} degrees.",
-                &uuid, rotation
-            );
-            // Unwrap is okay as we just checked that there is a value.
-            cfgtxn.set_rotation(uuid, rotation)?
+            if rotation != display.rotation() {
+                differences.push((
+                    uuid.as_str(),
+                    SettingDifference::Rotation(display.rotation(), rotation),
+                ));
+            }
         }
-
-        // Unwrap is safe as we know there is a display mode for each UUID.
-        cfgtxn.set_mode(uuid, selected_modes.get(uuid).unwrap())?;
-
-        if let Some(origin) = &config.origin {
-            info!("For display {}, using {} as origin.", &uuid, origin);
-            // Unwrap is okay as we just checked that there is a value.
-            cfgtxn.set_origin(uuid, origin)?
+        if !mode.is_same_mode(display.current_mode()) {
+            differences.push((
+                uuid.as_str(),
+                SettingDifference::Mode(display.current_mode(), mode),
+            ));
+        }
+        if let Some(origin) = origins.get(uuid) {
+            if origin != display.origin() {
+                differences.push((
+                    uuid.as_str(),
+                    SettingDifference::Origin(display.origin(), origin),
+                ));
+            }
+        }
+        if let Some(underscan) = config.underscan {
+            if Some(underscan) != display.underscan() {
+                differences.push((
+                    uuid.as_str(),
+                    SettingDifference::Underscan(display.underscan(), underscan),
+                ));
+            }
         }
     }
-
-    cfgtxn.commit()?;
-    info!("Configuration complete.");
-
-    Ok(())
+    differences
 }
 
-////////////////////////////////////////////////////////////////////////////////
+/// Stage the changes needed to configure the displays from the
+/// configuration group in a new transaction, without committing it.
+/// Returns the transaction along with a description of each change staged
+/// and the selected display modes.
+fn stage_configuration<DS: DisplayState>(
+    display_state: &DS,
+    config_group: &ValidConfigGroup,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+) -> Result<StagedConfiguration<DS>, Error> {
+    // Determine that we can find appropriate display modes for each
+    // configuration before we start configuring.
+    let selected_modes = select_modes(display_state, config_group, format, selection)?;
+    // Origins that refer to a display that is not yet enabled are left
+    // until it has been.
+    let origins = requested_origins(display_state, config_group, &selected_modes);
 
-/// Helper to convert a given display state into configuration groups.
-fn state_to_config<DS: DisplayState>(display_state: &DS) -> ConfigGroups {
-    let configs: Vec<Config> = display_state
-        .get_displays()
-        .iter()
-        .map(|(uuid, display)| {
-            let mode = display.current_mode();
-            Config {
-                uuid: uuid.clone(),
-                enabled: Some(display.enabled()),
-                origin: Some(display.origin().clone()),
-                extents: Some(mode.extents().clone()),
-                scaled: Some(mode.scaled()),
-                frequency: Some(mode.frequency()),
-                color_depth: Some(mode.color_depth()),
-                rotation: Some(display.rotation()),
+    for (uuid, config) in &config_group.configs {
+        if config.skip == Some(true) {
+            info!(target: DETAIL_TARGET, "For display {}, leaving its settings untouched.", &uuid);
+        }
+    }
+
+    // Only request changes for the settings that differ from the current
+    // state of each display, to minimize flicker.
+    let differences = setting_differences(display_state, config_group, &selected_modes, &origins);
+    let mut changes = Vec::new();
+    let mut cfgtxn = display_state.configure()?;
+    for (uuid, difference) in &differences {
+        match difference {
+            SettingDifference::Enabled(true) => {
+                info!(target: DETAIL_TARGET, "For display {}, enabling the display.", &uuid);
+                cfgtxn.set_enabled(uuid, true)?;
+                changes.push(format!("display {}: enable", uuid));
             }
-        })
-        .collect();
+            SettingDifference::Enabled(false) => {
+                info!(target: DETAIL_TARGET, "For display {} has been disabled.", &uuid);
+                cfgtxn.set_enabled(uuid, false)?;
+                changes.push(format!("display {}: disable", uuid));
+            }
+            // TODO roll back rotation if later steps fail?
+            SettingDifference::Rotation(current, rotation) => {
+                info!(target: DETAIL_TARGET,
+                    "For display {}, using rotation of {} degrees.",
+                    &uuid, rotation
+                );
+                cfgtxn.set_rotation(uuid, *rotation)?;
+                changes.push(format!(
+                    "display {}: rotation {} -> {} degrees",
+                    uuid, current, rotation
+                ));
+            }
+            SettingDifference::Mode(current, mode) => {
+                cfgtxn.set_mode(uuid, mode)?;
+                changes.push(format!(
+                    "display {}: mode {} -> {}",
+                    uuid,
+                    mode_summary(*current),
+                    mode_summary(*mode)
+                ));
+            }
+            SettingDifference::Origin(current, origin) => {
+                info!(target: DETAIL_TARGET, "For display {}, using {} as origin.", &uuid, origin);
+                cfgtxn.set_origin(uuid, origin)?;
+                changes.push(format!(
+                    "display {}: origin {} -> {}",
+                    uuid, current, origin
+                ));
+            }
+            SettingDifference::Underscan(current, underscan) => {
+                info!(target: DETAIL_TARGET, "For display {}, using underscan of {}.", &uuid, underscan);
+                cfgtxn.set_underscan(uuid, *underscan)?;
+                changes.push(match current {
+                    Some(current) => {
+                        format!("display {}: underscan {} -> {}", uuid, current, underscan)
+                    }
+                    None => format!("display {}: underscan {}", uuid, underscan),
+                });
+            }
+        }
+    }
 
-    ConfigGroups {
-        groups: vec![ConfigGroup { configs }],
+    // Changing the mode or rotation of one display can lead macOS to move
+    // its neighbours, so in that case every requested origin is staged,
+    // even those that already match.
+    let layout_changes = differences.iter().any(|(_, difference)| {
+        matches!(
+            difference,
+            SettingDifference::Rotation(..) | SettingDifference::Mode(..)
+        )
+    });
+    if layout_changes {
+        for (uuid, origin) in &origins {
+            let display = display_state.get_displays().get(uuid);
+            if display.is_some_and(|display| display.origin() == origin) {
+                info!(target: DETAIL_TARGET, "For display {}, using {} as origin.", &uuid, origin);
+                cfgtxn.set_origin(uuid, origin)?;
+            }
+        }
     }
+
+    Ok((cfgtxn, changes, selected_modes))
 }
 
-////////////////////////////////////////////////////////////////////////////////
+/// Compare the displays against a configuration group that was just
+/// committed, describing each requested setting that macOS silently did not
+/// apply.  Origins are not compared, as macOS adjusts them to remove gaps
+/// and overlaps between displays.
+fn ignored_settings<DS: DisplayState>(
+    config_group: &ValidConfigGroup,
+    selected_modes: &HashMap<String, DS::DisplayModeType>,
+) -> Result<Vec<String>, Error> {
+    let display_state = DS::current()?;
+    let origins = HashMap::new();
+    Ok(
+        setting_differences(&display_state, config_group, selected_modes, &origins)
+            .into_iter()
+            .filter_map(|(uuid, difference)| match difference {
+                SettingDifference::Enabled(true) => {
+                    Some(format!("display {} is not enabled", uuid))
+                }
+                SettingDifference::Enabled(false) => {
+                    Some(format!("display {} is not disabled", uuid))
+                }
+                SettingDifference::Rotation(current, rotation) => Some(format!(
+                    "display {} has a rotation of {} rather than {} degrees",
+                    uuid, current, rotation
+                )),
+                SettingDifference::Mode(..) => Some(format!(
+                    "display {} did not switch to the selected display mode",
+                    uuid
+                )),
+                SettingDifference::Origin(..) => None,
+                SettingDifference::Underscan(_, underscan) => Some(format!(
+                    "display {} did not use an underscan of {}",
+                    uuid, underscan
+                )),
+            })
+            .collect(),
+    )
+}
+
+/// Determine whether the displays have drifted from the configuration group
+/// since it was applied.  Origins are compared against those the displays
+/// settled on once it was applied, rather than the requested origins.
+/// Unlike `stage_configuration` this only inspects the display state,
+/// without opening a configuration transaction, so that it is cheap enough
+/// to poll.
+fn has_drifted<DS: DisplayState>(
+    display_state: &DS,
+    applied: &AppliedGroup,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+) -> Result<bool, Error> {
+    let selected_modes = select_modes(display_state, &applied.config_group, format, selection)?;
+    Ok(!setting_differences(
+        display_state,
+        &applied.config_group,
+        &selected_modes,
+        &applied.origins,
+    )
+    .is_empty())
+}
+
+/// Configure displays from configuration group.  Returns how long committing
+/// the changes took, if there were any changes to commit.
+fn configure_displays<DS: DisplayState>(
+    display_state: &DS,
+    config_group: ValidConfigGroup,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+    commit: &CommitOptions,
+) -> Result<Option<std::time::Duration>, Error> {
+    let (mut cfgtxn, changes, selected_modes) =
+        stage_configuration(display_state, &config_group, format, selection)?;
+
+    // Dropping the transaction without committing cancels it.
+    if changes.is_empty() {
+        info!(target: DETAIL_TARGET, "Displays already match the configuration, nothing to change.");
+        return Ok(None);
+    }
+    if commit.confirm && !confirm_changes(&changes)? {
+        return Err(Error::Unconfirmed);
+    }
+
+    if let Some(path) = &commit.backup {
+        write_backup(display_state, path, format)?;
+    }
+    if let Some(fade) = commit.fade {
+        cfgtxn.set_fade(fade)?;
+    }
+    let commit_start = Instant::now();
+    commit_with_timeout(cfgtxn, commit)?;
+    let commit_duration = commit_start.elapsed();
+
+    // macOS may report success while leaving some displays unchanged, so
+    // check that the changes actually took effect.
+    let ignored = ignored_settings::<DS>(&config_group, &selected_modes)?;
+    if !ignored.is_empty() {
+        return Err(Error::ConfigurationIgnored(ignored));
+    }
+
+    // Displays that were just enabled could not have their other settings
+    // configured, so configure them now that they are enabled.
+    let disabled = display_state.disabled_displays();
+    if config_group
+        .configs
+        .iter()
+        .any(|(uuid, config)| disabled.contains(uuid) && config.enabled == Some(true))
+    {
+        let display_state = DS::current()?;
+        let commit = CommitOptions {
+            // The backup must be of the state before any changes, and the
+            // changes were already confirmed.
+            backup: None,
+            confirm: false,
+            ..commit.clone()
+        };
+        let duration =
+            configure_displays(&display_state, config_group, format, selection, &commit)?;
+        return Ok(Some(commit_duration + duration.unwrap_or_default()));
+    }
+    info!(target: DETAIL_TARGET, "Configuration complete.");
+
+    Ok(Some(commit_duration))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Helper to convert a given display state into configuration groups.
+pub(crate) fn state_to_config<DS: DisplayState>(display_state: &DS) -> ConfigGroups {
+    detailed_state_to_config(display_state, false)
+}
+
+/// Helper to convert a given display state into configuration groups,
+/// including the display ID of each display and the mode number of its
+/// current display mode if details were requested.
+fn detailed_state_to_config<DS: DisplayState>(display_state: &DS, detailed: bool) -> ConfigGroups {
+    let configs: Vec<Config> = display_state
+        .get_displays()
+        .iter()
+        .map(|(uuid, display)| {
+            let mode = display.current_mode();
+            Config {
+                uuid: uuid.clone(),
+                enabled: Some(display.enabled()),
+                skip: None,
+                origin: Some(display.origin().clone().into()),
+                extents: Some(Resolution::Extents(mode.extents().clone())),
+                units: None,
+                aspect_ratio: None,
+                scaled: None,
+                scale: Some(mode.scale()),
+                frequency: Some(Frequency::Exact(mode.frequency())),
+                color_depth: Some(mode.color_depth()),
+                mode_id: mode.mode_number().filter(|_| detailed),
+                rotation: Some(display.rotation()),
+                closest: None,
+                underscan: display.underscan(),
+                mirroring: display.mirroring(),
+                kind: Some(display.kind()).filter(|_| detailed),
+                display_id: display.display_id().filter(|_| detailed),
+            }
+        })
+        .collect();
+
+    ConfigGroups {
+        groups: vec![ConfigGroup {
+            name: None,
+            description: None,
+            configs,
+            hooks: None,
+            when: None,
+            wait: None,
+        }],
+        ignored: vec![],
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The input provided to hooks on stdin.
+#[derive(Debug, Serialize)]
+struct HookInput<'a> {
+    /// The display state before the configuration group was applied.
+    old: &'a ConfigGroups,
+    /// For hooks run before the configuration group is applied, the
+    /// configuration group being applied.  Otherwise, the display state
+    /// after it was applied.
+    new: &'a ConfigGroups,
+}
+
+/// Helper to run a hook using the shell, providing the input on stdin.  The
+/// output of the hook is redirected to stderr, so that it cannot be confused
+/// with the output of knoll.
+fn run_hook(command: &str, input: &HookInput, format: crate::serde::Format) -> Result<(), Error> {
+    info!("Running hook: {}", command);
+    let mut child = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::io::stderr())
+        .spawn()?;
+    // Hooks are not obligated to read their input, so failures to write it,
+    // such as a broken pipe, are ignored.
+    if let Some(stdin) = child.stdin.take() {
+        let _ = crate::serde::serialize(format, input, stdin);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::HookFailed(command.to_owned(), status.to_string()));
+    }
+    Ok(())
+}
+
+/// Helper to run a configuration script using the shell, providing the
+/// current display state on stdin and returning its output.  Like hooks,
+/// anything the script writes to stderr is passed through.
+fn run_script(script: &Script, format: crate::serde::Format) -> Result<String, Error> {
+    info!("Running configuration script: {}", script.source);
+    let state = (script.state)()?;
+    let mut child = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(script.source.to_string())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    // The state is written from another thread, as the script may write
+    // its output before reading its input, and either pipe could fill.
+    // Scripts are not obligated to read their input, so failures to write
+    // it, such as a broken pipe, are ignored.
+    let stdin = child.stdin.take();
+    let writer = indirect_logger::spawn(move || {
+        if let Some(stdin) = stdin {
+            let _ = crate::serde::serialize(format, &state, stdin);
+        }
+    });
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(Error::ScriptFailed(
+            script.source.to_string(),
+            output.status.to_string(),
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Helper to evaluate a Rhai configuration script, providing it the
+/// current display state, and returning the configuration it evaluates to
+/// serialized as JSON.
+#[cfg(feature = "scripting")]
+fn run_rhai_script(script: &Script, path: &Path) -> Result<String, Error> {
+    info!("Evaluating configuration script: {}", path.display());
+    let state = serde_json::to_value((script.state)()?).map_err(crate::serde::Error::SerJson)?;
+    let source = std::fs::read_to_string(path)?;
+    let config = crate::scripting::evaluate(&source, &state)
+        .map_err(|e| Error::ScriptFailed(path.display().to_string(), e.to_string()))?;
+    Ok(serde_json::to_string(&config).map_err(crate::serde::Error::SerJson)?)
+}
+
+/// Without the `scripting` feature, Rhai configuration scripts cannot be
+/// evaluated.
+#[cfg(not(feature = "scripting"))]
+fn run_rhai_script(_script: &Script, path: &Path) -> Result<String, Error> {
+    Err(Error::ScriptFailed(
+        path.display().to_string(),
+        "knoll was built without the scripting feature".to_owned(),
+    ))
+}
+
+/// Helper to run the pre hook of the configuration group, if it has one.
+fn run_pre_hook(
+    config_group: &ValidConfigGroup,
+    old_config: &ConfigGroups,
+    format: crate::serde::Format,
+) -> Result<(), Error> {
+    if let Some(command) = config_group.hooks.as_ref().and_then(|h| h.pre.as_ref()) {
+        let new_config = ConfigGroups {
+            groups: vec![ConfigGroup {
+                name: None,
+                description: None,
+                configs: config_group.configs.values().cloned().collect(),
+                hooks: None,
+                when: None,
+                wait: None,
+            }],
+            ignored: vec![],
+        };
+        let input = HookInput {
+            old: old_config,
+            new: &new_config,
+        };
+        run_hook(command, &input, format)?;
+    }
+    Ok(())
+}
+
+/// Helper to run the post hook of the configuration group, if it has one,
+/// followed by the `--on-change` command, if there is one.
+fn run_post_hooks(
+    config_group: &ValidConfigGroup,
+    old_config: &ConfigGroups,
+    new_config: &ConfigGroups,
+    on_change: Option<&str>,
+    format: crate::serde::Format,
+) -> Result<(), Error> {
+    let post = config_group.hooks.as_ref().and_then(|h| h.post.as_deref());
+    let input = HookInput {
+        old: old_config,
+        new: new_config,
+    };
+    for command in post.into_iter().chain(on_change) {
+        run_hook(command, &input, format)?;
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Options controlling the behavior of pipeline mode.
+#[derive(Debug, Clone)]
+pub(crate) struct PipelineOptions {
+    /// Whether to suppress writing the display state to the output.
+    pub(crate) quiet: bool,
+    /// How display modes should be selected.
+    pub(crate) selection: ModeSelection,
+    /// Command to run after applying a configuration group, if any.
+    pub(crate) on_change: Option<String>,
+    /// Whether to include the display ID of each display in the output.
+    pub(crate) detailed: bool,
+    /// How configuration changes should be committed.
+    pub(crate) commit: CommitOptions,
+    /// Whether to exit with a distinct status when changes were made.
+    pub(crate) exit_code: bool,
+}
+
+/// Helper to apply the most precise of the given configuration groups,
+/// if there are any, returning the resulting display state along with the
+/// configuration group that was applied and whether any changes were made.
+/// The ignored displays are left untouched.
+pub(crate) fn apply_config_groups<DS: DisplayState>(
+    config_groups: &[ValidConfigGroup],
+    ignored: &[String],
+    format: crate::serde::Format,
+    options: &PipelineOptions,
+) -> Result<(DS, Option<ValidConfigGroup>, bool), Error> {
+    let mut display_state = DS::current()?;
+
+    // If there are no configuration groups, there is nothing to apply.
+    if config_groups.is_empty() {
+        return Ok((display_state, None, false));
+    }
+
+    let chosen_config =
+        find_most_precise_config_group(config_groups, &display_state, ignored, format)?;
+    let old_config = state_to_config(&display_state);
+    run_pre_hook(&chosen_config, &old_config, format)?;
+    let commit_duration = configure_displays(
+        &display_state,
+        chosen_config.clone(),
+        format,
+        &options.selection,
+        &options.commit,
+    )?;
+    // Update the display state with any changes that were applied.
+    display_state = DS::current()?;
+    let new_config = state_to_config(&display_state);
+    run_post_hooks(
+        &chosen_config,
+        &old_config,
+        &new_config,
+        options.on_change.as_deref(),
+        format,
+    )?;
+
+    Ok((
+        display_state,
+        Some(chosen_config),
+        commit_duration.is_some(),
+    ))
+}
+
+/// Helper to label the display state with the name and description of the
+/// configuration group that was applied to produce it, if any.
+pub(crate) fn label_state(state: &mut ConfigGroups, applied: Option<&ValidConfigGroup>) {
+    if let (Some(group), Some(applied)) = (state.groups.first_mut(), applied) {
+        group.name = applied.name.clone();
+        group.description = applied.description.clone();
+    }
+}
+
+/// Translate the origins of the displays in the configuration groups so that
+/// the upper left corner of their bounding box is at the origin.  The main
+/// display is always placed at the origin by macOS, so this makes the
+/// configuration independent of which display is the main display.
+fn normalize_origins(config_groups: &mut ConfigGroups) {
+    for group in &mut config_groups.groups {
+        let origins = group
+            .configs
+            .iter()
+            .filter_map(|config| config.origin.as_ref()?.exact());
+        let (Some(min_x), Some(min_y)) = (
+            origins.clone().map(|origin| origin.x).min(),
+            origins.map(|origin| origin.y).min(),
+        ) else {
+            continue;
+        };
+        for config in &mut group.configs {
+            if let Some(origin) = config.origin.as_ref().and_then(Origin::exact) {
+                config.origin = Some(
+                    Point {
+                        x: origin.x - min_x,
+                        y: origin.y - min_y,
+                    }
+                    .into(),
+                );
+            }
+        }
+    }
+}
+
+/// Write the current state of the displays to the output, without applying
+/// any configuration.
+fn state_command<DS: DisplayState>(
+    output: &mut dyn Write,
+    format: crate::serde::Format,
+    detailed: bool,
+    normalize: bool,
+) -> Result<(), Error> {
+    let mut cgs = detailed_state_to_config(&DS::current()?, detailed);
+    if normalize {
+        normalize_origins(&mut cgs);
+    }
+    crate::serde::serialize(format, &cgs, output)?;
+    Ok(())
+}
+
+fn pipeline_command<DS: DisplayState>(
+    mut config_reader: ConfigReader,
+    output: &mut dyn Write,
+    format: crate::serde::Format,
+    options: &PipelineOptions,
+) -> Result<RunResult, Error> {
+    let config_groups = config_reader.groups()?;
+    let (display_state, chosen_config, changed) =
+        apply_config_groups::<DS>(&config_groups, &config_reader.ignored(), format, options)?;
+
+    // Unless quieted, write the display state to the output
+    let mut cgs = detailed_state_to_config(&display_state, options.detailed);
+    label_state(&mut cgs, chosen_config.as_ref());
+    if !options.quiet {
+        crate::serde::serialize(format, &cgs, output)?;
+    }
+
+    Ok(RunResult {
+        state: Some(cgs),
+        applied_group: chosen_config.map(|vcg| vcg.uuids.into_iter().collect()),
+        changed,
+        // Like diff, report whether there were differences if requested.
+        exit_code: (options.exit_code && changed).into(),
+    })
+}
+
+/// Written in place of the display state when applying a configuration
+/// from a stream fails.
+#[derive(Debug, Serialize)]
+struct StreamError {
+    error: String,
+}
+
+/// Pipeline mode for a stream of newline-delimited configurations.  Each
+/// non-empty line of the input is applied in turn, and unless quieted,
+/// the resulting display state, or the error that occurred, is written as a
+/// single line to the output.  Errors do not end the stream, so that the
+/// process driving knoll may carry on.
+fn stream_command<DS: DisplayState>(
+    input: impl Read,
+    reader: impl Fn(String) -> ConfigReader,
+    output: &mut dyn Write,
+    format: crate::serde::Format,
+    options: &PipelineOptions,
+) -> Result<(), Error> {
+    for line in BufReader::new(input).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut config_reader = reader(line);
+        let result = config_reader
+            .groups()
+            .and_then(|config_groups| {
+                apply_config_groups::<DS>(&config_groups, &config_reader.ignored(), format, options)
+            })
+            .map(|(display_state, chosen_config, _)| {
+                let mut state = detailed_state_to_config(&display_state, options.detailed);
+                label_state(&mut state, chosen_config.as_ref());
+                state
+            });
+        if let Err(e) = &result {
+            error!("{}", e);
+        }
+        if options.quiet {
+            continue;
+        }
+        match result {
+            Ok(state) => crate::serde::serialize_compact(format, &state, &mut *output)?,
+            Err(e) => {
+                let error = StreamError {
+                    error: e.to_string(),
+                };
+                crate::serde::serialize_compact(format, &error, &mut *output)?
+            }
+        }
+        writeln!(output)?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The display modes available for a display.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
+pub struct DisplayModeGroup<DM>
+where
+    DM: Serialize,
+{
+    pub uuid: String,
+    /// How the display is participating in a mirror set, if at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirroring: Option<Mirroring>,
+    pub modes: Vec<DM>,
+    /// Modes that are not usable for the desktop GUI.  Only populated when
+    /// all modes were requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unsafe_modes: Vec<DM>,
+}
+
+impl<DM: Serialize> DisplayModeGroup<DM> {
+    /// Transform each of the display modes in the group.
+    fn map<T: Serialize>(self, mut f: impl FnMut(DM) -> T) -> DisplayModeGroup<T> {
+        DisplayModeGroup {
+            uuid: self.uuid,
+            mirroring: self.mirroring,
+            modes: self.modes.into_iter().map(&mut f).collect(),
+            unsafe_modes: self.unsafe_modes.into_iter().map(f).collect(),
+        }
+    }
+}
+
+impl<DM: DisplayMode> DisplayModeGroup<DM> {
+    /// Nest the display modes of the group by their resolution.
+    fn group_by_resolution(self) -> DisplayModeGroup<ResolutionGroup<DM>> {
+        DisplayModeGroup {
+            uuid: self.uuid,
+            mirroring: self.mirroring,
+            modes: ResolutionGroup::group(self.modes),
+            unsafe_modes: ResolutionGroup::group(self.unsafe_modes),
+        }
+    }
+}
+
+/// A summary of the current settings of a display, without its possible
+/// display modes.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+struct DisplaySummary<DM>
+where
+    DM: Serialize,
+{
+    uuid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    mode: DM,
+    origin: Point,
+    rotation: Rotation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mirroring: Option<Mirroring>,
+}
+
+impl<DM: DisplayMode> DisplaySummary<DM> {
+    fn new<D: Display<DisplayModeType = DM>>(uuid: &str, display: &D) -> Self {
+        DisplaySummary {
+            uuid: uuid.to_owned(),
+            name: display.name().map(str::to_owned),
+            mode: display.current_mode().clone(),
+            origin: display.origin().clone(),
+            rotation: display.rotation(),
+            mirroring: display.mirroring(),
+        }
+    }
+}
+
+/// Helper structure for serializing the display modes sharing the same
+/// resolution.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+struct ResolutionGroup<DM>
+where
+    DM: Serialize,
+{
+    extents: Point,
+    modes: Vec<DM>,
+}
+
+impl<DM: DisplayMode> ResolutionGroup<DM> {
+    /// Group the display modes by their resolution.  The groups are ordered
+    /// by the first of their modes, which otherwise retain their order.
+    fn group(modes: Vec<DM>) -> Vec<Self> {
+        let mut groups: Vec<ResolutionGroup<DM>> = Vec::new();
+        for mode in modes {
+            match groups.iter_mut().find(|g| &g.extents == mode.extents()) {
+                Some(group) => group.modes.push(mode),
+                None => groups.push(ResolutionGroup {
+                    extents: mode.extents().clone(),
+                    modes: vec![mode],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// Transform each of the display modes in the group.
+    fn map<T: Serialize>(self, f: impl FnMut(DM) -> T) -> ResolutionGroup<T> {
+        ResolutionGroup {
+            extents: self.extents,
+            modes: self.modes.into_iter().map(f).collect(),
+        }
+    }
+}
+
+/// Helper structure for serializing a display mode along with details about
+/// its underlying representation.  Primarily useful for debugging why
+/// seemingly duplicate display modes are reported.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+struct DetailedDisplayMode<DM>
+where
+    DM: Serialize,
+{
+    mode: DM,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt"
+    )]
+    mode_id: Option<i32>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt"
+    )]
+    io_flags: Option<u32>,
+}
+
+impl<DM: DisplayMode> DetailedDisplayMode<DM> {
+    fn new(mode: DM) -> Self {
+        DetailedDisplayMode {
+            mode_id: mode.mode_number(),
+            io_flags: mode.io_flags(),
+            mode,
+        }
+    }
+}
+
+/// Helper to render a display mode on a single line, in the form
+/// `uuid WxH@Hz depth scale`, for use in shell pipelines.  Modes that are
+/// not usable for the desktop are additionally marked as unsafe, and scaled
+/// modes are annotated with what they look like and their backing pixels.
+fn compact_mode_line<DM: DisplayMode>(uuid: &str, mode: &DM, usable: bool) -> String {
+    let mut line = format!("{} {}", uuid, mode_summary(mode));
+    if !usable {
+        line.push_str(" unsafe");
+    }
+    if let Some(looks_like) = mode.looks_like() {
+        let pixel_extents = mode.pixel_extents();
+        line.push_str(&format!(
+            " looks like {}x{} ({}x{} pixels)",
+            looks_like.x, looks_like.y, pixel_extents.x, pixel_extents.y
+        ));
+    }
+    line
+}
+
+/// Helper to summarize a display mode in the form `WxH@Hz depth scale`.
+fn mode_summary<DM: DisplayMode>(mode: &DM) -> String {
+    let extents = mode.extents();
+    format!(
+        "{}x{}@{} {} {}",
+        extents.x,
+        extents.y,
+        mode.frequency(),
+        mode.color_depth(),
+        mode.scale()
+    )
+}
+
+/// The orders in which the list command can sort display modes.  Each
+/// places the largest values first.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+enum ModeSort {
+    /// Sort by the number of pixels.
+    Resolution,
+    /// Sort by the refresh frequency.
+    Frequency,
+    /// Sort by the color depth.
+    Depth,
+}
+
+impl ModeSort {
+    /// Constant containing all the possible `ModeSort` values.
+    const VALUES: [ModeSort; 3] = [ModeSort::Resolution, ModeSort::Frequency, ModeSort::Depth];
+
+    /// The name used to refer to this order on the command-line.
+    fn name(&self) -> &'static str {
+        match self {
+            ModeSort::Resolution => "resolution",
+            ModeSort::Frequency => "frequency",
+            ModeSort::Depth => "depth",
+        }
+    }
+
+    /// Sort the display modes in this order.  The sort is stable, so modes
+    /// that tie retain the order the platform reported them in.
+    fn sort<DM: DisplayMode>(&self, modes: &mut [DM]) {
+        modes.sort_by_key(|mode| {
+            std::cmp::Reverse(match self {
+                ModeSort::Resolution => mode.extents().x * mode.extents().y,
+                ModeSort::Frequency => mode.frequency() as i64,
+                ModeSort::Depth => mode.color_depth() as i64,
+            })
+        });
+    }
+}
+
+impl TryFrom<&str> for ModeSort {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ModeSort::VALUES
+            .into_iter()
+            .find(|sort| sort.name() == value)
+            .ok_or(format!("{} is not a known sort order.", value))
+    }
+}
+
+/// Options controlling the output of the list command.
+#[derive(Debug, Clone, Default)]
+struct ListOptions {
+    /// Whether to include modes that are not usable for the desktop.
+    all_modes: bool,
+    /// Whether to include details of the underlying mode representation.
+    detailed: bool,
+    /// If non-empty, only list the modes for the displays with these UUIDs.
+    uuids: Vec<String>,
+    /// Whether to print one mode per line rather than serializing.
+    compact: bool,
+    /// How to handle display modes with identical properties.
+    duplicates: DuplicateModes,
+    /// The order to list the display modes in, if not the order the
+    /// platform reports them in.
+    sort: Option<ModeSort>,
+    /// Whether to nest the display modes by their resolution.
+    by_resolution: bool,
+    /// Whether to summarize each display rather than listing its modes.
+    displays: bool,
+}
+
+/// Helper to select the attached displays to list, or only those with the
+/// given UUIDs if any are given.  Fails if any of the UUIDs, or UUID prefixes,
+/// do not refer to an attached display, suggesting the closest attached
+/// display or alias for each.
+fn listed_displays<'a, DS: DisplayState>(
+    display_state: &'a DS,
+    uuids: &[String],
+    aliases: &Aliases,
+) -> Result<Vec<(&'a String, &'a DS::DisplayType)>, Error> {
+    // Check that all the requested displays are actually attached, resolving
+    // any UUID prefixes.
+    let mut unknown = Vec::new();
+    let mut resolved = Vec::new();
+    for uuid in uuids {
+        match resolve_uuid(uuid, display_state.get_displays())? {
+            Some(full) => resolved.push(full),
+            None => unknown.push(uuid.clone()),
+        }
+    }
+    if !unknown.is_empty() {
+        let suggestions = unknown
+            .iter()
+            .filter_map(|uuid| {
+                let candidates = display_state.get_displays().keys().chain(aliases.names());
+                suggest_uuid(uuid, candidates).map(|suggested| (uuid.clone(), suggested))
+            })
+            .collect();
+        return Err(Error::UnknownDisplays(unknown, suggestions));
+    }
+    Ok(display_state
+        .get_displays()
+        .iter()
+        .filter(|(uuid, _)| uuids.is_empty() || resolved.contains(uuid))
+        .collect())
+}
+
+/// Helper to collect the display modes of the attached displays, or only
+/// those with the given UUIDs if any are given.  Modes not usable for the
+/// desktop are only included if requested, and duplicate modes are handled
+/// according to the given policy.
+pub(crate) fn collect_mode_groups<DS: DisplayState>(
+    display_state: &DS,
+    uuids: &[String],
+    aliases: &Aliases,
+    all_modes: bool,
+    duplicates: DuplicateModes,
+) -> Result<Vec<DisplayModeGroup<DS::DisplayModeType>>, Error> {
+    let mut groups: Vec<DisplayModeGroup<DS::DisplayModeType>> = Vec::new();
+
+    // Collect up all modes, separating out those not usable for the desktop.
+    for (uuid, display) in listed_displays(display_state, uuids, aliases)? {
+        let (modes, unsafe_modes): (Vec<_>, Vec<_>) = duplicates
+            .dedup(display.possible_modes().to_vec())
+            .into_iter()
+            .partition(|m| m.usable_for_desktop());
+        groups.push(DisplayModeGroup {
+            uuid: uuid.clone(),
+            mirroring: display.mirroring(),
+            modes,
+            unsafe_modes: if all_modes { unsafe_modes } else { vec![] },
+        });
+    }
+
+    Ok(groups)
+}
+
+fn list_command<DS: DisplayState>(
+    output: &mut dyn Write,
+    format: crate::serde::Format,
+    aliases: &Aliases,
+    options: &ListOptions,
+) -> Result<(), Error> {
+    let display_state = DS::current()?;
+    if options.displays {
+        let summaries: Vec<_> = listed_displays(&display_state, &options.uuids, aliases)?
+            .into_iter()
+            .map(|(uuid, display)| DisplaySummary::new(uuid, display))
+            .collect();
+        crate::serde::serialize(format, &summaries, output)?;
+        return Ok(());
+    }
+    let mut groups = collect_mode_groups(
+        &display_state,
+        &options.uuids,
+        aliases,
+        options.all_modes,
+        options.duplicates,
+    )?;
+    if let Some(sort) = options.sort {
+        for group in &mut groups {
+            sort.sort(&mut group.modes);
+            sort.sort(&mut group.unsafe_modes);
+        }
+    }
+
+    // When all duplicates are kept, include the mode ids so that they may be
+    // told apart.
+    let detailed = options.detailed || options.duplicates == DuplicateModes::All;
+
+    // Serialize them to output.
+    if options.compact {
+        for group in &groups {
+            for mode in &group.modes {
+                writeln!(output, "{}", compact_mode_line(&group.uuid, mode, true))?;
+            }
+            for mode in &group.unsafe_modes {
+                writeln!(output, "{}", compact_mode_line(&group.uuid, mode, false))?;
+            }
+        }
+    } else if options.by_resolution && detailed {
+        let nested_groups: Vec<_> = groups
+            .into_iter()
+            .map(|group| {
+                group
+                    .group_by_resolution()
+                    .map(|nested| nested.map(DetailedDisplayMode::new))
+            })
+            .collect();
+        crate::serde::serialize(format, &nested_groups, output)?;
+    } else if options.by_resolution {
+        let nested_groups: Vec<_> = groups
+            .into_iter()
+            .map(DisplayModeGroup::group_by_resolution)
+            .collect();
+        crate::serde::serialize(format, &nested_groups, output)?;
+    } else if detailed {
+        let detailed_groups: Vec<_> = groups
+            .into_iter()
+            .map(|group| group.map(DetailedDisplayMode::new))
+            .collect();
+        crate::serde::serialize(format, &detailed_groups, output)?;
+    } else {
+        crate::serde::serialize(format, &groups, output)?;
+    }
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How often to check for displays being attached or detached once
+/// subscribed in serve mode.
+const SUBSCRIBE_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Helper to validate configuration groups received in serve mode, also
+/// returning the displays they ignore.  When strict, the configuration is
+/// first checked for unknown fields.
+fn serve_config_groups(
+    value: &serde_json::Value,
+    strict_config: bool,
+    aliases: &Aliases,
+) -> Result<(Vec<ValidConfigGroup>, Vec<String>), Error> {
+    let value = crate::serde::migrate(value.clone())?;
+    if strict_config {
+        let unknown = unknown_fields(&value);
+        if !unknown.is_empty() {
+            return Err(Error::UnknownFields(unknown));
+        }
+    }
+    let mut config_groups =
+        ConfigGroups::deserialize(value).map_err(crate::serde::Error::DeJson)?;
+    aliases.resolve_config_groups(&mut config_groups);
+    let ignored = std::mem::take(&mut config_groups.ignored);
+    Ok((validate_config_groups(config_groups)?, ignored))
+}
+
+/// Helper to carry out a single command in serve mode.
+fn serve_request<DS: DisplayState>(
+    command: serve::Command,
+    config: &serde_json::Value,
+    diagnostics: &Diagnostics,
+    strict_config: bool,
+    aliases: &Aliases,
+    options: &PipelineOptions,
+) -> Result<serve::ResponseKind, Error> {
+    use serve::{Command, ResponseKind};
+
+    let format = crate::serde::Format::Json;
+    Ok(match command {
+        Command::List { uuids, all_modes } => {
+            let uuids: Vec<String> = uuids.iter().map(|uuid| aliases.resolve(uuid)).collect();
+            let groups = collect_mode_groups(
+                &DS::current()?,
+                &uuids,
+                aliases,
+                all_modes,
+                options.selection.duplicates,
+            )?;
+            ResponseKind::Modes {
+                displays: serde_json::to_value(groups).map_err(crate::serde::Error::SerJson)?,
+            }
+        }
+        Command::Apply { .. } => {
+            let (config_groups, ignored) = serve_config_groups(config, strict_config, aliases)?;
+            diagnostics
+                .report(check_config_groups(&config_groups))
+                .map_err(Error::Strict)?;
+            let (display_state, chosen_config, _) =
+                apply_config_groups::<DS>(&config_groups, &ignored, format, options)?;
+            let mut state = state_to_config(&display_state);
+            label_state(&mut state, chosen_config.as_ref());
+            ResponseKind::State { state }
+        }
+        Command::Verify { .. } => {
+            let (config_groups, ignored) = serve_config_groups(config, strict_config, aliases)?;
+            let warnings = check_config_groups(&config_groups)
+                .iter()
+                .map(|warning| warning.to_string())
+                .collect();
+            let display_state = DS::current()?;
+            let group = match find_most_precise_config_group(
+                &config_groups,
+                &display_state,
+                &ignored,
+                format,
+            ) {
+                Ok(vcg) => Some(vcg.uuids.into_iter().collect()),
+                Err(Error::NoMatchingConfigGroup(..)) => None,
+                Err(e) => return Err(e),
+            };
+            ResponseKind::Verified { warnings, group }
+        }
+        // Subscriptions are handled by `serve_command`.
+        Command::Subscribe => ResponseKind::Subscribed,
+    })
+}
+
+/// Serve mode reads commands from the input, one per line, and writes a
+/// response to each to the output.  Once subscribed, events for displays
+/// being attached or detached are also written to the output from a
+/// separate thread, which is why the output must be shared.  Serving ends
+/// once the input is exhausted.
+fn serve_command<DS: DisplayState>(
+    input: impl BufRead,
+    mut output: SharedWriter,
+    diagnostics: Diagnostics,
+    strict_config: bool,
+    aliases: &Aliases,
+    options: &PipelineOptions,
+) -> Result<(), Error> {
+    use serve::{Request, Response, ResponseKind};
+
+    // Used to stop the subscription thread, if there is one, once serving
+    // has finished.
+    let stop = std::sync::Arc::new(AtomicBool::new(false));
+    let mut subscribed = false;
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Parse the request in two steps, so that the configuration can be
+        // checked for unknown fields.
+        let request = serde_json::from_str::<serde_json::Value>(&line)
+            .and_then(|value| Ok((Request::deserialize(&value)?, value)));
+        let (id, kind) = match request {
+            Err(e) => (
+                None,
+                ResponseKind::Error {
+                    message: format!("Invalid request: {}", e),
+                },
+            ),
+            Ok((request, value)) => {
+                info!("Received request: {:?}", request.command);
+                let config = value.get("config").unwrap_or(&serde_json::Value::Null);
+                let result = serve_request::<DS>(
+                    request.command,
+                    config,
+                    &diagnostics,
+                    strict_config,
+                    aliases,
+                    options,
+                );
+                let kind = result.unwrap_or_else(|e| {
+                    error!("{}", e);
+                    ResponseKind::Error {
+                        message: e.to_string(),
+                    }
+                });
+                (request.id, kind)
+            }
+        };
+
+        // When first subscribing, record the currently attached displays
+        // before responding, so that no changes are missed, but only start
+        // reporting changes afterward.
+        let event_writer = if kind == ResponseKind::Subscribed && !subscribed {
+            subscribed = true;
+            let mut writer = events::EventWriter::new(Box::new(output.clone()));
+            writer.update_displays(DS::current()?.get_displays().keys().cloned().collect())?;
+            Some(writer)
+        } else {
+            None
+        };
+
+        output.write_all(&serve::response_line(&Response { id, kind }))?;
+        output.flush()?;
+
+        if let Some(mut writer) = event_writer {
+            let stop = stop.clone();
+            indirect_logger::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    std::thread::sleep(SUBSCRIBE_POLL);
+                    match DS::current() {
+                        Ok(display_state) => {
+                            let uuids = display_state.get_displays().keys().cloned().collect();
+                            if let Err(e) = writer.update_displays(uuids) {
+                                error!("Error writing events: {}", e);
+                            }
+                        }
+                        Err(e) => error!("{}", e),
+                    }
+                }
+            });
+        }
+    }
+
+    stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A configuration group applied by the daemon, along with the origins the
+/// displays settled on once it was applied, for those displays whose origin
+/// it requests.
+struct AppliedGroup {
+    config_group: ValidConfigGroup,
+    origins: HashMap<String, Point>,
+}
+
+/// The state of a running daemon, shared between its threads.  Each daemon
+/// owns its state, so that several daemons may run within the same process.
+struct DaemonState {
+    // Reconfiguration is guarded by a lock.  Depending on the system
+    // configuration, doing something a simple as opening a closed laptop lid
+    // will trigger multiple invocations of the callback.  To prevent those
+    // from needlessly triggering reconfiguration multiple times, we use a
+    // mutex over a Boolean and signal via a condition variable that the
+    // reconfiguration worker thread should wake up.
+    reconfigure_lock: Mutex<bool>,
+    reconfigure_condvar: Condvar,
+    /// The reference point for `last_event`.
+    event_epoch: Instant,
+    /// When the most recent display configuration event occurred, as the
+    /// number of nanoseconds since `event_epoch`.  An atomic is used so that
+    /// recording an event from the callback never blocks.
+    last_event: AtomicU64,
+    /// The number of display configuration events received.  This is kept
+    /// separately from `metrics` so that counting events never blocks the
+    /// callback.
+    events_received: AtomicU64,
+    /// Whether the system has woken from sleep since the last
+    /// reconfiguration.
+    woke: AtomicBool,
+    /// Whether the computer was last known to be running on battery power.
+    /// Power source notifications are also delivered for changes such as
+    /// the battery charge level, so this is used to only reconfigure when
+    /// the source of power actually changes.
+    on_battery: Mutex<Option<bool>>,
+    /// The times of day at which a configuration group starts or stops
+    /// applying, collected from the `between` conditions of the
+    /// configuration groups.
+    schedule: Mutex<BTreeSet<TimeOfDay>>,
+    /// Whether the next reconfiguration should skip waiting for the display
+    /// configuration to quiesce.  Used for reconfigurations that were
+    /// explicitly requested, rather than triggered by a display
+    /// configuration change.
+    skip_wait: AtomicBool,
+    /// Whether the daemon is shutting down.  Once set, no further
+    /// reconfigurations will be started.
+    shutting_down: AtomicBool,
+    /// The number of reconfigurations the daemon has performed.
+    reconfigure_count: AtomicU64,
+    /// The configuration group, identified by its set of UUIDs, that was
+    /// requested via the control socket.  When set, it is used in preference
+    /// to the most precise configuration group for as long as its displays
+    /// are attached.
+    pinned_group: Mutex<Option<BTreeSet<String>>>,
+    /// The configuration group most recently applied, which is compared
+    /// against the displays when enforcing the configuration.
+    applied_group: Mutex<Option<AppliedGroup>>,
+    /// The status of the daemon, as reported to `knoll status`.
+    status: Mutex<control::DaemonStatus>,
+    /// The metrics collected by the daemon, other than the number of events.
+    metrics: Mutex<control::Metrics>,
+    /// Stops the event loop of the `DisplayState` the daemon is using.
+    stop_event_loop: fn(),
+}
+
+impl DaemonState {
+    fn new(stop_event_loop: fn()) -> Self {
+        DaemonState {
+            reconfigure_lock: Mutex::new(false),
+            reconfigure_condvar: Condvar::new(),
+            event_epoch: Instant::now(),
+            last_event: AtomicU64::new(0),
+            events_received: AtomicU64::new(0),
+            woke: AtomicBool::new(false),
+            on_battery: Mutex::new(core_graphics::io_ps_on_battery()),
+            schedule: Mutex::new(BTreeSet::new()),
+            skip_wait: AtomicBool::new(false),
+            shutting_down: AtomicBool::new(false),
+            reconfigure_count: AtomicU64::new(0),
+            pinned_group: Mutex::new(None),
+            applied_group: Mutex::new(None),
+            status: Mutex::new(control::DaemonStatus::new()),
+            metrics: Mutex::new(control::Metrics::default()),
+            stop_event_loop,
+        }
+    }
+
+    /// Acquire the reconfiguration lock and notify the conditional
+    /// variable.  If a reconfiguration is already in progress, the request
+    /// is dropped.
+    fn trigger_reconfig(&self) {
+        self.record_event();
+        if let Ok(ref mut reconfig_started) = self.reconfigure_lock.try_lock() {
+            **reconfig_started = true;
+            // Signal to the worker thread to wake up and perform
+            // the reconfiguration.
+            self.reconfigure_condvar.notify_one();
+        }
+    }
+
+    /// Record that a display configuration event just occurred.
+    fn record_event(&self) {
+        let nanos = self.event_epoch.elapsed().as_nanos();
+        self.last_event
+            .store(u64::try_from(nanos).unwrap_or(u64::MAX), Ordering::SeqCst);
+    }
+
+    /// Wait until no display configuration events have occurred for the
+    /// wait period, or until the maximum wait has elapsed, whichever comes
+    /// first.  Each new event restarts the wait period.
+    fn debounce(&self, wait_period: std::time::Duration, max_wait: std::time::Duration) {
+        let deadline = Instant::now() + max_wait;
+        loop {
+            let last_event = self.event_epoch
+                + std::time::Duration::from_nanos(self.last_event.load(Ordering::SeqCst));
+            let until = std::cmp::min(last_event + wait_period, deadline);
+            let now = Instant::now();
+            if now >= until {
+                return;
+            }
+            std::thread::sleep(until - now);
+        }
+    }
+
+    /// Handle a display configuration event, which is counted before
+    /// triggering reconfiguration.
+    fn display_reconfigured(&self) {
+        self.events_received.fetch_add(1, Ordering::SeqCst);
+        self.trigger_reconfig();
+    }
+
+    /// Handle the system waking from sleep.  Reconfiguration after waking
+    /// uses a separate wait period, as displays are often slow to settle.
+    fn system_woke(&self) {
+        self.woke.store(true, Ordering::SeqCst);
+        self.trigger_reconfig();
+    }
+
+    /// Handle a power source event, triggering reconfiguration if the source
+    /// of power has changed.
+    fn power_source_changed(&self, on_battery: Option<bool>) {
+        let changed = match self.on_battery.lock() {
+            Ok(mut last) => std::mem::replace(&mut *last, on_battery) != on_battery,
+            Err(pe) => {
+                error!("Error checking the power source: {}", pe);
+                false
+            }
+        };
+        if changed {
+            info!("Power source changed.");
+            self.trigger_reconfig();
+        }
+    }
+
+    /// Record the times of day at which the given configuration groups
+    /// start or stop applying.
+    fn update_schedule(&self, vcgs: &[ValidConfigGroup]) -> Result<(), Error> {
+        *self
+            .schedule
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))? = vcgs
+            .iter()
+            .filter_map(|vcg| vcg.when.as_ref().and_then(|when| when.between))
+            .flat_map(|range| [range.start, range.end])
+            .collect();
+        Ok(())
+    }
+
+    /// Trigger reconfiguration at the start of each minute at which a
+    /// configuration group starts or stops applying, until shutting down.
+    fn run_schedule(&self) {
+        while !self.shutting_down.load(Ordering::SeqCst) {
+            // Sleep until the start of the next minute.
+            let since_epoch = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            std::thread::sleep(
+                std::time::Duration::from_secs(60)
+                    - std::time::Duration::from_secs(since_epoch.as_secs() % 60),
+            );
+
+            let Some(now) = environment::local_time() else {
+                continue;
+            };
+            let scheduled = match self.schedule.lock() {
+                Ok(schedule) => schedule.contains(&now),
+                Err(pe) => {
+                    error!("Error checking the schedule: {}", pe);
+                    false
+                }
+            };
+            if scheduled {
+                info!("Scheduled reconfiguration at {}.", now);
+                self.trigger_reconfig();
+            }
+        }
+    }
+
+    /// Check whether the displays have drifted from the configuration group
+    /// most recently applied, such as when macOS or another application
+    /// rearranges them, and if so immediately reconfigure them.  Displays
+    /// that are being reconfigured, or that no longer include those of the
+    /// group, are left to the usual reconfiguration.
+    fn enforce<DS: DisplayState>(
+        &self,
+        format: crate::serde::Format,
+        selection: &ModeSelection,
+    ) -> Result<(), Error> {
+        // Inspect the displays without holding the reconfiguration lock, so
+        // that display configuration events are not dropped meanwhile.
+        let drifted = {
+            let applied = self
+                .applied_group
+                .lock()
+                .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?;
+            let Some(applied) = applied.as_ref() else {
+                return Ok(());
+            };
+            let display_state = DS::current()?;
+            let displays = display_state.get_displays();
+            let disabled = display_state.disabled_displays();
+            if !applied
+                .config_group
+                .uuids
+                .iter()
+                .all(|uuid| displays.contains_key(uuid) || disabled.contains(uuid))
+            {
+                return Ok(());
+            }
+            has_drifted(&display_state, applied, format, selection)?
+        };
+        if !drifted {
+            return Ok(());
+        }
+        // A reconfiguration in progress will replace the applied group.
+        let Ok(mut reconfig_started) = self.reconfigure_lock.try_lock() else {
+            return Ok(());
+        };
+        if *reconfig_started {
+            return Ok(());
+        }
+        info!("The displays have drifted from the configuration group, reapplying it.");
+        self.skip_wait.store(true, Ordering::SeqCst);
+        *reconfig_started = true;
+        self.reconfigure_condvar.notify_one();
+        Ok(())
+    }
+
+    /// Enforce the configuration group most recently applied at the given
+    /// interval, until shutting down.
+    fn run_enforcement<DS: DisplayState>(
+        &self,
+        interval: std::time::Duration,
+        format: crate::serde::Format,
+        selection: &ModeSelection,
+    ) {
+        while !self.shutting_down.load(Ordering::SeqCst) {
+            std::thread::sleep(interval);
+            if let Err(e) = self.enforce::<DS>(format, selection) {
+                error!("Error checking the displays for drift: {}", e);
+            }
+        }
+    }
+
+    /// Request an immediate reconfiguration.  Unlike `trigger_reconfig`
+    /// this will block if a reconfiguration is already in progress, so that
+    /// the request is not lost, and the reconfiguration will not wait for
+    /// the display configuration to quiesce.
+    fn request_immediate_reconfig(&self) -> Result<(), Error> {
+        let mut reconfig_started = self
+            .reconfigure_lock
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?;
+        self.skip_wait.store(true, Ordering::SeqCst);
+        *reconfig_started = true;
+        self.reconfigure_condvar.notify_one();
+        Ok(())
+    }
+
+    /// Reload the configuration, forgetting any pinned configuration group,
+    /// and immediately reconfigure the displays.
+    fn reload(&self) -> Result<(), Error> {
+        *self
+            .pinned_group
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))? = None;
+        self.request_immediate_reconfig()
+    }
+
+    /// Ask the daemon to shut down.  This stops the main run loop, after
+    /// which the daemon will wait for any reconfiguration in progress to
+    /// finish.
+    fn request_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        (self.stop_event_loop)();
+    }
+
+    /// Choose the configuration group to apply.  This is the pinned group if
+    /// there is one that matches the attached displays other than those
+    /// ignored, otherwise the most precise configuration group.
+    fn choose_config_group<DS: DisplayState>(
+        &self,
+        vcgs: &[ValidConfigGroup],
+        display_state: &DS,
+        ignored: &[String],
+        format: crate::serde::Format,
+    ) -> Result<ValidConfigGroup, Error> {
+        let pinned = self
+            .pinned_group
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?
+            .clone();
+        if let Some(uuids) = pinned {
+            let displays = display_state.get_displays();
+            let ignored_uuids = resolve_ignored(ignored, displays)?;
+            let unignored: BTreeMap<String, _> = displays
+                .iter()
+                .filter(|(uuid, _)| !ignored_uuids.contains(*uuid))
+                .map(|(uuid, display)| (uuid.clone(), display))
+                .collect();
+            let environment = DS::environment();
+            for vcg in vcgs {
+                if vcg.uuids != uuids || !environment.satisfies(&vcg.conditions()) {
+                    continue;
+                }
+                if let Some(vcg) = vcg.resolve(&unignored)? {
+                    if kinds_match(&vcg, displays) {
+                        info!("Using the pinned configuration group.");
+                        return Ok(vcg);
+                    }
+                }
+            }
+        }
+        find_most_precise_config_group(vcgs, display_state, ignored, format)
+    }
+
+    /// Update the daemon status.  Failures to obtain the lock are only
+    /// logged, as the status is purely informational.
+    fn record_status<F: FnOnce(&mut control::DaemonStatus)>(&self, update: F) {
+        match self.status.lock() {
+            Ok(mut status) => update(&mut status),
+            Err(pe) => error!("Error obtaining the daemon status lock: {}", pe),
+        }
+    }
+
+    /// Update the daemon metrics.  Failures to obtain the lock are only
+    /// logged, as the metrics are purely informational.
+    fn record_metrics<F: FnOnce(&mut control::Metrics)>(&self, update: F) {
+        match self.metrics.lock() {
+            Ok(mut metrics) => update(&mut metrics),
+            Err(pe) => error!("Error obtaining the daemon metrics lock: {}", pe),
+        }
+    }
+
+    /// Obtain a snapshot of the daemon metrics.
+    fn current_metrics(&self) -> Result<control::Metrics, Error> {
+        let mut metrics = self
+            .metrics
+            .lock()
+            .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?
+            .clone();
+        metrics.events = self.events_received.load(Ordering::SeqCst);
+        Ok(metrics)
+    }
+
+    /// Write the daemon metrics to a file.  The metrics are first written to
+    /// a temporary file that then replaces the file, so that readers never
+    /// observe a partially written file.
+    fn write_metrics_file(&self, path: &Path, format: crate::serde::Format) -> Result<(), Error> {
+        let metrics = self.current_metrics()?;
+        let temp_path = path.with_extension("tmp");
+        crate::serde::serialize(format, &metrics, std::fs::File::create(&temp_path)?)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+// The callbacks from the operating system do not carry any state, so each
+// notifies every daemon running in the process.  Daemons that have finished
+// are removed the next time a callback is invoked.
+static DAEMONS: Mutex<Vec<Weak<DaemonState>>> = Mutex::new(Vec::new());
+
+/// Used to only register the callbacks once, however many daemons run.
+static REGISTER_CALLBACKS: Once = Once::new();
+
+/// Helper to invoke the given function on every running daemon.  It must
+/// not panic, as it is used from callbacks invoked from C.
+fn for_each_daemon<F: Fn(&DaemonState)>(f: F) {
+    if let Ok(mut daemons) = DAEMONS.lock() {
+        daemons.retain(|daemon| match daemon.upgrade() {
+            Some(daemon) => {
+                f(&daemon);
+                true
+            }
+            None => false,
+        });
+    }
+}
 
-fn pipeline_command<DS: DisplayState>(
-    quiet: bool,
-    mut config_reader: ConfigReader,
-    output: &mut dyn Write,
+/// Callback for display configuration events.
+extern "C" fn display_reconfigured() {
+    for_each_daemon(DaemonState::display_reconfigured);
+}
+
+/// Callback for the system waking from sleep.
+extern "C" fn system_woke() {
+    info!("System woke from sleep.");
+    for_each_daemon(DaemonState::system_woke);
+}
+
+/// Callback for power source events.
+extern "C" fn power_source_changed(_context: *mut std::os::raw::c_void) {
+    let on_battery = core_graphics::io_ps_on_battery();
+    for_each_daemon(|daemon| daemon.power_source_changed(on_battery));
+}
+
+/// The signals handled by the daemon.
+const DAEMON_SIGNALS: [std::os::raw::c_int; 3] =
+    [signals::SIGHUP, signals::SIGINT, signals::SIGTERM];
+
+/// Helper to handle signals sent to the process.  `SIGHUP` reloads the
+/// configuration and reconfigures the displays, while `SIGINT` and `SIGTERM`
+/// shut the daemons down cleanly.
+fn handle_signals() {
+    loop {
+        match signals::wait_for_signal(&DAEMON_SIGNALS) {
+            Ok(signals::SIGHUP) => {
+                info!("Received SIGHUP, reloading the configuration.");
+                for_each_daemon(|daemon| {
+                    if let Err(e) = daemon.reload() {
+                        error!("Error reloading the configuration: {}", e);
+                    }
+                });
+            }
+            Ok(signal @ (signals::SIGINT | signals::SIGTERM)) => {
+                info!("Received signal {}, shutting down.", signal);
+                for_each_daemon(DaemonState::request_shutdown);
+            }
+            Ok(signal) => warn!("Ignoring unexpected signal {}.", signal),
+            Err(e) => {
+                error!("Error waiting for signals: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// How long to wait for an already running daemon to stop when replacing it.
+const REPLACE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Options controlling the behavior of the daemon.
+#[derive(Debug, Clone)]
+struct DaemonOptions {
+    /// How long display configuration events must cease before updating.
+    wait_period: std::time::Duration,
+    /// The longest to wait for display configuration events to cease.
+    max_wait: std::time::Duration,
+    /// How long display configuration events must cease before updating
+    /// after the system wakes from sleep.
+    wake_wait: std::time::Duration,
+    /// How long to ignore reconfiguration events after updating.
+    cooldown: std::time::Duration,
+    /// How to retry when the displays fail to reconfigure.
+    retry_policy: RetryPolicy,
+    /// How display modes should be selected.
+    selection: ModeSelection,
+    /// Command to run after applying a configuration group, if any.
+    on_change: Option<String>,
+    /// How configuration changes should be committed.
+    commit: CommitOptions,
+    /// Where to send notifications after each reconfiguration, if anywhere.
+    webhook: Option<webhook::Webhook>,
+    /// Whether to post Notification Center messages after reconfiguring.
+    notify: bool,
+    /// Where to write metrics after each reconfiguration, if anywhere.
+    metrics_file: Option<PathBuf>,
+    /// Whether to only log what would be applied, without reconfiguring.
+    observe: bool,
+    /// Whether to write a stream of events to the output.
+    events: bool,
+    /// Where to write events instead of standard output, if anywhere.
+    output: Option<PathBuf>,
+    /// Whether to append events to the output file rather than replacing it.
+    append: bool,
+    /// Where to listen for control requests, if at all.
+    control_socket: Option<PathBuf>,
+    /// The PID file used to ensure only one daemon runs at a time.
+    pid_file: PathBuf,
+    /// Whether to stop an already running daemon, rather than failing.
+    replace: bool,
+    /// The number of reconfigurations after which to exit, if any.
+    max_events: Option<u64>,
+    /// How often to check that the displays still match the configuration
+    /// group most recently applied, if at all.
+    enforce: Option<std::time::Duration>,
+}
+
+/// Helper to bind the control socket.  If there is a stale socket left over
+/// from a previous daemon it will be replaced, but it is an error if another
+/// daemon is still listening on it.
+fn bind_control_socket(path: &Path) -> Result<UnixListener, Error> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::DaemonRunning(path.to_path_buf()));
+        }
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(UnixListener::bind(path)?)
+}
+
+/// Helper to respond to a single control request.
+fn handle_control_request<DS: DisplayState>(
+    daemon: &DaemonState,
+    stream: &mut UnixStream,
+    config_reader: &mut ConfigReader,
     format: crate::serde::Format,
 ) -> Result<(), Error> {
-    let mut display_state = DS::current()?;
+    use control::{Request, Response};
+
+    let request = control::receive_request(stream, format)?;
+    info!("Received control request: {:?}", request);
+
+    let response = match &request {
+        Request::GetState => match DS::current() {
+            Ok(display_state) => Response::State(state_to_config(&display_state)),
+            Err(e) => Response::Error(e.to_string()),
+        },
+        Request::Reload => {
+            daemon.reload()?;
+            Response::Ok
+        }
+        Request::Apply(uuids) => {
+            let uuids: BTreeSet<String> = uuids.iter().cloned().collect();
+            match config_reader.groups() {
+                Ok(vcgs) if vcgs.iter().any(|vcg| vcg.uuids == uuids) => {
+                    *daemon
+                        .pinned_group
+                        .lock()
+                        .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))? = Some(uuids);
+                    daemon.trigger_reconfig();
+                    Response::Ok
+                }
+                Ok(_) => Response::Error(
+                    Error::UnknownConfigGroup(uuids.into_iter().collect()).to_string(),
+                ),
+                Err(e) => Response::Error(e.to_string()),
+            }
+        }
+        Request::Status => Response::Status(
+            daemon
+                .status
+                .lock()
+                .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?
+                .clone(),
+        ),
+        Request::Metrics => Response::Metrics(daemon.current_metrics()?),
+        Request::Trigger => {
+            daemon.request_immediate_reconfig()?;
+            Response::Ok
+        }
+        Request::Shutdown => Response::Ok,
+    };
+
+    control::send_response(stream, format, &response)?;
+
+    if request == Request::Shutdown {
+        info!("Shutting down.");
+        daemon.request_shutdown();
+    }
+
+    Ok(())
+}
+
+/// Helper to serve control requests on the given socket until the daemon
+/// exits.
+fn serve_control_requests<DS: DisplayState>(
+    daemon: &DaemonState,
+    listener: UnixListener,
+    mut config_reader: ConfigReader,
+    format: crate::serde::Format,
+) {
+    for incoming in listener.incoming() {
+        let result = incoming.map_err(Error::from).and_then(|mut stream| {
+            handle_control_request::<DS>(daemon, &mut stream, &mut config_reader, format)
+        });
+        if let Err(e) = result {
+            error!("Error handling control request: {}", e);
+        }
+    }
+}
+
+/// How the daemon retries applying a configuration group when the displays
+/// fail to reconfigure.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    /// How many times to retry after the initial attempt.
+    retries: u32,
+    /// How long to wait before the first retry.  The delay doubles with
+    /// each subsequent retry.
+    delay: std::time::Duration,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, counting from zero.
+    fn delay(&self, retry: u32) -> std::time::Duration {
+        self.delay.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
 
+/// Helper to obtain the current display state, both directly and as a
+/// configuration, and choose the configuration group to apply to it.
+fn prepare_reconfiguration<DS: DisplayState>(
+    daemon: &DaemonState,
+    config_reader: &mut ConfigReader,
+    format: crate::serde::Format,
+) -> Result<(DS, ConfigGroups, ValidConfigGroup), Error> {
     let config_groups = config_reader.groups()?;
+    if config_groups.is_empty() {
+        return Err(Error::NoConfigGroups);
+    }
+    daemon.update_schedule(&config_groups)?;
+
+    let display_state = DS::current()?;
+    let current_config = state_to_config(&display_state);
+    let config_str = serialize_to_string(format, &current_config).expect(
+        "Should be impossible to fail on serializing internally constructed configuration.",
+    );
+    info!(target: DETAIL_TARGET, "Current display state:\n{}", config_str);
 
-    // If there are any configuration groups, attempt to apply them.
-    if !config_groups.is_empty() {
-        let chosen_config = find_most_precise_config_group(&config_groups, &display_state, format)?;
-        configure_displays(&display_state, chosen_config, format)?;
-        // Update the display state with any changes that were applied.
-        display_state = DS::current()?;
+    let config_group = daemon.choose_config_group(
+        &config_groups,
+        &display_state,
+        &config_reader.ignored(),
+        format,
+    )?;
+    Ok((display_state, current_config, config_group))
+}
+
+/// Helper to find the wait period of the configuration group that would be
+/// applied to the current displays, if it has one.  Errors are only logged,
+/// as they will be reported again when reconfiguring.
+fn group_wait_period<DS: DisplayState>(
+    daemon: &DaemonState,
+    config_reader: &mut ConfigReader,
+    format: crate::serde::Format,
+) -> Option<std::time::Duration> {
+    let result = config_reader.groups().and_then(|config_groups| {
+        let display_state = DS::current()?;
+        daemon.choose_config_group(
+            &config_groups,
+            &display_state,
+            &config_reader.ignored(),
+            format,
+        )
+    });
+    match result {
+        Ok(config_group) => config_group.wait.map(|wait| wait.0),
+        Err(e) => {
+            debug!(
+                "Unable to determine the configuration group to wait for: {}",
+                e
+            );
+            None
+        }
     }
+}
 
-    // Unless quieted, write the display state to the output
-    if !quiet {
-        let cgs = state_to_config(&display_state);
-        crate::serde::serialize(format, &cgs, output)?;
+/// Helper to wait for the display configuration to quiesce before
+/// reconfiguring in daemon mode.  The wait period of the configuration group
+/// matching the current displays takes precedence over the given wait
+/// period.  As the displays may change while waiting, waiting continues for
+/// as long as the group then matching has a longer wait period.
+fn settle<DS: DisplayState>(
+    daemon: &DaemonState,
+    config_reader: &mut ConfigReader,
+    format: crate::serde::Format,
+    wait_period: std::time::Duration,
+    max_wait: std::time::Duration,
+) {
+    let mut waited = None;
+    loop {
+        let period = group_wait_period::<DS>(daemon, config_reader, format).unwrap_or(wait_period);
+        if waited.is_some_and(|waited| period <= waited) {
+            return;
+        }
+        if period != wait_period {
+            info!(
+                target: DETAIL_TARGET,
+                "Waiting {} for the configuration group's displays to settle.",
+                humantime::format_duration(period)
+            );
+        }
+        // Like the wake wait period, a group's wait period may exceed the
+        // maximum wait.
+        daemon.debounce(period, std::cmp::max(max_wait, period));
+        waited = Some(period);
     }
+}
 
+/// Helper to perform a single pass in observe mode.  This logs the
+/// configuration group and display modes that would be applied, without
+/// actually reconfiguring the displays or running any hooks.
+fn observe<DS: DisplayState>(
+    daemon: &DaemonState,
+    config_reader: &mut ConfigReader,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+) -> Result<(), Error> {
+    let (display_state, _, config_group) =
+        prepare_reconfiguration::<DS>(daemon, config_reader, format)?;
+    info!(
+        "Would apply configuration group:\n{}",
+        serialize_to_string(format, &config_group.to_config_group())?
+    );
+    // Selecting the display modes logs the mode chosen for each display.
+    select_modes(&display_state, &config_group, format, selection)?;
     Ok(())
 }
 
-////////////////////////////////////////////////////////////////////////////////
+/// Helper to perform a single reconfiguration pass in daemon mode.  Returns
+/// the UUIDs of the configuration group that was applied, along with the
+/// resulting display state.  Failures of the
+/// displays to reconfigure, such as those that occur briefly after waking from
+/// sleep, are retried according to the retry policy.
+fn reconfigure<DS: DisplayState>(
+    daemon: &DaemonState,
+    config_reader: &mut ConfigReader,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+    retry_policy: &RetryPolicy,
+    on_change: Option<&str>,
+    commit: &CommitOptions,
+) -> Result<(Vec<String>, ConfigGroups), Error> {
+    let (display_state, current_config, config_group) =
+        prepare_reconfiguration::<DS>(daemon, config_reader, format)?;
+    let uuids = config_group.uuids.iter().cloned().collect();
+    run_pre_hook(&config_group, &current_config, format)?;
+    let mut result = configure_displays(
+        &display_state,
+        config_group.clone(),
+        format,
+        selection,
+        commit,
+    );
+    for retry in 0..retry_policy.retries {
+        match result {
+            Err(e @ (Error::Displays(_) | Error::ConfigurationIgnored(_)))
+                if !daemon.shutting_down.load(Ordering::SeqCst) =>
+            {
+                let delay = retry_policy.delay(retry);
+                // Commits are refused until an abandoned commit finishes,
+                // so the retries also poll for it to finish.
+                if matches!(
+                    e,
+                    Error::Displays(displays::Error::Timeout(_) | displays::Error::CommitPending)
+                ) {
+                    warn!(
+                        "Waiting for the abandoned commit to finish, retrying in {}: {}",
+                        humantime::format_duration(delay),
+                        e
+                    );
+                } else {
+                    warn!(
+                        "Reconfiguration failed, retrying in {}: {}",
+                        humantime::format_duration(delay),
+                        e
+                    );
+                }
+                std::thread::sleep(delay);
+                // The display state may have changed in the meantime.
+                let display_state = DS::current()?;
+                result = configure_displays(
+                    &display_state,
+                    config_group.clone(),
+                    format,
+                    selection,
+                    commit,
+                );
+            }
+            _ => break,
+        }
+    }
+    if let Some(duration) = result? {
+        daemon.record_metrics(|metrics| metrics.record_commit(duration));
+    }
 
-/// Helper structure for serializing display modes.
-#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
-struct DisplayModeGroup<DM>
-where
-    DM: Serialize,
-{
-    uuid: String,
-    modes: Vec<DM>,
+    let new_state = DS::current()?;
+    let origins = config_group
+        .configs
+        .iter()
+        .filter(|(_, config)| config.origin.is_some() && config.skip != Some(true))
+        .filter_map(|(uuid, _)| {
+            let display = new_state.get_displays().get(uuid)?;
+            Some((uuid.clone(), display.origin().clone()))
+        })
+        .collect();
+    *daemon
+        .applied_group
+        .lock()
+        .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))? = Some(AppliedGroup {
+        config_group: config_group.clone(),
+        origins,
+    });
+
+    let mut new_config = state_to_config(&new_state);
+    run_post_hooks(
+        &config_group,
+        &current_config,
+        &new_config,
+        on_change,
+        format,
+    )?;
+    label_state(&mut new_config, Some(&config_group));
+    Ok((uuids, new_config))
 }
 
-fn list_command<DS: DisplayState>(
+/// Helper to send a webhook notification, if a webhook was configured.  The
+/// notification is sent from a separate thread, so that a slow server does not
+/// hold up reconfiguration.  Failures are only logged.
+fn notify_webhook(webhook: &Option<webhook::Webhook>, notification: webhook::Notification) {
+    if let Some(webhook) = webhook.clone() {
+        indirect_logger::spawn(move || {
+            if let Err(e) = webhook.send(&notification) {
+                error!("{}", e);
+            }
+        });
+    }
+}
+
+/// Helper to write an event, if events were requested.  Failures are only
+/// logged, so that they do not affect reconfiguration.
+fn write_event(event_writer: &mut Option<events::EventWriter>, kind: events::EventKind) {
+    if let Some(writer) = event_writer {
+        if let Err(e) = writer.write(kind) {
+            error!("Error writing events: {}", e);
+        }
+    }
+}
+
+/// Helper to post a Notification Center message.  Failures are only logged.
+fn post_notification(message: &str, failure: bool) {
+    if let Err(e) = notification::post(message, failure) {
+        error!("Error posting notification: {}", e);
+    }
+}
+
+/// Helper to send a request to a running daemon, converting error responses
+/// into an `Error`.
+fn send_control_request(
+    path: &Path,
+    format: crate::serde::Format,
+    request: &control::Request,
+) -> Result<control::Response, Error> {
+    match control::send_request(path, format, request)? {
+        control::Response::Error(msg) => Err(Error::DaemonError(msg)),
+        response => Ok(response),
+    }
+}
+
+fn trigger_command(path: &Path, format: crate::serde::Format) -> Result<(), Error> {
+    match send_control_request(path, format, &control::Request::Trigger)? {
+        control::Response::Ok => Ok(()),
+        response => Err(Error::UnexpectedResponse(format!("{:?}", response))),
+    }
+}
+
+/// The output of `knoll status`.
+#[derive(Debug, Clone, Serialize)]
+struct StatusReport {
+    /// Is there a daemon listening on the control socket?
+    running: bool,
+    /// The status reported by the daemon, if it is running.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt"
+    )]
+    status: Option<control::DaemonStatus>,
+}
+
+fn status_command(
+    path: &Path,
     output: &mut dyn Write,
     format: crate::serde::Format,
 ) -> Result<(), Error> {
-    let display_state = DS::current()?;
+    let report = match send_control_request(path, format, &control::Request::Status) {
+        Ok(control::Response::Status(status)) => StatusReport {
+            running: true,
+            status: Some(status),
+        },
+        Ok(response) => return Err(Error::UnexpectedResponse(format!("{:?}", response))),
+        // Failing to connect means that no daemon is listening.
+        Err(Error::Control(control::Error::Io(ie)))
+            if matches!(
+                ie.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            StatusReport {
+                running: false,
+                status: None,
+            }
+        }
+        Err(e) => return Err(e),
+    };
 
-    let mut groups: Vec<DisplayModeGroup<DS::DisplayModeType>> = Vec::new();
+    crate::serde::serialize(format, &report, output)?;
+    Ok(())
+}
 
-    // Collect up all modes.
-    for (uuid, display) in display_state.get_displays() {
-        groups.push(DisplayModeGroup {
-            uuid: uuid.clone(),
-            modes: Vec::from(display.possible_modes()),
-        });
+/// Summary of how long an operation took over several iterations.
+/// Durations are reported in microseconds.
+#[derive(Debug, Clone, Serialize)]
+struct Timing {
+    min_us: u64,
+    mean_us: u64,
+    max_us: u64,
+}
+
+impl Timing {
+    /// Summarize the given non-empty collection of durations.
+    fn new(samples: &[std::time::Duration]) -> Self {
+        let us =
+            |duration: std::time::Duration| u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let total: std::time::Duration = samples.iter().sum();
+        Timing {
+            min_us: samples.iter().copied().map(us).min().unwrap_or_default(),
+            mean_us: us(total / samples.len().max(1) as u32),
+            max_us: samples.iter().copied().map(us).max().unwrap_or_default(),
+        }
     }
+}
 
-    // Serialize them to output.
-    crate::serde::serialize(format, &groups, output)?;
+/// How long enumerating the display modes of a display took.
+#[derive(Debug, Clone, Serialize)]
+struct ModeEnumerationTiming {
+    uuid: String,
+    /// The number of possible display modes.
+    modes: usize,
+    timing: Timing,
+}
+
+/// The output of `knoll bench`.
+#[derive(Debug, Clone, Serialize)]
+struct BenchReport {
+    iterations: u32,
+    /// Obtaining the current state of the displays.
+    state_capture: Timing,
+    /// Enumerating the possible display modes of each display.
+    mode_enumeration: Vec<ModeEnumerationTiming>,
+    /// Choosing a configuration group and display modes for the displays,
+    /// if a configuration was given.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt"
+    )]
+    config_matching: Option<Timing>,
+    /// Staging the changes for the chosen configuration group in a
+    /// transaction that is then cancelled, if a dry run was requested.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt"
+    )]
+    staging: Option<Timing>,
+}
+
+/// Time each of the stages knoll goes through when configuring the displays,
+/// repeating them the given number of times.  The displays are never
+/// reconfigured.
+fn bench_command<DS: DisplayState>(
+    mut config_reader: ConfigReader,
+    output: &mut dyn Write,
+    format: crate::serde::Format,
+    selection: &ModeSelection,
+    iterations: u32,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let config_groups = config_reader.groups()?;
+    let configured = !config_groups.is_empty();
+
+    let mut state_capture = Vec::new();
+    let mut mode_enumeration: BTreeMap<String, (usize, Vec<std::time::Duration>)> = BTreeMap::new();
+    let mut config_matching = Vec::new();
+    let mut staging = Vec::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let display_state = DS::current()?;
+        state_capture.push(start.elapsed());
+
+        for (uuid, display) in display_state.get_displays() {
+            let start = Instant::now();
+            let modes = display.possible_modes().len();
+            let (count, samples) = mode_enumeration.entry(uuid.clone()).or_default();
+            samples.push(start.elapsed());
+            *count = modes;
+        }
+
+        if !configured {
+            continue;
+        }
+        let start = Instant::now();
+        let config_group = find_most_precise_config_group(
+            &config_groups,
+            &display_state,
+            &config_reader.ignored(),
+            format,
+        )?;
+        select_modes(&display_state, &config_group, format, selection)?;
+        config_matching.push(start.elapsed());
+
+        if dry_run {
+            let start = Instant::now();
+            // Dropping the transaction without committing cancels it.
+            let (cfgtxn, _, _) =
+                stage_configuration(&display_state, &config_group, format, selection)?;
+            drop(cfgtxn);
+            staging.push(start.elapsed());
+        }
+    }
 
+    let report = BenchReport {
+        iterations,
+        state_capture: Timing::new(&state_capture),
+        mode_enumeration: mode_enumeration
+            .into_iter()
+            .map(|(uuid, (modes, samples))| ModeEnumerationTiming {
+                uuid,
+                modes,
+                timing: Timing::new(&samples),
+            })
+            .collect(),
+        config_matching: configured.then(|| Timing::new(&config_matching)),
+        staging: (configured && dry_run).then(|| Timing::new(&staging)),
+    };
+    crate::serde::serialize(format, &report, output)?;
     Ok(())
 }
 
-////////////////////////////////////////////////////////////////////////////////
+fn service_command<OUT: Write>(
+    matches: &ArgMatches,
+    stdout: OUT,
+    format: crate::serde::Format,
+) -> Result<(), Error> {
+    // Calling unwrap here should be okay, as all the service subcommands have
+    // a label with a default value.
+    let label = |sub_matches: &ArgMatches| sub_matches.get_one::<String>("LABEL").unwrap().clone();
+    match matches.subcommand() {
+        Some(("install", sub_matches)) => {
+            // Calling unwrap here should be okay, as the input is required.
+            let config = sub_matches.get_one::<PathBuf>("IN").unwrap();
+            let label = label(sub_matches);
+            service::install(&label, config)?;
+            info!(
+                "Installed launchd agent {} at {}.",
+                label,
+                service::plist_path(&label).display()
+            );
+            Ok(())
+        }
+        Some(("uninstall", sub_matches)) => {
+            service::uninstall(&label(sub_matches))?;
+            Ok(())
+        }
+        Some(("status", sub_matches)) => {
+            let status = service::status(&label(sub_matches));
+            let mut output = open_output(stdout, sub_matches)?;
+            crate::serde::serialize(format, &status, &mut output)?;
+            output.finish()?;
+            Ok(())
+        }
+        // A subcommand is required by the argument parser.
+        _ => panic!("Invalid service subcommand"),
+    }
+}
+
+/// The signals that destroy a virtual display.
+const VIRTUAL_DISPLAY_SIGNALS: [std::os::raw::c_int; 2] = [signals::SIGINT, signals::SIGTERM];
+
+/// How long to wait for the process holding a virtual display to exit.
+const VIRTUAL_DISPLAY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
-// Reconfiguration in daemon mode is guarded by a lock.  Depending on the
-// system configuration, doing something a simple as opening a closed laptop
-// lid will trigger multiple invocations of the callback.  To prevent those
-// from needlessly triggering reconfiguration multiple times, we use a mutex
-// over a Boolean and signal via a condition variable that the reconfiguration
-// worker thread should wake up.
-static RECONFIGURE_LOCK: Mutex<bool> = Mutex::new(false);
-static RECONFIGURE_CONDVAR: Condvar = Condvar::new();
+/// Helper to carry out the virtual display subcommands.  A virtual display
+/// only exists as long as the process that created it, so creating one
+/// blocks until the process is signalled, which is what destroying it does.
+/// The process is found by a PID file, which also ensures that only one
+/// process uses it at a time.
+fn virtual_command<DS: DisplayState, OUT: Write>(
+    matches: &ArgMatches,
+    mut stdout: OUT,
+) -> Result<(), Error> {
+    let pid_path = |sub_matches: &ArgMatches| {
+        sub_matches
+            .get_one::<PathBuf>("PID_FILE")
+            .cloned()
+            .unwrap_or_else(|| {
+                pid_file::default_pid_file_path().with_file_name("knoll-virtual.pid")
+            })
+    };
+    match matches.subcommand() {
+        Some(("create", sub_matches)) => {
+            // Calling unwrap here should be okay, as the arguments are either
+            // required or have default values.
+            let spec = VirtualDisplaySpec {
+                name: sub_matches.get_one::<String>("NAME").unwrap().clone(),
+                extents: sub_matches.get_one::<Point>("EXTENTS").unwrap().clone(),
+                ppi: *sub_matches.get_one::<u32>("PPI").unwrap(),
+                frequency: *sub_matches.get_one::<usize>("FREQUENCY").unwrap(),
+            };
+            let pid_file = pid_file::PidFile::acquire(&pid_path(sub_matches))?;
+            signals::block_signals(&VIRTUAL_DISPLAY_SIGNALS)?;
+            let display = DS::create_virtual_display(&spec)?;
+            info!("Created virtual display {}.", display.uuid());
+            writeln!(stdout, "{}", display.uuid())?;
+            stdout.flush()?;
+
+            let signal = signals::wait_for_signal(&VIRTUAL_DISPLAY_SIGNALS)?;
+            info!(
+                "Received signal {}, destroying virtual display {}.",
+                signal,
+                display.uuid()
+            );
+            drop(display);
+            pid_file.remove();
+            Ok(())
+        }
+        Some(("destroy", sub_matches)) => {
+            let path = pid_path(sub_matches);
+            if pid_file::PidFile::terminate(&path, VIRTUAL_DISPLAY_TIMEOUT)? {
+                Ok(())
+            } else {
+                Err(Error::NoVirtualDisplay(path))
+            }
+        }
+        // A subcommand is required by the argument parser.
+        _ => panic!("Invalid virtual subcommand"),
+    }
+}
 
-/// Helper to acquire the reconfiguration lock and notify the conditional
-/// variable.  It can also be used a callback for when displace notification
-/// changes.
-extern "C" fn triger_reconfig() {
-    if let Ok(ref mut reconfig_started) = RECONFIGURE_LOCK.try_lock() {
-        **reconfig_started = true;
-        // Signal to the worker thread to wake up and perform
-        // the reconfiguration.
-        RECONFIGURE_CONDVAR.notify_one();
+/// Helper to carry out the alias subcommands, which update or report the
+/// aliases stored at the given path.
+fn alias_command<OUT: Write>(
+    matches: &ArgMatches,
+    mut aliases: Aliases,
+    path: &Path,
+    stdout: OUT,
+    format: crate::serde::Format,
+) -> Result<(), Error> {
+    // Calling unwrap here should be okay, as the names and UUIDs are
+    // required.
+    let name = |sub_matches: &ArgMatches| sub_matches.get_one::<String>("NAME").unwrap().clone();
+    match matches.subcommand() {
+        Some(("set", sub_matches)) => {
+            let name = name(sub_matches);
+            let uuid = sub_matches.get_one::<String>("UUID").unwrap();
+            aliases.set(&name, uuid)?;
+            aliases.save(path)?;
+            info!("Alias {} now refers to display {}.", name, uuid);
+            Ok(())
+        }
+        Some(("remove", sub_matches)) => {
+            let name = name(sub_matches);
+            let uuid = aliases.remove(&name)?;
+            aliases.save(path)?;
+            info!("Removed alias {} for display {}.", name, uuid);
+            Ok(())
+        }
+        Some(("list", sub_matches)) => {
+            let mut output = open_output(stdout, sub_matches)?;
+            crate::serde::serialize(format, &aliases, &mut output)?;
+            output.finish()?;
+            Ok(())
+        }
+        // A subcommand is required by the argument parser.
+        _ => panic!("Invalid alias subcommand"),
     }
 }
 
 fn daemon_command<DS: DisplayState>(
     mut config_reader: ConfigReader,
     format: crate::serde::Format,
-    wait_period: std::time::Duration,
+    options: DaemonOptions,
 ) -> Result<(), Error> {
+    let DaemonOptions {
+        wait_period,
+        max_wait,
+        wake_wait,
+        cooldown,
+        retry_policy,
+        selection,
+        on_change,
+        commit,
+        webhook,
+        notify,
+        metrics_file,
+        observe: observe_only,
+        events,
+        output,
+        append,
+        control_socket,
+        pid_file,
+        replace,
+        max_events,
+        enforce,
+    } = options;
+
+    // Ensure that this is the only daemon running before doing anything else,
+    // as multiple daemons will fight over the display configuration.
+    let pid_file = if replace {
+        pid_file::PidFile::replace(&pid_file, REPLACE_TIMEOUT)?
+    } else {
+        pid_file::PidFile::acquire(&pid_file)?
+    };
+
+    let daemon = Arc::new(DaemonState::new(DS::stop_event_loop));
+    DAEMONS
+        .lock()
+        .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?
+        .push(Arc::downgrade(&daemon));
+
+    // Block the signals handled by the daemon before spawning any threads, so
+    // that they are only received by the signal handling thread.
+    signals::block_signals(&DAEMON_SIGNALS)?;
+
+    // Start listening for control requests before anything else, so that
+    // it is an error to start a second daemon on the same socket.
+    if let Some(path) = &control_socket {
+        let listener = bind_control_socket(path)?;
+        info!("Listening for control requests on {}", path.display());
+        let control_reader = config_reader.clone();
+        let daemon = daemon.clone();
+        indirect_logger::spawn(move || {
+            serve_control_requests::<DS>(&daemon, listener, control_reader, format)
+        });
+    }
+
+    // Write events to the output, if requested.
+    let mut event_writer = if events {
+        let output: Box<dyn Write + Send> = match &output {
+            Some(path) => Box::new(create_output_file(path, append)?),
+            None => Box::new(std::io::stdout()),
+        };
+        Some(events::EventWriter::new(output))
+    } else {
+        None
+    };
+
+    // When the displays were last successfully reconfigured, for enforcing
+    // the cooldown period.
+    let mut last_reconfiguration: Option<std::time::Instant> = None;
+
+    // Reapply the configuration if the displays drift from it, if requested.
+    if let Some(interval) = enforce {
+        let enforcer = daemon.clone();
+        let selection = selection.clone();
+        indirect_logger::spawn(move || {
+            enforcer.run_enforcement::<DS>(interval, format, &selection)
+        });
+    }
+
     // Spawn a thread to watch for reconfiguration changes.
-    std::thread::spawn(move || 'loop_label: loop {
-        let mut reconfig_in_progress = match RECONFIGURE_LOCK.lock() {
+    let worker = daemon.clone();
+    indirect_logger::spawn(move || 'loop_label: loop {
+        let daemon = &worker;
+        let mut reconfig_in_progress = match daemon.reconfigure_lock.lock() {
             Ok(mutex) => mutex,
             Err(pe) => {
                 error!("Error obtaining reconfiguration lock: {}", pe);
@@ -732,7 +5040,7 @@ fn daemon_command<DS: DisplayState>(
 
         // Wait for the callback to notify that reconfiguration should take place.
         while !*reconfig_in_progress {
-            reconfig_in_progress = match RECONFIGURE_CONDVAR.wait(reconfig_in_progress) {
+            reconfig_in_progress = match daemon.reconfigure_condvar.wait(reconfig_in_progress) {
                 Ok(b) => b,
                 Err(pe) => {
                     error!(
@@ -744,60 +5052,190 @@ fn daemon_command<DS: DisplayState>(
             }
         }
 
-        // Wait for the display configuration to quiesce.
-        std::thread::sleep(wait_period);
-        info!("Reconfiguring displays.");
+        // Ignore display configuration changes during the cooldown period
+        // after a reconfiguration, unless an immediate reconfiguration was
+        // requested.
+        let immediate = daemon.skip_wait.swap(false, Ordering::SeqCst);
+        let woke = daemon.woke.swap(false, Ordering::SeqCst);
+        if !immediate && !woke && last_reconfiguration.is_some_and(|last| last.elapsed() < cooldown)
+        {
+            debug!("Ignoring a reconfiguration event during the cooldown period.");
+            *reconfig_in_progress = false;
+            continue;
+        }
+
+        // Wait for the display configuration to quiesce, unless an immediate
+        // reconfiguration was requested.  After waking from sleep, use the
+        // separate wake wait period, which may exceed the maximum wait.
+        if woke {
+            daemon.debounce(wake_wait, std::cmp::max(max_wait, wake_wait));
+        } else if !immediate {
+            settle::<DS>(daemon, &mut config_reader, format, wait_period, max_wait);
+        }
 
-        // As close as I think we can get to monadic binding.
-        let result = config_reader
-            .groups()
-            .and_then(|config_groups: Vec<ValidConfigGroup>| {
-                if config_groups.is_empty() {
-                    Err(Error::NoConfigGroups)
-                } else {
-                    DS::current()
-                        .map_err(|e| e.into())
-                        .and_then(|display_state: DS| {
-                            let current_config = state_to_config(&display_state);
-                            let config_str = serialize_to_string(format, &current_config).expect(
-                                "Should be impossible to fail on serializing internally constructed configuration.",
-                            );
-                            info!("Current display state:\n{}", config_str);
-
-                            find_most_precise_config_group(&config_groups, &display_state, format).and_then(
-                                |config_group: ValidConfigGroup| {
-                                    configure_displays(&display_state, config_group, format)
-                                },
-                            )
-                        })
+        // Do not start a reconfiguration if the daemon is shutting down.
+        if daemon.shutting_down.load(Ordering::SeqCst) {
+            *reconfig_in_progress = false;
+            continue;
+        }
+
+        // Report any displays that have been attached or detached.
+        if let Some(writer) = &mut event_writer {
+            match DS::current() {
+                Ok(display_state) => {
+                    let uuids = display_state.get_displays().keys().cloned().collect();
+                    if let Err(e) = writer.update_displays(uuids) {
+                        error!("Error writing events: {}", e);
+                    }
                 }
-            });
+                Err(e) => error!("{}", e),
+            }
+        }
 
-        match result {
-            Err(e) => {
-                error!("{}", e);
+        if observe_only {
+            info!("Observing displays.");
+            match observe::<DS>(daemon, &mut config_reader, format, &selection) {
+                Err(e) => {
+                    error!("{}", e);
+                    daemon.record_status(|status| status.record_error(e.to_string()));
+                }
+                Ok(()) => info!("Observation complete, displays left unchanged."),
             }
-            Ok(()) => {
-                info!("Reconfiguration successful.");
+        } else {
+            info!(target: DETAIL_TARGET, "Reconfiguring displays.");
+            let started = std::time::Instant::now();
+            match reconfigure::<DS>(
+                daemon,
+                &mut config_reader,
+                format,
+                &selection,
+                &retry_policy,
+                on_change.as_deref(),
+                &commit,
+            ) {
+                Err(e) => {
+                    error!("{}", e);
+                    daemon.record_status(|status| status.record_error(e.to_string()));
+                    daemon.record_metrics(|metrics| metrics.failures += 1);
+                    write_event(
+                        &mut event_writer,
+                        events::EventKind::Error {
+                            message: e.to_string(),
+                        },
+                    );
+                    notify_webhook(
+                        &webhook,
+                        webhook::Notification::Failed {
+                            error: e.to_string(),
+                        },
+                    );
+                    if notify {
+                        post_notification(&e.to_string(), true);
+                    }
+                }
+                Ok((uuids, state)) => {
+                    info!(target: DETAIL_TARGET, "Reconfiguration successful.");
+                    let plural = if uuids.len() == 1 { "" } else { "s" };
+                    info!(
+                        "Applied the configuration group for {} display{} ({}) in {}.",
+                        uuids.len(),
+                        plural,
+                        uuids.join(", "),
+                        // Sub-millisecond precision is just noise.
+                        humantime::format_duration(std::time::Duration::from_millis(
+                            started.elapsed().as_millis() as u64
+                        ))
+                    );
+                    daemon.record_status(|status| status.record_reconfiguration(uuids.clone()));
+                    daemon.record_metrics(|metrics| metrics.reconfigurations += 1);
+                    last_reconfiguration = Some(std::time::Instant::now());
+                    if notify {
+                        let message = format!(
+                            "Applied configuration group: {} display{}",
+                            uuids.len(),
+                            plural
+                        );
+                        post_notification(&message, false);
+                    }
+                    write_event(
+                        &mut event_writer,
+                        events::EventKind::ConfigApplied {
+                            uuids: uuids.clone(),
+                            state: state.clone(),
+                        },
+                    );
+                    notify_webhook(&webhook, webhook::Notification::Applied { uuids, state });
+                }
+            };
+        }
+
+        if let Some(path) = &metrics_file {
+            if let Err(e) = daemon.write_metrics_file(path, format) {
+                error!("Error writing the metrics file: {}", e);
             }
-        };
+        }
 
         // Reconfiguration has completed.
         *reconfig_in_progress = false;
+
+        // Exit once the requested number of reconfigurations has occurred.
+        let count = daemon.reconfigure_count.fetch_add(1, Ordering::SeqCst) + 1;
+        if max_events.is_some_and(|max| count >= max) {
+            info!("Completed {} reconfigurations, shutting down.", count);
+            daemon.request_shutdown();
+        }
+    });
+
+    REGISTER_CALLBACKS.call_once(|| {
+        std::thread::spawn(handle_signals);
+
+        // Also reconfigure when the source of power changes, so that
+        // configuration groups conditional upon it are applied.
+        if !core_graphics::io_ps_register_notification(power_source_changed) {
+            warn!("Unable to register for power source notifications.");
+        }
+
+        // Reconfigure after the system wakes from sleep.
+        if !core_graphics::io_register_for_system_wake(system_woke) {
+            warn!("Unable to register for system wake notifications.");
+        }
     });
 
-    // Install the display reconfiguration callback.
-    core_graphics::cg_display_register_reconfiguration_callback(triger_reconfig);
+    // Install the display reconfiguration callback, which is removed once
+    // the daemon shuts down.
+    let _reconfiguration_callback =
+        displays::ReconfigurationCallback::<DS>::register(display_reconfigured);
+
+    // Reconfigure when configuration groups with a schedule start or stop
+    // applying.
+    let scheduler = daemon.clone();
+    indirect_logger::spawn(move || scheduler.run_schedule());
 
     // Trigger an initial reconfiguration.  This is to handle the case that you
     // have knoll running as a launchd service, and as macOS starts up your
     // monitor configuration is incorrect even before knoll is started.
-    triger_reconfig();
+    daemon.trigger_reconfig();
+
+    // Callbacks are only delivered while the event loop is running.
+    DS::run_event_loop();
+
+    // The run loop only returns if the daemon was asked to shut down.  Wait
+    // for any reconfiguration in progress to finish, so that the displays are
+    // not left partially configured.  Holding the lock until returning
+    // ensures that no further reconfiguration can start.
+    daemon.shutting_down.store(true, Ordering::SeqCst);
+    let _reconfig_guard = daemon
+        .reconfigure_lock
+        .lock()
+        .map_err(|pe| displays::Error::Poisoned(format!("{}", pe)))?;
+
+    if let Some(path) = &control_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    pid_file.remove();
 
-    // macOS will not trigger the callback unless there is an application
-    // loop running.
-    core_graphics::ns_application_load();
-    core_graphics::cf_run_loop_run();
+    info!("Shut down.");
+    log::logger().flush();
 
     Ok(())
 }
@@ -5,12 +5,36 @@
 extern crate objc;
 extern crate static_assertions;
 
+pub mod aliases;
+pub mod api;
+#[cfg(feature = "testing")]
+pub mod arbitrary;
 pub mod config;
+pub mod control;
 pub mod core_graphics;
+pub mod diagnostics;
+pub mod display_events;
 pub mod displays;
+pub mod doctor;
+pub mod environment;
+pub mod events;
+pub mod expression;
 pub mod fake_displays;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod indirect_logger;
+pub mod json_logger;
 pub mod knoll;
+pub mod log_file;
+pub mod notification;
+pub mod pid_file;
 pub mod real_displays;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 mod serde;
+pub mod serve;
+pub mod service;
+pub mod signals;
+pub mod testing;
 pub mod valid_config;
+pub mod webhook;
@@ -0,0 +1,175 @@
+//! This module provides a log file that is rotated once it grows too large or
+//! too old.  Rotated files are renamed with an increasing numeric suffix,
+//! e.g. `knoll.log.1`, `knoll.log.2`, with the oldest being removed once
+//! there are too many.
+use coverage_helper::test;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// When a log file should be rotated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Rotate once the file has reached this many bytes.
+    pub max_size: Option<u64>,
+    /// Rotate once the file was created this long ago.
+    pub max_age: Option<Duration>,
+    /// How many rotated files to keep.
+    pub keep: usize,
+}
+
+/// Parse a size in bytes, with an optional binary suffix of `K`, `M`, or
+/// `G`, for example `10M`.  Suitable for use as a clap value parser.
+pub fn parse_size(str: &str) -> Result<u64, String> {
+    let trimmed = str
+        .trim()
+        .trim_end_matches(['B', 'b'])
+        .trim_end_matches(['i']);
+    let (digits, multiplier) = match trimmed.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&trimmed[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&trimmed[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&trimmed[..index], 1 << 30),
+        _ => (trimmed, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|size| size.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size {}, expected e.g. 500K or 10M", str))
+}
+
+/// A log file that rotates itself as it is written to.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    /// The size of the current file.
+    size: u64,
+    /// When the current file was created.
+    created: SystemTime,
+}
+
+/// Helper to open a log file for appending, creating it if necessary.
+pub fn open_append(path: &Path) -> std::io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+impl RotatingFile {
+    /// Open the log file at the given path for appending, creating it if
+    /// necessary.
+    pub fn open(path: &Path, rotation: Rotation) -> std::io::Result<Self> {
+        let file = open_append(path)?;
+        let metadata = file.metadata()?;
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            rotation,
+            size: metadata.len(),
+            // Not all file systems record when a file was created.
+            created: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+            file,
+        })
+    }
+
+    /// Helper to obtain the path of the given rotated file.
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    /// Whether the current file should be rotated before writing more.
+    fn should_rotate(&self) -> bool {
+        self.rotation
+            .max_size
+            .is_some_and(|max_size| self.size >= max_size)
+            || self
+                .rotation
+                .max_age
+                .is_some_and(|max_age| self.created.elapsed().is_ok_and(|age| age >= max_age))
+    }
+
+    /// Rename the current and rotated files, removing the oldest, and start a
+    /// new file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.rotation.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            // The oldest file may not exist, which is fine.
+            let _ = std::fs::remove_file(self.rotated_path(self.rotation.keep));
+            for index in (1..self.rotation.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.created = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check parsing of sizes.
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("512"), Ok(512));
+    assert_eq!(parse_size("4K"), Ok(4096));
+    assert_eq!(parse_size("10M"), Ok(10 << 20));
+    assert_eq!(parse_size("10MiB"), Ok(10 << 20));
+    assert_eq!(parse_size("1g"), Ok(1 << 30));
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("ten").is_err());
+}
+
+/// Check that the log file is rotated once it reaches the maximum size, and
+/// that only the requested number of rotated files are kept.
+#[test]
+fn test_rotating_file() {
+    let dir = tempfile::tempdir().expect("Creating a temporary directory failed.");
+    let path = dir.path().join("knoll.log");
+    let rotation = Rotation {
+        max_size: Some(4),
+        max_age: None,
+        keep: 2,
+    };
+    let mut file = RotatingFile::open(&path, rotation).expect("Opening failed.");
+    for line in ["aaaa", "bbbb", "cccc", "dddd"] {
+        file.write_all(line.as_bytes()).expect("Writing failed.");
+    }
+    file.flush().expect("Flushing failed.");
+
+    let read = |path: &Path| std::fs::read_to_string(path).expect("Reading failed.");
+    assert_eq!(read(&path), "dddd");
+    assert_eq!(read(&file.rotated_path(1)), "cccc");
+    assert_eq!(read(&file.rotated_path(2)), "bbbb");
+    assert!(!file.rotated_path(3).exists());
+}
@@ -6,41 +6,91 @@
 extern crate objc;
 extern crate static_assertions;
 
+mod aliases;
 mod config;
+mod control;
 mod core_graphics;
+mod diagnostics;
 mod displays;
+mod doctor;
+mod environment;
+mod events;
+mod expression;
 mod fake_displays;
 pub mod indirect_logger;
+mod json_logger;
 mod knoll;
+mod log_file;
+mod notification;
+mod pid_file;
 mod real_displays;
+#[cfg(feature = "scripting")]
+mod scripting;
 mod serde;
+mod serve;
+mod service;
+mod signals;
 mod valid_config;
+mod webhook;
 
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
+use displays::DisplayState;
+use fake_displays::FakeDisplayState;
 use real_displays::RealDisplayState;
 
+/// Helper to dispatch to the run function with the given `DisplayState`.
+/// As this is entry point to the real program, we use the actual stdin,
+/// stdout and stderr.
+fn run<DS: DisplayState>(args: &Vec<String>) -> Result<i32, knoll::Error> {
+    knoll::run::<DS, std::io::Stdin, std::io::Stdout, std::io::Stderr>(
+        args,
+        std::io::stdin(),
+        std::io::stdout(),
+        std::io::stderr(),
+    )
+}
+
+/// Helper to print the error to stderr and exit with its exit code.  The
+/// alternate form of the error uses color, so only use it when stderr is a
+/// terminal.
+fn exit_with_error(e: knoll::Error) -> ! {
+    if std::io::stderr().is_terminal() {
+        write!(std::io::stderr(), "{:#}", e).unwrap();
+    } else {
+        write!(std::io::stderr(), "{}", e).unwrap();
+    }
+    std::process::exit(e.exit_code());
+}
+
 /// Main entry point for the knoll command-line tool.  
 /// Most everything happens in the knoll module, as ii has been
 /// parameterized to make testing easier.
 pub fn main() {
-    // Dispatch to the run function.  As this is entry point to the real
-    // program, we use the actual stdin, stdout and RealDisplayState.
     let args: Vec<String> = std::env::args().into_iter().collect();
-    match knoll::run::<RealDisplayState, std::io::Stdin, std::io::Stdout, std::io::Stderr>(
-        &args,
-        std::io::stdin(),
-        std::io::stdout(),
-        std::io::stderr(),
-    ) {
+    // Detaching forks the process, so it must happen before anything else
+    // has a chance to start a thread.
+    // SAFETY: No other threads have been started yet.
+    match unsafe { knoll::detach(&args) } {
+        // The parent has nothing left to do once the child has started.
+        Ok(true) => std::process::exit(0),
+        Ok(false) => {}
+        Err(e) => exit_with_error(e),
+    }
+    // Use the real displays, unless a scenario of fake displays has been
+    // given for demonstration or testing purposes.
+    let result = if std::env::var_os(fake_displays::SCENARIO_VAR).is_some() {
+        run::<FakeDisplayState>(&args)
+    } else {
+        run::<RealDisplayState>(&args)
+    };
+    match result {
         // Hit an error, print it to stderr.
-        Err(e) => {
-            write!(std::io::stderr(), "{}", e).unwrap();
-            std::process::exit(1);
-        }
-        // Everything went as expected.
-        Ok(_) => {
-            std::process::exit(0);
+        Err(e) => exit_with_error(e),
+        // Everything went as expected, though the exit code may still
+        // report whether the displays were changed.
+        Ok(exit_code) => {
+            std::process::exit(exit_code);
         }
     }
 }
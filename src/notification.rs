@@ -0,0 +1,82 @@
+//! This module posts macOS Notification Center messages, so that the outcome
+//! of daemon reconfigurations is visible to the user.  Notifications are
+//! posted using `osascript`, as the native notification APIs require the
+//! calling process to be part of an application bundle.
+use coverage_helper::test;
+
+/// The title used for all notifications.
+const TITLE: &str = "knoll";
+
+/// The sound played for notifications of failures, to distinguish them
+/// from routine notifications.
+const FAILURE_SOUND: &str = "Basso";
+
+/// Helper to quote a string as an AppleScript string literal.
+fn quote(str: &str) -> String {
+    let mut quoted = String::with_capacity(str.len() + 2);
+    quoted.push('"');
+    for c in str.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Helper to construct the AppleScript to display a notification.
+fn script(message: &str, failure: bool) -> String {
+    if failure {
+        format!(
+            "display notification {} with title {} subtitle {} sound name {}",
+            quote(message),
+            quote(TITLE),
+            quote("Reconfiguration failed"),
+            quote(FAILURE_SOUND)
+        )
+    } else {
+        format!(
+            "display notification {} with title {}",
+            quote(message),
+            quote(TITLE)
+        )
+    }
+}
+
+/// Post a notification with the given message.  Failures are indicated with
+/// a subtitle and an alert sound.
+pub fn post(message: &str, failure: bool) -> std::io::Result<()> {
+    let status = std::process::Command::new("osascript")
+        .arg("-e")
+        .arg(script(message, failure))
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "osascript failed: {}",
+            status
+        )))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that messages are safely quoted in the generated script.
+#[test]
+fn test_notification_script() {
+    assert_eq!(
+        script("Applied \"desk\"", false),
+        r#"display notification "Applied \"desk\"" with title "knoll""#
+    );
+    assert_eq!(
+        script("C:\\", true),
+        r#"display notification "C:\\" with title "knoll" subtitle "Reconfiguration failed" sound name "Basso""#
+    );
+}
@@ -0,0 +1,208 @@
+//! This module provides a locked PID file, used to ensure that only a single
+//! knoll daemon runs at a time.  The lock is an advisory `flock` lock held on
+//! the open file, so it is released automatically by the operating system when
+//! the daemon exits, even if it crashes.
+use coverage_helper::test;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The possible failures when acquiring a PID file.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The PID file is locked by another process.  The process id recorded
+    /// in the file is included, if it could be read.
+    Locked(PathBuf, Option<u32>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(ie) => write!(f, "PID file I/O error: {}", ie),
+            Error::Locked(path, Some(pid)) => write!(
+                f,
+                "Another knoll daemon (pid {}) holds the PID file {}.",
+                pid,
+                path.display()
+            ),
+            Error::Locked(path, None) => write!(
+                f,
+                "Another knoll daemon holds the PID file {}.",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Locked(_, _) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The PID file location used when none is specified.  This is
+/// `~/.local/run/knoll.pid`, alongside the default control socket.
+pub fn default_pid_file_path() -> PathBuf {
+    crate::control::default_socket_path().with_extension("pid")
+}
+
+/// A PID file that is locked for as long as this value is alive.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// Try to lock the PID file at the given path, creating it if necessary,
+    /// and record the current process id in it.  Fails with `Error::Locked`
+    /// if another process already holds the lock.
+    pub fn acquire(path: &Path) -> Result<Self, Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Do not truncate the file on opening, as that would destroy the
+        // process id of the daemon holding the lock.
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        // SAFETY: The file descriptor is valid for the lifetime of `file`.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(Error::Io(e));
+            }
+            let mut contents = String::new();
+            let pid = file
+                .read_to_string(&mut contents)
+                .ok()
+                .and_then(|_| contents.trim().parse().ok());
+            return Err(Error::Locked(path.to_path_buf(), pid));
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+
+        Ok(PidFile {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Like `acquire`, but if another process holds the lock, ask it to
+    /// terminate and wait up to the given duration for it to do so.
+    pub fn replace(path: &Path, timeout: std::time::Duration) -> Result<Self, Error> {
+        let pid = match PidFile::acquire(path) {
+            Err(Error::Locked(_, Some(pid))) => pid,
+            result => return result,
+        };
+        Self::terminate_and_acquire(path, pid, timeout)
+    }
+
+    /// Ask the process holding the PID file at the given path, if any, to
+    /// terminate, and wait up to the given duration for it to do so.
+    /// Returns whether there was such a process.
+    pub fn terminate(path: &Path, timeout: std::time::Duration) -> Result<bool, Error> {
+        match PidFile::acquire(path) {
+            Err(Error::Locked(_, Some(pid))) => {
+                Self::terminate_and_acquire(path, pid, timeout)?.remove();
+                Ok(true)
+            }
+            Err(e) => Err(e),
+            Ok(pid_file) => {
+                pid_file.remove();
+                Ok(false)
+            }
+        }
+    }
+
+    /// Helper to send `SIGTERM` to the given process, and then wait up to
+    /// the given duration to acquire the PID file it holds.
+    fn terminate_and_acquire(
+        path: &Path,
+        pid: u32,
+        timeout: std::time::Duration,
+    ) -> Result<Self, Error> {
+        // SAFETY: Sending a signal has no memory safety implications.
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            match PidFile::acquire(path) {
+                Err(Error::Locked(_, _)) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Release the lock and remove the PID file.
+    pub fn remove(self) {
+        let _ = std::fs::remove_file(&self.path);
+        drop(self.file);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that a PID file cannot be acquired twice, and that it records the
+/// process id.
+#[test]
+fn test_pid_file_exclusive() {
+    let dir = tempfile::tempdir().expect("Creating a temporary directory failed.");
+    let path = dir.path().join("run/knoll.pid");
+    let pid_file = PidFile::acquire(&path).expect("Acquiring the PID file failed.");
+    assert_eq!(
+        std::fs::read_to_string(&path).expect("Reading the PID file failed."),
+        format!("{}\n", std::process::id())
+    );
+
+    match PidFile::acquire(&path) {
+        Err(Error::Locked(locked_path, pid)) => {
+            assert_eq!(locked_path, path);
+            assert_eq!(pid, Some(std::process::id()));
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    pid_file.remove();
+    assert!(!path.exists());
+    PidFile::acquire(&path).expect("Reacquiring the PID file failed.");
+}
+
+/// Check that terminating without a process holding the PID file reports
+/// that there was none, and leaves no PID file behind.
+#[test]
+fn test_pid_file_terminate_unheld() {
+    let dir = tempfile::tempdir().expect("Creating a temporary directory failed.");
+    let path = dir.path().join("knoll.pid");
+    let terminated =
+        PidFile::terminate(&path, std::time::Duration::from_secs(1)).expect("Terminating failed.");
+    assert!(!terminated);
+    assert!(!path.exists());
+}
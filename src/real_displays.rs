@@ -8,13 +8,17 @@
 /// possible that perhaps I have not defined the FFI bindings quite correctly,
 /// but for the time being the behavior of the private APIs seems closer to
 /// the desired functionality.  
+use coverage_helper::test;
 use log::*;
 use serde::Serialize;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::hash::{Hash, Hasher};
 
+use crate::config::{LidState, PowerSource};
 use crate::core_graphics::*;
 use crate::displays::*;
+use crate::doctor::SystemFacts;
+use crate::environment::Environment;
 
 /// Helper for converting a `CGError` with a context string into a
 /// `display::Error`.  Should not be used when `CGError` is `success`.
@@ -47,16 +51,24 @@ pub struct RealDisplayMode {
     /// Internal id for this specific mode.
     #[serde(skip_serializing)]
     mode: i32,
-    pub scaled: bool,
+    /// Whether this mode is usable for the desktop GUI.
+    #[serde(skip_serializing)]
+    usable_for_desktop: bool,
+    /// IOKit flags for this mode, if they are known.
+    #[serde(skip_serializing)]
+    io_flags: Option<u32>,
+    pub scale: Scale,
     pub color_depth: usize,
     /// Monitor refresh rate in Hz.  Some displays may report 0.
     pub frequency: usize,
     pub extents: Point,
+    /// The extents of the mode in backing pixels.
+    pub pixel_extents: Point,
 }
 
 impl PartialEq for RealDisplayMode {
     fn eq(&self, other: &Self) -> bool {
-        self.scaled() == other.scaled()
+        self.scale == other.scale
             && self.color_depth == other.color_depth
             && self.frequency == other.frequency
             && self.extents == other.extents
@@ -68,7 +80,7 @@ impl Eq for RealDisplayMode {}
 // TODO Only for debugging modes with seemingly identical properties.
 impl Hash for RealDisplayMode {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.scaled.hash(state);
+        self.scale.hash(state);
         self.color_depth.hash(state);
         self.frequency.hash(state);
         self.extents.hash(state);
@@ -89,7 +101,17 @@ impl RealDisplayMode {
         RealDisplayMode {
             display_id,
             mode: mode_desc.mode,
-            scaled: mode_desc.scale > 1.0,
+            // Assume modes are usable unless the public APIs report
+            // otherwise.
+            usable_for_desktop: true,
+            io_flags: None,
+            // Be defensive in case some display reports a nonsensical
+            // scale factor.
+            scale: Scale(if mode_desc.scale > 0.0 {
+                mode_desc.scale.into()
+            } else {
+                1.0
+            }),
             // TODO u32 does not have From for usize, apparently just in case
             //   a 16-bit platform is the target.  Revise when infallible
             //   try_from might be standard here?
@@ -99,13 +121,102 @@ impl RealDisplayMode {
                 x: mode_desc.width.into(),
                 y: mode_desc.height.into(),
             },
+            // Approximate the backing pixels from the scale factor until
+            // they can be obtained from the public APIs.
+            pixel_extents: Point {
+                x: (mode_desc.width as f32 * mode_desc.scale.max(1.0)).round() as i64,
+                y: (mode_desc.height as f32 * mode_desc.scale.max(1.0)).round() as i64,
+            },
         }
     }
 }
 
+/// Helper to determine the number of bits per color channel from a pixel
+/// encoding string such as `--RRRRRRRRRRGGGGGGGGGGBBBBBBBBBB`.  Returns
+/// `None` for encodings without a red channel, such as indexed color.
+fn pixel_encoding_channel_depth(encoding: &str) -> Option<usize> {
+    let red_bits = encoding.chars().filter(|&c| c == 'R').count();
+    if red_bits > 0 {
+        Some(red_bits)
+    } else {
+        None
+    }
+}
+
+/// Information about a display mode that is only available from the public
+/// `CGDisplayMode` APIs.
+struct PublicModeInfo {
+    /// The number of bits per color channel, if it could be determined.
+    channel_depth: Option<usize>,
+    /// Whether the mode is usable for the desktop GUI.
+    usable_for_desktop: bool,
+    /// The IOKit flags for the mode.
+    io_flags: u32,
+    /// The extents of the mode in backing pixels.
+    pixel_extents: Point,
+}
+
+/// Helper to obtain the information about the display modes of a display
+/// from the public APIs, keyed by mode number.  For example, the private mode
+/// descriptions report the same depth for eight and ten bit modes, so the
+/// public APIs are consulted to tell them apart.
+fn public_mode_info(display_id: DisplayID) -> HashMap<i32, PublicModeInfo> {
+    let mut infos = HashMap::new();
+    let Some(modes) = cg_display_copy_all_display_modes_with_duplicates(display_id) else {
+        return infos;
+    };
+    for idx in 0..cg_display_modes_get_count(&modes) {
+        let mode = cg_display_modes_at_index(&modes, idx as CFIndex);
+        infos.insert(
+            cg_display_mode_get_io_display_mode_id(&mode),
+            PublicModeInfo::new(&mode),
+        );
+    }
+    infos
+}
+
+/// Variant of `public_mode_info` that only obtains the information about
+/// the given mode, provided that it is the current mode of the display.
+fn public_current_mode_info(display_id: DisplayID, mode_num: i32) -> Option<PublicModeInfo> {
+    // The wrapper must outlive the uses of the raw reference.
+    let owned = cg_display_copy_display_mode(display_id)?;
+    let mode = owned.as_raw();
+    (cg_display_mode_get_io_display_mode_id(&mode) == mode_num).then(|| PublicModeInfo::new(&mode))
+}
+
+impl PublicModeInfo {
+    fn new(mode: &CGDisplayModeRef) -> Self {
+        PublicModeInfo {
+            channel_depth: cg_display_mode_copy_pixel_encoding(mode)
+                .as_deref()
+                .and_then(pixel_encoding_channel_depth),
+            usable_for_desktop: cg_display_mode_is_usable_for_desktop_gui(mode),
+            io_flags: cg_display_mode_get_io_flags(mode),
+            pixel_extents: Point {
+                x: cg_display_mode_get_pixel_width(mode) as i64,
+                y: cg_display_mode_get_pixel_height(mode) as i64,
+            },
+        }
+    }
+}
+
+/// Sanity check deriving color depths from pixel encodings.
+#[test]
+fn test_pixel_encoding_channel_depth() {
+    assert_eq!(
+        pixel_encoding_channel_depth("IIIIIIIIRRRRRRRRGGGGGGGGBBBBBBBB"),
+        Some(8)
+    );
+    assert_eq!(
+        pixel_encoding_channel_depth("--RRRRRRRRRRGGGGGGGGGGBBBBBBBBBB"),
+        Some(10)
+    );
+    assert_eq!(pixel_encoding_channel_depth("PPPPPPPP"), None);
+}
+
 impl DisplayMode for RealDisplayMode {
-    fn scaled(&self) -> bool {
-        self.scaled
+    fn scale(&self) -> Scale {
+        self.scale
     }
     fn color_depth(&self) -> usize {
         self.color_depth
@@ -116,6 +227,18 @@ impl DisplayMode for RealDisplayMode {
     fn extents(&self) -> &Point {
         &self.extents
     }
+    fn pixel_extents(&self) -> Point {
+        self.pixel_extents.clone()
+    }
+    fn usable_for_desktop(&self) -> bool {
+        self.usable_for_desktop
+    }
+    fn mode_number(&self) -> Option<i32> {
+        Some(self.mode)
+    }
+    fn io_flags(&self) -> Option<u32> {
+        self.io_flags
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -128,14 +251,24 @@ pub struct RealDisplayConfigTransaction {
     /// they will not be applied.  This is not strictly necessary, but
     /// it presents a more uniform behavior for the interface.
     rotations: HashMap<DisplayID, Rotation>,
+    /// Likewise, keep track of the requested underscan adjustments, as
+    /// they are also applied outside of the configuration transaction.
+    underscans: HashMap<DisplayID, Underscan>,
+    /// How long to fade the displays over when committing, if at all.
+    fade: Option<std::time::Duration>,
     /// The active configuration reference for this transaction.
     config_ref: CGDisplayConfigRef,
     /// Keep track whether the transaction has been dropped.
     dropped: bool,
 }
 
+// SAFETY: The configuration reference is only ever used by the thread that
+// owns the transaction, and the Core Graphics configuration functions do
+// not require being called from the thread that began the configuration.
+unsafe impl Send for RealDisplayConfigTransaction {}
+
 impl RealDisplayConfigTransaction {
-    fn new(real_display_map: &BTreeMap<String, RealDisplay>) -> Result<Self, Error> {
+    fn new(displays: BTreeMap<String, DisplayID>) -> Result<Self, Error> {
         let config_ref = cg_begin_display_configuration().map_err(|cg_error| {
             cg_error_to_error(
                 cg_error,
@@ -144,19 +277,16 @@ impl RealDisplayConfigTransaction {
         })?;
 
         // Documentation seems to indicate that it should be possible to
-        // configure a fade for the configuration change, but it always seems
-        // to fail with `notImplemented`.
-        // cg_error_to_result(
-        //     cg_configure_display_fade_effect(&config_ref, 0.3, 0.5, 0.0, 0.0, 0.0),
-        //     "Failure configuring the fade effect",
-        // )?;
+        // configure a fade for the configuration change with
+        // `cg_configure_display_fade_effect`, but it always seems to fail
+        // with `notImplemented`.  So instead `commit` explicitly fades the
+        // displays around completing the configuration.
 
         Ok(Self {
-            displays: real_display_map
-                .iter()
-                .map(|(uuid, real_display)| (uuid.clone(), real_display.display_id))
-                .collect(),
+            displays,
             rotations: HashMap::new(),
+            underscans: HashMap::new(),
+            fade: None,
             config_ref,
             dropped: false,
         })
@@ -180,8 +310,117 @@ impl RealDisplayConfigTransaction {
         std::mem::swap(&mut config_ref, &mut self.config_ref);
         config_ref
     }
+
+    /// Helper to fade the displays out to black before completing the
+    /// configuration.  Returns the fade reservation, along with how long
+    /// to fade back in, if the displays were faded.  As the fade is only
+    /// cosmetic, failures are logged rather than returned.
+    fn fade_out(&self) -> Option<(CGDisplayFadeReservationToken, CGDisplayFadeInterval)> {
+        let fade = self.fade?;
+        // The reservation must cover fading out, reconfiguring, and fading
+        // back in, so leave some time for the reconfiguration.
+        let half = (fade.as_secs_f32() / 2.0).min(MAX_FADE_SECONDS / 2.0);
+        let token = match cg_acquire_display_fade_reservation(kCGMaxDisplayReservationInterval) {
+            Ok(token) => token,
+            Err(cg_error) => {
+                warn!("Unable to reserve the displays for fading: {:?}", cg_error);
+                return None;
+            }
+        };
+        let cg_error = cg_display_fade(
+            token,
+            half,
+            kCGDisplayBlendNormal,
+            kCGDisplayBlendSolidColor,
+        );
+        if cg_error != CGError::success {
+            warn!("Failed to fade out the displays: {:?}", cg_error);
+        }
+        Some((token, half))
+    }
+
+    /// Helper to fade the displays back in after completing the
+    /// configuration, and release the fade reservation.
+    fn fade_in(token: CGDisplayFadeReservationToken, half: CGDisplayFadeInterval) {
+        let cg_error = cg_display_fade(
+            token,
+            half,
+            kCGDisplayBlendSolidColor,
+            kCGDisplayBlendNormal,
+        );
+        if cg_error != CGError::success {
+            warn!("Failed to fade in the displays: {:?}", cg_error);
+        }
+        if cg_release_display_fade_reservation(token) != CGError::success {
+            warn!("Failed to release the display fade reservation");
+        }
+    }
 }
 
+/// How long to wait for a rotation to take effect before concluding that
+/// it failed.
+const ROTATION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How often to check whether a rotation has taken effect.
+const ROTATION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Helper to wait for the display to report the given rotation, returning
+/// whether it did so before the timeout.
+fn await_rotation(display_id: DisplayID, rotation: Rotation) -> bool {
+    let deadline = std::time::Instant::now() + ROTATION_TIMEOUT;
+    loop {
+        if Rotation::try_from(cg_display_rotation(display_id)) == Ok(rotation) {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(ROTATION_POLL_INTERVAL);
+    }
+}
+
+/// Helper to rotate a display, verifying that the rotation took effect.
+/// The private `CGSSetDisplayRotation` function is tried first, falling back
+/// to the MPDisplay Objective-C class if it is unavailable or ineffective.
+fn rotate_display(display_id: DisplayID, rotation: Rotation) -> Result<(), Error> {
+    match cgs_set_display_rotation(display_id, rotation.into()) {
+        Some(CGError::success) if await_rotation(display_id, rotation) => return Ok(()),
+        Some(CGError::success) => {
+            warn!(
+                "CGSSetDisplayRotation did not rotate display {} to {}, \
+                falling back to MPDisplay",
+                display_id.raw(),
+                rotation
+            )
+        }
+        Some(cg_error) => {
+            warn!(
+                "CGSSetDisplayRotation failed to rotate display {} to {}: {:?}, \
+                falling back to MPDisplay",
+                display_id.raw(),
+                rotation,
+                cg_error
+            )
+        }
+        None => debug!("CGSSetDisplayRotation is unavailable, using MPDisplay"),
+    }
+
+    mpd_set_rotation(display_id, rotation.into());
+    if await_rotation(display_id, rotation) {
+        Ok(())
+    } else {
+        Err(Error::Internal(format!(
+            "Display {} did not rotate to {} degrees",
+            display_id.raw(),
+            rotation
+        )))
+    }
+}
+
+/// The longest fade, in seconds, leaving the remainder of the longest
+/// possible fade reservation for reconfiguring the displays.
+const MAX_FADE_SECONDS: CGDisplayFadeInterval = 10.0;
+
 impl DisplayConfigTransaction for RealDisplayConfigTransaction {
     type DisplayModeType = RealDisplayMode;
 
@@ -241,37 +480,72 @@ impl DisplayConfigTransaction for RealDisplayConfigTransaction {
         )
     }
 
-    fn set_enabled(&mut self, uuid: &str, enabled: bool) -> Result<(), Error> {
+    fn set_underscan(&mut self, uuid: &str, underscan: Underscan) -> Result<(), Error> {
         if self.dropped {
             return Err(Error::InvalidTransactionState);
         }
 
         let display_id = self.display_id(uuid)?;
-        if !enabled {
-            cg_error_to_result(
-                cgs_configure_display_enabled(&self.config_ref, display_id, enabled),
-                format!("While attempting to adjust the enablement of {}", uuid).as_str(),
-            )
-        } else {
-            Ok(())
+
+        if self.underscans.contains_key(&display_id) {
+            return Err(Error::DuplicateConfiguration(uuid.to_owned()));
         }
+
+        self.underscans.insert(display_id, underscan);
+
+        Ok(())
     }
 
-    fn commit(mut self) -> Result<(), Error> {
+    fn set_enabled(&mut self, uuid: &str, enabled: bool) -> Result<(), Error> {
         if self.dropped {
             return Err(Error::InvalidTransactionState);
         }
 
+        let display_id = self.display_id(uuid)?;
         cg_error_to_result(
-            cg_complete_display_configuration(
-                self.move_config(),
-                CGConfigureOption::kCGConfigurePermanently,
-            ),
+            cgs_configure_display_enabled(&self.config_ref, display_id, enabled),
+            format!("While attempting to adjust the enablement of {}", uuid).as_str(),
+        )
+    }
+
+    fn set_fade(&mut self, duration: std::time::Duration) -> Result<(), Error> {
+        if self.dropped {
+            return Err(Error::InvalidTransactionState);
+        }
+        self.fade = Some(duration);
+        Ok(())
+    }
+
+    fn commit(mut self, scope: ConfigurationScope) -> Result<(), Error> {
+        if self.dropped {
+            return Err(Error::InvalidTransactionState);
+        }
+
+        let option = match scope {
+            ConfigurationScope::App => CGConfigureOption::kCGConfigureForAppOnly,
+            ConfigurationScope::Session => CGConfigureOption::kCGConfigureForSession,
+            ConfigurationScope::Permanent => CGConfigureOption::kCGConfigurePermanently,
+        };
+        let fade = self.fade_out();
+        let result = cg_error_to_result(
+            cg_complete_display_configuration(self.move_config(), option),
             "While attempting to commit the configuration transaction",
-        )?;
+        );
+        // Fade back in even if the configuration failed, so that the
+        // displays are not left black.
+        if let Some((token, half)) = fade {
+            Self::fade_in(token, half);
+        }
+        result?;
 
+        // Rotation and underscan are set outside of the configuration
+        // transaction, which does not support scopes, so they always persist.
         for (&display_id, &rotation) in &self.rotations {
-            mpd_set_rotation(display_id, rotation.into())
+            rotate_display(display_id, rotation)?;
+        }
+
+        for (&display_id, &underscan) in &self.underscans {
+            mpd_set_underscan(display_id, underscan.0 as f32)
         }
 
         self.dropped = true;
@@ -302,8 +576,15 @@ pub struct RealDisplay {
     enabled: bool,
     origin: Point,
     rotation: Rotation,
+    underscan: Option<Underscan>,
+    kind: DisplayKind,
+    /// The name of the display product, if reported.
+    name: Option<String>,
+    mirroring: Option<Mirroring>,
     mode: RealDisplayMode,
-    modes: Vec<RealDisplayMode>,
+    /// The possible display modes, which are only enumerated when first
+    /// needed, as doing so is comparatively slow.
+    modes: once_cell::sync::OnceCell<Vec<RealDisplayMode>>,
 }
 
 /// Undo display_rotation to the Point.  Note that this is not
@@ -320,7 +601,38 @@ pub fn undo_display_rotation(point: Point, rotation: Rotation) -> Point {
     }
 }
 
+/// Swap the coordinates of the Point if necessary so that it has the same
+/// orientation, portrait or landscape, as the reference Point.
+fn orient_like(point: Point, reference: &Point) -> Point {
+    if (point.x >= point.y) == (reference.x >= reference.y) {
+        point
+    } else {
+        Point {
+            x: point.y,
+            y: point.x,
+        }
+    }
+}
+
 impl RealDisplay {
+    /// Determine what kind of device the given display is.  Sidecar
+    /// displays are only distinguished from other virtual displays by name.
+    fn compute_kind(info: &DisplayInfo) -> DisplayKind {
+        let sidecar = info
+            .product_name
+            .as_deref()
+            .is_some_and(|name| name.contains("Sidecar"));
+        if info.airplay {
+            DisplayKind::AirPlay
+        } else if sidecar {
+            DisplayKind::Sidecar
+        } else if info.virtual_device {
+            DisplayKind::Virtual
+        } else {
+            DisplayKind::Physical
+        }
+    }
+
     /// Obtain a unique identifying name for the given display.
     // TODO Perform some additional testing to see this remains "persistent"
     //  for identical model displays.
@@ -328,95 +640,95 @@ impl RealDisplay {
         // Use CoreGraphics UUID API.  I've already determined that for
         // some of dual displays that the manufacturer doesn't report a
         // meaningful serial number.
-        let cfuuid = cg_display_create_uuid_from_display_id(display_id);
-        let cfstring = cf_uuid_create_string(kCFAllocatorDefault, cfuuid);
-        let mut buffer: [u8; 37] = [0; 37];
-        if !cf_string_get_cstring(
-            cfstring,
-            &mut buffer,
-            CFStringBuiltInEncodings::ASCII.into(),
-        ) {
-            // It seems reasonable to panic here, as the UUID has a fixed
-            // format and length.
-            panic!("Buffer to receive UUID is too small.")
-        }
-        // Need to manually release the resources.
-        cf_release(cfstring);
-        cf_release(cfuuid);
-
-        // It is safe to unwrap here as we know that the UUID will always
-        // be ASCII which should never fail with from_utf8.
-        String::from_utf8(buffer[0..36].to_vec())
-            .unwrap()
+        // The CoreFoundation objects are released when they go out of scope.
+        let uuid = cg_display_create_uuid_from_display_id(display_id)
+            .and_then(|cfuuid| cf_uuid_create_string(kCFAllocatorDefault, &cfuuid))
+            .and_then(|cfstring| cfstring.to_ascii(36));
+        // It seems reasonable to panic here, as every display has a UUID,
+        // and the UUID has a fixed format and length.
+        uuid.expect("Unable to obtain the UUID of the display.")
             .to_lowercase()
             .replace('-', "")
     }
 
-    /// Create a `RealDisplay` given a `DisplayID`.
-    fn new(display_id: DisplayID) -> Result<Self, Error> {
-        let uuid = RealDisplay::compute_uuid(display_id);
-
-        let mut num_modes = 0;
+    /// Obtain the description of the given display mode, normalized for the
+    /// rotation of the display and refined using the public APIs if they
+    /// have information about it.
+    fn read_mode(
+        display_id: DisplayID,
+        uuid: &str,
+        mode_num: i32,
+        rotation: Rotation,
+        info: Option<&PublicModeInfo>,
+    ) -> Result<(RealDisplayMode, CGSDisplayModeDescription), Error> {
+        let mut desc = CGSDisplayModeDescription::default();
         cg_error_to_result(
-            cgs_get_number_of_display_modes(display_id, &mut num_modes),
-            format!(
-                "While attempting to obtain the number of display modes on {}",
-                uuid
-            )
-            .as_str(),
+            cgs_get_display_mode_description(display_id, mode_num, &mut desc),
+            format!("While attempting to obtain a mode description on {}", uuid).as_str(),
         )?;
 
-        // Obtain the current display rotation for normalizing the modes.
-        let float_rotation = cg_display_rotation(display_id);
-        let rotation = Rotation::try_from(float_rotation)
-            .expect(format!("Unexpected display rotation angle: {}", float_rotation).as_str());
+        // TODO Eliminate clone
+        let mut mode = RealDisplayMode::new(display_id, desc.clone());
+        // Normalize the extents.
+        mode.extents = undo_display_rotation(mode.extents, rotation);
+        mode.pixel_extents = undo_display_rotation(mode.pixel_extents, rotation);
+        if let Some(info) = info {
+            // Prefer the color depth reported by the public APIs, so that
+            // ten bit modes are distinguished from eight bit modes.
+            if let Some(depth) = info.channel_depth {
+                mode.color_depth = depth;
+            }
+            mode.usable_for_desktop = info.usable_for_desktop;
+            mode.io_flags = Some(info.io_flags);
+            // It is not clear whether the public APIs report the pixel
+            // extents rotated, so orient them to match the extents.
+            mode.pixel_extents = orient_like(info.pixel_extents.clone(), &mode.extents);
+        }
+        Ok((mode, desc))
+    }
 
-        let mut current_mode_num = 0;
+    /// Enumerate all of the possible display modes of this display.
+    fn enumerate_modes(&self) -> Result<Vec<RealDisplayMode>, Error> {
+        let mut num_modes = 0;
         cg_error_to_result(
-            cgs_get_current_display_mode(display_id, &mut current_mode_num),
+            cgs_get_number_of_display_modes(self.display_id, &mut num_modes),
             format!(
-                "While attempting to obtain the current display mode on {}",
-                uuid
+                "While attempting to obtain the number of display modes on {}",
+                self.uuid
             )
             .as_str(),
         )?;
-        let mut current_mode = None;
 
-        // Temporary for debugging
+        let infos = public_mode_info(self.display_id);
+
+        let mut modes = Vec::with_capacity(num_modes as usize);
+        // Group mode descriptions into buckets to report duplicates.
         let mut mode_buckets: HashMap<RealDisplayMode, Vec<CGSDisplayModeDescription>> =
             HashMap::new();
 
-        let mut possible_modes = Vec::new();
         for mode_num in 0..num_modes {
-            let mut desc = CGSDisplayModeDescription::default();
-            cg_error_to_result(
-                cgs_get_display_mode_description(display_id, mode_num, &mut desc),
-                format!("While attempting to obtain a mode description on {}", uuid).as_str(),
+            let (mode, desc) = RealDisplay::read_mode(
+                self.display_id,
+                &self.uuid,
+                mode_num,
+                self.rotation,
+                infos.get(&mode_num),
             )?;
 
-            // TODO Eliminate clone
-            let mut mode = RealDisplayMode::new(display_id, desc.clone());
-            // Normalize the extents.
-            mode.extents = undo_display_rotation(mode.extents, rotation);
-
-            if current_mode_num == mode_num {
-                current_mode = Some(mode.clone());
-            }
-
-            // Group mode descriptions into buckets for investigation.
             match mode_buckets.get_mut(&mode) {
                 Some(descs) => descs.push(desc),
                 None => {
                     mode_buckets.insert(mode.clone(), vec![desc]);
                 }
             }
-
-            possible_modes.push(mode);
+            modes.push(mode);
         }
 
         // Log the duplicates.
         // Further investigation is needed as to why some essentially duplicate
-        // modes are reported from the API.
+        // modes are reported from the API.  All of them are retained, in the
+        // order they were enumerated, so that the `DuplicateModes` policy can
+        // decide which to use.
         for (mode, descs) in &mode_buckets {
             if descs.len() > 1 {
                 warn!(
@@ -426,16 +738,47 @@ impl RealDisplay {
             }
         }
 
-        // TODO Is the likely enough that it should be reported as an actual
-        //   error condition?
-        assert!(current_mode.is_some());
+        Ok(modes)
+    }
+
+    /// Create a `RealDisplay` given a `DisplayID` and its UUID.  Only the
+    /// current display mode is obtained, and the possible display modes are
+    /// enumerated when first needed.
+    fn new(display_id: DisplayID, uuid: String) -> Result<Self, Error> {
+        // Obtain the current display rotation for normalizing the modes.
+        let float_rotation = cg_display_rotation(display_id);
+        let rotation = Rotation::try_from(float_rotation)
+            .expect(format!("Unexpected display rotation angle: {}", float_rotation).as_str());
+
+        let mut current_mode_num = 0;
+        cg_error_to_result(
+            cgs_get_current_display_mode(display_id, &mut current_mode_num),
+            format!(
+                "While attempting to obtain the current display mode on {}",
+                uuid
+            )
+            .as_str(),
+        )?;
+        let (mode, _) = RealDisplay::read_mode(
+            display_id,
+            &uuid,
+            current_mode_num,
+            rotation,
+            public_current_mode_info(display_id, current_mode_num).as_ref(),
+        )?;
 
-        let enabled = cg_display_is_active(display_id) || cg_display_is_in_mirror_set(display_id);
+        let in_mirror_set = cg_display_is_in_mirror_set(display_id);
+        let enabled = cg_display_is_active(display_id) || in_mirror_set;
+        let mirroring = match cg_display_mirrors_display(display_id) {
+            Some(primary_id) => Some(Mirroring::MirrorOf(RealDisplay::compute_uuid(primary_id))),
+            None if in_mirror_set => Some(Mirroring::Primary),
+            None => None,
+        };
         let cg_point = cg_display_bounds(display_id).origin;
+        let info = cd_display_info(display_id);
 
         Ok(RealDisplay {
             display_id,
-            uuid,
             enabled,
             origin: Point {
                 // TODO Could not find a safer more idiomatic way of converting?
@@ -443,8 +786,13 @@ impl RealDisplay {
                 y: cg_point.y as i64,
             },
             rotation,
-            mode: current_mode.unwrap(),
-            modes: mode_buckets.into_keys().collect::<Vec<RealDisplayMode>>(),
+            underscan: mpd_underscan(display_id).map(|u| Underscan(u.into())),
+            kind: RealDisplay::compute_kind(&info),
+            name: info.product_name,
+            mirroring,
+            mode,
+            modes: once_cell::sync::OnceCell::new(),
+            uuid,
         })
     }
 }
@@ -466,6 +814,26 @@ impl Display for RealDisplay {
         self.rotation
     }
 
+    fn underscan(&self) -> Option<Underscan> {
+        self.underscan
+    }
+
+    fn kind(&self) -> DisplayKind {
+        self.kind
+    }
+
+    fn display_id(&self) -> Option<u32> {
+        Some(self.display_id.raw())
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        self.mirroring.clone()
+    }
+
     type DisplayModeType = RealDisplayMode;
 
     fn current_mode(&self) -> &Self::DisplayModeType {
@@ -473,7 +841,16 @@ impl Display for RealDisplay {
     }
 
     fn possible_modes(&self) -> &[Self::DisplayModeType] {
-        self.modes.as_slice()
+        self.modes.get_or_init(|| {
+            self.enumerate_modes().unwrap_or_else(|e| {
+                // Fall back to the current mode, which is always possible.
+                warn!(
+                    "Unable to enumerate the display modes of {}: {}",
+                    self.uuid, e
+                );
+                vec![self.mode.clone()]
+            })
+        })
     }
 }
 
@@ -482,51 +859,335 @@ impl Display for RealDisplay {
 #[derive(Debug)]
 pub struct RealDisplayState {
     displays: BTreeMap<String, RealDisplay>,
+    /// Map from the UUIDs of disabled displays to their `DisplayID`s.
+    disabled: BTreeMap<String, DisplayID>,
+}
+
+/// A virtual display, along with its UUID.
+#[derive(Debug)]
+pub struct RealVirtualDisplay {
+    uuid: String,
+    _display: CgVirtualDisplay,
+}
+
+impl VirtualDisplay for RealVirtualDisplay {
+    fn uuid(&self) -> &str {
+        &self.uuid
+    }
+}
+
+/// A cache of the UUIDs of the online displays, keyed by the raw value of
+/// their `DisplayID`, as computing a UUID is comparatively slow.  A
+/// `DisplayID` may be reused for a different display once the original is
+/// detached, so the cache is cleared whenever the set of online displays
+/// changes.
+#[derive(Debug, Default)]
+struct UuidCache {
+    /// The displays that were online when the cache was last refreshed.
+    online: Vec<u32>,
+    uuids: HashMap<u32, String>,
+}
+
+impl UuidCache {
+    /// Clear the cache if the set of online displays is not the same as
+    /// when it was last refreshed.
+    fn refresh(&mut self, online: &[u32]) {
+        let mut online = online.to_vec();
+        online.sort_unstable();
+        if online != self.online {
+            debug!("The online displays changed, so clearing the UUID cache.");
+            self.online = online;
+            self.uuids.clear();
+        }
+    }
+
+    /// Obtain the UUID of the given display, computing it if it is not
+    /// already cached.
+    fn uuid(&mut self, display_id: u32, compute: impl FnOnce() -> String) -> String {
+        self.uuids.entry(display_id).or_insert_with(compute).clone()
+    }
+}
+
+static UUID_CACHE: once_cell::sync::Lazy<std::sync::Mutex<UuidCache>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+/// Test that UUIDs are only computed once while the set of online displays
+/// stays the same.
+#[test]
+fn test_uuid_cache() {
+    let computed = std::cell::Cell::new(0);
+    let lookup = |cache: &mut UuidCache, id: u32| {
+        cache.uuid(id, || {
+            computed.set(computed.get() + 1);
+            format!("uuid{}", id)
+        })
+    };
+
+    let mut cache = UuidCache::default();
+    cache.refresh(&[1, 2]);
+    assert_eq!(lookup(&mut cache, 1), "uuid1");
+    assert_eq!(lookup(&mut cache, 2), "uuid2");
+    // The order the displays are reported in does not matter.
+    cache.refresh(&[2, 1]);
+    assert_eq!(lookup(&mut cache, 1), "uuid1");
+    assert_eq!(lookup(&mut cache, 2), "uuid2");
+    assert_eq!(computed.get(), 2);
+
+    // Attaching a display clears the cache.
+    cache.refresh(&[1, 2, 3]);
+    for id in [1, 2, 3] {
+        lookup(&mut cache, id);
+    }
+    assert_eq!(computed.get(), 5);
+}
+
+/// How many times to try listing displays, should displays keep being
+/// attached while listing them.
+const DISPLAY_LIST_ATTEMPTS: usize = 3;
+
+/// List displays with a function that follows the conventions of
+/// `CGGetOnlineDisplayList`, first counting the displays and then growing
+/// the list until it holds all of them.
+fn list_display_ids(
+    list: impl Fn(&mut [DisplayID], &mut u32) -> CGError,
+    what: &str,
+) -> Result<Vec<DisplayID>, Error> {
+    let mut num_displays: u32 = 0;
+    cg_error_to_result(
+        list(&mut [], &mut num_displays),
+        &format!("Unable to count the {} displays", what),
+    )?;
+    // TODO u32 does not have From for usize, apparently just in case
+    //   a 16-bit platform is the target.  Revise when infallible
+    //   try_from might be standard here?
+    // Leave room for an extra display, so that a list that fills the
+    // buffer can be recognized as possibly truncated, as it is when a
+    // display is attached between counting and listing.
+    let mut capacity = num_displays as usize + 1;
+    for _ in 0..DISPLAY_LIST_ATTEMPTS {
+        let mut display_ids = vec![DisplayID::default(); capacity];
+        cg_error_to_result(
+            list(&mut display_ids, &mut num_displays),
+            &format!("Unable to list the {} displays", what),
+        )?;
+        if (num_displays as usize) < capacity {
+            display_ids.truncate(num_displays as usize);
+            return Ok(display_ids);
+        }
+        capacity *= 2;
+    }
+    Err(Error::Internal(format!(
+        "The {} displays did not fit in a list of {} displays",
+        what,
+        capacity / 2
+    )))
+}
+
+/// List the online displays.  We want the online rather than active displays
+/// as that will not include mirrored or sleeping displays.
+fn online_display_ids() -> Result<Vec<DisplayID>, Error> {
+    list_display_ids(cg_get_online_display_list, "online")
+}
+
+/// List the displays that are attached but have been disabled, and so are
+/// not online.  Returns an empty list if this version of macOS does not
+/// provide a way to list them.
+fn disabled_display_ids(online: &[DisplayID]) -> Result<Vec<DisplayID>, Error> {
+    if cgs_get_display_list(&mut [], &mut 0).is_none() {
+        return Ok(Vec::new());
+    }
+    let all = list_display_ids(
+        // Okay to unwrap, as the function was just found to be present.
+        |display_ids, display_count| cgs_get_display_list(display_ids, display_count).unwrap(),
+        "attached",
+    )?;
+    Ok(all.into_iter().filter(|id| !online.contains(id)).collect())
 }
 
 impl DisplayState for RealDisplayState {
     fn current() -> Result<Self, Error> {
-        // The current Mac Pro supports eight monitors:
-        // https://support.apple.com/en-us/HT213665
-        // I have seen references to twelve being supported on some models.
-        // So I feel for now 64 is a reasonable bound on real monitors.
-        // I will need to experiment with if/how Sidecar displays respond
-        // to these APIs.
-        let mut display_ids: [DisplayID; 64] = [DisplayID::default(); 64];
-        let mut num_displays: u32 = 0;
-        // We want the online rather than active displays as that will not
-        // include mirrored or sleeping displays.
-        cg_get_online_display_list(&mut display_ids, &mut num_displays);
-        assert!(
-            num_displays <= 64,
-            "Number of displays is more than the input array."
-        );
+        let display_ids = &online_display_ids()?;
+        let uuids = {
+            let mut cache = UUID_CACHE
+                .lock()
+                .map_err(|pe| Error::Poisoned(format!("{}", pe)))?;
+            let online = display_ids.iter().map(|id| id.raw()).collect::<Vec<_>>();
+            cache.refresh(&online);
+            display_ids
+                .iter()
+                .map(|&id| cache.uuid(id.raw(), || RealDisplay::compute_uuid(id)))
+                .collect::<Vec<_>>()
+        };
 
         let mut displays = Vec::new();
-        // TODO u32 does not have From for usize, apparently just in case
-        //   a 16-bit platform is the target.  Revise when infallible
-        //   try_from might be standard here?
-        for id in display_ids.into_iter().take(num_displays as usize) {
-            displays.push(RealDisplay::new(id)?);
+        for (&id, uuid) in display_ids.iter().zip(uuids) {
+            displays.push(RealDisplay::new(id, uuid)?);
         }
 
+        let disabled = disabled_display_ids(display_ids)?
+            .into_iter()
+            .map(|id| (RealDisplay::compute_uuid(id), id))
+            .collect();
+
         Ok(RealDisplayState {
             displays: displays
                 .into_iter()
                 .map(|d: RealDisplay| (d.uuid.clone(), d))
                 .collect(),
+            disabled,
         })
     }
 
+    fn environment() -> Environment {
+        Environment {
+            lid: io_clamshell_closed().map(|closed| {
+                if closed {
+                    LidState::Closed
+                } else {
+                    LidState::Open
+                }
+            }),
+            power: io_ps_on_battery().map(|on_battery| {
+                if on_battery {
+                    PowerSource::Battery
+                } else {
+                    PowerSource::Ac
+                }
+            }),
+            ssid: cw_current_ssid(),
+            time: crate::environment::local_time(),
+        }
+    }
+
     type DisplayModeType = RealDisplayMode;
     type DisplayType = RealDisplay;
     type DisplayConfigTransactionType = RealDisplayConfigTransaction;
+    type VirtualDisplayType = RealVirtualDisplay;
 
     fn get_displays(&self) -> &BTreeMap<String, Self::DisplayType> {
         &self.displays
     }
 
+    fn disabled_displays(&self) -> BTreeSet<String> {
+        self.disabled.keys().cloned().collect()
+    }
+
     fn configure(&self) -> Result<Self::DisplayConfigTransactionType, Error> {
-        RealDisplayConfigTransaction::new(&self.displays)
+        let displays = self
+            .displays
+            .iter()
+            .map(|(uuid, real_display)| (uuid.clone(), real_display.display_id))
+            .chain(self.disabled.clone())
+            .collect();
+        RealDisplayConfigTransaction::new(displays)
+    }
+
+    fn create_virtual_display(
+        spec: &VirtualDisplaySpec,
+    ) -> Result<Self::VirtualDisplayType, Error> {
+        let dimension = |value: i64| {
+            u32::try_from(value)
+                .ok()
+                .filter(|&value| value > 0)
+                .ok_or_else(|| {
+                    Error::Internal(format!("Invalid virtual display extents {}", spec.extents))
+                })
+        };
+        // The UUID of a virtual display is derived from its serial number,
+        // so distinguish the displays created by different processes.
+        let serial_number = std::process::id();
+        let display = CgVirtualDisplay::new(
+            &spec.name,
+            dimension(spec.extents.x)?,
+            dimension(spec.extents.y)?,
+            spec.ppi,
+            spec.frequency as f64,
+            serial_number,
+        )
+        .ok_or_else(|| Error::Internal("Unable to create a virtual display".to_owned()))?;
+        Ok(RealVirtualDisplay {
+            uuid: RealDisplay::compute_uuid(display.display_id()),
+            _display: display,
+        })
+    }
+
+    fn sleep_displays() -> Result<(), Error> {
+        if io_display_wrangler_request_idle() {
+            Ok(())
+        } else {
+            Err(Error::Internal(
+                "Unable to put the displays to sleep".to_owned(),
+            ))
+        }
+    }
+
+    fn wake_displays() -> Result<(), Error> {
+        if io_pm_declare_user_activity(c"knoll") {
+            Ok(())
+        } else {
+            Err(Error::Internal("Unable to wake the displays".to_owned()))
+        }
+    }
+
+    fn system_facts() -> SystemFacts {
+        // The private functions that are linked against, without which
+        // knoll cannot enumerate or configure display modes.
+        const REQUIRED_SYMBOLS: [&std::ffi::CStr; 6] = [
+            c"CGSGetCurrentDisplayMode",
+            c"CGSGetNumberOfDisplayModes",
+            c"CGSGetDisplayModeDescriptionOfLength",
+            c"CGSConfigureDisplayMode",
+            c"CGSConfigureDisplayEnabled",
+            c"CoreDisplay_DisplayCreateInfoDictionary",
+        ];
+        const OPTIONAL_SYMBOLS: [&std::ffi::CStr; 2] =
+            [c"CGSSetDisplayRotation", c"CGSGetDisplayList"];
+        const OPTIONAL_CLASSES: [&str; 2] = ["MPDisplay", "CGVirtualDisplay"];
+
+        let missing = |symbols: &[&std::ffi::CStr]| -> Vec<String> {
+            symbols
+                .iter()
+                .filter(|symbol| !dl_symbol_present(symbol))
+                .map(|symbol| symbol.to_string_lossy().into_owned())
+                .collect()
+        };
+        let mut missing_optional_symbols = missing(&OPTIONAL_SYMBOLS);
+        missing_optional_symbols.extend(
+            OPTIONAL_CLASSES
+                .iter()
+                .filter(|class| !objc_class_present(class))
+                .map(|class| class.to_string()),
+        );
+        let session = cg_session_info();
+        SystemFacts {
+            os_version: os_product_version(),
+            missing_required_symbols: missing(&REQUIRED_SYMBOLS),
+            missing_optional_symbols,
+            ssh: ["SSH_CONNECTION", "SSH_TTY"]
+                .iter()
+                .any(|name| std::env::var_os(name).is_some()),
+            session: session.is_some(),
+            on_console: session.as_ref().is_some_and(|info| info.on_console),
+            screen_locked: session.as_ref().is_some_and(|info| info.screen_locked),
+        }
+    }
+
+    fn register_reconfiguration_callback(callback: extern "C" fn()) {
+        cg_display_register_reconfiguration_callback(callback);
+    }
+
+    fn remove_reconfiguration_callback(callback: extern "C" fn()) {
+        cg_display_remove_reconfiguration_callback(callback);
+    }
+
+    fn run_event_loop() {
+        // macOS will not invoke the callbacks unless there is an application
+        // loop running.
+        ns_application_load();
+        cf_run_loop_run();
+    }
+
+    fn stop_event_loop() {
+        cf_run_loop_stop_main();
     }
 }
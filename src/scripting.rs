@@ -0,0 +1,105 @@
+//! This module evaluates configuration scripts written in
+//! [Rhai](https://rhai.rs), so that a configuration can compute values that
+//! static JSON or RON cannot express, such as origins derived from the
+//! number of attached displays.  A script is given the current display
+//! state as the variable `state`, in the same shape as the output of knoll,
+//! and evaluates to the configuration groups.  It is only built with the
+//! `scripting` feature.
+use coverage_helper::test;
+use std::fmt::Formatter;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The most operations a script may perform, so that a script that never
+/// finishes cannot stall the daemon.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The possible failures when evaluating a configuration script.
+#[derive(Debug)]
+pub enum Error {
+    /// The script could not be parsed.
+    Parse(rhai::ParseError),
+    /// Evaluating the script failed, or it did not evaluate to a value
+    /// that could be used as a configuration.
+    Eval(Box<rhai::EvalAltResult>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(pe) => write!(f, "Script parse error: {}", pe),
+            Error::Eval(ee) => write!(f, "Script evaluation error: {}", ee),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::Eval(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<rhai::ParseError> for Error {
+    fn from(e: rhai::ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<Box<rhai::EvalAltResult>> for Error {
+    fn from(e: Box<rhai::EvalAltResult>) -> Self {
+        Error::Eval(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Evaluate the script, providing it the given display state, and return
+/// the value it evaluates to.
+pub fn evaluate(script: &str, state: &serde_json::Value) -> Result<serde_json::Value, Error> {
+    let mut engine = rhai::Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let ast = engine.compile(script)?;
+    let mut scope = rhai::Scope::new();
+    scope.push_constant("state", rhai::serde::to_dynamic(state)?);
+    let result: rhai::Dynamic = engine.eval_ast_with_scope(&mut scope, &ast)?;
+    Ok(rhai::serde::from_dynamic(&result)?)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_evaluate() {
+    use serde_json::json;
+
+    let state = json!([[{"uuid": "a", "extents": [1920, 1080]}, {"uuid": "b"}]]);
+    // Place the displays side by side, however many there are.
+    let script = r#"
+        let x = 0;
+        let configs = [];
+        for display in state[0] {
+            configs.push(#{uuid: display.uuid, origin: [x, 0]});
+            x += 1920;
+        }
+        [configs]
+    "#;
+    assert_eq!(
+        evaluate(script, &state).unwrap(),
+        json!([[{"uuid": "a", "origin": [0, 0]}, {"uuid": "b", "origin": [1920, 0]}]])
+    );
+
+    assert!(matches!(evaluate("[[", &state), Err(Error::Parse(_))));
+    assert!(matches!(
+        evaluate(r#"throw "no layout""#, &state),
+        Err(Error::Eval(_))
+    ));
+    // Scripts that never finish are stopped.
+    assert!(matches!(evaluate("loop {}", &state), Err(Error::Eval(_))));
+    // The state cannot be modified.
+    assert!(matches!(
+        evaluate("state = []", &state),
+        Err(Error::Eval(_))
+    ));
+}
@@ -1,6 +1,9 @@
+use coverage_helper::test;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt::Formatter;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -13,6 +16,75 @@ pub enum Error {
     SerJson(serde_json::Error),
     Utf8Conversion(std::string::FromUtf8Error),
     UnsupportedFormat(String),
+    /// The configuration was written for a version of the schema this
+    /// version of knoll does not know about.
+    UnsupportedVersion(u64),
+    /// A deserialization error along with where in the input it occurred.
+    Located(Box<Error>, Location),
+}
+
+/// The location in the input of a deserialization error.
+#[derive(Debug)]
+pub struct Location {
+    /// The file the input was read from, if any.
+    pub path: Option<PathBuf>,
+    /// The line, counting from one.
+    pub line: usize,
+    /// The column, counting from one.
+    pub column: usize,
+    /// The text of the offending line.
+    pub text: String,
+}
+
+impl Location {
+    /// Helper to construct a `Location` for the given position in the
+    /// input.  Returns `None` if the position is not within the input.
+    fn new(input: &str, line: usize, column: usize) -> Option<Self> {
+        let text = input.lines().nth(line.checked_sub(1)?)?;
+        Some(Location {
+            path: None,
+            line,
+            column,
+            text: text.to_owned(),
+        })
+    }
+}
+
+/// ANSI escape codes used when rendering errors for a terminal.
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+impl Error {
+    /// Record the file the input was read from in the location of the
+    /// error, if it has one.
+    pub fn with_path(self, path: &Path) -> Self {
+        match self {
+            Error::Located(e, mut location) => {
+                location.path = Some(path.to_owned());
+                Error::Located(e, location)
+            }
+            e => e,
+        }
+    }
+
+    /// Helper to write the message of a deserialization error without
+    /// the position that is otherwise included.
+    fn fmt_message(&self, f: &mut Formatter<'_>, location: &Location) -> std::fmt::Result {
+        match self {
+            Error::DeRon(e) => write!(f, "RON deserialization error: {}", e.code),
+            Error::DeJson(e) => {
+                let message = e.to_string();
+                let suffix = format!(" at line {} column {}", location.line, location.column);
+                write!(
+                    f,
+                    "JSON deserialization error: {}",
+                    message.strip_suffix(&suffix).unwrap_or(&message)
+                )
+            }
+            e => std::fmt::Display::fmt(e, f),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -38,6 +110,45 @@ impl std::fmt::Display for Error {
             UnsupportedFormat(fs) => {
                 write!(f, "Unsupported format: {}", fs)
             }
+            UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "The configuration is for version {} of the schema, but only \
+                    versions 1 to {} are supported",
+                    version, CONFIG_VERSION
+                )
+            }
+            // Render the location in the style of compiler diagnostics,
+            // using color if the alternate flag was given.
+            Located(e, location) => {
+                let (red, blue, reset) = if f.alternate() {
+                    (BOLD_RED, BOLD_BLUE, RESET)
+                } else {
+                    ("", "", "")
+                };
+                let line = location.line.to_string();
+                let gutter = " ".repeat(line.len());
+                write!(f, "{}", red)?;
+                e.fmt_message(f, location)?;
+                write!(f, "{}", reset)?;
+                write!(f, "\n{}{}-->{} ", gutter, blue, reset)?;
+                if let Some(path) = &location.path {
+                    write!(f, "{}:", path.display())?;
+                }
+                write!(f, "{}:{}", location.line, location.column)?;
+                write!(f, "\n{} {}|{}", gutter, blue, reset)?;
+                write!(f, "\n{}{} |{} {}", blue, line, reset, location.text)?;
+                write!(
+                    f,
+                    "\n{} {}|{} {}{}^{}",
+                    gutter,
+                    blue,
+                    reset,
+                    " ".repeat(location.column.saturating_sub(1)),
+                    red,
+                    reset
+                )
+            }
         }
     }
 }
@@ -51,7 +162,8 @@ impl std::error::Error for Error {
             DeJson(e) => Some(e),
             SerJson(e) => Some(e),
             Utf8Conversion(e) => Some(e),
-            UnsupportedFormat(_) => None,
+            UnsupportedFormat(_) | UnsupportedVersion(_) => None,
+            Located(e, _) => Some(e),
         }
     }
 }
@@ -118,11 +230,227 @@ pub fn serialize_to_string<S: Serialize>(format: Format, s: &S) -> Result<String
     Ok(str)
 }
 
+/// Helper to serialize a value compactly on a single line, suitable for
+/// newline-delimited streams.
+pub fn serialize_compact<S: Serialize, W: Write>(
+    format: Format,
+    s: &S,
+    writer: W,
+) -> Result<(), Error> {
+    match format {
+        Format::Ron => ron::ser::to_writer(writer, s)?,
+        Format::Json => serde_json::ser::to_writer(writer, s).map_err(Error::SerJson)?,
+    }
+    Ok(())
+}
+
 /// Helper to abstract over deserialization, parameterized by the selected
-/// data format.
+/// data format.  Errors will include their location in the input when it
+/// is known.
 pub fn deserialize<'a, D: Deserialize<'a>>(format: Format, str: &'a str) -> Result<D, Error> {
-    Ok(match format {
-        Format::Ron => ron::de::from_str(str)?,
-        Format::Json => serde_json::from_str(str).map_err(Error::DeJson)?,
+    let (error, line, column) = match format {
+        Format::Ron => match ron::de::from_str(str) {
+            Ok(d) => return Ok(d),
+            Err(e) => {
+                let (line, column) = (e.position.line, e.position.col);
+                (Error::DeRon(e), line, column)
+            }
+        },
+        Format::Json => match serde_json::from_str(str) {
+            Ok(d) => return Ok(d),
+            Err(e) => {
+                let (line, column) = (e.line(), e.column());
+                (Error::DeJson(e), line, column)
+            }
+        },
+    };
+    Err(match Location::new(str, line, column) {
+        Some(location) => Error::Located(Box::new(error), location),
+        None => error,
     })
 }
+
+/// Helper to deserialize into a format independent generic value.  RON
+/// structures are represented as objects.
+pub fn deserialize_value(format: Format, str: &str) -> Result<serde_json::Value, Error> {
+    match format {
+        // RON identifiers cannot be deserialized directly into JSON values,
+        // so go by way of a RON value.
+        Format::Ron => {
+            let value: ron::Value = deserialize(format, str)?;
+            serde_json::to_value(value).map_err(Error::SerJson)
+        }
+        Format::Json => deserialize(format, str),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A function that upgrades the configuration groups of a configuration,
+/// represented as a generic value, from one version of the schema to the
+/// next.
+type Migration = fn(&mut serde_json::Value);
+
+/// The migrations between successive versions of the configuration schema,
+/// where the first upgrades from version 1 to version 2, and so on.  Version
+/// 1 is the first version of the schema, so there are none yet.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The current version of the configuration schema.  Adding a migration
+/// introduces a new version.  Note that the display state knoll writes does
+/// not include the version, so once there is more than one version, it must.
+pub const CONFIG_VERSION: u64 = MIGRATIONS.len() as u64 + 1;
+
+/// The version of configurations that are simply a list of configuration
+/// groups, which includes any written before the schema was versioned.
+const UNVERSIONED: u64 = 1;
+
+/// Helper to determine the version of the schema a configuration was written
+/// for, given the current version.  Malformed versions are treated as the
+/// current version, so that deserialization will report them.
+fn config_version(value: &serde_json::Value, current: u64) -> Result<u64, Error> {
+    let version = match value {
+        serde_json::Value::Object(map) => match map.get("version").and_then(|v| v.as_u64()) {
+            Some(version) => version,
+            None => return Ok(current),
+        },
+        _ => UNVERSIONED,
+    };
+    if version == 0 || version > current {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    Ok(version)
+}
+
+/// Helper to upgrade a configuration using the given migrations.
+fn migrate_with(
+    mut value: serde_json::Value,
+    migrations: &[Migration],
+) -> Result<serde_json::Value, Error> {
+    let current = migrations.len() as u64 + 1;
+    let version = config_version(&value, current)?;
+    if version == current {
+        return Ok(value);
+    }
+    let mut groups = match &mut value {
+        serde_json::Value::Object(map) => map.remove("groups").unwrap_or_default(),
+        groups => groups.take(),
+    };
+    for migration in &migrations[(version - 1) as usize..] {
+        migration(&mut groups);
+    }
+    // Any other fields are preserved, so that they may still be reported as
+    // unknown.
+    let mut map = match value {
+        serde_json::Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    map.insert("version".to_owned(), current.into());
+    map.insert("groups".to_owned(), groups);
+    Ok(serde_json::Value::Object(map))
+}
+
+/// Upgrade a configuration, represented as a generic value, to the current
+/// version of the schema.
+pub fn migrate(value: serde_json::Value) -> Result<serde_json::Value, Error> {
+    migrate_with(value, MIGRATIONS)
+}
+
+/// Helper to deserialize a configuration, first upgrading it to the current
+/// version of the schema if it was written for an older one.
+pub fn deserialize_config<D: DeserializeOwned>(format: Format, str: &str) -> Result<D, Error> {
+    match deserialize_value(format, str) {
+        Ok(value) if config_version(&value, CONFIG_VERSION)? != CONFIG_VERSION => {
+            D::deserialize(migrate(value)?).map_err(Error::DeJson)
+        }
+        // Otherwise, deserialize the input directly, so that any errors
+        // include their location in the input.
+        _ => deserialize(format, str),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A value that will be serialized using the given format when it is
+/// displayed.  This allows errors to embed configurations or display modes
+/// that are rendered in the format the user selected, while deferring the
+/// serialization, and any failure it may produce, until the error is
+/// actually reported.
+#[derive(Clone)]
+pub struct Formatted {
+    format: Format,
+    value: std::sync::Arc<dyn Fn(Format) -> Result<String, Error> + Send + Sync>,
+}
+
+impl Formatted {
+    /// Wrap the given value to be displayed using the given format.
+    pub fn new<S: Serialize + Send + Sync + 'static>(format: Format, value: S) -> Self {
+        Formatted {
+            format,
+            value: std::sync::Arc::new(move |format| serialize_to_string(format, &value)),
+        }
+    }
+}
+
+impl std::fmt::Display for Formatted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.value)(self.format) {
+            Ok(str) => write!(f, "{}", str),
+            Err(e) => write!(f, "<{}>", e),
+        }
+    }
+}
+
+impl std::fmt::Debug for Formatted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Formatted({:?}, {})", self.format, self)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test that the version of the schema is checked, and that migrations are
+/// applied in order from the version the configuration was written for.
+#[test]
+fn test_migrate() {
+    use serde_json::json;
+
+    // Migrations that record that they were applied.
+    let migrations: &[Migration] = &[
+        |groups| groups.as_array_mut().unwrap().push(json!(2)),
+        |groups| groups.as_array_mut().unwrap().push(json!(3)),
+    ];
+    assert_eq!(
+        migrate_with(json!([]), migrations).unwrap(),
+        json!({"version": 3, "groups": [2, 3]})
+    );
+    assert_eq!(
+        migrate_with(json!({"version": 2, "groups": [], "other": 0}), migrations).unwrap(),
+        json!({"version": 3, "groups": [3], "other": 0})
+    );
+    let current = json!({"version": 3, "groups": []});
+    assert_eq!(migrate_with(current.clone(), migrations).unwrap(), current);
+    for version in [0, 4] {
+        assert!(matches!(
+            migrate_with(json!({"version": version, "groups": []}), migrations),
+            Err(Error::UnsupportedVersion(v)) if v == version
+        ));
+    }
+
+    // Both forms of the current version can be read.
+    let groups: crate::config::ConfigGroups =
+        deserialize_config(Format::Json, r#"[[{"uuid": "abcdef1234"}]]"#).unwrap();
+    let versioned: crate::config::ConfigGroups = deserialize_config(
+        Format::Ron,
+        r#"(version: 1, groups: [[(uuid: "abcdef1234")]])"#,
+    )
+    .unwrap();
+    assert_eq!(groups, versioned);
+    assert!(matches!(
+        deserialize_config::<crate::config::ConfigGroups>(
+            Format::Json,
+            r#"{"version": 2, "groups": []}"#
+        ),
+        Err(Error::UnsupportedVersion(2))
+    ));
+}
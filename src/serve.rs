@@ -0,0 +1,124 @@
+//! This module provides the protocol used by `knoll serve`, which allows a
+//! single long-running knoll process to be driven by another program.  Each
+//! request and response is a single line of JSON.  Requests may carry an
+//! `id`, which is echoed back in the corresponding response so that
+//! clients can match them up.  Once subscribed, events about displays being
+//! attached and detached are interleaved with the responses, and are
+//! distinguished by having an `event` rather than a `response` field.
+use coverage_helper::test;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigGroups;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The commands that may be sent to `knoll serve`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    /// List the display modes of the attached displays.  If any UUIDs are
+    /// given, only the modes of those displays are listed.
+    List {
+        #[serde(default)]
+        uuids: Vec<String>,
+        /// Whether to include modes that are not usable for the desktop.
+        #[serde(default)]
+        all_modes: bool,
+    },
+    /// Apply the most precise of the given configuration groups.
+    Apply { config: ConfigGroups },
+    /// Check the given configuration groups, reporting any warnings and
+    /// the configuration group that would be applied, without changing the
+    /// displays.
+    Verify { config: ConfigGroups },
+    /// Start reporting displays being attached or detached.
+    Subscribe,
+}
+
+/// A command along with the identifier the client chose for it, if any.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// The possible results of a command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum ResponseKind {
+    /// The display modes of the attached displays.
+    Modes { displays: serde_json::Value },
+    /// The display state after applying a configuration.
+    State { state: ConfigGroups },
+    /// The result of verifying a configuration.
+    Verified {
+        /// Warnings about likely mistakes in the configuration.
+        warnings: Vec<String>,
+        /// The UUIDs of the configuration group that would be applied to
+        /// the attached displays, if any would be.
+        group: Option<Vec<String>>,
+    },
+    /// Events will now be reported.
+    Subscribed,
+    /// The command failed.
+    Error { message: String },
+}
+
+/// A response, along with the identifier of the request it corresponds to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub kind: ResponseKind,
+}
+
+/// Helper to serialize a response as a single line, ready to be written.
+pub fn response_line(response: &Response) -> Vec<u8> {
+    // Serializing these types to JSON cannot fail, as all their maps have
+    // string keys.
+    let mut line = serde_json::to_vec(response).expect("Serializing a response should not fail");
+    line.push(b'\n');
+    line
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check the serialization of requests and responses.
+#[test]
+fn test_serve_protocol() {
+    let request: Request =
+        serde_json::from_str(r#"{"id": 1, "command": "list", "uuids": ["abcdef1234"]}"#)
+            .expect("Deserialization should not fail");
+    assert_eq!(
+        request,
+        Request {
+            id: Some(serde_json::json!(1)),
+            command: Command::List {
+                uuids: vec!["abcdef1234".to_owned()],
+                all_modes: false,
+            },
+        }
+    );
+
+    let request: Request = serde_json::from_str(r#"{"command": "subscribe"}"#)
+        .expect("Deserialization should not fail");
+    assert_eq!(request.id, None);
+    assert_eq!(request.command, Command::Subscribe);
+
+    assert!(serde_json::from_str::<Request>(r#"{"command": "bogus"}"#).is_err());
+
+    let response = Response {
+        id: Some(serde_json::json!("a")),
+        kind: ResponseKind::Verified {
+            warnings: vec![],
+            group: Some(vec!["abcdef1234".to_owned()]),
+        },
+    };
+    assert_eq!(
+        String::from_utf8(response_line(&response)).unwrap(),
+        "{\"id\":\"a\",\"response\":\"verified\",\"warnings\":[],\"group\":[\"abcdef1234\"]}\n"
+    );
+}
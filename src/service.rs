@@ -0,0 +1,236 @@
+//! This module manages running the knoll daemon as a `launchd` user agent.
+//! It generates the property list describing the agent, and uses `launchctl`
+//! to load, unload, and query it.
+use coverage_helper::test;
+use serde::Serialize;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The possible failures when managing the `launchd` agent.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// A `launchctl` command failed.  The arguments are the `launchctl`
+    /// subcommand and its error output.
+    Launchctl(String, String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(ie) => write!(f, "Service I/O error: {}", ie),
+            Error::Launchctl(subcommand, stderr) => {
+                write!(f, "launchctl {} failed: {}", subcommand, stderr.trim())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Launchctl(_, _) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The label used for the agent when none is specified.
+pub const DEFAULT_LABEL: &str = "io.github.gawashburn.knoll";
+
+/// Helper to obtain the home directory, falling back to the current
+/// directory if it is not known.
+fn home() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+}
+
+/// The location of the property list for the agent with the given label.
+pub fn plist_path(label: &str) -> PathBuf {
+    home()
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", label))
+}
+
+/// The location the agent logs to.
+pub fn log_path(label: &str) -> PathBuf {
+    home().join("Library/Logs").join(format!("{}.log", label))
+}
+
+/// Helper to escape text for inclusion in XML.
+fn escape(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Generate the property list for an agent with the given label, that runs
+/// the daemon using the given program and configuration file.
+pub fn plist(label: &str, program: &Path, config: &Path, log: &Path) -> String {
+    let arguments = [
+        program.display().to_string(),
+        "daemon".to_owned(),
+        "-vv".to_owned(),
+        format!("--input={}", config.display()),
+    ];
+    let arguments: String = arguments
+        .iter()
+        .map(|a| format!("            <string>{}</string>\n", escape(a)))
+        .collect();
+    let log = escape(&log.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple Computer//DTD PLIST 1.0//EN"
+        "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+    <dict>
+        <key>KeepAlive</key>
+        <true/>
+        <key>Label</key>
+        <string>{}</string>
+        <key>ProgramArguments</key>
+        <array>
+{}        </array>
+        <key>RunAtLoad</key>
+        <true/>
+        <key>StandardErrorPath</key>
+        <string>{}</string>
+        <key>StandardOutPath</key>
+        <string>{}</string>
+    </dict>
+</plist>
+"#,
+        escape(label),
+        arguments,
+        log,
+        log
+    )
+}
+
+/// Helper to obtain the `launchd` domain for the current user's GUI session.
+fn domain() -> String {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+/// Helper to run `launchctl` with the given arguments, returning its output.
+fn launchctl(args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("launchctl").args(args).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::Launchctl(
+            args[0].to_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+/// Install the agent, replacing any existing agent with the same label, and
+/// start it.
+pub fn install(label: &str, config: &Path) -> Result<(), Error> {
+    let program = std::env::current_exe()?;
+    let config = std::fs::canonicalize(config)?;
+    let path = plist_path(label);
+    let log = log_path(label);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Some(parent) = log.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Unload any previously installed agent, which will fail if there is
+    // none, so the result is ignored.
+    let _ = launchctl(&["bootout", &format!("{}/{}", domain(), label)]);
+    std::fs::write(&path, plist(label, &program, &config, &log))?;
+    launchctl(&["bootstrap", &domain(), &path.display().to_string()])?;
+    Ok(())
+}
+
+/// Stop and remove the agent.
+pub fn uninstall(label: &str) -> Result<(), Error> {
+    let path = plist_path(label);
+    launchctl(&["bootout", &format!("{}/{}", domain(), label)])?;
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The status of the agent, as reported by `knoll service status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceStatus {
+    /// The label of the agent.
+    pub label: String,
+    /// The location of the property list, if it is installed.
+    pub plist: Option<PathBuf>,
+    /// Whether the agent is loaded into `launchd`.
+    pub loaded: bool,
+    /// The process id of the daemon, if it is running.
+    pub pid: Option<u32>,
+}
+
+/// Helper to extract the process id from the output of `launchctl print`.
+fn parse_pid(output: &str) -> Option<u32> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("pid = "))
+        .find_map(|pid| pid.trim().parse().ok())
+}
+
+/// Query the status of the agent.
+pub fn status(label: &str) -> ServiceStatus {
+    let path = plist_path(label);
+    let output = launchctl(&["print", &format!("{}/{}", domain(), label)]).ok();
+    ServiceStatus {
+        label: label.to_owned(),
+        plist: path.exists().then_some(path),
+        loaded: output.is_some(),
+        pid: output.as_deref().and_then(parse_pid),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that the generated property list runs the daemon and escapes its
+/// arguments.
+#[test]
+fn test_plist() {
+    let plist = plist(
+        "my.knoll",
+        Path::new("/usr/local/bin/knoll"),
+        Path::new("/Users/me/a&b.json"),
+        Path::new("/Users/me/Library/Logs/my.knoll.log"),
+    );
+    assert!(plist.contains("<string>my.knoll</string>"));
+    assert!(plist.contains(
+        "            <string>/usr/local/bin/knoll</string>\n            <string>daemon</string>\n"
+    ));
+    assert!(plist.contains("<string>--input=/Users/me/a&amp;b.json</string>"));
+    assert!(plist.contains("<string>/Users/me/Library/Logs/my.knoll.log</string>"));
+}
+
+/// Check extracting the process id from `launchctl print` output.
+#[test]
+fn test_parse_pid() {
+    let output = "gui/501/my.knoll = {\n\tactive count = 1\n\tstate = running\n\tpid = 1234\n}\n";
+    assert_eq!(parse_pid(output), Some(1234));
+    assert_eq!(
+        parse_pid("gui/501/my.knoll = {\n\tstate = waiting\n}\n"),
+        None
+    );
+}
@@ -0,0 +1,65 @@
+//! This module provides minimal safe wrappers for synchronously handling
+//! Unix signals.  Rather than installing asynchronous signal handlers, which
+//! are extremely limited in what they may safely do, the signals of interest
+//! are blocked and then a dedicated thread waits for them to arrive.
+use coverage_helper::test;
+pub use libc::{SIGHUP, SIGINT, SIGTERM};
+use std::os::raw::c_int;
+
+/// Helper to construct a signal set containing the given signals.
+fn signal_set(signals: &[c_int]) -> libc::sigset_t {
+    // SAFETY: sigset_t is a plain data type, and is fully initialized by
+    // sigemptyset before it is used.
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in signals {
+            libc::sigaddset(&mut set, *signal);
+        }
+        set
+    }
+}
+
+/// Block the given signals for the calling thread.  Threads spawned
+/// afterwards inherit the signal mask, so this should be called from the
+/// main thread before any other threads are started.
+pub fn block_signals(signals: &[c_int]) -> std::io::Result<()> {
+    let set = signal_set(signals);
+    // SAFETY: The set is initialized and the old mask is not requested.
+    let result = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::from_raw_os_error(result))
+    }
+}
+
+/// Wait until one of the given signals is delivered, and return it.  The
+/// signals must have already been blocked with `block_signals`.
+pub fn wait_for_signal(signals: &[c_int]) -> std::io::Result<c_int> {
+    let set = signal_set(signals);
+    let mut signal: c_int = 0;
+    // SAFETY: The set is initialized and signal is a valid location to
+    // store the received signal.
+    let result = unsafe { libc::sigwait(&set, &mut signal) };
+    if result == 0 {
+        Ok(signal)
+    } else {
+        Err(std::io::Error::from_raw_os_error(result))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check that a blocked signal raised by the process is received by
+/// `wait_for_signal`.
+#[test]
+fn test_wait_for_signal() {
+    block_signals(&[SIGHUP]).expect("Blocking signals failed.");
+    // SAFETY: Sending a signal to the current thread has no other effect, as
+    // it is blocked.
+    unsafe {
+        libc::pthread_kill(libc::pthread_self(), SIGHUP);
+    }
+    assert_eq!(wait_for_signal(&[SIGHUP]).expect("Waiting failed."), SIGHUP);
+}
@@ -0,0 +1,180 @@
+//! This module provides utilities for writing integration tests of programs
+//! that embed knoll, without needing to copy knoll's own test harness.  The
+//! knoll command can be run with the given arguments and input, capturing
+//! its output, against either the real displays or the fake displays.
+//!
+//! As knoll checks whether its input and error output are terminals, they
+//! must be files rather than in-memory buffers, so each `Harness` owns a
+//! temporary directory for them.  It can also hold other files a test
+//! needs, such as configurations or PID files, and is removed when the
+//! `Harness` is dropped.
+use coverage_helper::test;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+use crate::displays::DisplayState;
+use crate::fake_displays::{FakeDisplayState, Scenario};
+use crate::knoll::{run, Error};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The outcome of running the knoll command.
+#[derive(Debug)]
+pub struct Output {
+    /// The error the command failed with, if any.
+    pub error: Option<Error>,
+    /// Everything written to stdout.
+    pub stdout: String,
+    /// Everything written to stderr.
+    pub stderr: String,
+}
+
+/// A temporary directory in which to run the knoll command.
+#[derive(Debug)]
+pub struct Harness {
+    dir: TempDir,
+}
+
+impl Harness {
+    /// Create a harness with a new, empty, temporary directory.
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Harness {
+            dir: tempfile::tempdir()?,
+        })
+    }
+
+    /// The temporary directory.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The path of the named file in the temporary directory.  The file
+    /// need not exist.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Write the named file in the temporary directory, returning its path.
+    pub fn write_file(&self, name: &str, contents: &str) -> std::io::Result<PathBuf> {
+        let path = self.path(name);
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Read the named file in the temporary directory.
+    pub fn read_file(&self, name: &str) -> std::io::Result<String> {
+        std::fs::read_to_string(self.path(name))
+    }
+
+    /// Run the knoll command with the given arguments, including the
+    /// command name, against the given display state.  If there is input,
+    /// it is provided on stdin, otherwise the actual stdin is used.
+    pub fn run<DS: DisplayState>(
+        &self,
+        args: &[&str],
+        input: Option<&str>,
+    ) -> std::io::Result<Output> {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+        let err_path = self.path("stderr");
+        let file_err = std::fs::File::create(&err_path)?;
+        let mut vec_out: Vec<u8> = Vec::new();
+        let result = match input {
+            Some(input) => {
+                let in_path = self.write_file("stdin", input)?;
+                run::<DS, std::fs::File, &mut Vec<u8>, std::fs::File>(
+                    &args,
+                    std::fs::File::open(in_path)?,
+                    &mut vec_out,
+                    file_err,
+                )
+            }
+            None => run::<DS, std::io::Stdin, &mut Vec<u8>, std::fs::File>(
+                &args,
+                std::io::stdin(),
+                &mut vec_out,
+                file_err,
+            ),
+        };
+
+        let mut stderr = String::new();
+        std::fs::File::open(&err_path)?.read_to_string(&mut stderr)?;
+        Ok(Output {
+            error: result.err(),
+            stdout: String::from_utf8_lossy(&vec_out).into_owned(),
+            stderr,
+        })
+    }
+}
+
+/// Run the knoll command with the given arguments and optional input
+/// against the given display state, in a fresh `Harness`.
+pub fn run_knoll<DS: DisplayState>(args: &[&str], input: Option<&str>) -> std::io::Result<Output> {
+    Harness::new()?.run::<DS>(args, input)
+}
+
+/// Run the knoll command with the given arguments and optional input
+/// against the fake displays as they currently are.
+pub fn run_knoll_fake(args: &[&str], input: Option<&str>) -> std::io::Result<Output> {
+    run_knoll::<FakeDisplayState>(args, input)
+}
+
+/// Replace the fake displays with those described by the scenario, and then
+/// run the knoll command against them.  As the fake displays are shared by
+/// the whole process, tests using different scenarios must not run
+/// concurrently.
+pub fn run_knoll_scenario(
+    scenario: &Scenario,
+    args: &[&str],
+    input: Option<&str>,
+) -> std::io::Result<Output> {
+    FakeDisplayState::set_scenario(scenario)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    run_knoll_fake(args, input)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Test running the knoll command in a harness.
+#[test]
+fn test_harness() {
+    let harness = Harness::new().expect("Creating a harness should not fail");
+    let config = harness
+        .write_file("config.json", "[]")
+        .expect("Writing should not fail");
+    assert_eq!(harness.read_file("config.json").unwrap(), "[]");
+
+    let output = harness
+        .run::<FakeDisplayState>(
+            &[
+                "knoll",
+                "--input",
+                config.to_str().unwrap(),
+                "--format",
+                "json",
+            ],
+            None,
+        )
+        .expect("Running should not fail");
+    assert!(
+        output.error.is_none(),
+        "Unexpected error: {:?}",
+        output.error
+    );
+    assert!(output.stdout.starts_with("[\n  [\n"));
+
+    let output = run_knoll_fake(&["knoll", "list"], Some("")).expect("Running should not fail");
+    assert!(
+        output.error.is_none(),
+        "Unexpected error: {:?}",
+        output.error
+    );
+
+    let output = run_knoll_fake(&["knoll", "--bogus"], Some("")).expect("Running should not fail");
+    assert!(matches!(output.error, Some(Error::Argument(_))));
+    assert_eq!(output.stdout, "");
+
+    let dir = harness.dir().to_owned();
+    drop(harness);
+    assert!(!dir.exists());
+}
@@ -4,13 +4,17 @@
 //! and is non-empty.
 use coverage_helper::test;
 use std::collections::hash_map::Entry;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
 
 use crate::config::*;
+use crate::displays::{DisplayKind, Point, Rotation};
+#[cfg(test)]
+use crate::displays::{Scale, Underscan};
+use crate::expression::Property;
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -22,10 +26,51 @@ pub enum Error {
     /// appear multiple times.
     DuplicateDisplays(HashSet<String>),
     /// Reported with a there are multiple configuration groups that contain
-    /// the exact same set of displays.
+    /// the exact same set of displays and conditions.
     DuplicateGroups(HashSet<ValidConfigGroup>),
     /// Reported when a configuration group contains no displays.
     EmptyGroup,
+    /// Reported when a configuration requests an underscan outside of the
+    /// range `0.0` to `1.0`.  The arguments are the UUID and the requested
+    /// underscan.
+    InvalidUnderscan(String, f64),
+    /// Reported when a configuration requests a scale that is not a positive
+    /// number.  The arguments are the UUID and the requested scale.
+    InvalidScale(String, f64),
+    /// Reported when a UUID prefix matches more than one attached display.
+    /// The arguments are the prefix and the UUIDs of the displays.
+    AmbiguousUuidPrefix(String, Vec<String>),
+    /// Reported when an expression in the origin of a display refers to a
+    /// display that is not enabled by the same configuration group.  The
+    /// arguments are the UUID and the reference.
+    InvalidReference(String, String),
+    /// Reported when the origins of displays refer to one another, so that
+    /// they cannot be computed.  The argument is the UUIDs of the displays.
+    CyclicReference(Vec<String>),
+    /// Reported when validation found more than one problem.  The argument
+    /// contains each of the problems found, none of which will themselves be
+    /// `Multiple`.
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Helper to combine the errors found during validation into a single
+    /// result.  Nested `Multiple` errors are flattened, and a lone error is
+    /// reported as is.
+    fn combine(errors: Vec<Error>) -> Result<(), Error> {
+        let mut flattened: Vec<Error> = errors
+            .into_iter()
+            .flat_map(|e| match e {
+                Error::Multiple(errors) => errors,
+                e => vec![e],
+            })
+            .collect();
+        match flattened.len() {
+            0 => Ok(()),
+            1 => Err(flattened.pop().unwrap()),
+            _ => Err(Error::Multiple(flattened)),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -56,11 +101,45 @@ impl std::fmt::Display for Error {
                 write!(
                     f,
                     "There are multiple configuration groups with the same \
-                    set of displays: {}",
+                    set of displays and conditions: {}",
                     dups
                 )
             }
             Error::EmptyGroup => write!(f, "A configuration group is empty."),
+            Error::InvalidUnderscan(uuid, underscan) => write!(
+                f,
+                "The underscan {} for display {} is not between 0.0 and 1.0.",
+                underscan, uuid
+            ),
+            Error::InvalidScale(uuid, scale) => write!(
+                f,
+                "The scale {} for display {} is not a positive number.",
+                scale, uuid
+            ),
+            Error::AmbiguousUuidPrefix(prefix, uuids) => write!(
+                f,
+                "The UUID prefix {} matches more than one attached display: {}.",
+                prefix,
+                uuids.join(", ")
+            ),
+            Error::InvalidReference(uuid, reference) => write!(
+                f,
+                "The origin of display {} refers to {}, which is not a display \
+                enabled by the same configuration group.",
+                uuid, reference
+            ),
+            Error::CyclicReference(uuids) => write!(
+                f,
+                "The origins of displays {} refer to one another.",
+                uuids.join(", ")
+            ),
+            Error::Multiple(errors) => {
+                write!(f, "The configuration contains {} problems:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  * {}", error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -73,24 +152,97 @@ impl std::error::Error for Error {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Problems with a configuration that do not prevent it from being used,
+/// but likely indicate a mistake.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Warning {
+    /// Reported when a configuration has a UUID that is neither in the form
+    /// used for displays nor a prefix of one, so it can never match any
+    /// display.  The argument is the UUID.
+    MalformedUuid(String),
+    /// Reported when a configuration requests a frequency of zero, which
+    /// will only match displays that do not report a refresh frequency.  The
+    /// argument is the UUID.
+    ZeroFrequency(String),
+    /// Reported when two displays in the same configuration group would
+    /// overlap.  The arguments are the UUIDs of the displays.
+    OverlappingDisplays(String, String),
+    /// Reported when a configuration that is skipped also requests settings,
+    /// which will be ignored.  The argument is the UUID.
+    SkippedSettings(String),
+    /// Reported when a configuration that gives a mode id also requests
+    /// other display mode settings, which will be ignored.  The argument is
+    /// the UUID.
+    IgnoredModeSettings(String),
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::MalformedUuid(uuid) => write!(
+                f,
+                "The UUID {} is not made up of at most 32 hexadecimal digits, so will never match a display.",
+                uuid
+            ),
+            Warning::ZeroFrequency(uuid) => write!(
+                f,
+                "The configuration for display {} requests a frequency of 0, \
+                which only matches displays that do not report a frequency.",
+                uuid
+            ),
+            Warning::OverlappingDisplays(first, second) => write!(
+                f,
+                "The displays {} and {} overlap in the same configuration group.",
+                first, second
+            ),
+            Warning::SkippedSettings(uuid) => write!(
+                f,
+                "The configuration for display {} is skipped, so its other settings \
+                will be ignored.",
+                uuid
+            ),
+            Warning::IgnoredModeSettings(uuid) => write!(
+                f,
+                "The configuration for display {} selects a display mode by its mode id, \
+                so its other display mode settings will be ignored.",
+                uuid
+            ),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 #[derive(Debug, Clone)]
 pub struct ValidConfigGroup {
+    pub name: Option<String>,
+    pub description: Option<String>,
     pub uuids: BTreeSet<String>,
     pub configs: HashMap<String, Config>,
+    pub hooks: Option<Hooks>,
+    pub when: Option<Conditions>,
+    pub wait: Option<WaitPeriod>,
 }
 
 impl Hash for ValidConfigGroup {
-    /// For the purposes of hashing, we hash the individual UUIDs.
+    /// For the purposes of hashing, we hash the individual UUIDs, the
+    /// conditions, and the required kinds of display.
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.uuids.iter().for_each(|uuid| uuid.hash(state));
+        self.conditions().hash(state);
+        self.kinds().hash(state);
     }
 }
 
 impl PartialEq for ValidConfigGroup {
     /// Equality is defined by the configuration group having the same set
-    /// of UUIDS
+    /// of UUIDS, the same conditions, and the same required kinds of
+    /// display.  A group with no conditions is equal to one with an empty
+    /// set of conditions.
     fn eq(&self, other: &Self) -> bool {
         self.uuids == other.uuids
+            && self.conditions() == other.conditions()
+            && self.kinds() == other.kinds()
     }
 }
 
@@ -100,6 +252,8 @@ impl PartialOrd for ValidConfigGroup {
     /// Ordering is by reverse inclusion.  We consider sets that contain
     /// more elements, or are more "precise", to be "smaller".  For
     /// incomparable configurations, the ordering is based upon size.
+    /// Configuration groups with the same set of displays are ordered so
+    /// that those with more requirements come first.
     ///
     /// Despite this being ostensibly a "partial ordering", because
     /// Rust's sort only uses `partial_cmp` rather than `cmp`, this has
@@ -111,7 +265,7 @@ impl PartialOrd for ValidConfigGroup {
                 self.uuids.is_superset(&other.uuids),
                 other.uuids.is_superset(&self.uuids),
             ) {
-                (true, true) => Equal,
+                (true, true) => other.requirements().cmp(&self.requirements()),
                 (true, false) => Less,
                 (false, true) => Greater,
 
@@ -137,14 +291,60 @@ impl Ord for ValidConfigGroup {
 }
 
 impl ValidConfigGroup {
+    /// The conditions under which the configuration group applies.
+    pub fn conditions(&self) -> Conditions {
+        self.when.clone().unwrap_or_default()
+    }
+
+    /// The kinds of display required by the configurations in the group,
+    /// keyed by UUID.
+    pub fn kinds(&self) -> BTreeMap<&str, DisplayKind> {
+        self.configs
+            .iter()
+            .filter_map(|(uuid, config)| config.kind.map(|kind| (uuid.as_str(), kind)))
+            .collect()
+    }
+
+    /// The number of conditions and required kinds of display.  Of groups
+    /// for the same displays, those with more requirements are considered
+    /// more precise.
+    pub fn requirements(&self) -> usize {
+        self.conditions().len() + self.kinds().len()
+    }
+
+    /// Convert back into a configuration group, such as for reporting it.
+    pub fn to_config_group(&self) -> ConfigGroup {
+        ConfigGroup {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            configs: self.configs.values().cloned().collect(),
+            hooks: self.hooks.clone(),
+            when: self.when.clone(),
+            wait: self.wait,
+        }
+    }
+
     /// Helper to convert a configuration group into a map.  This enforces
-    /// that no config in the group has the same UUID and that the group
-    /// is non-empty.
+    /// that no config in the group has the same UUID, that the group
+    /// is non-empty, that any requested scale is positive, and that any
+    /// requested underscan is in range.  All of
+    /// the problems found are reported together.
     pub fn from(cg: ConfigGroup) -> Result<Self, Error> {
+        let mut errors = Vec::new();
         let mut duplicates = HashSet::new();
         let mut configs = HashMap::new();
 
         for config in cg.configs {
+            if let Some(scale) = config.scale {
+                if !scale.is_valid() {
+                    errors.push(Error::InvalidScale(config.uuid.clone(), scale.0));
+                }
+            }
+            if let Some(underscan) = config.underscan {
+                if !underscan.is_valid() {
+                    errors.push(Error::InvalidUnderscan(config.uuid.clone(), underscan.0));
+                }
+            }
             let uuid = &config.uuid;
             if let Entry::Vacant(e) = configs.entry(uuid.clone()) {
                 e.insert(config);
@@ -155,26 +355,221 @@ impl ValidConfigGroup {
 
         // If there are any duplicate displays report the error.
         if !duplicates.is_empty() {
-            Err(Error::DuplicateDisplays(duplicates))
-            // A group must have at least one Config.
-        } else if configs.is_empty() {
-            Err(Error::EmptyGroup)
-        } else {
-            Ok(ValidConfigGroup {
-                uuids: configs.keys().cloned().collect(),
-                configs,
-            })
+            errors.push(Error::DuplicateDisplays(duplicates));
+        }
+        // A group must have at least one Config.
+        if configs.is_empty() {
+            errors.push(Error::EmptyGroup);
+        }
+        // Origins may only refer to other displays enabled by the group,
+        // and must not depend upon themselves.
+        for (uuid, config) in &configs {
+            for reference in config.origin.iter().flat_map(Origin::references) {
+                if configs
+                    .get(&reference.display)
+                    .is_none_or(|config| config.enabled == Some(false))
+                {
+                    errors.push(Error::InvalidReference(uuid.clone(), reference.to_string()));
+                }
+            }
+        }
+        if let Some(cycle) = reference_cycle(&configs) {
+            errors.push(Error::CyclicReference(cycle));
+        }
+        Error::combine(errors)?;
+
+        Ok(ValidConfigGroup {
+            name: cg.name,
+            description: cg.description,
+            uuids: configs.keys().cloned().collect(),
+            configs,
+            hooks: cg.hooks,
+            // An empty set of conditions is the same as none at all.
+            when: cg.when.filter(|when| !when.is_empty()),
+            wait: cg.wait,
+        })
+    }
+
+    /// Replace any UUID prefixes in the configuration group with the full
+    /// UUIDs of the attached displays they refer to.  Returns `None` if any
+    /// of the displays in the group are not attached.
+    pub fn resolve<D>(&self, displays: &BTreeMap<String, D>) -> Result<Option<Self>, Error> {
+        // Check that every display is attached before reporting any
+        // ambiguous prefixes, as the group cannot be used either way.
+        let mut resolved = Vec::new();
+        for uuid in &self.uuids {
+            match resolve_uuid(uuid, displays) {
+                Ok(None) => return Ok(None),
+                result => resolved.push((uuid, result)),
+            }
+        }
+
+        let mut full_uuids = HashMap::new();
+        for (uuid, result) in resolved {
+            // Okay to unwrap, as unattached displays were handled above.
+            let full = result?.unwrap();
+            // Two prefixes may turn out to refer to the same display.
+            if full_uuids.values().any(|other| *other == full) {
+                return Err(Error::DuplicateDisplays(HashSet::from([full])));
+            }
+            full_uuids.insert(uuid, full);
         }
+
+        let mut configs = HashMap::new();
+        for (uuid, full) in &full_uuids {
+            let mut config = Config {
+                uuid: full.clone(),
+                ..self.configs[*uuid].clone()
+            };
+            // Validation ensures references only name displays in the group.
+            for reference in config.origin.iter_mut().flat_map(Origin::references_mut) {
+                reference.display = full_uuids[&reference.display].clone();
+            }
+            configs.insert(full.clone(), config);
+        }
+        Ok(Some(ValidConfigGroup {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            uuids: configs.keys().cloned().collect(),
+            configs,
+            hooks: self.hooks.clone(),
+            when: self.when.clone(),
+            wait: self.wait,
+        }))
     }
 }
 
+/// Helper to find displays whose origins refer to one another, returning
+/// their sorted UUIDs.  References to extents cannot form a cycle, as
+/// extents only depend upon the selected display mode.
+fn reference_cycle(configs: &HashMap<String, Config>) -> Option<Vec<String>> {
+    fn visit<'a>(
+        uuid: &'a str,
+        configs: &'a HashMap<String, Config>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(index) = path.iter().position(|other| *other == uuid) {
+            let mut cycle: Vec<String> = path[index..].iter().map(|u| u.to_string()).collect();
+            cycle.sort();
+            return Some(cycle);
+        }
+        if done.contains(uuid) {
+            return None;
+        }
+        path.push(uuid);
+        let references = configs
+            .get(uuid)
+            .and_then(|config| config.origin.as_ref())
+            .into_iter()
+            .flat_map(Origin::references)
+            .filter(|reference| reference.property == Property::Origin);
+        for reference in references {
+            if let Some(cycle) = visit(&reference.display, configs, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(uuid);
+        None
+    }
+
+    let mut uuids: Vec<&String> = configs.keys().collect();
+    uuids.sort();
+    let mut done = HashSet::new();
+    uuids
+        .into_iter()
+        .find_map(|uuid| visit(uuid, configs, &mut Vec::new(), &mut done))
+}
+
+/// Find the attached display a UUID refers to.  Much like abbreviated git
+/// commit hashes, any prefix of a display's UUID that is not shared with
+/// another attached display may be used in its place.  Returns `None` if no
+/// attached display matches.
+pub fn resolve_uuid<D>(
+    uuid: &str,
+    displays: &BTreeMap<String, D>,
+) -> Result<Option<String>, Error> {
+    if displays.contains_key(uuid) {
+        return Ok(Some(uuid.to_owned()));
+    }
+    // An empty prefix would match every display.
+    if uuid.is_empty() {
+        return Ok(None);
+    }
+    let matching: Vec<String> = displays
+        .keys()
+        .filter(|key| key.starts_with(uuid))
+        .cloned()
+        .collect();
+    match matching.as_slice() {
+        [] => Ok(None),
+        [full] => Ok(Some(full.clone())),
+        _ => Err(Error::AmbiguousUuidPrefix(uuid.to_owned(), matching)),
+    }
+}
+
+/// Helper to compute the Levenshtein edit distance between two strings,
+/// ignoring case.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != cb);
+            current.push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Suggest the candidate closest to a UUID, or alias, that did not match
+/// anything, to catch typos and copy-paste truncations.  Candidates must be
+/// within an edit distance of a third of their length to be suggested, and
+/// ties are broken by taking the first.
+pub fn suggest_uuid<'a>(
+    uuid: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> Option<String> {
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(uuid, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len().max(uuid.len()) / 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Find the attached displays that are ignored, given their UUIDs or
+/// prefixes of them, as for `resolve_uuid`.  Ignored displays that are not
+/// attached are skipped.
+pub fn resolve_ignored<D>(
+    ignored: &[String],
+    displays: &BTreeMap<String, D>,
+) -> Result<BTreeSet<String>, Error> {
+    let mut resolved = BTreeSet::new();
+    for uuid in ignored {
+        resolved.extend(resolve_uuid(uuid, displays)?);
+    }
+    Ok(resolved)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 /// Check that `ValidConfigGroup::from` correctly reports an error for an
 /// empty group.
 #[test]
 fn test_valid_config_from_empty() {
-    match ValidConfigGroup::from(ConfigGroup { configs: vec![] }) {
+    match ValidConfigGroup::from(ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![],
+        hooks: None,
+        when: None,
+        wait: None,
+    }) {
         Err(Error::EmptyGroup) => { /* Correctly detected error, so no-op */ }
         Err(_) => panic!("Unexpected error in validation."),
         Ok(_) => panic!("Failed to detect empty configuration."),
@@ -186,29 +581,54 @@ fn test_valid_config_from_empty() {
 #[test]
 fn test_valid_config_from_duplicate() {
     match ValidConfigGroup::from(ConfigGroup {
+        name: None,
+        description: None,
         configs: vec![
             Config {
                 uuid: "abcdef1234".to_owned(),
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
             Config {
                 uuid: "abcdef1234".to_owned(),
 
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
         ],
+        hooks: None,
+        when: None,
+        wait: None,
     }) {
         Err(Error::DuplicateDisplays(uuids)) => {
             assert_eq!(uuids.len(), 1);
@@ -219,49 +639,94 @@ fn test_valid_config_from_duplicate() {
     }
 
     match ValidConfigGroup::from(ConfigGroup {
+        name: None,
+        description: None,
         configs: vec![
             Config {
                 uuid: "abcdef1234".to_owned(),
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
             Config {
                 uuid: "abcdef1234".to_owned(),
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
             Config {
                 uuid: "foobarbaz".to_owned(),
 
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
             Config {
                 uuid: "foobarbaz".to_owned(),
                 enabled: Some(false),
+                skip: None,
                 origin: None,
                 extents: None,
+                units: None,
+                aspect_ratio: None,
                 scaled: None,
+                scale: None,
                 frequency: None,
                 color_depth: None,
+                mode_id: None,
                 rotation: None,
+                closest: None,
+                underscan: None,
+                mirroring: None,
+                kind: None,
+                display_id: None,
             },
         ],
+        hooks: None,
+        when: None,
+        wait: None,
     }) {
         Err(Error::DuplicateDisplays(uuids)) => {
             assert_eq!(uuids.len(), 2);
@@ -276,18 +741,26 @@ fn test_valid_config_from_duplicate() {
 ////////////////////////////////////////////////////////////////////////////////
 
 /// Helper to convert configuration groups into a vector of valid
-/// configuration groups.  This enforces that no configuration group applies
-/// to the same set of UUIDs.  The result will also be sorted from most
-/// specific configuration to least specific.
+/// configuration groups.  This enforces that no two configuration groups
+/// apply to the same set of UUIDs under the same conditions.  The result will also be sorted from most
+/// specific configuration to least specific.  All of the problems found,
+/// across every configuration group, are reported together.
 pub fn validate_config_groups(cgs: ConfigGroups) -> Result<Vec<ValidConfigGroup>, Error> {
     // We might be tempted to use a BTreeSet here. However, because
     // incomparable configuration groups with the same number of
     // configurations will be treated as equal, we have to rely on hashing
     // which will correctly distinguish them.
+    let mut errors = Vec::new();
     let mut duplicate_groups = HashSet::new();
     let mut valid_groups = HashSet::new();
     for config_group in cgs.groups {
-        let valid_group = ValidConfigGroup::from(config_group)?;
+        let valid_group = match ValidConfigGroup::from(config_group) {
+            Ok(valid_group) => valid_group,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
         if valid_groups.contains(&valid_group) {
             duplicate_groups.insert(valid_group);
         } else {
@@ -297,8 +770,9 @@ pub fn validate_config_groups(cgs: ConfigGroups) -> Result<Vec<ValidConfigGroup>
 
     // If there are any duplicates report them.
     if !duplicate_groups.is_empty() {
-        return Err(Error::DuplicateGroups(duplicate_groups));
+        errors.push(Error::DuplicateGroups(duplicate_groups));
     }
+    Error::combine(errors)?;
 
     // Order the groups by the most precise first.
     let mut vec_groups: Vec<ValidConfigGroup> = valid_groups.into_iter().collect();
@@ -306,38 +780,219 @@ pub fn validate_config_groups(cgs: ConfigGroups) -> Result<Vec<ValidConfigGroup>
     Ok(vec_groups)
 }
 
+/// Helper to compute the rectangle, as an origin and extents, that a
+/// configuration would occupy if it specifies both an origin and explicit
+/// extents and does not disable or skip the display.  Origins given by
+/// expressions, and geometry given in backing pixels, are not known in
+/// points until display modes are selected.
+fn config_bounds(config: &Config) -> Option<(Point, Point)> {
+    if config.enabled == Some(false)
+        || config.skip == Some(true)
+        || config.units == Some(Units::Pixels)
+    {
+        return None;
+    }
+    let origin = config.origin.as_ref()?.exact()?;
+    let extents = match &config.extents {
+        Some(Resolution::Extents(extents)) => extents,
+        _ => return None,
+    };
+    // Extents are always given in landscape orientation, so account for
+    // any rotation.
+    let extents = match config.rotation {
+        Some(Rotation::Ninety) | Some(Rotation::TwoSeventy) => Point {
+            x: extents.y,
+            y: extents.x,
+        },
+        _ => extents.clone(),
+    };
+    Some((origin, extents))
+}
+
+/// Check validated configuration groups for problems that do not prevent
+/// them from being used, but likely indicate a mistake.  The warnings are
+/// returned in sorted order without duplicates.
+pub fn check_config_groups(groups: &[ValidConfigGroup]) -> Vec<Warning> {
+    let mut warnings = BTreeSet::new();
+    for group in groups {
+        for (uuid, config) in &group.configs {
+            // Shorter UUIDs may be prefixes of a display's UUID.
+            if uuid.is_empty() || uuid.len() > 32 || !uuid.chars().all(|c| c.is_ascii_hexdigit()) {
+                warnings.insert(Warning::MalformedUuid(uuid.clone()));
+            }
+            if config.frequency == Some(Frequency::Exact(0)) {
+                warnings.insert(Warning::ZeroFrequency(uuid.clone()));
+            }
+            // Only what identifies the display may accompany `skip`.
+            let skipped = Config {
+                uuid: config.uuid.clone(),
+                skip: Some(true),
+                kind: config.kind,
+                display_id: config.display_id,
+                ..Config::default()
+            };
+            if config.skip == Some(true) && *config != skipped {
+                warnings.insert(Warning::SkippedSettings(uuid.clone()));
+            }
+            let mode_settings = config.extents.is_some()
+                || config.aspect_ratio.is_some()
+                || config.scaled.is_some()
+                || config.scale.is_some()
+                || config.frequency.is_some()
+                || config.color_depth.is_some()
+                || config.closest.is_some();
+            if config.mode_id.is_some() && mode_settings {
+                warnings.insert(Warning::IgnoredModeSettings(uuid.clone()));
+            }
+        }
+
+        let bounds: Vec<(&String, (Point, Point))> = group
+            .uuids
+            .iter()
+            .filter_map(|uuid| Some((uuid, config_bounds(&group.configs[uuid])?)))
+            .collect();
+        for (i, (first, (first_origin, first_extents))) in bounds.iter().enumerate() {
+            for (second, (second_origin, second_extents)) in &bounds[i + 1..] {
+                if first_origin.x < second_origin.x + second_extents.x
+                    && second_origin.x < first_origin.x + first_extents.x
+                    && first_origin.y < second_origin.y + second_extents.y
+                    && second_origin.y < first_origin.y + first_extents.y
+                {
+                    warnings.insert(Warning::OverlappingDisplays(
+                        (*first).clone(),
+                        (*second).clone(),
+                    ));
+                }
+            }
+        }
+    }
+    warnings.into_iter().collect()
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Check that `ValidConfigGroup::from` correctly reports an error for an
+/// underscan that is out of range.
+#[test]
+fn test_valid_config_from_invalid_underscan() {
+    match ValidConfigGroup::from(ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            enabled: None,
+            skip: None,
+            origin: None,
+            extents: None,
+            units: None,
+            aspect_ratio: None,
+            scaled: None,
+            scale: None,
+            frequency: None,
+            color_depth: None,
+            mode_id: None,
+            rotation: None,
+            closest: None,
+            underscan: Some(Underscan(1.5)),
+            mirroring: None,
+            kind: None,
+            display_id: None,
+        }],
+        hooks: None,
+        when: None,
+        wait: None,
+    }) {
+        Err(Error::InvalidUnderscan(uuid, _)) => assert_eq!(uuid, "abcdef1234"),
+        Err(_) => panic!("Unexpected error in validation."),
+        Ok(_) => panic!("Failed to detect invalid underscan."),
+    }
+}
+
+/// Check that `ValidConfigGroup::from` reports an error for scales that are
+/// not positive numbers.
+#[test]
+fn test_valid_config_from_invalid_scale() {
+    for scale in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+        match ValidConfigGroup::from(ConfigGroup {
+            name: None,
+            description: None,
+            configs: vec![Config {
+                uuid: "abcdef1234".to_owned(),
+                scale: Some(Scale(scale)),
+                ..Config::default()
+            }],
+            hooks: None,
+            when: None,
+            wait: None,
+        }) {
+            Err(Error::InvalidScale(uuid, _)) => assert_eq!(uuid, "abcdef1234"),
+            Err(_) => panic!("Unexpected error in validation."),
+            Ok(_) => panic!("Failed to detect invalid scale {}.", scale),
+        }
+    }
+}
+
 /// Test that `validate_config_groups` detects duplicate configuration groups
 #[test]
 fn test_config_validation_duplicates() {
     match validate_config_groups(ConfigGroups {
         groups: vec![
             ConfigGroup {
+                name: None,
+                description: None,
                 configs: vec![Config {
                     uuid: "abcdef1234".to_owned(),
                     enabled: Some(false),
+                    skip: None,
                     origin: None,
                     extents: None,
+                    units: None,
+                    aspect_ratio: None,
                     scaled: None,
+                    scale: None,
                     frequency: None,
                     color_depth: None,
+                    mode_id: None,
                     rotation: None,
+                    closest: None,
+                    underscan: None,
+                    mirroring: None,
+                    kind: None,
+                    display_id: None,
                 }],
+                hooks: None,
+                when: None,
+                wait: None,
             },
             ConfigGroup {
+                name: None,
+                description: None,
                 configs: vec![Config {
                     uuid: "abcdef1234".to_owned(),
                     enabled: Some(false),
+                    skip: None,
                     origin: None,
                     extents: None,
+                    units: None,
+                    aspect_ratio: None,
                     scaled: None,
+                    scale: None,
                     frequency: None,
                     color_depth: None,
+                    mode_id: None,
                     rotation: None,
+                    closest: None,
+                    underscan: None,
+                    mirroring: None,
+                    kind: None,
+                    display_id: None,
                 }],
+                hooks: None,
+                when: None,
+                wait: None,
             },
         ],
+        ignored: vec![],
     }) {
         Err(Error::DuplicateGroups(groups)) => {
             assert_eq!(groups.len(), 1);
@@ -352,54 +1007,105 @@ fn test_config_validation_duplicates() {
     match validate_config_groups(ConfigGroups {
         groups: vec![
             ConfigGroup {
+                name: None,
+                description: None,
                 configs: vec![
                     Config {
                         uuid: "abcdef1234".to_owned(),
                         enabled: Some(false),
+                        skip: None,
                         origin: None,
                         extents: None,
+                        units: None,
+                        aspect_ratio: None,
                         scaled: None,
+                        scale: None,
                         frequency: None,
                         color_depth: None,
+                        mode_id: None,
                         rotation: None,
+                        closest: None,
+                        underscan: None,
+                        mirroring: None,
+                        kind: None,
+                        display_id: None,
                     },
                     Config {
                         uuid: "foobarbaz".to_owned(),
                         enabled: Some(false),
+                        skip: None,
                         origin: None,
                         extents: None,
+                        units: None,
+                        aspect_ratio: None,
                         scaled: None,
+                        scale: None,
                         frequency: None,
                         color_depth: None,
+                        mode_id: None,
                         rotation: None,
+                        closest: None,
+                        underscan: None,
+                        mirroring: None,
+                        kind: None,
+                        display_id: None,
                     },
                 ],
+                hooks: None,
+                when: None,
+                wait: None,
             },
             ConfigGroup {
+                name: None,
+                description: None,
                 configs: vec![
                     Config {
                         uuid: "foobarbaz".to_owned(),
                         enabled: Some(false),
+                        skip: None,
                         origin: None,
                         extents: None,
+                        units: None,
+                        aspect_ratio: None,
                         scaled: None,
+                        scale: None,
                         frequency: None,
                         color_depth: None,
+                        mode_id: None,
                         rotation: None,
+                        closest: None,
+                        underscan: None,
+                        mirroring: None,
+                        kind: None,
+                        display_id: None,
                     },
                     Config {
                         uuid: "abcdef1234".to_owned(),
                         enabled: Some(false),
+                        skip: None,
                         origin: None,
                         extents: None,
+                        units: None,
+                        aspect_ratio: None,
                         scaled: None,
+                        scale: None,
                         frequency: None,
                         color_depth: None,
+                        mode_id: None,
                         rotation: None,
+                        closest: None,
+                        underscan: None,
+                        mirroring: None,
+                        kind: None,
+                        display_id: None,
                     },
                 ],
+                hooks: None,
+                when: None,
+                wait: None,
             },
         ],
+        ignored: vec![],
     }) {
         Err(Error::DuplicateGroups(groups)) => {
             assert_eq!(groups.len(), 1);
@@ -426,8 +1132,13 @@ fn test_config_group_sorting() {
 
     fn convert(vec: Vec<&str>) -> ValidConfigGroup {
         ValidConfigGroup {
+            name: None,
+            description: None,
             uuids: BTreeSet::from_iter(vec.into_iter().map(String::from)),
             configs: HashMap::new(),
+            hooks: None,
+            when: None,
+            wait: None,
         }
     }
 
@@ -439,3 +1150,351 @@ fn test_config_group_sorting() {
         r#"[{"a", "b", "c"}, {"a", "b"}, {"a", "c"}, {"a"}, {"b"}, {"c"}]"#
     );
 }
+
+/// Test that configuration groups for the same displays are permitted when
+/// their conditions differ, and that the group with conditions sorts first.
+#[test]
+fn test_config_validation_conditions() {
+    let group = |when: Option<Conditions>| ConfigGroup {
+        name: None,
+        description: None,
+        configs: vec![Config {
+            uuid: "abcdef1234".to_owned(),
+            ..Config::default()
+        }],
+        hooks: None,
+        when,
+        wait: None,
+    };
+    let closed = Conditions {
+        lid: Some(LidState::Closed),
+        ..Conditions::default()
+    };
+
+    let groups = validate_config_groups(ConfigGroups {
+        groups: vec![group(None), group(Some(closed.clone()))],
+        ignored: vec![],
+    })
+    .expect("Validation should not fail");
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].when, Some(closed));
+    assert_eq!(groups[1].when, None);
+
+    // An empty set of conditions is the same as no conditions.
+    match validate_config_groups(ConfigGroups {
+        groups: vec![group(None), group(Some(Conditions::default()))],
+        ignored: vec![],
+    }) {
+        Err(Error::DuplicateGroups(groups)) => assert_eq!(groups.len(), 1),
+        Err(_) => panic!("Unexpected error in validation."),
+        Ok(_) => panic!("Failed to detect duplicate configuration groups."),
+    }
+}
+
+/// Test that validation reports every problem found rather than just the
+/// first.
+#[test]
+fn test_config_validation_multiple() {
+    let config = |uuid: &str, underscan: Option<f64>| Config {
+        uuid: uuid.to_owned(),
+        underscan: underscan.map(Underscan),
+        ..Config::default()
+    };
+
+    match validate_config_groups(ConfigGroups {
+        groups: vec![
+            ConfigGroup {
+                name: None,
+                description: None,
+                configs: vec![
+                    config("abcdef1234", Some(1.5)),
+                    config("foobarbaz", Some(-0.5)),
+                    config("foobarbaz", None),
+                ],
+                hooks: None,
+                when: None,
+                wait: None,
+            },
+            ConfigGroup {
+                name: None,
+                description: None,
+                configs: vec![],
+                hooks: None,
+                when: None,
+                wait: None,
+            },
+            ConfigGroup {
+                name: None,
+                description: None,
+                configs: vec![config("abcdef1234", None)],
+                hooks: None,
+                when: None,
+                wait: None,
+            },
+            ConfigGroup {
+                name: None,
+                description: None,
+                configs: vec![config("abcdef1234", None)],
+                hooks: None,
+                when: None,
+                wait: None,
+            },
+        ],
+        ignored: vec![],
+    }) {
+        Err(Error::Multiple(errors)) => {
+            assert_eq!(errors.len(), 5);
+            let count = |f: fn(&Error) -> bool| errors.iter().filter(|e| f(e)).count();
+            assert_eq!(count(|e| matches!(e, Error::InvalidUnderscan(_, _))), 2);
+            assert_eq!(count(|e| matches!(e, Error::DuplicateDisplays(_))), 1);
+            assert_eq!(count(|e| matches!(e, Error::EmptyGroup)), 1);
+            assert_eq!(count(|e| matches!(e, Error::DuplicateGroups(_))), 1);
+        }
+        Err(e) => panic!("Unexpected error in validation: {}", e),
+        Ok(_) => panic!("Failed to detect invalid configuration."),
+    }
+}
+
+/// Test that `check_config_groups` reports likely mistakes.
+#[test]
+fn test_check_config_groups() {
+    let config = |uuid: &str, origin: (i64, i64), rotation: Rotation| Config {
+        uuid: uuid.to_owned(),
+        origin: Some(
+            Point {
+                x: origin.0,
+                y: origin.1,
+            }
+            .into(),
+        ),
+        extents: Some(Resolution::Extents(Point { x: 1920, y: 1080 })),
+        rotation: Some(rotation),
+        ..Config::default()
+    };
+    let first = "37d8832a2d6602cab9f78f30a301b230";
+    let second = "d4e58b1f0a2c4e5b8f3a9c7d6e1b2a30";
+
+    // Side by side displays do not overlap.
+    let groups = validate_config_groups(ConfigGroups {
+        groups: vec![ConfigGroup {
+            name: None,
+            description: None,
+            configs: vec![
+                config(first, (0, 0), Rotation::Zero),
+                config(second, (1920, 0), Rotation::Zero),
+            ],
+            hooks: None,
+            when: None,
+            wait: None,
+        }],
+        ignored: vec![],
+    })
+    .expect("Validation should not fail");
+    assert_eq!(check_config_groups(&groups), vec![]);
+
+    // But they do once the first display is rotated.
+    let groups = validate_config_groups(ConfigGroups {
+        groups: vec![ConfigGroup {
+            name: None,
+            description: None,
+            configs: vec![
+                config(first, (0, 0), Rotation::Ninety),
+                config(second, (1000, 0), Rotation::Zero),
+                Config {
+                    uuid: "office".to_owned(),
+                    frequency: Some(Frequency::Exact(0)),
+                    ..Config::default()
+                },
+                Config {
+                    skip: Some(true),
+                    ..config("a0b1c2d3", (0, 0), Rotation::Zero)
+                },
+                Config {
+                    uuid: "b1c2d3e4".to_owned(),
+                    skip: Some(true),
+                    kind: Some(DisplayKind::Physical),
+                    ..Config::default()
+                },
+                Config {
+                    uuid: "c2d3e4f5".to_owned(),
+                    mode_id: Some(3),
+                    frequency: Some(Frequency::Exact(60)),
+                    ..Config::default()
+                },
+                Config {
+                    uuid: "d3e4f5a6".to_owned(),
+                    mode_id: Some(3),
+                    ..Config::default()
+                },
+            ],
+            hooks: None,
+            when: None,
+            wait: None,
+        }],
+        ignored: vec![],
+    })
+    .expect("Validation should not fail");
+    assert_eq!(
+        check_config_groups(&groups),
+        vec![
+            Warning::MalformedUuid("office".to_owned()),
+            Warning::ZeroFrequency("office".to_owned()),
+            Warning::OverlappingDisplays(first.to_owned(), second.to_owned()),
+            Warning::SkippedSettings("a0b1c2d3".to_owned()),
+            Warning::IgnoredModeSettings("c2d3e4f5".to_owned()),
+        ]
+    );
+}
+
+/// Test that UUID prefixes are resolved against the attached displays.
+#[test]
+fn test_resolve_uuid_prefixes() {
+    let first = "37d8832a2d6602cab9f78f30a301b230";
+    let second = "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00";
+    let displays = BTreeMap::from([(first.to_owned(), ()), (second.to_owned(), ())]);
+
+    assert_eq!(
+        resolve_uuid(first, &displays).unwrap(),
+        Some(first.to_owned())
+    );
+    assert_eq!(
+        resolve_uuid("37d88", &displays).unwrap(),
+        Some(first.to_owned())
+    );
+    assert_eq!(
+        resolve_uuid("37d8f", &displays).unwrap(),
+        Some(second.to_owned())
+    );
+    assert_eq!(resolve_uuid("4", &displays).unwrap(), None);
+    assert_eq!(resolve_uuid("", &displays).unwrap(), None);
+    match resolve_uuid("37d8", &displays) {
+        Err(Error::AmbiguousUuidPrefix(prefix, uuids)) => {
+            assert_eq!(prefix, "37d8");
+            assert_eq!(uuids, vec![first.to_owned(), second.to_owned()]);
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    let group = |uuids: &[&str]| {
+        ValidConfigGroup::from(ConfigGroup {
+            name: None,
+            description: None,
+            configs: uuids
+                .iter()
+                .map(|uuid| Config {
+                    uuid: uuid.to_string(),
+                    origin: Some(Point { x: 0, y: 0 }.into()),
+                    ..Config::default()
+                })
+                .collect(),
+            hooks: None,
+            when: None,
+            wait: None,
+        })
+        .expect("Validation should not fail")
+    };
+
+    let resolved = group(&["37d88", "37d8f"])
+        .resolve(&displays)
+        .expect("Resolution should not fail")
+        .expect("The displays should be attached");
+    assert_eq!(resolved, group(&[first, second]));
+    assert_eq!(resolved.configs[first].uuid, first);
+    assert_eq!(
+        resolved.configs[first].origin,
+        Some(Point { x: 0, y: 0 }.into())
+    );
+
+    // Groups with an unattached display do not match, even if another
+    // prefix is ambiguous.
+    assert!(group(&["37d8", "4"]).resolve(&displays).unwrap().is_none());
+    assert!(matches!(
+        group(&["37d8"]).resolve(&displays),
+        Err(Error::AmbiguousUuidPrefix(_, _))
+    ));
+    // Different prefixes of the same display cannot be used together.
+    assert!(matches!(
+        group(&["37d88", first]).resolve(&displays),
+        Err(Error::DuplicateDisplays(_))
+    ));
+}
+
+/// Test the edit distance and the suggestions made from it.
+#[test]
+fn test_suggest_uuid() {
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("ABC", "abc"), 0);
+
+    let candidates = vec![
+        "37d8832a2d6602cab9f78f30a301b230".to_owned(),
+        "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00".to_owned(),
+        "office".to_owned(),
+    ];
+    // A UUID missing characters from the middle.
+    assert_eq!(
+        suggest_uuid("37d8832a2d6602cab9f78301b230", &candidates),
+        Some(candidates[0].clone())
+    );
+    // A mistyped alias.
+    assert_eq!(
+        suggest_uuid("ofice", &candidates),
+        Some("office".to_owned())
+    );
+    // Nothing is close enough.
+    assert_eq!(suggest_uuid("abcdef1234", &candidates), None);
+}
+
+/// Test that references in origins are checked, and follow UUID prefixes as
+/// they are resolved.
+#[test]
+fn test_config_validation_references() {
+    let group = |json: &str| {
+        let config_group: ConfigGroup = serde_json::from_str(json).expect("Failed to parse");
+        ValidConfigGroup::from(config_group)
+    };
+
+    let first = "37d8832a2d6602cab9f78f30a301b230";
+    let second = "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00";
+    let displays = BTreeMap::from([(first.to_owned(), ()), (second.to_owned(), ())]);
+    let resolved = group(
+        r#"[{"uuid": "37d88", "origin": [0, 0]},
+            {"uuid": "37d8f", "origin": ["${37d88.origin.x} + ${37d88.extents.x}", 0]}]"#,
+    )
+    .expect("Validation should not fail")
+    .resolve(&displays)
+    .expect("Resolution should not fail")
+    .expect("The displays should be attached");
+    let origin = resolved.configs[second].origin.as_ref().unwrap();
+    assert_eq!(
+        origin.to_string(),
+        format!("(${{{first}.origin.x}} + ${{{first}.extents.x}}, 0)")
+    );
+
+    // A display may refer to its own extents, but not its own origin.
+    assert!(group(r#"[{"uuid": "37d88", "origin": ["-${37d88.extents.x}", 0]}]"#).is_ok());
+    match group(r#"[{"uuid": "37d88", "origin": ["${37d88.origin.y}", 0]}]"#) {
+        Err(Error::CyclicReference(uuids)) => assert_eq!(uuids, vec!["37d88".to_owned()]),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    match group(
+        r#"[{"uuid": "37d88", "origin": ["${37d8f.origin.x}", 0]},
+            {"uuid": "37d8f", "origin": [0, "${37d88.origin.y}"]}]"#,
+    ) {
+        Err(Error::CyclicReference(uuids)) => {
+            assert_eq!(uuids, vec!["37d88".to_owned(), "37d8f".to_owned()])
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    // References must name displays enabled by the group.
+    match group(
+        r#"[{"uuid": "37d88", "origin": ["${4.extents.x}", "${37d8f.extents.y}"]},
+            {"uuid": "37d8f", "enabled": false}]"#,
+    ) {
+        Err(Error::Multiple(errors)) => assert!(errors
+            .iter()
+            .all(|e| matches!(e, Error::InvalidReference(uuid, _) if uuid == "37d88"))),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
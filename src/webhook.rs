@@ -0,0 +1,228 @@
+//! This module provides webhook notifications from the daemon.  After each
+//! reconfiguration the daemon may POST a JSON description of the outcome to a
+//! URL.  To avoid depending upon a full HTTP client, only plain `http` URLs
+//! are supported, using a minimal HTTP/1.1 implementation.
+use coverage_helper::test;
+use serde::Serialize;
+use std::fmt::Formatter;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::config::ConfigGroups;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The possible failures when sending a webhook notification.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The URL could not be parsed, or does not use the `http` scheme.
+    InvalidUrl(String),
+    /// The server responded with something other than success.  The
+    /// argument is the status line of the response.
+    Status(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(ie) => write!(f, "Webhook I/O error: {}", ie),
+            Error::Json(je) => write!(f, "Error serializing webhook notification: {}", je),
+            Error::InvalidUrl(url) => write!(
+                f,
+                "Invalid webhook URL {}, only http URLs are supported.",
+                url
+            ),
+            Error::Status(status) => write!(f, "Webhook request failed: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The body of a webhook notification.  Notifications are always sent as
+/// JSON, regardless of the configured serialization format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Notification {
+    /// A configuration group was applied.
+    Applied {
+        /// The UUIDs of the configuration group that was applied.
+        uuids: Vec<String>,
+        /// The resulting display state.
+        state: ConfigGroups,
+    },
+    /// Reconfiguration failed.
+    Failed {
+        /// The error message.
+        error: String,
+    },
+}
+
+/// How long to wait when connecting to, writing to, or reading from the server.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A parsed webhook URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    /// Parse a URL of the form `http://host[:port][/path]`.
+    pub fn parse(url: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidUrl(url.to_owned());
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Webhook {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+
+    /// POST the notification to the webhook, failing if the server does not
+    /// respond with a success status.
+    pub fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let body = serde_json::to_string(notification)?;
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::InvalidUrl(format!("http://{}:{}", self.host, self.port)))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\n\
+             Host: {}:{}\r\n\
+             User-Agent: knoll/{}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\
+             \r\n\
+             {}",
+            self.path,
+            self.host,
+            self.port,
+            clap::crate_version!(),
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+
+        // Only the status line of the response is of interest.
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        let status = status.trim_end().to_owned();
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(Error::Status(status)),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check parsing of valid and invalid webhook URLs.
+#[test]
+fn test_webhook_parse() {
+    assert_eq!(
+        Webhook::parse("http://example.com:8123/api/knoll").unwrap(),
+        Webhook {
+            host: "example.com".to_owned(),
+            port: 8123,
+            path: "/api/knoll".to_owned(),
+        }
+    );
+    assert_eq!(
+        Webhook::parse("http://example.com").unwrap(),
+        Webhook {
+            host: "example.com".to_owned(),
+            port: 80,
+            path: "/".to_owned(),
+        }
+    );
+    for url in [
+        "https://example.com",
+        "example.com",
+        "http://:80/",
+        "http://a:b/",
+    ] {
+        match Webhook::parse(url) {
+            Err(Error::InvalidUrl(_)) => { /* Correctly detected error, so no-op */ }
+            result => panic!("Unexpected result for {}: {:?}", url, result),
+        }
+    }
+}
+
+/// Check that a notification is delivered to a local server.
+#[test]
+fn test_webhook_send() {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Binding failed.");
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("Accepting failed.");
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        // Read until the body has arrived, as the client waits for the
+        // response before closing the connection.
+        while !String::from_utf8_lossy(&request).ends_with('}') {
+            let read = stream.read(&mut buffer).expect("Reading failed.");
+            request.extend_from_slice(&buffer[..read]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .expect("Writing failed.");
+        String::from_utf8(request).expect("Request should be valid UTF-8.")
+    });
+
+    let webhook = Webhook::parse(&format!("http://127.0.0.1:{}/hook", port)).unwrap();
+    let notification = Notification::Failed {
+        error: "failure".to_owned(),
+    };
+    webhook.send(&notification).expect("Sending failed.");
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+    assert!(request.ends_with("\r\n\r\n{\"failed\":{\"error\":\"failure\"}}"));
+}
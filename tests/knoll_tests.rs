@@ -3,10 +3,11 @@ extern crate knoll;
 
 use coverage_helper::test;
 use knoll::displays::DisplayState;
+use knoll::doctor::SystemFacts;
 use knoll::fake_displays::FakeDisplayState;
-use knoll::knoll::{run, Error};
+use knoll::knoll::{run_structured, Error};
 use knoll::real_displays::*;
-use std::io::{Read, Write};
+use knoll::testing;
 use tempfile::tempdir;
 
 #[cfg_attr(all(coverage_nightly, test), coverage(off))]
@@ -17,49 +18,8 @@ fn run_knoll<DS: DisplayState>(
     args: Vec<&str>,
     input: Option<String>,
 ) -> (Option<Error>, String, String) {
-    let mut vec_out: Vec<u8> = Vec::new();
-    let dir = tempdir().expect("Failed to create temporary directory.");
-    let err_path = dir.path().join("stderr");
-    let file_err =
-        std::fs::File::create(err_path.clone()).expect("Failed to open temporary file for stderr.");
-    let file_err_clone = file_err.try_clone().expect("Cloning stderr file failed");
-    let res = if let Some(input_str) = input {
-        let in_path = dir.path().join("stdin");
-        let mut file_in = std::fs::File::create(in_path.clone())
-            .expect("Failed to open temporary file for stdin.");
-        file_in
-            .write(input_str.as_bytes())
-            .expect("Failed to write to file.");
-        file_in.flush().expect("Failed to flush file.");
-        // Need to reopen the file for knoll to read from it.
-        let file_in =
-            std::fs::File::open(in_path).expect("Failed to open temporary file for stdin.");
-        run::<DS, std::fs::File, &mut Vec<u8>, std::fs::File>(
-            &args.into_iter().map(|s| String::from(s)).collect(),
-            file_in,
-            &mut vec_out,
-            file_err_clone,
-        )
-    } else {
-        run::<DS, std::io::Stdin, &mut Vec<u8>, std::fs::File>(
-            &args.into_iter().map(|s| String::from(s)).collect(),
-            std::io::stdin(),
-            &mut vec_out,
-            file_err_clone,
-        )
-    };
-    // Convert the result to an option
-    let opt_error = match res {
-        Ok(_) => None,
-        Err(e) => Some(e),
-    };
-
-    let mut file_err =
-        std::fs::File::open(err_path).expect("Failed to open temporary file for stderr.");
-    let mut string_err = String::new();
-    file_err.read_to_string(&mut string_err).unwrap();
-
-    (opt_error, String::from_utf8(vec_out).unwrap(), string_err)
+    let output = testing::run_knoll::<DS>(&args, input.as_deref()).expect("Failed to run knoll.");
+    (output.error, output.stdout, output.stderr)
 }
 
 #[cfg_attr(all(coverage_nightly, test), coverage(off))]
@@ -80,9 +40,13 @@ fn test_help() {
     let (opt_err, _, _) = run_knoll_real(vec!["knoll", "--help"], None);
     // Verify that a help error was produced.
     match opt_err {
-        Some(Error::Argument(e)) => assert_eq!(e.kind(), clap::error::ErrorKind::DisplayHelp),
+        Some(Error::Argument(ref e)) => {
+            assert_eq!(e.kind(), clap::error::ErrorKind::DisplayHelp)
+        }
         _ => panic!("Unexpected error: {:?}", opt_err),
     }
+    // Requesting help is not a failure.
+    assert_eq!(opt_err.unwrap().exit_code(), 0);
 }
 
 #[test]
@@ -131,3 +95,544 @@ fn test_fake_default() {
 fn test_fake_list() {
     run_knoll_fake(vec!["knoll", "list"], None);
 }
+
+#[test]
+/// Test the knoll list command with all modes with fake displays.
+fn test_fake_list_all_modes() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "list", "--all-modes"], None);
+    assert!(opt_err.is_none());
+}
+
+#[test]
+/// Test the knoll list command with detailed output with fake displays.
+fn test_fake_list_detailed() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "list", "--detailed"], None);
+    assert!(opt_err.is_none());
+}
+
+#[test]
+/// Test the knoll list command with an unknown UUID with fake displays.
+fn test_fake_list_unknown_uuid() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "list", "abcdef1234"], None);
+    match opt_err {
+        Some(Error::UnknownDisplays(ref uuids, _)) => assert_eq!(uuids, &vec!["abcdef1234"]),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 4);
+}
+
+#[test]
+/// Test that the knoll list command only lists the requested displays, by
+/// UUID prefix, with fake displays.
+fn test_fake_list_prefix() {
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "list", "4c7f"], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(stdout.contains("4c7f1a2e9d3b4e8f8a6c0b5d2e1f3a47"));
+    assert!(!stdout.contains("9a3e5b7c1d2f4a6b8c0d1e2f3a4b5c6d"));
+    assert!(!stdout.contains("e1d2c3b4a5964877a8b9c0d1e2f3a4b5"));
+}
+
+#[test]
+/// Test the knoll list command with compact output with fake displays.
+fn test_fake_list_compact() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "list", "--compact"], None);
+    assert!(opt_err.is_none());
+}
+
+#[test]
+/// Test that knoll trigger reports an error when no daemon is listening.
+fn test_trigger_no_daemon() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let socket = dir.path().join("knoll.sock");
+    let (opt_err, _, _) = run_knoll_fake(
+        vec!["knoll", "trigger", "--socket", socket.to_str().unwrap()],
+        None,
+    );
+    match opt_err {
+        Some(Error::Control(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 7);
+}
+
+#[test]
+/// Test that knoll status reports when no daemon is running.
+fn test_status_no_daemon() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let socket = dir.path().join("knoll.sock");
+    let (opt_err, stdout, _) = run_knoll_fake(
+        vec!["knoll", "status", "--socket", socket.to_str().unwrap()],
+        None,
+    );
+    assert!(opt_err.is_none());
+    assert!(stdout.contains("\"running\": false"));
+}
+
+#[test]
+/// Test that the daemon rejects a maximum of zero reconfiguration events.
+fn test_daemon_max_events_zero() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "daemon", "--max-events", "0"], None);
+    match opt_err {
+        Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 2);
+}
+
+#[test]
+/// Test that the daemon rejects an invalid cooldown duration.
+fn test_daemon_invalid_cooldown() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "daemon", "--cooldown", "soon"], None);
+    match opt_err {
+        Some(Error::Duration(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that arguments for applying configurations given before the daemon
+/// subcommand are used by the daemon, rather than silently dropped.
+fn test_daemon_arguments_before_subcommand() {
+    for args in [
+        vec!["knoll", "--fade", "slowly", "daemon"],
+        vec!["knoll", "--fuzzy", "--commit-timeout", "soon", "daemon"],
+    ] {
+        let (opt_err, _, _) = run_knoll_fake(args, None);
+        match opt_err {
+            Some(Error::Duration(_)) => { /* Correctly detected error, so no-op */ }
+            _ => panic!("Unexpected error: {:?}", opt_err),
+        }
+    }
+}
+
+#[test]
+/// Test that an invalid fade duration is rejected.
+fn test_invalid_fade() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--fade", "slowly"], Some("[]".to_owned()));
+    match opt_err {
+        Some(Error::Duration(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that the doctor reports each check, and fails only when a problem
+/// would prevent knoll from working.
+fn test_doctor() {
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "doctor"], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    for name in [
+        "macos-version",
+        "private-symbols",
+        "ssh",
+        "session",
+        "screen-lock",
+    ] {
+        assert!(stdout.contains(name), "Missing check {}: {}", name, stdout);
+    }
+    assert!(!stdout.contains("advice"));
+
+    FakeDisplayState::set_system_facts(Some(SystemFacts {
+        screen_locked: true,
+        ..SystemFacts::default()
+    }))
+    .expect("Failed to set system facts.");
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "doctor"], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(stdout.contains("Unlock the screen"));
+
+    FakeDisplayState::set_system_facts(Some(SystemFacts {
+        missing_required_symbols: vec!["CGSConfigureDisplayMode".to_owned()],
+        ..SystemFacts::default()
+    }))
+    .expect("Failed to set system facts.");
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "doctor"], None);
+    FakeDisplayState::set_system_facts(None).expect("Failed to reset system facts.");
+    match opt_err {
+        Some(Error::Unhealthy(checks)) => assert_eq!(checks, vec!["private-symbols"]),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert!(stdout.contains("CGSConfigureDisplayMode"));
+}
+
+#[test]
+/// Test that the displays can be put to sleep and woken again.
+fn test_sleep_wake() {
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "sleep"], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(stdout.is_empty());
+    assert!(FakeDisplayState::displays_asleep());
+
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "wake"], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(!FakeDisplayState::displays_asleep());
+}
+
+#[test]
+/// Test that destroying a virtual display fails when there is none, and that
+/// invalid extents are rejected.
+fn test_virtual_display_arguments() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("virtual.pid");
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "virtual",
+            "destroy",
+            "--pid-file",
+            pid_path.to_str().unwrap(),
+        ],
+        None,
+    );
+    match opt_err {
+        Some(Error::NoVirtualDisplay(path)) => assert_eq!(path, pid_path),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert!(!pid_path.exists());
+
+    for extents in ["1920", "1920,", "0,1080", "wide,tall"] {
+        let (opt_err, _, _) = run_knoll_fake(
+            vec!["knoll", "virtual", "create", "--extents", extents],
+            None,
+        );
+        match opt_err {
+            Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+            _ => panic!("Unexpected error for {}: {:?}", extents, opt_err),
+        }
+    }
+}
+
+#[test]
+/// Test that the log file, and any missing directories, are created.
+fn test_log_file_created() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let log_path = dir.path().join("logs/knoll.log");
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "--log-file",
+            log_path.to_str().unwrap(),
+            "daemon",
+            "--cooldown",
+            "soon",
+        ],
+        None,
+    );
+    match opt_err {
+        Some(Error::Duration(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert!(log_path.exists());
+}
+
+#[test]
+/// Test that invalid log rotation sizes are rejected.
+fn test_invalid_log_max_size() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--log-max-size", "big", "list"], None);
+    match opt_err {
+        Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that the daemon rejects an invalid wait period after waking.
+fn test_daemon_invalid_wake_wait() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "daemon", "--wake-wait", "later"], None);
+    match opt_err {
+        Some(Error::Duration(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that the daemon rejects webhook URLs it cannot send to.
+fn test_daemon_invalid_webhook() {
+    let (opt_err, _, _) = run_knoll_fake(
+        vec!["knoll", "daemon", "--webhook", "https://example.com/hook"],
+        None,
+    );
+    match opt_err {
+        Some(Error::Webhook(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that installing the launchd agent requires a configuration file.
+fn test_service_install_requires_input() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "service", "install"], None);
+    match opt_err {
+        Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that configuration warnings are written to stderr, and are treated
+/// as errors with --strict.
+fn test_config_warnings() {
+    let config = r#"[[{"uuid": "office"}]]"#.to_owned();
+    let (opt_err, _, stderr) = run_knoll_fake(vec!["knoll"], Some(config.clone()));
+    // The fake displays do not include the display, so no group matches.
+    match opt_err {
+        Some(Error::NoMatchingConfigGroup(..)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert!(stderr.contains("Warning: The UUID office"));
+
+    let (opt_err, _, stderr) = run_knoll_fake(vec!["knoll", "--strict"], Some(config));
+    match opt_err {
+        Some(Error::Strict(ref warnings)) => assert_eq!(warnings.len(), 1),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 3);
+    assert!(!stderr.contains("Warning:"));
+}
+
+#[test]
+/// Test that parse errors report where in the configuration they occurred.
+fn test_config_parse_error_location() {
+    let config = "[\n  [{\"uuid\": \"abcdef1234\" \"enabled\": true}]\n]".to_owned();
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], Some(config));
+    let err = opt_err.expect("Expected a parse error");
+    assert_eq!(
+        err.to_string(),
+        "JSON deserialization error: expected `,` or `}`\n \
+         --> 2:26\n  \
+          |\n\
+        2 |   [{\"uuid\": \"abcdef1234\" \"enabled\": true}]\n  \
+          |                          ^"
+    );
+    // The alternate form is colored for terminals.
+    assert!(format!("{:#}", err).contains("\x1b[1;31m"));
+    assert_eq!(err.exit_code(), 3);
+}
+
+#[test]
+/// Test that unknown fields are only rejected with --strict-config.
+fn test_strict_config() {
+    let config = r#"[[{"uuid": "37d8832a2d6602cab9f78f30a301b230", "rotaton": 90}]]"#.to_owned();
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], Some(config.clone()));
+    match opt_err {
+        Some(Error::NoMatchingConfigGroup(..)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--strict-config"], Some(config));
+    match opt_err {
+        Some(Error::UnknownFields(ref fields)) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].field, "rotaton");
+        }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 3);
+}
+
+#[test]
+/// Test that streaming mode applies each line of input in turn, and
+/// continues after an error.
+fn test_stream() {
+    let input = "[]\n\n[[\n[[{\"uuid\": \"abcdef1234\"}]]\n".to_owned();
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "--stream"], Some(input));
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Unexpected output: {}", stdout);
+    // With no configuration groups, the current state is written.
+    assert!(
+        lines[0].starts_with(r#"[[{"uuid":"#),
+        "Unexpected output: {}",
+        stdout
+    );
+    assert!(lines[1].starts_with(r#"{"error":"JSON deserialization error"#));
+    assert!(lines[2].starts_with(r#"{"error":"No configuration group matches"#));
+}
+
+#[test]
+/// Test that serve mode responds to each request in turn, and continues
+/// after an invalid request.
+fn test_serve() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let out_path = dir.path().join("responses.jsonl");
+    let input = [
+        r#"{"id": 1, "command": "list"}"#,
+        r#"{"id": 2, "command": "verify", "config": [[{"uuid": "abcdef1234"}]]}"#,
+        r#"{"command": "bogus"}"#,
+        "",
+    ]
+    .join("\n");
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "serve",
+            "--stdio",
+            "--output",
+            out_path.to_str().unwrap(),
+        ],
+        Some(input),
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let output = std::fs::read_to_string(&out_path).expect("Failed to read responses.");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3, "Unexpected output: {}", output);
+    assert!(lines[0].starts_with(r#"{"id":1,"response":"modes","displays":"#));
+    // The fake displays do not include the display, so no group matches.
+    assert!(lines[1].starts_with(r#"{"id":2,"response":"verified","warnings":["#));
+    assert!(lines[1].ends_with(r#""group":null}"#));
+    assert!(lines[2].starts_with(r#"{"response":"error","message":"Invalid request"#));
+}
+
+#[test]
+/// Test that output files are replaced only once the output is complete,
+/// unless appending was requested.
+fn test_output_file() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let out_path = dir.path().join("profile.json");
+    let out_file = out_path.to_str().unwrap();
+
+    let (opt_err, stdout, _) = run_knoll_fake(vec!["knoll", "list", "-o", out_file], None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(stdout.is_empty());
+    let listing = std::fs::read_to_string(&out_path).expect("Failed to read output.");
+    assert!(!listing.is_empty());
+
+    let (opt_err, _, _) = run_knoll_fake(
+        vec!["knoll", "list", "--append", "--output", out_file],
+        None,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let appended = std::fs::read_to_string(&out_path).expect("Failed to read output.");
+    assert_eq!(appended, format!("{}{}", listing, listing));
+
+    // A failure part way through leaves the file as it was.
+    std::fs::write(&out_path, "saved").expect("Failed to write file.");
+    let (opt_err, _, _) = run_knoll_fake(
+        vec!["knoll", "-o", out_file],
+        Some("not a configuration".to_owned()),
+    );
+    assert!(opt_err.is_some());
+    assert_eq!(
+        std::fs::read_to_string(&out_path).expect("Failed to read output."),
+        "saved"
+    );
+    let entries = std::fs::read_dir(dir.path())
+        .expect("Failed to read directory.")
+        .count();
+    assert_eq!(entries, 1, "Temporary output file was left behind.");
+}
+
+#[test]
+/// Test setting, listing, using, and removing display aliases.
+fn test_aliases() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let aliases_path = dir.path().join("config/aliases");
+    let aliases_file = aliases_path.to_str().unwrap();
+    let uuid = "37d8832a2d6602cab9f78f30a301b230";
+
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "--aliases-file",
+            aliases_file,
+            "alias",
+            "set",
+            "desk",
+            uuid,
+        ],
+        None,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    let (opt_err, out, _) = run_knoll_fake(
+        vec!["knoll", "--aliases-file", aliases_file, "alias", "list"],
+        None,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(out.contains(&format!("\"desk\": \"{}\"", uuid)), "{}", out);
+
+    // Aliases are resolved on the command-line.
+    let (opt_err, _, _) = run_knoll_fake(
+        vec!["knoll", "--aliases-file", aliases_file, "list", "desk"],
+        None,
+    );
+    match opt_err {
+        Some(Error::UnknownDisplays(uuids, _)) => assert_eq!(uuids, vec![uuid.to_owned()]),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+
+    // Names that could be mistaken for UUIDs are rejected.
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "--aliases-file",
+            aliases_file,
+            "alias",
+            "set",
+            uuid,
+            uuid,
+        ],
+        None,
+    );
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(2));
+
+    let args = vec![
+        "knoll",
+        "--aliases-file",
+        aliases_file,
+        "alias",
+        "remove",
+        "desk",
+    ];
+    let (opt_err, _, _) = run_knoll_fake(args.clone(), None);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let (opt_err, _, _) = run_knoll_fake(args, None);
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(4));
+}
+
+#[test]
+/// Test that serve mode requires the transport to be given.
+fn test_serve_requires_stdio() {
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "serve"], None);
+    match opt_err {
+        Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that the structured result reports the resulting display state,
+/// even when the output is quieted.
+fn test_run_structured() {
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let in_path = dir.path().join("config.json");
+    std::fs::write(&in_path, "[]").expect("Failed to write configuration.");
+    let mut vec_out: Vec<u8> = Vec::new();
+    let result = run_structured::<FakeDisplayState, std::io::Stdin, &mut Vec<u8>, std::fs::File>(
+        &vec!["knoll", "--quiet", "--input", in_path.to_str().unwrap()]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        std::io::stdin(),
+        &mut vec_out,
+        std::fs::File::create(dir.path().join("stderr")).expect("Failed to create stderr."),
+    )
+    .expect("Running knoll should not fail");
+    assert!(vec_out.is_empty());
+    // The state is a single group with a configuration for each fake
+    // display, and with no configuration groups none was applied.
+    let state = result.state.expect("There should be a display state");
+    assert_eq!(state.groups.len(), 1);
+    assert_eq!(state.groups[0].configs.len(), 3);
+    assert_eq!(result.applied_group, None);
+
+    // Other modes do not produce a display state.
+    let result = run_structured::<FakeDisplayState, std::io::Stdin, &mut Vec<u8>, std::fs::File>(
+        &vec!["knoll".to_owned(), "list".to_owned()],
+        std::io::stdin(),
+        &mut vec_out,
+        std::fs::File::create(dir.path().join("stderr")).expect("Failed to create stderr."),
+    )
+    .expect("Running knoll should not fail");
+    assert_eq!(result.state, None);
+}
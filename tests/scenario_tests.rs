@@ -0,0 +1,1424 @@
+#![cfg_attr(all(coverage_nightly, test), feature(coverage_attribute))]
+//! Tests that exercise knoll against scenarios of fake displays.  These are
+//! kept separate from the other integration tests, as the fake displays are
+//! shared by the whole process, and those tests expect there to be none.
+extern crate knoll;
+
+use coverage_helper::test;
+use knoll::api::capture;
+use knoll::config::Resolution;
+use knoll::displays;
+use knoll::displays::{
+    ConfigurationScope, Display, DisplayState, Point, VirtualDisplay, VirtualDisplaySpec,
+};
+use knoll::fake_displays::{FakeDisplayState, FakeOperation, InjectedFailure, Scenario};
+use knoll::knoll::{run_structured, Error};
+use knoll::testing;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// Tests using different scenarios must not run concurrently.
+static SCENARIO_LOCK: Mutex<()> = Mutex::new(());
+
+/// Two displays whose UUIDs share the prefix `37d8`.
+const SCENARIO: &str = r#"[
+    {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+     "modes": [{"extents": [1920, 1080]}, {"extents": [1280, 720]}]},
+    {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00", "origin": [1920, 0],
+     "modes": [{"extents": [2560, 1440], "frequency": 144}]}
+]"#;
+
+#[cfg_attr(all(coverage_nightly, test), coverage(off))]
+/// Run the knoll command with the given arguments and input against the
+/// scenario, returning whether it resulted in an error and the text output
+/// to stdout and stderr.
+fn run_knoll_scenario(
+    scenario: &str,
+    args: Vec<&str>,
+    input: &str,
+) -> (Option<Error>, String, String) {
+    let scenario: Scenario = serde_json::from_str(scenario).expect("Failed to parse scenario.");
+    let output =
+        testing::run_knoll_scenario(&scenario, &args, Some(input)).expect("Failed to run knoll.");
+    (output.error, output.stdout, output.stderr)
+}
+
+#[cfg_attr(all(coverage_nightly, test), coverage(off))]
+/// Run the knoll command with the given arguments and input against the
+/// fake displays as they currently are.
+fn run_knoll_fake(args: Vec<&str>, input: &str) -> (Option<Error>, String, String) {
+    let output = testing::run_knoll_fake(&args, Some(input)).expect("Failed to run knoll.");
+    (output.error, output.stdout, output.stderr)
+}
+
+#[test]
+/// Test that listing reports the displays in the scenario.
+fn test_scenario_list() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "list", "--compact"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(
+        out,
+        "37d8832a2d6602cab9f78f30a301b230 1920x1080@60 8 1\n\
+         37d8832a2d6602cab9f78f30a301b230 1280x720@60 8 1\n\
+         37d8f1a26f1e4c9c8b7d3b1c1a2e9f00 2560x1440@144 8 1\n"
+    );
+}
+
+#[test]
+/// Test applying a configuration to the displays in the scenario, referring
+/// to them by UUID prefixes.
+fn test_scenario_apply() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]},
+                      {"uuid": "37d8f", "origin": [1280, 0]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["uuid"], "37d8832a2d6602cab9f78f30a301b230");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1280, 720]));
+    assert_eq!(state[0][1]["uuid"], "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00");
+    assert_eq!(state[0][1]["origin"], serde_json::json!([1280, 0]));
+
+    // A prefix shared by both displays is ambiguous.
+    let config = r#"[[{"uuid": "37d8"}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(5));
+}
+
+#[test]
+/// Test that failures injected into the fake displays are reported, and
+/// that a failed reconfiguration leaves the displays unchanged.
+fn test_scenario_injected_failures() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let first = "37d8832a2d6602cab9f78f30a301b230";
+    let config = format!(r#"[[{{"uuid": "{}", "extents": [1280, 720]}}]]"#, first);
+    let failure = |operation, uuid: Option<&str>| InjectedFailure {
+        operation,
+        uuid: uuid.map(str::to_owned),
+        times: Some(1),
+    };
+
+    // Only the first of the two failures is for an operation that is used.
+    FakeDisplayState::inject_failure(failure(FakeOperation::SetRotation, None)).unwrap();
+    FakeDisplayState::inject_failure(failure(FakeOperation::SetMode, Some(first))).unwrap();
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], &config);
+    match opt_err {
+        Some(Error::Displays(ref e)) => assert_eq!(
+            e.to_string(),
+            format!("Injected failure of SetMode for display {}", first)
+        ),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.unwrap().exit_code(), 6);
+    FakeDisplayState::clear_injected_failures().unwrap();
+
+    // A failed commit does not change the displays, but as the failure only
+    // occurs once, the same configuration can then be applied.
+    let extents = || {
+        let state = capture::<FakeDisplayState>().expect("Capturing should not fail");
+        state.groups[0].configs[0].extents.clone().unwrap()
+    };
+    FakeDisplayState::inject_failure(failure(FakeOperation::Commit, Some(first))).unwrap();
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], &config);
+    assert!(matches!(opt_err, Some(Error::Displays(_))));
+    assert_eq!(extents(), Resolution::Extents(Point { x: 1920, y: 1080 }));
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], &config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(extents(), Resolution::Extents(Point { x: 1280, y: 720 }));
+
+    // Failing to inspect the displays is also reported.
+    FakeDisplayState::inject_failure(failure(FakeOperation::Current, None)).unwrap();
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "list"], "");
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(6));
+    FakeDisplayState::clear_injected_failures().unwrap();
+}
+
+#[test]
+/// Test that origins matching the current state are left alone, unless the
+/// mode of a display in the group changes, as macOS may then move the
+/// other displays.
+fn test_scenario_origins_after_mode_change() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let second = "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00";
+    // Failing to set the origin of the second display reveals whether it
+    // was requested.
+    FakeDisplayState::inject_failure(InjectedFailure {
+        operation: FakeOperation::SetOrigin,
+        uuid: Some(second.to_owned()),
+        times: None,
+    })
+    .unwrap();
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]},
+                      {"uuid": "37d8f", "origin": [1920, 0]}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]},
+                      {"uuid": "37d8f", "origin": [1920, 0]}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    match opt_err {
+        Some(Error::Displays(ref e)) => assert_eq!(
+            e.to_string(),
+            format!("Injected failure of SetOrigin for display {}", second)
+        ),
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    FakeDisplayState::clear_injected_failures().unwrap();
+}
+
+#[cfg_attr(all(coverage_nightly, test), coverage(off))]
+/// Wait for the first fake display to have the given extents, failing the
+/// test if it takes too long.
+fn wait_for_extents(x: i64, y: i64) {
+    let expected = Resolution::Extents(Point { x, y });
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let state = capture::<FakeDisplayState>().expect("Capturing should not fail");
+        if state.groups[0].configs[0].extents.as_ref() == Some(&expected) {
+            return;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Timed out waiting for {:?}",
+            expected
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+/// Test that the daemon applies the most precise configuration group as
+/// displays are attached.
+fn test_scenario_daemon_hotplug() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("knoll.pid");
+
+    // Start with only the first display attached.
+    let mut scenario: Scenario = serde_json::from_str(SCENARIO).expect("Parsing failed");
+    let second = scenario.displays.pop().unwrap();
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}],
+                     [{"uuid": "37d88", "extents": [1920, 1080]}, {"uuid": "37d8f"}]]"#;
+    let args = [
+        "knoll".to_owned(),
+        "daemon".to_owned(),
+        "--wait".to_owned(),
+        "10ms".to_owned(),
+        "--pid-file".to_owned(),
+        pid_path.to_str().unwrap().to_owned(),
+        "--max-events".to_owned(),
+        "2".to_owned(),
+    ];
+    let daemon = std::thread::spawn(move || {
+        let args = args.iter().map(String::as_str).collect();
+        run_knoll_fake(args, config).0.map(|e| e.to_string())
+    });
+
+    // The initial reconfiguration applies the group for the single display.
+    wait_for_extents(1280, 720);
+    // Give the daemon a moment to finish the reconfiguration, as events
+    // that occur during one are ignored.
+    std::thread::sleep(Duration::from_millis(200));
+
+    // Attaching the second display makes the other group apply, after which
+    // the daemon exits, having reconfigured twice.
+    FakeDisplayState::add_display(&second).expect("Failed to add display.");
+    wait_for_extents(1920, 1080);
+    let opt_err = daemon.join().expect("The daemon panicked.");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    // Displays can only be attached once, and detached if attached.
+    assert!(FakeDisplayState::add_display(&second).is_err());
+    FakeDisplayState::remove_display(&second.uuid).expect("Failed to remove display.");
+    assert!(FakeDisplayState::remove_display(&second.uuid).is_err());
+}
+
+#[test]
+/// Test that the wait period of a configuration group overrides the daemon's
+/// wait period.
+fn test_scenario_daemon_group_wait() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("knoll.pid");
+    let scenario: Scenario = serde_json::from_str(SCENARIO).expect("Parsing failed");
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+
+    let config = r#"[{"configs": [{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}],
+                      "wait": "500ms"}]"#;
+    let started = Instant::now();
+    let (opt_err, _, _) = run_knoll_fake(
+        vec![
+            "knoll",
+            "daemon",
+            "--wait",
+            "10ms",
+            "--pid-file",
+            pid_path.to_str().unwrap(),
+            "--max-events",
+            "1",
+        ],
+        config,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(
+        started.elapsed() >= Duration::from_millis(500),
+        "The daemon should have waited for the group's wait period"
+    );
+    wait_for_extents(1280, 720);
+}
+
+#[test]
+/// Test that when enforcing, the daemon reapplies the configuration group
+/// once the displays drift from it, even without a display configuration
+/// event.
+fn test_scenario_daemon_enforce() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("knoll.pid");
+    let scenario: Scenario = serde_json::from_str(SCENARIO).expect("Parsing failed");
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    let args = [
+        "knoll".to_owned(),
+        "daemon".to_owned(),
+        "--wait".to_owned(),
+        "10ms".to_owned(),
+        "--enforce".to_owned(),
+        "--enforce-interval".to_owned(),
+        "50ms".to_owned(),
+        "--pid-file".to_owned(),
+        pid_path.to_str().unwrap().to_owned(),
+        "--max-events".to_owned(),
+        "2".to_owned(),
+    ];
+    let daemon = std::thread::spawn(move || {
+        let args = args.iter().map(String::as_str).collect();
+        run_knoll_fake(args, config).0.map(|e| e.to_string())
+    });
+
+    wait_for_extents(1280, 720);
+    std::thread::sleep(Duration::from_millis(200));
+
+    // Replacing the scenario resets the display modes without invoking the
+    // reconfiguration callbacks, as if another application had changed them.
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+    wait_for_extents(1280, 720);
+    let opt_err = daemon.join().expect("The daemon panicked.");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+}
+
+#[test]
+/// Test that when enforcing, origins that macOS adjusts after applying the
+/// configuration group are not mistaken for drift, but that the displays
+/// changing afterwards still are.
+fn test_scenario_daemon_enforce_normalized_origins() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("knoll.pid");
+    let scenario: Scenario = serde_json::from_str(SCENARIO).expect("Parsing failed");
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+    FakeDisplayState::set_normalize_origins(true);
+
+    // The requested origins leave a gap at the left, which is removed.
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720], "origin": [100, 0]},
+                      {"uuid": "37d8f", "origin": [1380, 0]}]]"#;
+    let args = [
+        "knoll".to_owned(),
+        "daemon".to_owned(),
+        "--wait".to_owned(),
+        "10ms".to_owned(),
+        "--enforce".to_owned(),
+        "--enforce-interval".to_owned(),
+        "20ms".to_owned(),
+        "--pid-file".to_owned(),
+        pid_path.to_str().unwrap().to_owned(),
+        "--max-events".to_owned(),
+        "2".to_owned(),
+    ];
+    let daemon = std::thread::spawn(move || {
+        let args = args.iter().map(String::as_str).collect();
+        run_knoll_fake(args, config).0.map(|e| e.to_string())
+    });
+
+    wait_for_extents(1280, 720);
+    // Had the adjusted origins been considered drift, the daemon would have
+    // reapplied the group and exited by now.
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(!daemon.is_finished(), "The adjusted origins were reapplied");
+    let state = FakeDisplayState::current().expect("Failed to get state.");
+    let origins: Vec<&Point> = state
+        .get_displays()
+        .values()
+        .map(|display| display.origin())
+        .collect();
+    assert_eq!(
+        origins,
+        vec![&Point { x: 0, y: 0 }, &Point { x: 1280, y: 0 }]
+    );
+
+    // Another application changing the displays is still drift.
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+    wait_for_extents(1280, 720);
+    let opt_err = daemon.join().expect("The daemon panicked.");
+    FakeDisplayState::set_normalize_origins(false);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+}
+
+#[test]
+/// Test that inconsistent scenarios are rejected.
+fn test_scenario_invalid() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario: Scenario =
+        serde_json::from_str(r#"[{"uuid": "37d8", "modes": []}]"#).expect("Parsing failed");
+    assert!(FakeDisplayState::set_scenario(&scenario).is_err());
+}
+
+#[test]
+/// Test that configuration changes are committed with the requested scope,
+/// and that unknown scopes are rejected.
+fn test_scenario_scope() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(
+        SCENARIO,
+        vec!["knoll", "--quiet", "--scope", "session"],
+        config,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(
+        FakeDisplayState::last_commit_scope(),
+        Some(ConfigurationScope::Session)
+    );
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--quiet"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(
+        FakeDisplayState::last_commit_scope(),
+        Some(ConfigurationScope::Permanent)
+    );
+
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--scope", "forever"], config);
+    assert!(matches!(opt_err, Some(Error::Argument(_))));
+}
+
+#[test]
+/// Test that configuration changes are still applied when fading.
+fn test_scenario_fade() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "--fade", "500ms"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1280, 720]));
+}
+
+#[test]
+/// Test that virtual displays are attached until they are dropped, and can
+/// be configured like any other display.
+fn test_scenario_virtual_display() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario: Scenario = serde_json::from_str(SCENARIO).expect("Failed to parse scenario.");
+    FakeDisplayState::set_scenario(&scenario).expect("Failed to set scenario.");
+
+    let spec = VirtualDisplaySpec {
+        name: "test".to_owned(),
+        extents: Point { x: 1600, y: 900 },
+        ppi: 110,
+        frequency: 30,
+    };
+    let display = FakeDisplayState::create_virtual_display(&spec)
+        .expect("Creating a virtual display should not fail");
+    let uuid = display.uuid().to_owned();
+    let state = FakeDisplayState::current().expect("Failed to get the display state.");
+    assert_eq!(state.get_displays().len(), 3);
+
+    let config = format!(r#"[[{{"uuid": "{}", "origin": [-1600, 0]}}]]"#, uuid);
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], &config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    let virtual_config = state[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|config| config["uuid"] == uuid.as_str())
+        .expect("The virtual display should be listed");
+    assert_eq!(virtual_config["extents"], serde_json::json!([1600, 900]));
+    assert_eq!(virtual_config["origin"], serde_json::json!([-1600, 0]));
+
+    drop(display);
+    let state = FakeDisplayState::current().expect("Failed to get the display state.");
+    assert_eq!(state.get_displays().len(), 2);
+    assert!(!state.get_displays().contains_key(&uuid));
+
+    FakeDisplayState::inject_failure(InjectedFailure {
+        operation: FakeOperation::CreateVirtualDisplay,
+        uuid: None,
+        times: Some(1),
+    })
+    .expect("Failed to inject failure.");
+    assert!(FakeDisplayState::create_virtual_display(&spec).is_err());
+}
+
+#[test]
+/// Test that configuration groups only match displays of the required kind,
+/// and that the kind is reported in detailed output.
+fn test_scenario_display_kind() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080]}]},
+        {"uuid": "5c1dec4a2d6602cab9f78f30a301b230", "kind": "sidecar",
+         "origin": [1920, 0], "modes": [{"extents": [1366, 1024]}]}
+    ]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll", "--detailed"], "[]");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["kind"], "physical");
+    assert_eq!(state[0][1]["kind"], "sidecar");
+
+    let config = r#"[
+        [{"uuid": "37d8"}, {"uuid": "5c1d", "kind": "airplay", "origin": [0, 1080]}],
+        [{"uuid": "37d8"}, {"uuid": "5c1d", "kind": "sidecar", "origin": [-1366, 0]}]
+    ]"#;
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][1]["origin"], serde_json::json!([-1366, 0]));
+
+    let config = r#"[[{"uuid": "37d8"}, {"uuid": "5c1d", "kind": "virtual"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::NoMatchingConfigGroup(..))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that benchmarking reports each stage, and that staging a
+/// configuration in a dry run does not change the displays.
+fn test_scenario_bench() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "bench"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let report: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(report["iterations"], 5);
+    assert!(report["state_capture"]["mean_us"].is_u64());
+    assert_eq!(report["mode_enumeration"][0]["modes"], 2);
+    assert_eq!(report["mode_enumeration"][1]["modes"], 1);
+    assert!(report.get("config_matching").is_none());
+    assert!(report.get("staging").is_none());
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    let (opt_err, out, _) = run_knoll_fake(
+        vec!["knoll", "bench", "--dry-run", "--iterations", "2"],
+        config,
+    );
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let report: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(report["iterations"], 2);
+    assert!(report["config_matching"]["max_us"].is_u64());
+    assert!(report["staging"]["min_us"].is_u64());
+
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "bench", "--iterations", "0"], "");
+    match opt_err {
+        Some(Error::Argument(_)) => { /* Correctly detected error, so no-op */ }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that the state of the displays is backed up before changing them,
+/// and that the backup restores that state.
+fn test_scenario_backup() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let backup_path = dir.path().join("state/backup.json");
+    let backup_file = backup_path.to_str().unwrap();
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+
+    let (opt_err, _, _) =
+        run_knoll_scenario(SCENARIO, vec!["knoll", "--backup", backup_file], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let backup = std::fs::read_to_string(&backup_path).expect("Failed to read backup.");
+    let state: serde_json::Value = serde_json::from_str(&backup).expect("Backup should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+
+    // Nothing is backed up when there is nothing to change.
+    std::fs::remove_file(&backup_path).expect("Failed to remove backup.");
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--backup", backup_file], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(!backup_path.exists());
+
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], &backup);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+}
+
+#[test]
+/// Test that a commit that does not complete in time is abandoned, and
+/// reported as a failure of the displays.
+fn test_scenario_commit_timeout() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    FakeDisplayState::set_commit_delay(Some(Duration::from_millis(300)))
+        .expect("Failed to set commit delay.");
+    let (opt_err, _, _) =
+        run_knoll_scenario(SCENARIO, vec!["knoll", "--commit-timeout", "50ms"], config);
+    FakeDisplayState::set_commit_delay(None).expect("Failed to reset commit delay.");
+    match &opt_err {
+        Some(Error::Displays(displays::Error::Timeout(timeout))) => {
+            assert_eq!(*timeout, Duration::from_millis(50))
+        }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(6));
+
+    // The abandoned commit still completes in the background.
+    wait_for_extents(1280, 720);
+
+    // The daemon refuses further commits until the abandoned commit
+    // finishes, after which it retries, finding the displays already
+    // configured.
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let pid_path = dir.path().join("knoll.pid");
+    FakeDisplayState::set_scenario(&serde_json::from_str(SCENARIO).expect("Parsing failed"))
+        .expect("Failed to set scenario.");
+    FakeDisplayState::set_commit_delay(Some(Duration::from_millis(300)))
+        .expect("Failed to set commit delay.");
+    let (opt_err, _, err) = run_knoll_fake(
+        vec![
+            "knoll",
+            "-v",
+            "--commit-timeout",
+            "50ms",
+            "daemon",
+            "--wait",
+            "10ms",
+            "--retries",
+            "5",
+            "--retry-delay",
+            "100ms",
+            "--pid-file",
+            pid_path.to_str().unwrap(),
+            "--max-events",
+            "1",
+        ],
+        config,
+    );
+    FakeDisplayState::set_commit_delay(None).expect("Failed to reset commit delay.");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(
+        err.contains("Waiting for the abandoned commit to finish"),
+        "{}",
+        err
+    );
+    wait_for_extents(1280, 720);
+
+    // Without a delay, the commit completes within the timeout.
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]}, {"uuid": "37d8f"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--commit-timeout", "5s"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+}
+
+#[test]
+/// Test that a commit macOS reports as successful, but which leaves the
+/// displays unchanged, is reported as an error.
+fn test_scenario_configuration_ignored() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    FakeDisplayState::set_ignore_commits(true);
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    FakeDisplayState::set_ignore_commits(false);
+    match &opt_err {
+        Some(Error::ConfigurationIgnored(settings)) => {
+            assert_eq!(settings.len(), 1);
+            assert!(settings[0].contains("37d88"), "{}", settings[0]);
+        }
+        _ => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert_eq!(opt_err.map(|e| e.exit_code()), Some(6));
+
+    // Once commits are applied again, the same configuration succeeds.
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+}
+
+#[test]
+/// Test that a display that was disabled can be enabled again, and that its
+/// other settings are configured once it is enabled.
+fn test_scenario_reenable() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config =
+        r#"[[{"uuid": "37d88", "extents": [1920, 1080]}, {"uuid": "37d8f", "enabled": false}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state = FakeDisplayState::current().expect("Failed to get the display state.");
+    assert!(!state
+        .get_displays()
+        .contains_key("37d8f1a26f1e4c9c8b7d3b1c1a2e9f00"));
+    assert!(state
+        .disabled_displays()
+        .contains("37d8f1a26f1e4c9c8b7d3b1c1a2e9f00"));
+
+    // The same configuration still matches, and changes nothing.
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    // A disabled display is only matched by configurations that say whether
+    // it should be enabled.
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]}, {"uuid": "37d8f"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::NoMatchingConfigGroup(..))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]},
+                      {"uuid": "37d8f", "enabled": true, "origin": [0, 1080]}]]"#;
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][1]["uuid"], "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00");
+    assert_eq!(state[0][1]["enabled"], true);
+    assert_eq!(state[0][1]["origin"], serde_json::json!([0, 1080]));
+}
+
+#[test]
+/// Test that ranges of extents and frequencies select a mode that lies
+/// within them.
+fn test_scenario_ranges() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[[{"uuid": "37d88", "extents": {"min": [1600, 900]}},
+                      {"uuid": "37d8f", "extents": {"min": [2560, 1440]}, "frequency": ">=120"}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+    assert_eq!(state[0][1]["frequency"], 144);
+
+    // No mode lies in the range.
+    let config = r#"[[{"uuid": "37d88", "extents": {"max": [1600, 900]}, "frequency": "<=30"},
+                      {"uuid": "37d8f"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::NoMatchingDisplayMode(_))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that an aspect ratio selects the display mode of that shape, allowing
+/// for modes that are not exactly in proportion.
+fn test_scenario_aspect_ratio() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1024, 768]}, {"extents": [1366, 768]}, {"extents": [1280, 800]}]}
+    ]"#;
+    for (ratio, extents) in [
+        ("4:3", [1024, 768]),
+        ("16:9", [1366, 768]),
+        ("16:10", [1280, 800]),
+    ] {
+        let config = format!(r#"[[{{"uuid": "37d88", "aspect_ratio": "{}"}}]]"#, ratio);
+        let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll"], &config);
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        assert_eq!(state[0][0]["extents"], serde_json::json!(extents));
+    }
+
+    let config = r#"[[{"uuid": "37d88", "aspect_ratio": "21:9"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::NoMatchingDisplayMode(_))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that `max` selects the largest extents and highest frequency amongst
+/// the display modes that otherwise match.
+fn test_scenario_max() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080], "frequency": 60},
+                   {"extents": [1920, 1080], "frequency": 120},
+                   {"extents": [1280, 720], "frequency": 144}]}
+    ]"#;
+    for (config, extents, frequency) in [
+        (r#""extents": "max", "frequency": "max""#, [1920, 1080], 120),
+        (r#""frequency": "max""#, [1280, 720], 144),
+        (r#""extents": "max", "frequency": 60"#, [1920, 1080], 60),
+    ] {
+        let config = format!(r#"[[{{"uuid": "37d88", {}}}]]"#, config);
+        let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll"], &config);
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        assert_eq!(state[0][0]["extents"], serde_json::json!(extents));
+        assert_eq!(state[0][0]["frequency"], frequency);
+    }
+
+    // Without `max`, the largest extents match two frequencies.
+    let config = r#"[[{"uuid": "37d88", "extents": "max"}]]"#;
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::AmbiguousDisplayMode(_))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that a skipped display must be attached for its configuration group
+/// to match, but that its settings are left untouched.
+fn test_scenario_skip() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    // Without `skip`, the modes of 37d88 would be ambiguous.
+    let config = r#"[[{"uuid": "37d88", "skip": true},
+                      {"uuid": "37d8f", "origin": [0, 1080]}],
+                     [{"uuid": "37d88", "extents": [1280, 720]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+    assert_eq!(state[0][0]["origin"], serde_json::json!([0, 0]));
+    assert_eq!(state[0][1]["origin"], serde_json::json!([0, 1080]));
+
+    // Skipping every display changes nothing.
+    let config = r#"[[{"uuid": "37d88", "skip": true}, {"uuid": "37d8f", "skip": true}]]"#;
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][1]["origin"], serde_json::json!([0, 1080]));
+}
+
+#[test]
+/// Test that a configuration script is given the current state, and that
+/// its output is applied.
+fn test_scenario_script() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    // The script only produces a configuration if the 2560x1440 display is
+    // present in its input.
+    let script = r#"grep -q 2560 && echo '[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]'"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "--script", script], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["extents"], serde_json::json!([1280, 720]));
+
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--script", "exit 3"], "");
+    match opt_err {
+        Some(err @ Error::ScriptFailed(_, _)) => assert_eq!(err.exit_code(), 8),
+        other => panic!("Expected the script to fail: {:?}", other),
+    }
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+/// Test that a Rhai configuration script is given the current state, and
+/// that the configuration it evaluates to is applied.
+fn test_scenario_rhai_script() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    // Arrange the displays in a column, however many there are.
+    let path = dir.path().join("layout.rhai");
+    std::fs::write(
+        &path,
+        r#"
+        let y = 0;
+        let configs = [];
+        for display in state[0] {
+            configs.push(#{uuid: display.uuid, extents: display.extents, origin: [0, y]});
+            y += display.extents[1];
+        }
+        [configs]
+        "#,
+    )
+    .expect("Failed to write script.");
+    let path = path.to_str().unwrap();
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "--input", path], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][1]["origin"], serde_json::json!([0, 1080]));
+
+    let path = dir.path().join("fail.rhai");
+    std::fs::write(&path, r#"throw "no layout""#).expect("Failed to write script.");
+    let (opt_err, _, _) = run_knoll_fake(vec!["knoll", "--input", path.to_str().unwrap()], "");
+    match opt_err {
+        Some(err @ Error::ScriptFailed(_, _)) => assert_eq!(err.exit_code(), 8),
+        other => panic!("Expected the script to fail: {:?}", other),
+    }
+}
+
+#[test]
+/// Test that origins may refer to the display modes selected for other
+/// displays in the group.
+fn test_scenario_references() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    for (x, y) in [(1280, 720), (1920, 1080)] {
+        let config = format!(
+            r#"[[{{"uuid": "37d88", "extents": [{x}, {y}], "origin": [0, 0]}},
+                 {{"uuid": "37d8f", "origin": ["${{37d88.origin.x}} + ${{37d88.extents.x}}", 0]}}]]"#
+        );
+        let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], &config);
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        assert_eq!(state[0][1]["origin"], serde_json::json!([x, 0]));
+    }
+}
+
+#[test]
+/// Test each of the policies for handling display modes with identical
+/// properties.
+fn test_scenario_duplicate_modes() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230", "mode": 2,
+         "modes": [{"extents": [1920, 1080]}, {"extents": [1920, 1080]},
+                   {"extents": [1280, 720]}]}
+    ]"#;
+    for (policy, mode_ids) in [
+        ("keep-first", vec![0, 2]),
+        ("keep-highest-mode-id", vec![1, 2]),
+        ("all", vec![0, 1, 2]),
+    ] {
+        // Keeping all the duplicates includes the mode ids without having
+        // to ask for them.
+        let mut args = vec!["knoll", "list", "--duplicate-modes", policy];
+        if policy != "all" {
+            args.push("--detailed");
+        }
+        let (opt_err, out, _) = run_knoll_scenario(scenario, args, "");
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        let listed: Vec<_> = list[0]["modes"]
+            .as_array()
+            .expect("Modes should be listed")
+            .iter()
+            .map(|mode| mode["mode_id"].clone())
+            .collect();
+        assert_eq!(serde_json::json!(listed), serde_json::json!(mode_ids));
+    }
+
+    let config = r#"[[{"uuid": "37d88", "extents": [1920, 1080]}]]"#;
+    for policy in ["keep-first", "keep-highest-mode-id"] {
+        let args = vec!["knoll", "--duplicate-modes", policy];
+        let (opt_err, out, _) = run_knoll_scenario(scenario, args, config);
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+    }
+
+    // When all are kept, the duplicates cannot be told apart.
+    let args = vec!["knoll", "--duplicate-modes", "all"];
+    let (opt_err, _, _) = run_knoll_scenario(scenario, args, config);
+    assert!(
+        matches!(opt_err, Some(Error::AmbiguousDisplayMode(_))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that a mode id selects a display mode directly, even when it cannot
+/// otherwise be told apart from another.
+fn test_scenario_mode_id() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230", "mode": 2,
+         "modes": [{"extents": [1920, 1080]}, {"extents": [1920, 1080]},
+                   {"extents": [1280, 720]}]}
+    ]"#;
+    for mode_id in [0, 1] {
+        let config = format!(r#"[[{{"uuid": "37d88", "mode_id": {}}}]]"#, mode_id);
+        let args = vec!["knoll", "--duplicate-modes", "all", "--detailed"];
+        let (opt_err, out, _) = run_knoll_scenario(scenario, args, &config);
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        assert_eq!(state[0][0]["extents"], serde_json::json!([1920, 1080]));
+        assert_eq!(state[0][0]["mode_id"], mode_id);
+    }
+
+    let config = r#"[[{"uuid": "37d88", "mode_id": 3}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(scenario, vec!["knoll"], config);
+    assert!(
+        matches!(opt_err, Some(Error::NoMatchingDisplayMode(_))),
+        "Unexpected error: {:?}",
+        opt_err
+    );
+}
+
+#[test]
+/// Test that ignored displays, whether given on the command-line or in the
+/// configuration, do not participate in matching configuration groups.
+fn test_scenario_ignore() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let groups = r#"[[{"uuid": "37d88", "extents": [1280, 720]}],
+                     [{"uuid": "37d88", "extents": [1920, 1080]}, {"uuid": "37d8f"}]]"#;
+    let extents = |out: &str| {
+        let state: serde_json::Value = serde_json::from_str(out).expect("Output should be JSON");
+        state[0][0]["extents"].clone()
+    };
+
+    // Without ignoring any displays, the group for both displays applies.
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], groups);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(extents(&out), serde_json::json!([1920, 1080]));
+
+    let args = vec!["knoll", "--ignore", "37d8f"];
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, args, groups);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(extents(&out), serde_json::json!([1280, 720]));
+
+    let config = format!(
+        r#"{{"version": 1, "groups": {}, "ignored": ["37d8f"]}}"#,
+        groups
+    );
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], &config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(extents(&out), serde_json::json!([1280, 720]));
+
+    // Ignored displays are not reported as unmatched.
+    let config = r#"[[{"uuid": "37d88"}, {"uuid": "37d8f"}]]"#;
+    let args = vec!["knoll", "--ignore", "37d8f"];
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, args, config);
+    match opt_err {
+        Some(Error::NoMatchingConfigGroup(uuids, ..)) => {
+            assert_eq!(uuids, vec!["37d8832a2d6602cab9f78f30a301b230".to_owned()])
+        }
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that mirror sets are reported when listing and in the state.
+fn test_scenario_mirroring() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080]}]},
+        {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00",
+         "mirror_of": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080]}]}
+    ]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll", "list"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(list[0]["mirroring"], "primary");
+    assert_eq!(
+        list[1]["mirroring"],
+        serde_json::json!({"mirror_of": "37d8832a2d6602cab9f78f30a301b230"})
+    );
+
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["mirroring"], "primary");
+    assert_eq!(
+        state[0][1]["mirroring"],
+        serde_json::json!({"mirror_of": "37d8832a2d6602cab9f78f30a301b230"})
+    );
+
+    // The mirroring is ignored when the state is applied.
+    let (opt_err, _, _) = run_knoll_scenario(scenario, vec!["knoll"], &out);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    // Displays not in a mirror set report nothing.
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "list"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert!(list[0].get("mirroring").is_none());
+}
+
+#[test]
+/// Test sorting the listed display modes, and nesting them by resolution.
+fn test_scenario_list_sort() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1280, 720]},
+                   {"extents": [1920, 1080]},
+                   {"extents": [1920, 1080], "frequency": 120, "color_depth": 10},
+                   {"extents": [1280, 720], "frequency": 144}]}
+    ]"#;
+    for (sort, expected) in [
+        (
+            "resolution",
+            [
+                "1920x1080@60 8",
+                "1920x1080@120 10",
+                "1280x720@60 8",
+                "1280x720@144 8",
+            ],
+        ),
+        (
+            "frequency",
+            [
+                "1280x720@144 8",
+                "1920x1080@120 10",
+                "1280x720@60 8",
+                "1920x1080@60 8",
+            ],
+        ),
+        (
+            "depth",
+            [
+                "1920x1080@120 10",
+                "1280x720@60 8",
+                "1920x1080@60 8",
+                "1280x720@144 8",
+            ],
+        ),
+    ] {
+        let args = vec!["knoll", "list", "--compact", "--sort", sort];
+        let (opt_err, out, _) = run_knoll_scenario(scenario, args, "");
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let expected: String = expected
+            .iter()
+            .map(|mode| format!("37d8832a2d6602cab9f78f30a301b230 {} 1\n", mode))
+            .collect();
+        assert_eq!(out, expected, "Unexpected order sorting by {}", sort);
+    }
+
+    // The nested groups follow the order of the modes.
+    for (args, extents) in [
+        (
+            vec!["knoll", "list", "--by-resolution"],
+            [[1280, 720], [1920, 1080]],
+        ),
+        (
+            vec![
+                "knoll",
+                "list",
+                "--by-resolution",
+                "--sort",
+                "resolution",
+                "--detailed",
+            ],
+            [[1920, 1080], [1280, 720]],
+        ),
+    ] {
+        let (opt_err, out, _) = run_knoll_scenario(scenario, args, "");
+        assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+        let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+        let groups = list[0]["modes"].as_array().expect("Modes should be listed");
+        assert_eq!(groups.len(), 2);
+        for (group, extents) in groups.iter().zip(extents) {
+            assert_eq!(group["extents"], serde_json::json!(extents));
+            assert_eq!(group["modes"].as_array().map(Vec::len), Some(2));
+        }
+    }
+}
+
+#[test]
+/// Test summarizing the displays instead of listing their display modes.
+fn test_scenario_list_displays() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230", "name": "Studio Display",
+         "modes": [{"extents": [1920, 1080]}, {"extents": [1280, 720]}], "mode": 1},
+        {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00", "origin": [1280, 0], "rotation": 90,
+         "mirror_of": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [2560, 1440], "frequency": 144}]}
+    ]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll", "list", "--displays"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(list.as_array().map(Vec::len), Some(2));
+    assert_eq!(list[0]["uuid"], "37d8832a2d6602cab9f78f30a301b230");
+    assert_eq!(list[0]["name"], "Studio Display");
+    assert_eq!(list[0]["mode"]["extents"], serde_json::json!([1280, 720]));
+    assert_eq!(list[0]["origin"], serde_json::json!([0, 0]));
+    assert_eq!(list[0]["rotation"], 0);
+    assert_eq!(list[0]["mirroring"], "primary");
+    assert!(list[1].get("name").is_none());
+    assert_eq!(list[1]["mode"]["frequency"], 144);
+    assert_eq!(list[1]["origin"], serde_json::json!([1280, 0]));
+    assert_eq!(list[1]["rotation"], 90);
+    assert!(list[1]["mirroring"].get("mirror_of").is_some());
+
+    // Displays may be selected in the same way as when listing modes.
+    let args = vec!["knoll", "list", "--displays", "37d8f"];
+    let (opt_err, out, _) = run_knoll_scenario(scenario, args, "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let list: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(list.as_array().map(Vec::len), Some(1));
+    assert_eq!(list[0]["uuid"], "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00");
+}
+
+#[test]
+/// Test printing the state of the displays without applying a
+/// configuration.
+fn test_scenario_state() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080]}]},
+        {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00", "origin": [-2560, -360],
+         "modes": [{"extents": [2560, 1440], "frequency": 144}]}
+    ]"#;
+    // The input is not read, even if it contains a configuration.
+    let config =
+        r#"[[{"uuid": "37d88", "origin": [0, 0]}, {"uuid": "37d8f", "origin": [1920, 0]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll", "state"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["origin"], serde_json::json!([0, 0]));
+    assert_eq!(state[0][1]["origin"], serde_json::json!([-2560, -360]));
+    assert!(state[0][0].get("mode_id").is_none());
+
+    let args = vec!["knoll", "state", "--normalize", "--detailed"];
+    let (opt_err, out, _) = run_knoll_scenario(scenario, args, "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0][0]["origin"], serde_json::json!([2560, 360]));
+    assert_eq!(state[0][1]["origin"], serde_json::json!([0, 0]));
+    assert_eq!(state[0][0]["mode_id"], 0);
+    assert_eq!(state[0][0]["kind"], "physical");
+}
+
+#[test]
+/// Test that the daemon refuses to detach when knoll is run as a library,
+/// as forking is only safe before any threads have been started.
+fn test_scenario_detach_unavailable() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let groups = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    let args = vec!["knoll", "daemon", "--detach", "--max-events", "1"];
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, args, groups);
+    match opt_err {
+        Some(err @ Error::DetachUnavailable) => assert_eq!(err.exit_code(), 2),
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that confirming changes fails without a terminal, rather than
+/// applying the changes unconfirmed.
+fn test_scenario_confirm_without_terminal() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let groups = r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#;
+    let args = vec!["knoll", "--confirm"];
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, args, groups);
+    match opt_err {
+        Some(err @ Error::ConfirmationUnavailable) => assert_eq!(err.exit_code(), 2),
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+    assert!(out.is_empty());
+
+    // The displays were left untouched.
+    let (opt_err, out, _) = run_knoll_fake(vec!["knoll", "state"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert!(!out.contains("1280"), "Unexpected state: {}", out);
+}
+
+#[test]
+/// Test that `--exit-code` distinguishes between changing the displays and
+/// finding that they already match.
+fn test_scenario_exit_code() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let dir = tempdir().expect("Failed to create temporary directory.");
+    let in_path = dir.path().join("config.json");
+    std::fs::write(
+        &in_path,
+        r#"[[{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]]"#,
+    )
+    .expect("Failed to write configuration.");
+    let run = |args: Vec<&str>| {
+        run_structured::<FakeDisplayState, std::io::Stdin, std::io::Sink, std::fs::File>(
+            &args.into_iter().map(String::from).collect(),
+            std::io::stdin(),
+            std::io::sink(),
+            std::fs::File::create(dir.path().join("stderr")).expect("Failed to create stderr."),
+        )
+        .expect("Running knoll should not fail")
+    };
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "state"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+
+    // The first run changes the displays, the second finds nothing to do.
+    let args = vec!["knoll", "--exit-code", "--input", in_path.to_str().unwrap()];
+    let result = run(args.clone());
+    assert!(result.changed);
+    assert_eq!(result.exit_code, 1);
+    let result = run(args);
+    assert!(!result.changed);
+    assert_eq!(result.exit_code, 0);
+
+    // Without the flag, changes still exit successfully.
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll", "state"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let result = run(vec!["knoll", "--input", in_path.to_str().unwrap()]);
+    assert!(result.changed);
+    assert_eq!(result.exit_code, 0);
+}
+
+#[test]
+/// Test that the origin and extents of a display may be given in backing
+/// pixels, which are converted using the scale of the selected display mode.
+fn test_scenario_units() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [1920, 1080]}, {"extents": [1280, 720], "scale": 2.0}]},
+        {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00", "origin": [1920, 0],
+         "modes": [{"extents": [2560, 1440], "frequency": 144}]}
+    ]"#;
+    let state = |out: &str| {
+        let state: serde_json::Value = serde_json::from_str(out).expect("Output should be JSON");
+        (state[0][0].clone(), state[0][1].clone())
+    };
+
+    // In points there is no display mode this large.
+    let config = r#"[[{"uuid": "37d88", "extents": [2560, 1440]},
+                      {"uuid": "37d8f", "origin": [0, 0]}]]"#;
+    let (opt_err, _, _) = run_knoll_scenario(scenario, vec!["knoll"], config);
+    match opt_err {
+        Some(Error::NoMatchingDisplayMode(_)) => (),
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+
+    let config = r#"[[{"uuid": "37d88", "units": "pixels",
+                       "extents": [2560, 1440], "origin": [5120, 0]},
+                      {"uuid": "37d8f", "origin": [0, 0]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let (first, _) = state(&out);
+    assert_eq!(first["extents"], serde_json::json!([1280, 720]));
+    assert_eq!(first["origin"], serde_json::json!([2560, 0]));
+
+    // References to other displays are measured in the same backing pixels.
+    let config = r#"[[{"uuid": "37d88", "units": "pixels", "extents": [2560, 1440],
+                       "origin": ["${37d8f.extents.x}", 0]},
+                      {"uuid": "37d8f", "origin": [0, 0]}]]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let (first, second) = state(&out);
+    assert_eq!(first["origin"], serde_json::json!([2560, 0]));
+    assert_eq!(second["origin"], serde_json::json!([0, 0]));
+}
+
+#[test]
+/// Test that compact listings annotate scaled modes with what they look like
+/// and their backing pixels.
+fn test_scenario_list_looks_like() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let scenario = r#"[
+        {"uuid": "37d8832a2d6602cab9f78f30a301b230",
+         "modes": [{"extents": [2560, 1440]},
+                   {"extents": [1280, 720], "scale": 2.0},
+                   {"extents": [1920, 1080], "scale": 2.0, "pixel_extents": [3840, 2160]}]}
+    ]"#;
+    let (opt_err, out, _) = run_knoll_scenario(scenario, vec!["knoll", "list", "--compact"], "");
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    assert_eq!(
+        out,
+        "37d8832a2d6602cab9f78f30a301b230 2560x1440@60 8 1\n\
+         37d8832a2d6602cab9f78f30a301b230 1280x720@60 8 2 looks like 1280x720 (2560x1440 pixels)\n\
+         37d8832a2d6602cab9f78f30a301b230 1920x1080@60 8 2 looks like 1920x1080 (3840x2160 pixels)\n"
+    );
+}
+
+#[test]
+/// Test that the name and description of the configuration group applied
+/// are included in the resulting state, and that names are reported in
+/// errors.
+fn test_scenario_group_names() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    let config = r#"[{"name": "office", "description": "Docked at the office",
+                      "configs": [{"uuid": "37d88", "extents": [1280, 720]}, {"uuid": "37d8f"}]}]"#;
+    let (opt_err, out, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    assert!(opt_err.is_none(), "Unexpected error: {:?}", opt_err);
+    let state: serde_json::Value = serde_json::from_str(&out).expect("Output should be JSON");
+    assert_eq!(state[0]["name"], "office");
+    assert_eq!(state[0]["description"], "Docked at the office");
+    assert_eq!(
+        state[0]["configs"][0]["extents"],
+        serde_json::json!([1280, 720])
+    );
+
+    let config = r#"[{"name": "travel", "configs": [{"uuid": "22222"}]},
+                     {"name": "home", "configs": [{"uuid": "11111"}, {"uuid": "37d8f"}]}]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    match opt_err {
+        Some(err @ Error::NoMatchingConfigGroup(..)) => assert!(
+            err.to_string()
+                .ends_with("The named groups considered were: home, travel."),
+            "Unexpected error: {}",
+            err
+        ),
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+
+    let config = r#"[{"name": "left", "configs": [{"uuid": "37d88"}]},
+                     {"name": "right", "configs": [{"uuid": "37d8f"}]}]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    match opt_err {
+        Some(err @ Error::AmbiguousConfigGroup(_)) => {
+            let message = err.to_string();
+            assert!(
+                message.contains("left") && message.contains("right"),
+                "{}",
+                message
+            )
+        }
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+}
+
+#[test]
+/// Test that UUIDs which do not match an attached display are reported with
+/// a suggestion of the closest attached display.
+fn test_scenario_uuid_suggestions() {
+    let _lock = SCENARIO_LOCK.lock().unwrap_or_else(|pe| pe.into_inner());
+    // A UUID pasted with characters missing from the middle.
+    let config = r#"[{"configs": [{"uuid": "37d8832a2d6602cab9f78301b230"},
+                                  {"uuid": "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00"}]}]"#;
+    let (opt_err, _, _) = run_knoll_scenario(SCENARIO, vec!["knoll"], config);
+    match opt_err {
+        Some(err @ Error::NoMatchingConfigGroup(..)) => assert!(
+            err.to_string().ends_with(
+                "Did you mean 37d8832a2d6602cab9f78f30a301b230 \
+                 for 37d8832a2d6602cab9f78301b230?"
+            ),
+            "Unexpected error: {}",
+            err
+        ),
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+
+    let (opt_err, _, _) = run_knoll_scenario(
+        SCENARIO,
+        vec!["knoll", "list", "37d8f1a26f1e4c9c8b7d3b1ca2e9f00", "abcdef"],
+        "",
+    );
+    match opt_err {
+        Some(Error::UnknownDisplays(uuids, suggestions)) => {
+            assert_eq!(uuids, vec!["37d8f1a26f1e4c9c8b7d3b1ca2e9f00", "abcdef"]);
+            assert_eq!(
+                suggestions,
+                std::collections::BTreeMap::from([(
+                    "37d8f1a26f1e4c9c8b7d3b1ca2e9f00".to_owned(),
+                    "37d8f1a26f1e4c9c8b7d3b1c1a2e9f00".to_owned()
+                )])
+            );
+        }
+        opt_err => panic!("Unexpected error: {:?}", opt_err),
+    }
+}